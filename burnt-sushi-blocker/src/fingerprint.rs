@@ -0,0 +1,69 @@
+//! Versions of the CEF build loaded into Spotify, reported to the app so that failed blocking can
+//! be matched to the build.
+
+use std::{
+    ffi::{c_char, c_int, CStr},
+    mem,
+};
+
+use dll_syringe::process::OwnedProcessModule;
+
+type CefVersionInfoFn = unsafe extern "C" fn(c_int) -> c_int;
+/// `cef_api_hash(entry)` before CEF 134.
+type CefApiHashFn = unsafe extern "C" fn(c_int) -> *const c_char;
+/// `cef_api_hash(version, entry)` since CEF 134, which added API versioning.
+type CefVersionedApiHashFn = unsafe extern "C" fn(c_int, c_int) -> *const c_char;
+type CefApiVersionFn = unsafe extern "C" fn() -> c_int;
+
+pub struct Fingerprint {
+    pub cef_version: String,
+    pub cef_api_hash: String,
+}
+
+pub fn read() -> Result<Fingerprint, Box<dyn std::error::Error>> {
+    let libcef =
+        OwnedProcessModule::find_local_by_name("libcef.dll")?.ok_or("libcef.dll not found")?;
+    let cef_version_info = libcef.get_local_procedure_address("cef_version_info")?;
+    let cef_version_info = unsafe { mem::transmute::<_, CefVersionInfoFn>(cef_version_info) };
+    let cef_api_hash = libcef.get_local_procedure_address("cef_api_hash")?;
+    // Only exported by builds with API versioning, whose `cef_api_hash` takes the version first.
+    let cef_api_version = libcef.get_local_procedure_address("cef_api_version").ok();
+
+    // Entries 0 to 2 are the CEF version, 3 its commit number and 4 to 7 the Chromium version.
+    let entry = |entry| unsafe { cef_version_info(entry) };
+    let cef_version = format!(
+        "{}.{}.{}+chromium-{}.{}.{}.{}",
+        entry(0),
+        entry(1),
+        entry(2),
+        entry(4),
+        entry(5),
+        entry(6),
+        entry(7)
+    );
+
+    // Entry 0 is the hash of the API of the current platform.
+    let hash = match cef_api_version {
+        Some(cef_api_version) => unsafe {
+            let cef_api_version = mem::transmute::<_, CefApiVersionFn>(cef_api_version);
+            let cef_api_hash = mem::transmute::<_, CefVersionedApiHashFn>(cef_api_hash);
+            cef_api_hash(cef_api_version(), 0)
+        },
+        None => unsafe {
+            let cef_api_hash = mem::transmute::<_, CefApiHashFn>(cef_api_hash);
+            cef_api_hash(0)
+        },
+    };
+    let cef_api_hash = if hash.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(hash) }
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    Ok(Fingerprint {
+        cef_version,
+        cef_api_hash,
+    })
+}
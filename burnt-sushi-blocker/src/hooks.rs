@@ -1,118 +1,194 @@
-use std::{ffi::CStr, mem, panic::AssertUnwindSafe, ptr, slice, sync::Arc, sync::OnceLock};
+use std::{
+    ffi::CStr,
+    mem,
+    panic::AssertUnwindSafe,
+    ptr, slice,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+    sync::{LazyLock, OnceLock, RwLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use cef::*;
 use dll_syringe::process::OwnedProcessModule;
 use enum_map::EnumMap;
-use retour::static_detour;
+use retour::{Function, GenericDetour};
 use winapi::{
     shared::{minwindef::INT, ntdef::PCSTR, ws2def::ADDRINFOA},
     um::winsock2::WSAHOST_NOT_FOUND,
 };
 
-use crate::{cef, utils::panic_info_to_string, FilterRuleset};
+use crate::{cef, perf, utils::panic_info_to_string, FilterRuleset};
 
 type GetAddrInfoFn =
     unsafe extern "system" fn(PCSTR, PCSTR, *const ADDRINFOA, *const *const ADDRINFOA) -> INT;
-static_detour! {
-    static GetAddrInfoHook: unsafe extern "system" fn(PCSTR, PCSTR, *const ADDRINFOA, *const *const ADDRINFOA) -> INT;
-}
 type CefUrlRequestCreateFn = unsafe extern "C" fn(
     *mut cef::_cef_request_t,
     *mut cef::_cef_urlrequest_client_t,
     *mut cef::_cef_request_context_t,
 ) -> *mut cef::cef_urlrequest_t;
 type CefStringUserfreeUtf16FreeFn = unsafe extern "C" fn(cef::cef_string_userfree_utf16_t);
-static_detour! {
-    static CefUrlRequestCreateHook: unsafe extern "C" fn(*mut _cef_request_t, *mut _cef_urlrequest_client_t, *mut _cef_request_context_t) -> *mut cef_urlrequest_t;
+
+pub type Filters = Arc<EnumMap<shared::rpc::blocker_service::FilterHook, FilterRuleset>>;
+
+/// Detour together with the state used by the detour function, as plain functions cannot capture.
+struct Hook<T: Function> {
+    detour: GenericDetour<T>,
+    log_tx: tokio::sync::mpsc::UnboundedSender<LogParams>,
 }
 
+impl<T: Function> Hook<T> {
+    fn log(&self, params: LogParams) {
+        if self.log_tx.send(params).is_ok() {
+            perf::log_queued();
+        }
+    }
+}
+
+/// Rulesets used by the hooks, replaced as a whole so that a request is never checked against a
+/// partially updated set of rules.
+static FILTERS: LazyLock<RwLock<Filters>> = LazyLock::new(RwLock::default);
+
+static GET_ADDR_INFO_HOOK: OnceLock<Hook<GetAddrInfoFn>> = OnceLock::new();
+static CEF_URL_REQUEST_CREATE_HOOK: OnceLock<Hook<CefUrlRequestCreateFn>> = OnceLock::new();
+static CEF_STRING_USERFREE_UTF16_FREE: OnceLock<CefStringUserfreeUtf16FreeFn> = OnceLock::new();
+
+/// Sequence number of the next request reported by any hook.
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
 pub enum LogParams {
     Message(String),
     Request {
         url: String,
         blocked: bool,
         hook: shared::rpc::blocker_service::FilterHook,
+        seq: u64,
+        timestamp_micros: u64,
     },
 }
 
+impl LogParams {
+    /// Report of a request seen by a hook, numbered and timestamped when it was seen.
+    fn request(hook: shared::rpc::blocker_service::FilterHook, blocked: bool, url: String) -> Self {
+        let timestamp_micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64);
+        LogParams::Request {
+            url,
+            blocked,
+            hook,
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            timestamp_micros,
+        }
+    }
+}
+
+/// Switches all hooks to new rulesets at once.
+pub fn set_filters(filters: Filters) {
+    *FILTERS.write().unwrap() = filters;
+}
+
+fn filters() -> Filters {
+    FILTERS.read().unwrap().clone()
+}
+
 pub fn enable(
-    filters: Arc<EnumMap<shared::rpc::blocker_service::FilterHook, FilterRuleset>>,
     log_tx: tokio::sync::mpsc::UnboundedSender<LogParams>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    static GET_ADDR_INFO_HOOK: OnceLock<()> = OnceLock::new();
-    static CEF_URL_REQUEST_CREATE_HOOK: OnceLock<()> = OnceLock::new();
-
-    GET_ADDR_INFO_HOOK
-        .get_or_try_init(|| init_get_addr_info_hook(filters.clone(), log_tx.clone()))?;
-    CEF_URL_REQUEST_CREATE_HOOK
-        .get_or_try_init(|| init_cef_urlrequest_create_hook(filters, log_tx))?;
+    if GET_ADDR_INFO_HOOK.get().is_none() {
+        let hook = init_get_addr_info_hook(log_tx.clone())?;
+        let _ = GET_ADDR_INFO_HOOK.set(hook);
+    }
+    if CEF_URL_REQUEST_CREATE_HOOK.get().is_none() {
+        let hook = init_cef_urlrequest_create_hook(log_tx)?;
+        let _ = CEF_URL_REQUEST_CREATE_HOOK.set(hook);
+    }
 
-    unsafe { GetAddrInfoHook.enable() }?;
-    unsafe { CefUrlRequestCreateHook.enable() }?;
+    unsafe { GET_ADDR_INFO_HOOK.get().unwrap().detour.enable() }?;
+    unsafe { CEF_URL_REQUEST_CREATE_HOOK.get().unwrap().detour.enable() }?;
 
     Ok(())
 }
 
+pub fn is_enabled() -> bool {
+    GET_ADDR_INFO_HOOK
+        .get()
+        .is_some_and(|hook| hook.detour.is_enabled())
+        && CEF_URL_REQUEST_CREATE_HOOK
+            .get()
+            .is_some_and(|hook| hook.detour.is_enabled())
+}
+
 pub fn disable() -> Result<(), Box<dyn std::error::Error>> {
-    if GetAddrInfoHook.is_enabled() {
-        unsafe { GetAddrInfoHook.disable() }?;
+    if let Some(hook) = GET_ADDR_INFO_HOOK
+        .get()
+        .filter(|hook| hook.detour.is_enabled())
+    {
+        unsafe { hook.detour.disable() }?;
     }
-    if CefUrlRequestCreateHook.is_enabled() {
-        unsafe { CefUrlRequestCreateHook.disable()? };
+    if let Some(hook) = CEF_URL_REQUEST_CREATE_HOOK
+        .get()
+        .filter(|hook| hook.detour.is_enabled())
+    {
+        unsafe { hook.detour.disable() }?;
     }
     Ok(())
 }
 
 fn init_get_addr_info_hook(
-    filters: Arc<EnumMap<shared::rpc::blocker_service::FilterHook, FilterRuleset>>,
     log_tx: tokio::sync::mpsc::UnboundedSender<LogParams>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Hook<GetAddrInfoFn>, Box<dyn std::error::Error>> {
     let ws2 =
         OwnedProcessModule::find_local_by_name("WS2_32.dll")?.ok_or("WS2_32.dll not found")?;
     let getaddrinfo = ws2.get_local_procedure_address("getaddrinfo")?;
     let getaddrinfo = unsafe { mem::transmute::<_, GetAddrInfoFn>(getaddrinfo) };
-    unsafe {
-        GetAddrInfoHook.initialize(
-            getaddrinfo,
-            move |node_name, service_name, hints, result| {
-                let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
-                    let url = CStr::from_ptr(node_name).to_str().unwrap(); // TODO:
-                    let block =
-                        !filters[shared::rpc::blocker_service::FilterHook::GetAddrInfo].check(url);
-
-                    let _ = log_tx.send(LogParams::Request {
-                        hook: shared::rpc::blocker_service::FilterHook::GetAddrInfo,
-                        blocked: block,
-                        url: url.to_string(),
-                    });
-
-                    block
-                }));
-
-                let block = match res {
-                    Ok(block) => block,
-                    Err(e) => {
-                        let _ = log_tx.send(LogParams::Message(panic_info_to_string(e)));
-                        false
-                    }
-                };
-
-                if block {
-                    WSAHOST_NOT_FOUND as _
-                } else {
-                    GetAddrInfoHook.call(node_name, service_name, hints, result)
-                }
-            },
-        )
-    }?;
+    let detour = unsafe { GenericDetour::new(getaddrinfo, get_addr_info_detour as GetAddrInfoFn) }?;
 
-    Ok(())
+    Ok(Hook { detour, log_tx })
+}
+
+unsafe extern "system" fn get_addr_info_detour(
+    node_name: PCSTR,
+    service_name: PCSTR,
+    hints: *const ADDRINFOA,
+    result: *const *const ADDRINFOA,
+) -> INT {
+    let hook = GET_ADDR_INFO_HOOK.get().unwrap();
+    let start = Instant::now();
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let url = unsafe { CStr::from_ptr(node_name) }.to_str().unwrap(); // TODO:
+        let block = !filters()[shared::rpc::blocker_service::FilterHook::GetAddrInfo].check(url);
+
+        hook.log(LogParams::request(
+            shared::rpc::blocker_service::FilterHook::GetAddrInfo,
+            block,
+            url.to_string(),
+        ));
+
+        block
+    }));
+    perf::record(
+        shared::rpc::blocker_service::FilterHook::GetAddrInfo,
+        start.elapsed(),
+    );
+
+    let block = match res {
+        Ok(block) => block,
+        Err(e) => {
+            hook.log(LogParams::Message(panic_info_to_string(e)));
+            false
+        }
+    };
+
+    if block {
+        WSAHOST_NOT_FOUND as _
+    } else {
+        unsafe { hook.detour.call(node_name, service_name, hints, result) }
+    }
 }
 
 fn init_cef_urlrequest_create_hook(
-    filters: Arc<EnumMap<shared::rpc::blocker_service::FilterHook, FilterRuleset>>,
     log_tx: tokio::sync::mpsc::UnboundedSender<LogParams>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Hook<CefUrlRequestCreateFn>, Box<dyn std::error::Error>> {
     let libcef =
         OwnedProcessModule::find_local_by_name("libcef.dll")?.ok_or("libcef.dll not found")?;
     let cef_urlrequest_create = libcef.get_local_procedure_address("cef_urlrequest_create")?;
@@ -123,54 +199,66 @@ fn init_cef_urlrequest_create_hook(
     let cef_string_userfree_utf16_free = unsafe {
         mem::transmute::<_, CefStringUserfreeUtf16FreeFn>(cef_string_userfree_utf16_free)
     };
+    let _ = CEF_STRING_USERFREE_UTF16_FREE.set(cef_string_userfree_utf16_free);
 
-    unsafe {
-        CefUrlRequestCreateHook.initialize(
+    let detour = unsafe {
+        GenericDetour::new(
             cef_urlrequest_create,
-            move |request, client, request_context| -> *mut cef::cef_urlrequest_t {
-                let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
-                    if request.is_null() {
-                        return false;
-                    }
-
-                    let cef_url = ((*request).get_url)(request);
-                    if cef_url.is_null() {
-                        return false;
-                    }
-
-                    let wide_url = slice::from_raw_parts((*cef_url).str_, (*cef_url).length as _);
-                    let url = String::from_utf16_lossy(wide_url);
-                    cef_string_userfree_utf16_free(cef_url);
-
-                    let block = !filters
-                        [shared::rpc::blocker_service::FilterHook::CefUrlRequestCreate]
-                        .check(&url);
-
-                    let _ = log_tx.send(LogParams::Request {
-                        hook: shared::rpc::blocker_service::FilterHook::CefUrlRequestCreate,
-                        blocked: block,
-                        url,
-                    });
-
-                    block
-                }));
-
-                let block = match res {
-                    Ok(block) => block,
-                    Err(e) => {
-                        let _ = log_tx.send(LogParams::Message(panic_info_to_string(e)));
-                        false
-                    }
-                };
-
-                if block {
-                    ptr::null_mut()
-                } else {
-                    CefUrlRequestCreateHook.call(request, client, request_context)
-                }
-            },
+            cef_urlrequest_create_detour as CefUrlRequestCreateFn,
         )
     }?;
 
-    Ok(())
+    Ok(Hook { detour, log_tx })
+}
+
+unsafe extern "C" fn cef_urlrequest_create_detour(
+    request: *mut cef::_cef_request_t,
+    client: *mut cef::_cef_urlrequest_client_t,
+    request_context: *mut cef::_cef_request_context_t,
+) -> *mut cef::cef_urlrequest_t {
+    let hook = CEF_URL_REQUEST_CREATE_HOOK.get().unwrap();
+    let start = Instant::now();
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        if request.is_null() {
+            return false;
+        }
+
+        let cef_url = unsafe { ((*request).get_url)(request) };
+        if cef_url.is_null() {
+            return false;
+        }
+
+        let wide_url = unsafe { slice::from_raw_parts((*cef_url).str_, (*cef_url).length as _) };
+        let url = String::from_utf16_lossy(wide_url);
+        unsafe { CEF_STRING_USERFREE_UTF16_FREE.get().unwrap()(cef_url) };
+
+        let block =
+            !filters()[shared::rpc::blocker_service::FilterHook::CefUrlRequestCreate].check(&url);
+
+        hook.log(LogParams::request(
+            shared::rpc::blocker_service::FilterHook::CefUrlRequestCreate,
+            block,
+            url,
+        ));
+
+        block
+    }));
+    perf::record(
+        shared::rpc::blocker_service::FilterHook::CefUrlRequestCreate,
+        start.elapsed(),
+    );
+
+    let block = match res {
+        Ok(block) => block,
+        Err(e) => {
+            hook.log(LogParams::Message(panic_info_to_string(e)));
+            false
+        }
+    };
+
+    if block {
+        ptr::null_mut()
+    } else {
+        unsafe { hook.detour.call(request, client, request_context) }
+    }
 }
@@ -1,10 +1,8 @@
-#![feature(once_cell_try)]
-
 use std::{
     cell::{OnceCell, RefCell},
     net::{Ipv4Addr, SocketAddrV4},
     sync::LazyLock,
-    sync::{Arc, Mutex},
+    sync::{atomic::Ordering, Arc, Mutex},
     thread,
 };
 
@@ -17,7 +15,10 @@ use regex::RegexSet;
 use tokio::select;
 
 mod cef;
+mod fingerprint;
 mod hooks;
+mod peer;
+mod perf;
 mod utils;
 
 static RPC_STATE: LazyLock<Mutex<Option<RpcState>>> = LazyLock::new(|| Mutex::new(None));
@@ -28,6 +29,18 @@ struct RpcState {
     socket_addr: SocketAddrV4,
 }
 
+dll_syringe::payload_procedure! {
+    fn burnt_sushi_blocker_marker() -> String {
+        shared::BLOCKER_MARKER.to_string()
+    }
+}
+
+dll_syringe::payload_procedure! {
+    fn burnt_sushi_blocker_version() -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+}
+
 dll_syringe::payload_procedure! {
     fn start_rpc() -> SocketAddrV4 {
         let mut state = RPC_STATE.lock().unwrap();
@@ -80,41 +93,44 @@ async fn run_rpc(
         .unwrap();
     let client: shared::rpc::blocker_service::Client = capnp_rpc::new_client(ServerImpl::new());
 
-    loop {
-        select! {
-            res = listener.accept() => {
-                let stream = match res {
-                    Ok((stream, _)) => stream,
-                    Err(e) => return Err(e.into()),
-                };
-
-                stream.set_nodelay(true)?;
-                let (reader, writer) =
-                    tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
-                let network = twoparty::VatNetwork::new(
-                    reader,
-                    writer,
-                    rpc_twoparty_capnp::Side::Server,
-                    Default::default(),
-                );
-
-                let rpc_system = RpcSystem::new(Box::new(network), Some(client.clone().client));
-
-                let disconnector = rpc_system.get_disconnector();
-                let mut disconnect_signal = disconnect_signal.clone();
-                tokio::task::spawn_local(async move {
-                    disconnect_signal.changed().await.unwrap();
-                    let _ = hooks::disable();
-                    disconnector.await.unwrap();
-                });
-
-                tokio::task::spawn_local(Box::pin(rpc_system.map(|_| ())));
-            },
+    // The app that injected the blocker connects right after `start_rpc`. Connections of other
+    // users are refused, and the listener is closed after the first accepted one.
+    let stream = loop {
+        let (stream, peer) = select! {
+            res = listener.accept() => res?,
             _ = disconnect_signal.changed() => {
                 return Ok(());
             }
+        };
+        if peer::is_current_user_peer(stream.local_addr()?, peer)? {
+            break stream;
         }
-    }
+    };
+    drop(listener);
+
+    stream.set_nodelay(true)?;
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let network = twoparty::VatNetwork::new(
+        reader,
+        writer,
+        rpc_twoparty_capnp::Side::Server,
+        Default::default(),
+    );
+
+    let rpc_system = RpcSystem::new(Box::new(network), Some(client.client));
+
+    let disconnector = rpc_system.get_disconnector();
+    let mut connection_disconnect_signal = disconnect_signal.clone();
+    tokio::task::spawn_local(async move {
+        connection_disconnect_signal.changed().await.unwrap();
+        let _ = hooks::disable();
+        disconnector.await.unwrap();
+    });
+
+    tokio::task::spawn_local(Box::pin(rpc_system.map(|_| ())));
+
+    let _ = disconnect_signal.changed().await;
+    Ok(())
 }
 
 #[derive(Clone)]
@@ -141,6 +157,8 @@ impl LoggerManager {
         hook: shared::rpc::blocker_service::FilterHook,
         blocked: bool,
         url: &str,
+        seq: u64,
+        timestamp_micros: u64,
     ) {
         let loggers = self.loggers.borrow();
         let futures = futures::future::join_all(loggers.iter().map(|logger| {
@@ -149,6 +167,8 @@ impl LoggerManager {
             builder.set_hook(hook);
             builder.set_blocked(blocked);
             builder.set_url(url);
+            builder.set_seq(seq);
+            builder.set_timestamp_micros(timestamp_micros);
             req.send().promise
         }));
         drop(loggers);
@@ -178,9 +198,17 @@ impl LoggerManager {
         tokio::task::spawn_local(async move {
             loop {
                 while let Some(m) = rx.recv().await {
+                    perf::log_sent();
                     match m {
-                        LogParams::Request { hook, blocked, url } => {
-                            this.log_request(hook, blocked, &url).await;
+                        LogParams::Request {
+                            hook,
+                            blocked,
+                            url,
+                            seq,
+                            timestamp_micros,
+                        } => {
+                            this.log_request(hook, blocked, &url, seq, timestamp_micros)
+                                .await;
                         }
                         LogParams::Message(message) => {
                             this.log_message(&message).await;
@@ -207,9 +235,69 @@ impl FilterRuleset {
     }
 }
 
+/// Rules received in a transfer started by `beginRulesets`, compiled on `commitRulesets`.
+#[derive(Debug, Default)]
+struct PendingRuleset {
+    whitelist: Vec<String>,
+    blacklist: Vec<String>,
+}
+
+impl PendingRuleset {
+    fn read(
+        raw_ruleset: shared::rpc::blocker_service::filter_ruleset::Reader,
+    ) -> capnp::Result<Self> {
+        let read_patterns = |patterns: capnp::text_list::Reader| {
+            patterns
+                .iter()
+                .map(|pattern| pattern.map(|p| String::from_utf8_lossy(p.as_bytes()).into_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            whitelist: read_patterns(raw_ruleset.get_whitelist()?)?,
+            blacklist: read_patterns(raw_ruleset.get_blacklist()?)?,
+        })
+    }
+
+    /// Reads a ruleset serialized as a message and compressed.
+    fn read_compressed(
+        compression: shared::rpc::blocker_service::Compression,
+        data: &[u8],
+    ) -> capnp::Result<Self> {
+        let data = match compression {
+            shared::rpc::blocker_service::Compression::None => data.to_vec(),
+            shared::rpc::blocker_service::Compression::Deflate => {
+                shared::compression::inflate(data)?
+            }
+        };
+        let options = capnp::message::ReaderOptions {
+            traversal_limit_in_words: Some(shared::compression::MAX_DECOMPRESSED_BYTES / 8),
+            nesting_limit: 64,
+        };
+        let message = capnp::serialize::read_message(&mut data.as_slice(), options)?;
+        Self::read(message.get_root()?)
+    }
+
+    fn len(&self) -> usize {
+        self.whitelist.len() + self.blacklist.len()
+    }
+
+    fn compile(&self) -> capnp::Result<FilterRuleset> {
+        Ok(FilterRuleset {
+            whitelist: RegexSet::new(&self.whitelist)
+                .map_err(|e| capnp::Error::failed(e.to_string()))?,
+            blacklist: RegexSet::new(&self.blacklist)
+                .map_err(|e| capnp::Error::failed(e.to_string()))?,
+        })
+    }
+}
+
+/// Most rules accepted in a single transfer, so that a runaway sender cannot exhaust memory.
+const MAX_PENDING_RULES: usize = 1_000_000;
+
 struct ServerImpl {
     logger: LoggerManager,
-    filters: Arc<EnumMap<shared::rpc::blocker_service::FilterHook, FilterRuleset>>,
+    filters: hooks::Filters,
+    pending: Option<EnumMap<shared::rpc::blocker_service::FilterHook, PendingRuleset>>,
 }
 
 impl ServerImpl {
@@ -217,8 +305,50 @@ impl ServerImpl {
         Self {
             logger: LoggerManager::new(),
             filters: Arc::new(EnumMap::default()),
+            pending: None,
         }
     }
+
+    /// Replaces the ruleset of a single hook.
+    fn apply_ruleset(
+        &mut self,
+        hook: shared::rpc::blocker_service::FilterHook,
+        ruleset: PendingRuleset,
+    ) -> capnp::Result<()> {
+        let mut filters = (*self.filters).clone();
+        filters[hook] = ruleset.compile()?;
+        self.replace_filters(filters);
+        Ok(())
+    }
+
+    fn replace_filters(
+        &mut self,
+        filters: EnumMap<shared::rpc::blocker_service::FilterHook, FilterRuleset>,
+    ) {
+        self.filters = Arc::new(filters);
+        hooks::set_filters(self.filters.clone());
+    }
+
+    fn add_rules(
+        &mut self,
+        hook: shared::rpc::blocker_service::FilterHook,
+        rules: PendingRuleset,
+    ) -> capnp::Result<()> {
+        let pending = self
+            .pending
+            .as_mut()
+            .ok_or_else(|| capnp::Error::failed("no ruleset transfer in progress".to_string()))?;
+        let total = pending.values().map(PendingRuleset::len).sum::<usize>() + rules.len();
+        if total > MAX_PENDING_RULES {
+            self.pending = None;
+            return Err(capnp::Error::failed(format!(
+                "ruleset transfer exceeds {MAX_PENDING_RULES} rules"
+            )));
+        }
+        pending[hook].whitelist.extend(rules.whitelist);
+        pending[hook].blacklist.extend(rules.blacklist);
+        Ok(())
+    }
 }
 
 impl shared::rpc::blocker_service::Server for ServerImpl {
@@ -240,40 +370,122 @@ impl shared::rpc::blocker_service::Server for ServerImpl {
     ) -> Promise<(), ::capnp::Error> {
         pry!((move || {
             let hook = params.get()?.get_hook()?;
-            let raw_ruleset = params.get()?.get_ruleset()?;
-            let whitelist = raw_ruleset.get_whitelist()?;
-            let blacklist = raw_ruleset.get_blacklist()?;
-
-            let ruleset = &mut Arc::get_mut(&mut self.filters).ok_or_else(|| {
-                ::capnp::Error::failed("cannot modify filters while in use".to_string())
-            })?[hook];
-            ruleset.whitelist = RegexSet::new(
-                whitelist
-                    .iter()
-                    .map(|pattern| pattern.map(|p| String::from_utf8_lossy(p.as_bytes())))
-                    .collect::<Result<Vec<_>, _>>()?,
-            )
-            .map_err(|e| capnp::Error::failed(e.to_string()))?;
-            ruleset.blacklist = RegexSet::new(
-                blacklist
-                    .iter()
-                    .map(|pattern| pattern.map(|p| String::from_utf8_lossy(p.as_bytes())))
-                    .collect::<Result<Vec<_>, _>>()?,
-            )
-            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+            let ruleset = PendingRuleset::read(params.get()?.get_ruleset()?)?;
+            self.apply_ruleset(hook, ruleset)
+        })());
+
+        Promise::ok(())
+    }
+
+    fn set_ruleset_compressed(
+        &mut self,
+        params: shared::rpc::blocker_service::SetRulesetCompressedParams,
+        mut _results: shared::rpc::blocker_service::SetRulesetCompressedResults,
+    ) -> Promise<(), ::capnp::Error> {
+        pry!((move || {
+            let params = params.get()?;
+            let ruleset =
+                PendingRuleset::read_compressed(params.get_compression()?, params.get_ruleset()?)?;
+            self.apply_ruleset(params.get_hook()?, ruleset)
+        })());
+
+        Promise::ok(())
+    }
+
+    fn begin_rulesets(
+        &mut self,
+        _params: shared::rpc::blocker_service::BeginRulesetsParams,
+        mut _results: shared::rpc::blocker_service::BeginRulesetsResults,
+    ) -> Promise<(), ::capnp::Error> {
+        self.pending = Some(EnumMap::default());
 
+        Promise::ok(())
+    }
+
+    fn add_rules(
+        &mut self,
+        params: shared::rpc::blocker_service::AddRulesParams,
+        mut _results: shared::rpc::blocker_service::AddRulesResults,
+    ) -> Promise<(), ::capnp::Error> {
+        pry!((move || {
+            let params = params.get()?;
+            let rules = PendingRuleset::read(params.get_ruleset()?)?;
+            self.add_rules(params.get_hook()?, rules)
+        })());
+
+        Promise::ok(())
+    }
+
+    fn add_rules_compressed(
+        &mut self,
+        params: shared::rpc::blocker_service::AddRulesCompressedParams,
+        mut _results: shared::rpc::blocker_service::AddRulesCompressedResults,
+    ) -> Promise<(), ::capnp::Error> {
+        pry!((move || {
+            let params = params.get()?;
+            let rules =
+                PendingRuleset::read_compressed(params.get_compression()?, params.get_ruleset()?)?;
+            self.add_rules(params.get_hook()?, rules)
+        })());
+
+        Promise::ok(())
+    }
+
+    fn commit_rulesets(
+        &mut self,
+        _params: shared::rpc::blocker_service::CommitRulesetsParams,
+        mut _results: shared::rpc::blocker_service::CommitRulesetsResults,
+    ) -> Promise<(), ::capnp::Error> {
+        pry!((move || {
+            let pending = self.pending.take().ok_or_else(|| {
+                capnp::Error::failed("no ruleset transfer in progress".to_string())
+            })?;
+            // Everything is compiled before the hooks see any of it.
+            let mut filters = EnumMap::<_, FilterRuleset>::default();
+            for (hook, ruleset) in pending {
+                filters[hook] = ruleset.compile()?;
+            }
+            self.replace_filters(filters);
             Ok::<(), capnp::Error>(())
         })());
 
         Promise::ok(())
     }
 
+    fn get_capabilities(
+        &mut self,
+        _params: shared::rpc::blocker_service::GetCapabilitiesParams,
+        mut results: shared::rpc::blocker_service::GetCapabilitiesResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let mut results = results.get();
+        results.set_chunked_rulesets(true);
+        let mut compressions = results.init_compressions(1);
+        compressions.set(0, shared::rpc::blocker_service::Compression::Deflate);
+
+        Promise::ok(())
+    }
+
+    fn get_fingerprint(
+        &mut self,
+        _params: shared::rpc::blocker_service::GetFingerprintParams,
+        mut results: shared::rpc::blocker_service::GetFingerprintResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let fingerprint =
+            pry!(fingerprint::read().map_err(|e| capnp::Error::failed(e.to_string())));
+
+        let mut builder = results.get().init_fingerprint();
+        builder.set_cef_version(&fingerprint.cef_version);
+        builder.set_cef_api_hash(&fingerprint.cef_api_hash);
+
+        Promise::ok(())
+    }
+
     fn enable_filtering(
         &mut self,
         _params: shared::rpc::blocker_service::EnableFilteringParams,
         mut _results: shared::rpc::blocker_service::EnableFilteringResults,
     ) -> Promise<(), ::capnp::Error> {
-        match hooks::enable(self.filters.clone(), self.logger.log_sender()) {
+        match hooks::enable(self.logger.log_sender()) {
             Ok(()) => Promise::ok(()),
             Err(e) => Promise::err(capnp::Error::failed(e.to_string())),
         }
@@ -289,4 +501,45 @@ impl shared::rpc::blocker_service::Server for ServerImpl {
             Err(e) => Promise::err(capnp::Error::failed(e.to_string())),
         }
     }
+
+    fn get_status(
+        &mut self,
+        _params: shared::rpc::blocker_service::GetStatusParams,
+        mut results: shared::rpc::blocker_service::GetStatusResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let rule_count = self
+            .filters
+            .values()
+            .map(|ruleset| ruleset.whitelist.len() + ruleset.blacklist.len())
+            .sum::<usize>();
+
+        let mut results = results.get();
+        results.set_filtering(hooks::is_enabled());
+        results.set_rule_count(rule_count as u32);
+
+        Promise::ok(())
+    }
+
+    fn get_perf_stats(
+        &mut self,
+        _params: shared::rpc::blocker_service::GetPerfStatsParams,
+        mut results: shared::rpc::blocker_service::GetPerfStatsResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let mut stats = results.get().init_stats();
+        let hooks = perf::hooks();
+        let mut hook_stats = stats.reborrow().init_hooks(hooks.len() as u32);
+        for (i, (hook, counters)) in hooks.iter().enumerate() {
+            let mut builder = hook_stats.reborrow().get(i as u32);
+            builder.set_hook(hook);
+            builder.set_calls(counters.calls.load(Ordering::Relaxed));
+            builder.set_total_nanos(counters.total_nanos.load(Ordering::Relaxed));
+            builder.set_max_nanos(counters.max_nanos.load(Ordering::Relaxed));
+        }
+        stats.set_pending_logs(perf::pending_logs() as u32);
+        stats.set_max_pending_logs(perf::max_pending_logs() as u32);
+        stats.set_working_set_bytes(perf::working_set_bytes());
+        stats.set_cpu_time_millis(perf::cpu_time_millis());
+
+        Promise::ok(())
+    }
 }
@@ -0,0 +1,130 @@
+//! Tells which user opened a loopback connection, so that the RPC listener only serves the user
+//! running Spotify and with it the app that injected the blocker.
+
+use std::{
+    io, mem,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    ptr, slice,
+};
+
+use winapi::{
+    shared::{
+        iprtrmib::TCP_TABLE_OWNER_PID_CONNECTIONS,
+        minwindef::{DWORD, FALSE},
+        tcpmib::MIB_TCPTABLE_OWNER_PID,
+        winerror::{ERROR_INSUFFICIENT_BUFFER, NO_ERROR},
+        ws2def::AF_INET,
+    },
+    um::{
+        handleapi::CloseHandle,
+        iphlpapi::GetExtendedTcpTable,
+        processthreadsapi::{GetCurrentProcess, OpenProcess, OpenProcessToken},
+        securitybaseapi::{EqualSid, GetTokenInformation},
+        winnt::{
+            TokenUser, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION, PSID, TOKEN_QUERY, TOKEN_USER,
+        },
+    },
+};
+
+/// Whether a loopback connection accepted on `local` was opened by a process of the user running
+/// Spotify. Connections of processes whose owner cannot be determined are treated as foreign.
+pub fn is_current_user_peer(local: SocketAddr, peer: SocketAddr) -> io::Result<bool> {
+    let (SocketAddr::V4(local), SocketAddr::V4(peer)) = (local, peer) else {
+        return Ok(false);
+    };
+    // The peer's side of the connection is the one whose local endpoint is its address.
+    let Some(pid) = tcp_connection_owner(peer, local)? else {
+        return Ok(false);
+    };
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if process.is_null() {
+        return Ok(false);
+    }
+    let peer_user = unsafe { process_user(process) };
+    unsafe { CloseHandle(process) };
+    let Ok(peer_user) = peer_user else {
+        return Ok(false);
+    };
+
+    let current_user = unsafe { process_user(GetCurrentProcess()) }?;
+    Ok(unsafe { EqualSid(user_sid(&current_user), user_sid(&peer_user)) } != 0)
+}
+
+/// Returns the `TOKEN_USER` of the process, in a buffer aligned for the pointer it contains.
+///
+/// # Safety
+/// `process` must be a valid process handle with query access.
+unsafe fn process_user(process: HANDLE) -> io::Result<Vec<u64>> {
+    let mut token = ptr::null_mut();
+    if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut size: DWORD = 0;
+    unsafe { GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut size) };
+    let mut buffer = vec![0u64; (size as usize).div_ceil(mem::size_of::<u64>())];
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenUser,
+            buffer.as_mut_ptr().cast(),
+            size,
+            &mut size,
+        )
+    };
+    unsafe { CloseHandle(token) };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(buffer)
+}
+
+fn user_sid(token_user: &[u64]) -> PSID {
+    unsafe { &*token_user.as_ptr().cast::<TOKEN_USER>() }
+        .User
+        .Sid
+}
+
+/// Returns the id of the process owning the TCP connection from `local` to `remote`.
+fn tcp_connection_owner(local: SocketAddrV4, remote: SocketAddrV4) -> io::Result<Option<u32>> {
+    let mut size: DWORD = 0;
+    let mut buffer: Vec<u32> = Vec::new();
+    loop {
+        let result = unsafe {
+            GetExtendedTcpTable(
+                buffer.as_mut_ptr().cast(),
+                &mut size,
+                FALSE,
+                AF_INET as u32,
+                TCP_TABLE_OWNER_PID_CONNECTIONS,
+                0,
+            )
+        };
+        match result {
+            NO_ERROR => break,
+            ERROR_INSUFFICIENT_BUFFER => {
+                buffer.resize((size as usize).div_ceil(mem::size_of::<u32>()), 0)
+            }
+            code => return Err(io::Error::from_raw_os_error(code as i32)),
+        }
+    }
+
+    let table = unsafe { &*buffer.as_ptr().cast::<MIB_TCPTABLE_OWNER_PID>() };
+    let rows = unsafe { slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize) };
+    Ok(rows
+        .iter()
+        .find(|row| {
+            endpoint(row.dwLocalAddr, row.dwLocalPort) == local
+                && endpoint(row.dwRemoteAddr, row.dwRemotePort) == remote
+        })
+        .map(|row| row.dwOwningPid))
+}
+
+/// Converts an address and port in network byte order as found in the TCP table.
+fn endpoint(addr: DWORD, port: DWORD) -> SocketAddrV4 {
+    SocketAddrV4::new(
+        Ipv4Addr::from(u32::from_be(addr)),
+        u16::from_be(port as u16),
+    )
+}
@@ -0,0 +1,100 @@
+//! Counters of the time spent in the hooks and the backlog of the log channel, so that reports of
+//! Spotify getting slow can be attributed to the blocker or ruled out.
+
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        LazyLock,
+    },
+    time::Duration,
+};
+
+use enum_map::EnumMap;
+use shared::rpc::blocker_service::FilterHook;
+use winapi::{
+    shared::minwindef::{DWORD, FILETIME},
+    um::{
+        processthreadsapi::{GetCurrentProcess, GetProcessTimes},
+        psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+    },
+};
+
+static HOOKS: LazyLock<EnumMap<FilterHook, HookCounters>> = LazyLock::new(EnumMap::default);
+static PENDING_LOGS: AtomicUsize = AtomicUsize::new(0);
+static MAX_PENDING_LOGS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Default)]
+pub struct HookCounters {
+    pub calls: AtomicU64,
+    pub total_nanos: AtomicU64,
+    pub max_nanos: AtomicU64,
+}
+
+/// Records the time a hook spent filtering a request, excluding the call to the original function.
+pub fn record(hook: FilterHook, elapsed: Duration) {
+    let nanos = elapsed.as_nanos() as u64;
+    let counters = &HOOKS[hook];
+    counters.calls.fetch_add(1, Ordering::Relaxed);
+    counters.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+    counters.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+}
+
+pub fn hooks() -> &'static EnumMap<FilterHook, HookCounters> {
+    &HOOKS
+}
+
+/// Called when a hook queues a log entry.
+pub fn log_queued() {
+    let pending = PENDING_LOGS.fetch_add(1, Ordering::Relaxed) + 1;
+    MAX_PENDING_LOGS.fetch_max(pending, Ordering::Relaxed);
+}
+
+/// Called when a queued log entry was sent to the loggers.
+pub fn log_sent() {
+    PENDING_LOGS.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub fn pending_logs() -> usize {
+    PENDING_LOGS.load(Ordering::Relaxed)
+}
+
+pub fn max_pending_logs() -> usize {
+    MAX_PENDING_LOGS.load(Ordering::Relaxed)
+}
+
+/// Working set of the current process in bytes.
+pub fn working_set_bytes() -> u64 {
+    let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { mem::zeroed() };
+    let size = mem::size_of::<PROCESS_MEMORY_COUNTERS>() as DWORD;
+    if unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) } == 0 {
+        return 0;
+    }
+    counters.WorkingSetSize as u64
+}
+
+/// User and kernel time used by the current process in milliseconds.
+pub fn cpu_time_millis() -> u64 {
+    let mut creation: FILETIME = unsafe { mem::zeroed() };
+    let mut exit: FILETIME = unsafe { mem::zeroed() };
+    let mut kernel: FILETIME = unsafe { mem::zeroed() };
+    let mut user: FILETIME = unsafe { mem::zeroed() };
+    let result = unsafe {
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        )
+    };
+    if result == 0 {
+        return 0;
+    }
+    // FILETIME counts in units of 100ns.
+    (filetime_to_u64(kernel) + filetime_to_u64(user)) / 10_000
+}
+
+fn filetime_to_u64(time: FILETIME) -> u64 {
+    (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime)
+}
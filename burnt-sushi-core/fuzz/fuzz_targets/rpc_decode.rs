@@ -0,0 +1,32 @@
+//! Feeds arbitrary bytes to the decoder of messages from the blocker, reading them as every message
+//! type the app receives. Run with `cargo +nightly fuzz run rpc_decode` from `burnt-sushi-core`.
+
+#![no_main]
+
+use burnt_sushi_core::rpc::decode;
+use capnp::serialize;
+use libfuzzer_sys::fuzz_target;
+use shared::rpc::blocker_service::{self, logger};
+
+fuzz_target!(|data: &[u8]| {
+    let mut data = data;
+    let Ok(message) = serialize::read_message(&mut data, decode::reader_options()) else {
+        return;
+    };
+
+    if let Ok(request) = message.get_root::<logger::request::Reader>() {
+        let _ = decode::log_request(request);
+    }
+    if let Ok(text) = message.get_root::<capnp::text::Reader>() {
+        let _ = decode::log_message(text);
+    }
+    if let Ok(results) = message.get_root::<blocker_service::get_status_results::Reader>() {
+        let _ = decode::status(results);
+    }
+    if let Ok(stats) = message.get_root::<blocker_service::perf_stats::Reader>() {
+        let _ = decode::perf_stats(stats);
+    }
+    if let Ok(fingerprint) = message.get_root::<blocker_service::fingerprint::Reader>() {
+        let _ = decode::fingerprint(fingerprint);
+    }
+});
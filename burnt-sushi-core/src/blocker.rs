@@ -0,0 +1,80 @@
+//! Typed access to the procedures exported by the blocker. Looking them up is only sound if the
+//! signatures match the exports of the blocker, so all lookups and export names are kept here.
+
+use std::net::SocketAddrV4;
+
+use dll_syringe::{
+    process::{BorrowedProcess, ProcessModule},
+    Syringe,
+};
+
+use crate::error::{Error, Result};
+
+/// Address the RPC server of the blocker listens on.
+pub type Endpoint = SocketAddrV4;
+
+/// Blocker module loaded into a Spotify process.
+#[derive(Clone, Copy)]
+pub struct BlockerHandle<'a> {
+    syringe: &'a Syringe,
+    module: ProcessModule<BorrowedProcess<'a>>,
+}
+
+impl<'a> BlockerHandle<'a> {
+    pub fn new(syringe: &'a Syringe, module: ProcessModule<BorrowedProcess<'a>>) -> Self {
+        Self { syringe, module }
+    }
+
+    /// Whether the module is a blocker, checked by the marker it exports.
+    pub fn is_blocker(&self) -> bool {
+        let marker = unsafe {
+            self.syringe.get_payload_procedure::<fn() -> String>(
+                self.module,
+                shared::BLOCKER_MARKER_PROCEDURE,
+            )
+        };
+        match marker {
+            Ok(Some(marker)) => marker
+                .call()
+                .is_ok_and(|marker| marker == shared::BLOCKER_MARKER),
+            _ => false,
+        }
+    }
+
+    /// Starts the RPC server of the blocker, or returns the endpoint of the running one.
+    pub fn start(&self) -> Result<Endpoint> {
+        let start_rpc = unsafe {
+            self.syringe
+                .get_payload_procedure::<fn() -> SocketAddrV4>(self.module, START_RPC)
+        }?
+        .ok_or(Error::MissingProcedure(START_RPC))?;
+        Ok(start_rpc.call()?)
+    }
+
+    /// Disables the hooks and stops the RPC server of the blocker, which disconnects all clients.
+    pub fn stop(&self) -> Result<()> {
+        let stop_rpc = unsafe {
+            self.syringe
+                .get_payload_procedure::<fn()>(self.module, STOP_RPC)
+        }?
+        .ok_or(Error::MissingProcedure(STOP_RPC))?;
+        Ok(stop_rpc.call()?)
+    }
+
+    /// Version of the blocker build, `None` for blockers that predate exporting it.
+    pub fn version(&self) -> Result<Option<String>> {
+        let version = unsafe {
+            self.syringe.get_payload_procedure::<fn() -> String>(
+                self.module,
+                shared::BLOCKER_VERSION_PROCEDURE,
+            )
+        }?;
+        match version {
+            Some(version) => Ok(Some(version.call()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+const START_RPC: &str = "start_rpc";
+const STOP_RPC: &str = "stop_rpc";
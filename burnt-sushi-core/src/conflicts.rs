@@ -0,0 +1,91 @@
+//! Detection of other modifications of Spotify that can interfere with the blocker, e.g. another ad
+//! blocker hooking the same functions.
+
+use std::{fmt, path::Path};
+
+use dll_syringe::process::{BorrowedProcess, Process};
+use log::debug;
+
+/// Modules of other ad blockers that hook the same functions as the blocker. Proxy modules are only
+/// considered if they are loaded from the Spotify directory, as Windows ships modules with the
+/// same name.
+const AD_BLOCKER_MODULES: &[(&str, &str, bool)] = &[
+    // (file name, ad blocker, proxy)
+    ("blockthespot.dll", "BlockTheSpot", false),
+    ("dpapi.dll", "BlockTheSpot", true),
+];
+
+/// Another modification of Spotify.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// Spicetify extracted and patched the client UI.
+    Spicetify,
+    /// The client UI was patched and a backup of the original kept, as done by SpotX.
+    PatchedClient,
+    /// A module of another ad blocker is loaded into Spotify.
+    AdBlocker { name: &'static str, module: String },
+}
+
+impl Conflict {
+    /// Whether the modification hooks the same functions as the blocker, so that both filtering
+    /// the same requests can break them.
+    pub fn hooks_same_functions(&self) -> bool {
+        matches!(self, Conflict::AdBlocker { .. })
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Spicetify => write!(f, "Spicetify"),
+            Conflict::PatchedClient => write!(f, "patched client UI (SpotX)"),
+            Conflict::AdBlocker { name, module } => write!(f, "{name} ({module})"),
+        }
+    }
+}
+
+/// Looks for other modifications in the installation and the loaded modules of a Spotify process.
+pub fn detect(process: BorrowedProcess<'_>) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    let spotify_dir = process
+        .path()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf));
+
+    if let Some(dir) = &spotify_dir {
+        let apps_dir = dir.join("Apps");
+        if apps_dir.join("xpui").is_dir() {
+            conflicts.push(Conflict::Spicetify);
+        }
+        if apps_dir.join("xpui.bak").is_file() {
+            conflicts.push(Conflict::PatchedClient);
+        }
+    }
+
+    match process.modules() {
+        Ok(modules) => {
+            for module in modules {
+                let Ok(path) = module.path() else {
+                    continue;
+                };
+                let Some(file_name) = path.file_name() else {
+                    continue;
+                };
+                let file_name = file_name.to_string_lossy().to_lowercase();
+                let in_spotify_dir = spotify_dir.as_deref() == path.parent();
+                let known = AD_BLOCKER_MODULES
+                    .iter()
+                    .find(|(module, _, proxy)| *module == file_name && (!proxy || in_spotify_dir));
+                if let Some((_, name, _)) = known {
+                    conflicts.push(Conflict::AdBlocker {
+                        name,
+                        module: file_name,
+                    });
+                }
+            }
+        }
+        Err(e) => debug!("Failed to list modules while looking for conflicts: {e}"),
+    }
+
+    conflicts
+}
@@ -0,0 +1,189 @@
+use std::{fmt, io, iter};
+
+use dll_syringe::error::{EjectError, InjectError, SyringeError};
+use thiserror::Error;
+use winapi::shared::winerror::{
+    ERROR_ACCESS_DISABLED_BY_POLICY, ERROR_INVALID_IMAGE_HASH, ERROR_VIRUS_DELETED,
+    ERROR_VIRUS_INFECTED, WSAEACCES,
+};
+
+use crate::{integrity::IntegrityMismatch, spotify_verification::VerificationError};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors that can occur while hooking or unhooking Spotify.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to inspect modules of Spotify process")]
+    InspectModules(#[source] io::Error),
+    #[error("Failed to load filter config")]
+    FilterConfig(#[source] io::Error),
+    #[error("Failed to prepare blocker")]
+    PrepareBlocker(#[source] io::Error),
+    #[error("Failed to inject blocker")]
+    Inject(#[source] InjectError),
+    #[error("Failed to eject blocker")]
+    Eject(#[source] EjectError),
+    #[error("Blocker module does not export `{0}`")]
+    MissingProcedure(&'static str),
+    #[error("Failed to communicate with Spotify process")]
+    Syringe(#[from] SyringeError),
+    #[error("Failed to connect to blocker")]
+    RpcConnect(#[source] io::Error),
+    #[error("RPC task panicked")]
+    RpcTaskPanicked,
+    #[error("Blocker did not start working")]
+    NotReady(#[source] HealthError),
+    #[error(
+        "Spotify (PID={pid}) is already being blocked by another instance, likely of another user"
+    )]
+    HookedElsewhere { pid: u32 },
+    #[error("Failed to coordinate with instances in other sessions")]
+    Claim(#[source] io::Error),
+    #[error("Process does not look like the genuine Spotify client")]
+    NotSpotify(#[source] VerificationError),
+    #[error("Blocker is not signed by a trusted publisher (use --allow-unsigned-blocker to inject it anyway)")]
+    UntrustedBlocker(#[source] io::Error),
+    #[error("Spotify is a 32-bit process, but the blocker only supports 64-bit Spotify")]
+    ArchitectureMismatch,
+    #[error("Spotify is already modified by {0}, which hooks the same functions")]
+    Conflict(String),
+    #[error("{0}, {}", .0.advice())]
+    IntegrityMismatch(IntegrityMismatch),
+}
+
+/// Cause of a failure to hook Spotify in terms the user can act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    ArchitectureMismatch,
+    /// The Microsoft Store version of Spotify refused the blocker.
+    StorePackage,
+    /// An antivirus or application control policy blocked or removed the blocker.
+    SecuritySoftware,
+    /// The connection to the blocker was blocked.
+    Firewall,
+    /// Another ad blocker is loaded into Spotify.
+    Conflict,
+    /// Spotify runs elevated while the app does not.
+    IntegrityMismatch,
+    /// The blocker kept stopping or being unloaded after it was injected.
+    Unstable,
+    Other,
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureReason::ArchitectureMismatch => write!(f, "unsupported Spotify architecture"),
+            FailureReason::StorePackage => write!(f, "Microsoft Store version of Spotify"),
+            FailureReason::SecuritySoftware => write!(f, "blocked by security software"),
+            FailureReason::Firewall => write!(f, "blocked by firewall"),
+            FailureReason::Conflict => write!(f, "conflicting modification"),
+            FailureReason::IntegrityMismatch => write!(f, "Spotify runs as administrator"),
+            FailureReason::Unstable => write!(f, "blocker keeps stopping"),
+            FailureReason::Other => write!(f, "hooking failed"),
+        }
+    }
+}
+
+impl Error {
+    /// Whether the error was caused by the Spotify process going away.
+    pub fn is_process_gone(&self) -> bool {
+        matches!(
+            self,
+            Error::Syringe(SyringeError::ProcessInaccessible | SyringeError::ModuleInaccessible)
+        )
+    }
+
+    /// Whether the connection to the blocker was denied, which points to a firewall or other
+    /// security software. Refused, reset and timed out connections also happen while the blocker
+    /// is still starting and stay retryable.
+    pub fn is_firewall_blocked(&self) -> bool {
+        let Error::RpcConnect(e) = self else {
+            return false;
+        };
+        e.raw_os_error().map(|code| code as u32) == Some(WSAEACCES)
+    }
+
+    /// Classifies the error, `store_package` tells whether the Spotify process belongs to the
+    /// Microsoft Store package.
+    pub fn failure_reason(&self, store_package: bool) -> FailureReason {
+        if matches!(self, Error::ArchitectureMismatch) {
+            FailureReason::ArchitectureMismatch
+        } else if matches!(self, Error::Conflict(_)) {
+            FailureReason::Conflict
+        } else if matches!(self, Error::IntegrityMismatch(_)) {
+            FailureReason::IntegrityMismatch
+        } else if self.is_firewall_blocked() {
+            FailureReason::Firewall
+        } else if self.is_blocked_by_security_software() {
+            FailureReason::SecuritySoftware
+        } else if store_package && matches!(self, Error::Inject(_) | Error::Syringe(_)) {
+            FailureReason::StorePackage
+        } else {
+            FailureReason::Other
+        }
+    }
+
+    /// Whether any OS error in the chain is raised when an antivirus quarantines a file or an
+    /// application control policy prevents loading it.
+    fn is_blocked_by_security_software(&self) -> bool {
+        iter::successors(Some(self as &(dyn std::error::Error + 'static)), |e| {
+            e.source()
+        })
+        .filter_map(|e| e.downcast_ref::<io::Error>())
+        .filter_map(|e| e.raw_os_error())
+        .any(|code| {
+            matches!(
+                code as u32,
+                ERROR_VIRUS_INFECTED
+                    | ERROR_VIRUS_DELETED
+                    | ERROR_ACCESS_DISABLED_BY_POLICY
+                    | ERROR_INVALID_IMAGE_HASH
+            )
+        })
+    }
+
+    /// Whether retrying the failed operation could reasonably succeed.
+    pub fn is_retryable(&self) -> bool {
+        !self.is_process_gone()
+            && !self.is_firewall_blocked()
+            && !matches!(
+                self,
+                Error::FilterConfig(_)
+                    | Error::MissingProcedure(_)
+                    | Error::HookedElsewhere { .. }
+                    | Error::NotSpotify(_)
+                    | Error::UntrustedBlocker(_)
+                    | Error::ArchitectureMismatch
+                    | Error::Conflict(_)
+                    | Error::IntegrityMismatch(_)
+            )
+    }
+}
+
+/// Errors of requests sent to a running blocker.
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("RPC task has stopped")]
+    Stopped,
+    #[error("RPC did not respond in time")]
+    Timeout,
+    #[error("RPC request failed")]
+    Failed(#[source] capnp::Error),
+}
+
+/// Reasons why a running blocker is considered unhealthy.
+#[derive(Debug, Error)]
+pub enum HealthError {
+    #[error("Failed to inspect modules of Spotify process")]
+    InspectModules(#[source] io::Error),
+    #[error("Blocker module is no longer loaded")]
+    ModuleMissing,
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error("Filtering is disabled")]
+    FilteringDisabled,
+    #[error("Blocker has {actual} filter rules but {expected} were applied")]
+    RuleCountMismatch { expected: usize, actual: usize },
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Mutex,
+    time::Instant,
+};
+
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+use shared::rpc::blocker_service::FilterHook;
+
+use crate::metrics::METRICS;
+
+/// Rule reported for requests blocked because they are not on the allowlist.
+pub const NOT_ALLOWLISTED: &str = "<not allowlisted>";
+/// Number of urls whose decision is kept, as matching a url against large lists is slow.
+pub const DECISION_CACHE_SIZE: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FilterConfig {
+    pub allowlist: Vec<String>,
+    pub denylist: Vec<String>,
+}
+
+/// Regression tests declared as `[[tests]]` next to the rules of a filter file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterTests {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<FilterTest>,
+}
+
+/// Url together with whether the rules are expected to block it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FilterTest {
+    pub url: String,
+    pub expect: Expectation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Expectation {
+    Blocked,
+    Allowed,
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expectation::Blocked => write!(f, "blocked"),
+            Expectation::Allowed => write!(f, "allowed"),
+        }
+    }
+}
+
+/// Test whose url was not treated as expected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterTestFailure {
+    pub test: FilterTest,
+    /// Rule that blocked the url, if it was blocked.
+    pub rule: Option<String>,
+}
+
+impl fmt::Display for FilterTestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' should be {}", self.test.url, self.test.expect)?;
+        match &self.rule {
+            Some(rule) => write!(f, ", but was blocked by '{rule}'"),
+            None => write!(f, ", but was allowed"),
+        }
+    }
+}
+
+/// Host-side copy of the filters applied by the blocker, used to attribute blocked requests to rules.
+/// The decisions for recently evaluated urls are cached, since the same urls are requested over and
+/// over.
+#[derive(Debug)]
+pub struct CompiledFilters {
+    allowlist: RegexSet,
+    denylist: RegexSet,
+    cache: Mutex<DecisionCache>,
+}
+
+impl Clone for CompiledFilters {
+    fn clone(&self) -> Self {
+        Self {
+            allowlist: self.allowlist.clone(),
+            denylist: self.denylist.clone(),
+            cache: Mutex::default(),
+        }
+    }
+}
+
+impl CompiledFilters {
+    pub fn new(config: &FilterConfig) -> Result<Self, regex::Error> {
+        Ok(Self {
+            allowlist: RegexSet::new(&config.allowlist)?,
+            denylist: RegexSet::new(&config.denylist)?,
+            cache: Mutex::default(),
+        })
+    }
+
+    /// Number of urls whose decision is cached.
+    pub fn cached_decisions(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    fn decide(&self, url: &str) -> Decision {
+        if let Some(decision) = self.cache.lock().unwrap().get(url) {
+            METRICS.filter_cache_hits.inc();
+            return decision;
+        }
+
+        let start = Instant::now();
+        let decision = Decision {
+            allowlisted: self.allowlist.is_empty() || self.allowlist.is_match(host(url)),
+            denied_by: self.denylist.matches(url).into_iter().next(),
+        };
+        METRICS.filter_cache_misses.inc();
+        METRICS.filter_latency.observe(start.elapsed());
+
+        self.cache.lock().unwrap().insert(url, decision);
+        decision
+    }
+
+    fn denylist_rule(&self, index: usize) -> &str {
+        self.denylist.patterns()[index].as_str()
+    }
+
+    /// Returns the rule that would block a request for the url in either hook, checking the host
+    /// against the allowlist like `getaddrinfo` and the full url against the denylist.
+    pub fn evaluate(&self, url: &str) -> Option<&str> {
+        let decision = self.decide(url);
+        if !decision.allowlisted {
+            return Some(NOT_ALLOWLISTED);
+        }
+        decision.denied_by.map(|index| self.denylist_rule(index))
+    }
+
+    /// Runs the tests against the rules and returns the failed ones.
+    pub fn run_tests(&self, tests: &[FilterTest]) -> Vec<FilterTestFailure> {
+        tests
+            .iter()
+            .filter_map(|test| {
+                let rule = self.evaluate(&test.url);
+                let actual = if rule.is_some() {
+                    Expectation::Blocked
+                } else {
+                    Expectation::Allowed
+                };
+                (actual != test.expect).then(|| FilterTestFailure {
+                    test: test.clone(),
+                    rule: rule.map(str::to_string),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the rule that caused a request to be blocked.
+    pub fn blocking_rule(&self, hook: FilterHook, url: &str) -> Option<&str> {
+        match hook {
+            // getaddrinfo only uses the allowlist
+            FilterHook::GetAddrInfo => Some(NOT_ALLOWLISTED),
+            FilterHook::CefUrlRequestCreate => self
+                .decide(url)
+                .denied_by
+                .map(|index| self.denylist_rule(index)),
+        }
+    }
+}
+
+/// How the rules treat a url.
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    /// Whether the host is on the allowlist, always the case without one.
+    allowlisted: bool,
+    /// Index of the first denylist rule matching the url.
+    denied_by: Option<usize>,
+}
+
+/// Decisions for the most recently evaluated urls, evicting the least recently used one when full.
+#[derive(Debug, Default)]
+struct DecisionCache {
+    /// Decision and tick of the last use by url.
+    entries: HashMap<String, (Decision, u64)>,
+    /// Urls by the tick of their last use.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl DecisionCache {
+    fn get(&mut self, url: &str) -> Option<Decision> {
+        let (decision, last_used) = self.entries.get_mut(url)?;
+        self.tick += 1;
+        if let Some(url) = self.recency.remove(&*last_used) {
+            self.recency.insert(self.tick, url);
+        }
+        *last_used = self.tick;
+        Some(*decision)
+    }
+
+    fn insert(&mut self, url: &str, decision: Decision) {
+        if self.entries.len() >= DECISION_CACHE_SIZE && !self.entries.contains_key(url) {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(url.to_string(), (decision, self.tick)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, url.to_string());
+    }
+}
+
+/// Host of a url, which is what `getaddrinfo` sees of a request.
+pub fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    host.rsplit_once(':')
+        .filter(|(_, port)| port.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(host, |(host, _)| host)
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How often the running blocker is checked.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How long the blocker may take to answer a status request.
+pub const RPC_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a freshly injected blocker may take to complete the handshake and answer its first
+/// status request.
+pub const READY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Number of consecutive failed checks after which the user is notified.
+const NOTIFY_AFTER_FAILURES: u32 = 3;
+/// Number of consecutive failed checks after which the blocker is re-injected, as a single check
+/// may fail while Spotify is busy.
+const REHOOK_AFTER_FAILURES: u32 = 3;
+/// Delay before the first re-injection in a row, doubled with each further one.
+const MIN_REHOOK_BACKOFF: Duration = Duration::from_secs(5);
+/// Number of re-injections in a row after which the blocker is given up on until the next
+/// scheduled attempt to hook Spotify.
+const MAX_REHOOKS: u32 = 5;
+/// Time the blocker has to keep working after a re-injection before the next one counts as the
+/// first in a row again.
+const REHOOK_RESET_TIME: Duration = Duration::from_secs(10 * 60);
+/// Period in which unloads of the blocker by Spotify count as recurring.
+const EJECTION_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// Number of unloads within [`EJECTION_WINDOW`] after which the user is notified.
+const NOTIFY_AFTER_EJECTIONS: usize = 3;
+
+/// Tracks consecutive failed health checks, recent unloads of the blocker and re-injections to
+/// decide when to re-inject the blocker and when to escalate to the user.
+#[derive(Debug, Default)]
+pub struct HealthMonitor {
+    consecutive_failures: u32,
+    ejections: VecDeque<Instant>,
+    /// Re-injections since the blocker last kept working for [`REHOOK_RESET_TIME`].
+    rehooks: u32,
+    last_rehook: Option<Instant>,
+    rehook_at: Option<Instant>,
+}
+
+impl HealthMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        if self
+            .last_rehook
+            .is_some_and(|last_rehook| last_rehook.elapsed() >= REHOOK_RESET_TIME)
+        {
+            self.rehooks = 0;
+            self.last_rehook = None;
+        }
+    }
+
+    /// Records a failed check and returns whether the user should be notified.
+    pub fn record_failure(&mut self) -> bool {
+        self.consecutive_failures += 1;
+        self.consecutive_failures == NOTIFY_AFTER_FAILURES
+    }
+
+    /// Whether the failed checks so far call for re-injecting the blocker.
+    pub fn should_rehook(&self) -> bool {
+        self.consecutive_failures > 0 && self.consecutive_failures % REHOOK_AFTER_FAILURES == 0
+    }
+
+    /// Schedules a re-injection after a delay that doubles with each one in a row. Returns `false`
+    /// without scheduling once [`MAX_REHOOKS`] were reached, as the blocker keeps failing.
+    pub fn schedule_rehook(&mut self) -> bool {
+        if self.rehook_at.is_some() {
+            return true;
+        }
+        if self.rehooks >= MAX_REHOOKS {
+            return false;
+        }
+        let backoff = MIN_REHOOK_BACKOFF * 2u32.pow(self.rehooks);
+        self.rehooks += 1;
+        self.rehook_at = Some(Instant::now() + backoff);
+        true
+    }
+
+    /// When the scheduled re-injection is due.
+    pub fn scheduled_rehook(&self) -> Option<Instant> {
+        self.rehook_at
+    }
+
+    /// Marks the scheduled re-injection as done.
+    pub fn take_scheduled_rehook(&mut self) {
+        if self.rehook_at.take().is_some() {
+            self.last_rehook = Some(Instant::now());
+        }
+    }
+
+    /// Forgets the re-injections in a row, e.g. after hooking a new Spotify process.
+    pub fn reset_rehooks(&mut self) {
+        self.rehooks = 0;
+        self.last_rehook = None;
+        self.rehook_at = None;
+    }
+
+    /// Number of re-injections in a row.
+    pub fn rehooks(&self) -> u32 {
+        self.rehooks
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Records that Spotify unloaded the blocker and returns whether the user should be notified,
+    /// which is only the case once it happened repeatedly.
+    pub fn record_ejection(&mut self) -> bool {
+        let now = Instant::now();
+        self.ejections
+            .retain(|&ejection| now.duration_since(ejection) < EJECTION_WINDOW);
+        self.ejections.push_back(now);
+        self.ejections.len() == NOTIFY_AFTER_EJECTIONS
+    }
+
+    /// Whether Spotify unloaded the blocker [`NOTIFY_AFTER_EJECTIONS`] times or more within the
+    /// last [`EJECTION_WINDOW`].
+    pub fn ejections_recurring(&self) -> bool {
+        self.ejections.len() >= NOTIFY_AFTER_EJECTIONS
+    }
+
+    /// Number of unloads within the last [`EJECTION_WINDOW`].
+    pub fn recent_ejections(&self) -> usize {
+        self.ejections.len()
+    }
+}
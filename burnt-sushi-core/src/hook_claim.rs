@@ -0,0 +1,50 @@
+use std::{io, ptr};
+
+use widestring::U16CString;
+use winapi::{
+    shared::winerror::{ERROR_ACCESS_DENIED, ERROR_ALREADY_EXISTS},
+    um::{
+        errhandlingapi::GetLastError, handleapi::CloseHandle, synchapi::CreateEventW, winnt::HANDLE,
+    },
+};
+
+use crate::APP_NAME;
+
+/// Marks a Spotify process as hooked by this instance in a namespace shared by all sessions,
+/// so that instances of other users do not inject into the same process.
+#[derive(Debug)]
+pub struct HookClaim(HANDLE);
+
+// The handle is only closed on drop.
+unsafe impl Send for HookClaim {}
+
+impl HookClaim {
+    /// Claims the process, returns `None` if an instance already claimed it.
+    pub fn try_acquire(pid: u32) -> io::Result<Option<Self>> {
+        let name = U16CString::from_str(format!("Global\\{APP_NAME} HOOK {pid}")).unwrap();
+        let handle = unsafe { CreateEventW(ptr::null_mut(), 0, 0, name.as_ptr()) };
+        let last_error = unsafe { GetLastError() };
+
+        if handle.is_null() {
+            // Events created by other users are not accessible.
+            return if last_error == ERROR_ACCESS_DENIED {
+                Ok(None)
+            } else {
+                Err(io::Error::from_raw_os_error(last_error as i32))
+            };
+        }
+
+        if last_error == ERROR_ALREADY_EXISTS {
+            unsafe { CloseHandle(handle) };
+            return Ok(None);
+        }
+
+        Ok(Some(Self(handle)))
+    }
+}
+
+impl Drop for HookClaim {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    env, fmt, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use dll_syringe::{
+    process::{BorrowedProcess, OwnedProcess, OwnedProcessModule, Process, ProcessModule},
+    Syringe,
+};
+use log::{debug, error, info, warn};
+use tokio::sync::{mpsc, oneshot, watch};
+
+use crate::{
+    blocker::BlockerHandle,
+    error::{Error, HealthError, Result, RpcError},
+    filters::FilterConfig,
+    health,
+    metrics::METRICS,
+    rpc::{self, Fingerprint, PerfStats, RequestObserver, RpcCommand, RpcExit},
+    timing::{self, Stage},
+    DEFAULT_BLOCKER_FILE_NAME,
+};
+
+/// Blocker injected into a Spotify process together with the RPC task talking to it.
+pub struct InjectedBlocker {
+    syringe: Syringe,
+    payload: OwnedProcessModule,
+    rpc_task: tokio::task::JoinHandle<()>,
+    /// Set once the RPC task stopped, closed without a value if it panicked.
+    rpc_exit: watch::Receiver<Option<RpcExit>>,
+    rpc_commands: mpsc::UnboundedSender<RpcCommand>,
+    rule_count: usize,
+    version: Option<String>,
+}
+
+impl InjectedBlocker {
+    /// Injects the blocker at `payload_path`, connects to it and applies the filter config. The RPC
+    /// task is spawned on the current [`LocalSet`](tokio::task::LocalSet), which has to keep
+    /// running until the blocker is ejected.
+    pub fn inject(
+        syringe: Syringe,
+        payload_path: &Path,
+        filter_config: FilterConfig,
+        observer: Arc<dyn RequestObserver>,
+    ) -> Result<Self> {
+        info!("Injecting blocker...");
+        let payload = timing::measure(Stage::Inject, || syringe.inject(payload_path))
+            .map_err(Error::Inject)?;
+
+        let blocker = BlockerHandle::new(&syringe, payload);
+        let version = match blocker.version() {
+            Ok(Some(version)) => {
+                debug!("Injected blocker v{version}");
+                Some(version)
+            }
+            Ok(None) => {
+                debug!("Injected blocker does not report its version");
+                None
+            }
+            Err(e) => {
+                debug!("Failed to query blocker version: {e}");
+                None
+            }
+        };
+
+        debug!("Starting RPC...");
+        let rpc_socket_addr = timing::measure(Stage::StartRpc, || blocker.start())?;
+        let rpc_stream = rpc::connect(rpc_socket_addr.into()).map_err(Error::RpcConnect)?;
+
+        let rule_count = filter_config.allowlist.len() + filter_config.denylist.len();
+        let (rpc_commands, rpc_command_rx) = mpsc::unbounded_channel();
+        let (rpc_exit_tx, rpc_exit) = watch::channel(None);
+        let rpc_task = tokio::task::spawn_local(async move {
+            let exit = match rpc::run(rpc_stream, filter_config, rpc_command_rx, observer).await {
+                Ok(()) => RpcExit::Disconnected,
+                Err(e) => {
+                    METRICS.rpc_errors.inc();
+                    error!("RPC failed: {e}");
+                    RpcExit::Failed(e.to_string())
+                }
+            };
+            let _ = rpc_exit_tx.send(Some(exit));
+        });
+
+        METRICS.injections.inc();
+        Ok(Self {
+            payload: payload.try_to_owned().map_err(Error::InspectModules)?,
+            syringe,
+            rpc_task,
+            rpc_exit,
+            rpc_commands,
+            rule_count,
+            version,
+        })
+    }
+
+    /// Checks that the blocker is still loaded, responds to RPC and has the filter config applied.
+    pub async fn check_health(&self) -> Result<(), HealthError> {
+        self.check_health_within(health::RPC_TIMEOUT).await
+    }
+
+    /// Waits until the blocker completed the handshake and passed a first health check, so that it
+    /// is known to work before it is reported as hooked.
+    pub async fn wait_until_ready(&self) -> Result<(), HealthError> {
+        tokio::select! {
+            result = self.check_health_within(health::READY_TIMEOUT) => result,
+            exit = self.rpc_stopped() => {
+                debug!("RPC stopped before the blocker was ready: {exit}");
+                Err(HealthError::Rpc(RpcError::Stopped))
+            }
+        }
+    }
+
+    async fn check_health_within(&self, timeout: Duration) -> Result<(), HealthError> {
+        if !self.is_loaded().map_err(HealthError::InspectModules)? {
+            return Err(HealthError::ModuleMissing);
+        }
+
+        let status = self.request_within(timeout, RpcCommand::Status).await?;
+        if !status.filtering {
+            return Err(HealthError::FilteringDisabled);
+        }
+        if status.rule_count != self.rule_count {
+            return Err(HealthError::RuleCountMismatch {
+                expected: self.rule_count,
+                actual: status.rule_count,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the filter rules of the running blocker.
+    pub async fn set_filters(&mut self, filter_config: FilterConfig) -> Result<(), RpcError> {
+        let rule_count = filter_config.allowlist.len() + filter_config.denylist.len();
+        self.request(|response| RpcCommand::SetFilters(filter_config, response))
+            .await?;
+        self.rule_count = rule_count;
+        Ok(())
+    }
+
+    /// Collects the overhead of the blocker inside Spotify.
+    pub async fn perf_stats(&self) -> Result<PerfStats, RpcError> {
+        self.request(RpcCommand::PerfStats).await
+    }
+
+    /// Asks the blocker which versions of the Spotify internals it hooked, `None` for blockers that
+    /// do not report them.
+    pub async fn fingerprint(&self) -> Result<Option<Fingerprint>, RpcError> {
+        self.request(RpcCommand::Fingerprint).await
+    }
+
+    /// Waits until the RPC task stops, which only happens on its own if the connection to the
+    /// blocker was lost.
+    pub async fn rpc_stopped(&self) -> RpcExit {
+        let mut rpc_exit = self.rpc_exit.clone();
+        match rpc_exit.wait_for(Option::is_some).await {
+            Ok(exit) => exit.clone().unwrap_or(RpcExit::Panicked),
+            Err(_) => RpcExit::Panicked,
+        }
+    }
+
+    /// Whether the blocker module is still loaded, Spotify may unload it e.g. during its crash
+    /// recovery.
+    pub fn is_loaded(&self) -> io::Result<bool> {
+        Ok(self
+            .syringe
+            .process()
+            .modules()?
+            .iter()
+            .any(|module| module.handle() == self.payload.handle()))
+    }
+
+    /// Number of filter rules last applied to the blocker.
+    pub fn rule_count(&self) -> usize {
+        self.rule_count
+    }
+
+    /// Version of the blocker build, `None` for blockers that predate exporting it.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<Result<T, capnp::Error>>) -> RpcCommand,
+    ) -> Result<T, RpcError> {
+        self.request_within(health::RPC_TIMEOUT, command).await
+    }
+
+    async fn request_within<T>(
+        &self,
+        timeout: Duration,
+        command: impl FnOnce(oneshot::Sender<Result<T, capnp::Error>>) -> RpcCommand,
+    ) -> Result<T, RpcError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.rpc_commands
+            .send(command(response_tx))
+            .map_err(|_| RpcError::Stopped)?;
+        tokio::time::timeout(timeout, response_rx)
+            .await
+            .map_err(|_| RpcError::Timeout)?
+            .map_err(|_| RpcError::Stopped)?
+            .map_err(|e| {
+                METRICS.rpc_errors.inc();
+                RpcError::Failed(e)
+            })
+    }
+
+    /// Stops the RPC task and ejects the blocker if the process is still alive.
+    pub async fn eject(self) -> Result<()> {
+        // Nothing is left to stop in a blocker that was unloaded.
+        if !self.is_loaded().unwrap_or(true) {
+            debug!("Blocker was already unloaded");
+            self.rpc_task.abort();
+            return Ok(());
+        }
+
+        debug!("Stopping RPC...");
+        BlockerHandle::new(&self.syringe, self.payload.borrowed()).stop()?;
+        self.rpc_task.await.map_err(|_| Error::RpcTaskPanicked)?;
+        debug!("Stopped RPC");
+
+        if self.payload.process().is_alive() {
+            info!("Ejecting blocker...");
+            self.syringe.eject(self.payload.borrowed())?;
+            info!("Ejected blocker");
+        }
+
+        Ok(())
+    }
+}
+
+/// Blockers loaded in the process, found by their exported marker so that blockers injected from a
+/// custom `--blocker` path are found as well. Blockers of older versions have no marker and are
+/// found by the default file name instead.
+pub fn find_blockers(syringe: &Syringe) -> Result<Vec<ProcessModule<BorrowedProcess<'_>>>> {
+    let process = syringe.process();
+    // Checking the exports of every module is slow, so modules of Windows and Spotify itself are
+    // skipped.
+    let system_dir = env::var_os("SystemRoot");
+    let spotify_dir = process
+        .path()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf));
+
+    let mut blockers = Vec::new();
+    for module in process.modules().map_err(Error::InspectModules)? {
+        let Ok(path) = module.path() else {
+            continue;
+        };
+        if system_dir.as_ref().is_some_and(|dir| path.starts_with(dir))
+            || spotify_dir.as_deref() == path.parent()
+        {
+            continue;
+        }
+        let legacy = path
+            .file_name()
+            .is_some_and(|name| name.eq_ignore_ascii_case(DEFAULT_BLOCKER_FILE_NAME));
+        if legacy || BlockerHandle::new(syringe, module).is_blocker() {
+            debug!("Found blocker at '{}'", path.display());
+            blockers.push(module);
+        }
+    }
+    Ok(blockers)
+}
+
+/// Stops and ejects blockers left in the process, e.g. by a previous instance that crashed.
+pub fn eject_previous_blockers(syringe: &Syringe) -> Result<()> {
+    for prev_payload in find_blockers(syringe)? {
+        warn!("Found previously injected blocker");
+        eject_blocker(syringe, prev_payload)?;
+    }
+
+    Ok(())
+}
+
+/// Stops and ejects the blocker found by [`sweep`], which is only done if it still exports the
+/// marker, so that a module that merely has the same name is never ejected.
+pub fn eject_swept_blocker(syringe: &Syringe, blocker: &LoadedBlocker) -> Result<()> {
+    let modules = syringe.process().modules().map_err(Error::InspectModules)?;
+    for module in modules {
+        if module.path().is_ok_and(|path| path == blocker.path)
+            && BlockerHandle::new(syringe, module).is_blocker()
+        {
+            eject_blocker(syringe, module)?;
+        }
+    }
+    Ok(())
+}
+
+fn eject_blocker(syringe: &Syringe, payload: ProcessModule<BorrowedProcess<'_>>) -> Result<()> {
+    debug!("Stopping RPC of previous blocker");
+    match BlockerHandle::new(syringe, payload).stop() {
+        Ok(_) => {
+            debug!("Stopped RPC of previous blocker");
+        }
+        Err(e) => {
+            error!("Failed to stop RPC of previous blocker: {}", e);
+        }
+    }
+
+    info!("Ejecting previous blocker...");
+    syringe.eject(payload).map_err(Error::Eject)?;
+    info!("Ejected previous blocker");
+    Ok(())
+}
+
+/// Blocker found in a process by [`sweep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedBlocker {
+    pub pid: u32,
+    /// File name of the executable of the process.
+    pub process_name: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for LoadedBlocker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (PID={}) from '{}'",
+            self.process_name,
+            self.pid,
+            self.path.display()
+        )
+    }
+}
+
+/// Looks for blockers in all processes that can be inspected, e.g. blockers that ended up in child
+/// processes of Spotify. Only modules named like one of `file_names` are checked for the marker, as
+/// calling into every module of every process would take minutes.
+pub fn sweep(file_names: &[String]) -> Vec<LoadedBlocker> {
+    let mut found = Vec::new();
+    for process in OwnedProcess::all() {
+        // Processes of other users and protected processes cannot be inspected.
+        let has_candidate = process.borrowed().modules().is_ok_and(|modules| {
+            modules
+                .iter()
+                .any(|module| has_file_name(module, file_names))
+        });
+        if !has_candidate {
+            continue;
+        }
+        let Ok(pid) = process.pid() else {
+            continue;
+        };
+        let process_name = process
+            .path()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "<unknown>".to_string());
+
+        let syringe = Syringe::for_process(process);
+        let Ok(modules) = syringe.process().modules() else {
+            continue;
+        };
+        for module in modules {
+            if has_file_name(&module, file_names)
+                && BlockerHandle::new(&syringe, module).is_blocker()
+            {
+                found.push(LoadedBlocker {
+                    pid: pid.get(),
+                    process_name: process_name.clone(),
+                    path: module.path().unwrap_or_default(),
+                });
+            }
+        }
+    }
+    found
+}
+
+fn has_file_name(module: &ProcessModule<BorrowedProcess<'_>>, file_names: &[String]) -> bool {
+    module.path().is_ok_and(|path| {
+        path.file_name().is_some_and(|name| {
+            file_names
+                .iter()
+                .any(|file_name| name.eq_ignore_ascii_case(file_name))
+        })
+    })
+}
@@ -0,0 +1,162 @@
+//! Integrity levels of processes. Windows denies access to processes above the caller's integrity
+//! level, so an elevated Spotify cannot be hooked by an app started normally.
+
+use std::{fmt, io, mem, ptr};
+
+use winapi::{
+    shared::{minwindef::FALSE, winerror::ERROR_ACCESS_DENIED},
+    um::{
+        handleapi::CloseHandle,
+        processthreadsapi::{GetCurrentProcess, OpenProcess, OpenProcessToken},
+        securitybaseapi::{GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation},
+        winnt::{
+            TokenIntegrityLevel, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION,
+            SECURITY_MANDATORY_HIGH_RID, SECURITY_MANDATORY_LOW_RID, SECURITY_MANDATORY_MEDIUM_RID,
+            SECURITY_MANDATORY_SYSTEM_RID, TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+        },
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IntegrityLevel {
+    Untrusted,
+    Low,
+    Medium,
+    /// Elevated, i.e. run as administrator.
+    High,
+    System,
+}
+
+impl IntegrityLevel {
+    fn from_rid(rid: u32) -> Self {
+        match rid {
+            rid if rid >= SECURITY_MANDATORY_SYSTEM_RID => IntegrityLevel::System,
+            rid if rid >= SECURITY_MANDATORY_HIGH_RID => IntegrityLevel::High,
+            rid if rid >= SECURITY_MANDATORY_MEDIUM_RID => IntegrityLevel::Medium,
+            rid if rid >= SECURITY_MANDATORY_LOW_RID => IntegrityLevel::Low,
+            _ => IntegrityLevel::Untrusted,
+        }
+    }
+
+    /// Integrity level of the current process.
+    pub fn current() -> io::Result<Self> {
+        unsafe { process_integrity(GetCurrentProcess()) }
+    }
+
+    /// Integrity level of the process with the given id.
+    pub fn of_process(pid: u32) -> io::Result<Self> {
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+        if process.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let result = unsafe { process_integrity(process) };
+        unsafe { CloseHandle(process) };
+        result
+    }
+}
+
+impl fmt::Display for IntegrityLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityLevel::Untrusted => write!(f, "untrusted"),
+            IntegrityLevel::Low => write!(f, "low"),
+            IntegrityLevel::Medium => write!(f, "medium"),
+            IntegrityLevel::High => write!(f, "high (elevated)"),
+            IntegrityLevel::System => write!(f, "system"),
+        }
+    }
+}
+
+/// Integrity levels of the app and Spotify if they differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegrityMismatch {
+    pub app: IntegrityLevel,
+    pub spotify: IntegrityLevel,
+}
+
+impl IntegrityMismatch {
+    /// Compares the integrity levels of the app and the Spotify process with the given id. A
+    /// Spotify whose token cannot be read although the app is not elevated is assumed to be
+    /// elevated, as that is what denies the access.
+    pub fn check(pid: u32) -> io::Result<Option<Self>> {
+        let app = IntegrityLevel::current()?;
+        let spotify = match IntegrityLevel::of_process(pid) {
+            Ok(spotify) => spotify,
+            Err(e)
+                if e.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32)
+                    && app < IntegrityLevel::High =>
+            {
+                IntegrityLevel::High
+            }
+            Err(e) => return Err(e),
+        };
+        Ok((app != spotify).then_some(Self { app, spotify }))
+    }
+
+    /// Whether Windows denies the app access to Spotify.
+    pub fn prevents_hooking(&self) -> bool {
+        self.spotify > self.app
+    }
+
+    /// How the user can start the app and Spotify so that they match.
+    pub fn advice(&self) -> &'static str {
+        if self.prevents_hooking() {
+            "start Spotify normally instead of as administrator, or start BurntSushi as administrator too"
+        } else {
+            "BurntSushi does not need to run as administrator, start it normally"
+        }
+    }
+}
+
+impl fmt::Display for IntegrityMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Spotify runs at {} integrity but BurntSushi at {}",
+            self.spotify, self.app
+        )
+    }
+}
+
+/// # Safety
+/// `process` must be a valid process handle with query access.
+unsafe fn process_integrity(process: HANDLE) -> io::Result<IntegrityLevel> {
+    let mut token = ptr::null_mut();
+    if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let result = unsafe { token_integrity(token) };
+    unsafe { CloseHandle(token) };
+    result
+}
+
+/// # Safety
+/// `token` must be a valid token handle with query access.
+unsafe fn token_integrity(token: HANDLE) -> io::Result<IntegrityLevel> {
+    let mut size = 0;
+    unsafe { GetTokenInformation(token, TokenIntegrityLevel, ptr::null_mut(), 0, &mut size) };
+    // u64 for the alignment of the pointer in the label.
+    let mut buffer = vec![0u64; (size as usize).div_ceil(mem::size_of::<u64>())];
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenIntegrityLevel,
+            buffer.as_mut_ptr().cast(),
+            size,
+            &mut size,
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let label = unsafe { &*buffer.as_ptr().cast::<TOKEN_MANDATORY_LABEL>() };
+
+    // The level is the last sub authority of the label's SID.
+    let sid = label.Label.Sid;
+    let count = unsafe { *GetSidSubAuthorityCount(sid) };
+    if count == 0 {
+        return Ok(IntegrityLevel::Untrusted);
+    }
+    let rid = unsafe { *GetSidSubAuthority(sid, u32::from(count) - 1) };
+    Ok(IntegrityLevel::from_rid(rid))
+}
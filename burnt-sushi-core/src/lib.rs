@@ -0,0 +1,25 @@
+#![warn(unsafe_op_in_unsafe_fn)]
+
+//! Platform side of BurntSushi without any user interface: finding Spotify, injecting the blocker
+//! and talking to it over RPC. Frontends decide when to hook and how to present the results.
+
+pub mod blocker;
+pub mod conflicts;
+pub mod error;
+pub mod filters;
+pub mod health;
+pub mod hook_claim;
+pub mod injector;
+pub mod integrity;
+pub mod metrics;
+pub mod rpc;
+pub mod security;
+pub mod signature;
+pub mod spotify_process_scanner;
+pub mod spotify_verification;
+pub mod timing;
+
+pub const APP_NAME: &str = "BurntSushi";
+pub const DEFAULT_BLOCKER_FILE_NAME: &str = "BurntSushiBlocker_x64.dll";
+/// File name of a blocker downloaded by a blocker-only update.
+pub const UPDATED_BLOCKER_FILE_NAME: &str = "BurntSushiBlocker_x64.update.dll";
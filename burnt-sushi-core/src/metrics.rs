@@ -0,0 +1,234 @@
+use std::{
+    fmt::Write,
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::security;
+
+/// Longest request line that is read, longer ones are answered with 404.
+const MAX_REQUEST_LINE_LEN: u64 = 1024;
+/// Time a client has to send the request line before the connection is closed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters collected while the app is running.
+pub static METRICS: Metrics = Metrics::new();
+
+#[derive(Debug)]
+pub struct Metrics {
+    pub injections: Counter,
+    pub reinjections: Counter,
+    pub rpc_errors: Counter,
+    pub requests_blocked: Counter,
+    pub requests_allowed: Counter,
+    pub requests_dropped: Counter,
+    pub filter_cache_hits: Counter,
+    pub filter_cache_misses: Counter,
+    /// Time from a Spotify window appearing until the scanner identified it.
+    pub scanner_latency: Summary,
+    /// Time taken to match urls that were not cached against the rules.
+    pub filter_latency: Summary,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            injections: Counter::new(),
+            reinjections: Counter::new(),
+            rpc_errors: Counter::new(),
+            requests_blocked: Counter::new(),
+            requests_allowed: Counter::new(),
+            requests_dropped: Counter::new(),
+            filter_cache_hits: Counter::new(),
+            filter_cache_misses: Counter::new(),
+            scanner_latency: Summary::new(),
+            filter_latency: Summary::new(),
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "injections",
+                "Number of successful blocker injections.",
+                &self.injections,
+            ),
+            (
+                "reinjections",
+                "Number of re-injections after failed health checks.",
+                &self.reinjections,
+            ),
+            (
+                "rpc_errors",
+                "Number of failed RPC calls.",
+                &self.rpc_errors,
+            ),
+            (
+                "requests_blocked",
+                "Number of requests blocked in Spotify.",
+                &self.requests_blocked,
+            ),
+            (
+                "requests_allowed",
+                "Number of requests allowed in Spotify.",
+                &self.requests_allowed,
+            ),
+            (
+                "requests_dropped",
+                "Number of requests reported by the blocker that never arrived.",
+                &self.requests_dropped,
+            ),
+            (
+                "filter_cache_hits",
+                "Number of urls whose cached filter decision was reused.",
+                &self.filter_cache_hits,
+            ),
+            (
+                "filter_cache_misses",
+                "Number of urls matched against the rules as their decision was not cached.",
+                &self.filter_cache_misses,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP burnt_sushi_{name}_total {help}");
+            let _ = writeln!(out, "# TYPE burnt_sushi_{name}_total counter");
+            let _ = writeln!(out, "burnt_sushi_{name}_total {}", counter.get());
+        }
+
+        let summaries = [
+            (
+                "scanner_latency",
+                "Time taken to detect a Spotify window after it appeared.",
+                &self.scanner_latency,
+            ),
+            (
+                "filter_latency",
+                "Time taken to match uncached urls against the rules.",
+                &self.filter_latency,
+            ),
+        ];
+        for (name, help, summary) in summaries {
+            let name = format!("burnt_sushi_{name}_seconds");
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} summary");
+            let _ = writeln!(out, "{name}_sum {}", summary.sum().as_secs_f64());
+            let _ = writeln!(out, "{name}_count {}", summary.count());
+        }
+        out
+    }
+}
+
+#[derive(Debug)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Count and sum of observed durations.
+#[derive(Debug)]
+pub struct Summary {
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Summary {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> Duration {
+        Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+    }
+}
+
+/// Serves the metrics on `http://127.0.0.1:<port>/metrics` to processes of the current user.
+pub async fn serve(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).await?;
+    debug!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        match security::is_current_user_peer(stream.local_addr()?, peer) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!("Rejected metrics request of another user");
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to check owner of metrics request: {e}");
+                continue;
+            }
+        }
+        tokio::task::spawn(async move {
+            if let Err(e) = handle_client(stream).await {
+                warn!("Failed to serve metrics: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_client(stream: TcpStream) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LINE_LEN));
+    tokio::time::timeout(REQUEST_TIMEOUT, reader.read_line(&mut request_line))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Request timed out."))??;
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = METRICS.render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
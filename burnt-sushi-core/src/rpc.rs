@@ -0,0 +1,530 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    future::Future,
+    io,
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ::capnp::capability::Promise;
+use capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem};
+use futures::{AsyncReadExt, FutureExt};
+use log::{debug, info, warn};
+use shared::{
+    compression,
+    rpc::blocker_service::{Compression, FilterHook},
+};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    filters::{CompiledFilters, FilterConfig},
+    metrics::METRICS,
+    timing::{self, Stage},
+};
+
+pub mod decode;
+pub mod recording;
+
+/// Receives the requests reported by the blocker.
+pub trait RequestObserver: Send + Sync {
+    /// Called for every request seen by a hook, `rule` is the filter rule that blocked it. `time`
+    /// is when the hook saw the request, or when it arrived for blockers that do not report it.
+    fn on_request(
+        &self,
+        time: SystemTime,
+        hook: FilterHook,
+        url: &str,
+        blocked: bool,
+        rule: Option<&str>,
+    );
+}
+
+/// Requests sent from the app to the RPC task while the blocker is running.
+#[derive(Debug)]
+pub enum RpcCommand {
+    Status(oneshot::Sender<Result<BlockerStatus, capnp::Error>>),
+    /// Replaces the rules of all hooks.
+    SetFilters(FilterConfig, oneshot::Sender<Result<(), capnp::Error>>),
+    PerfStats(oneshot::Sender<Result<PerfStats, capnp::Error>>),
+    /// `None` for blockers that do not report a fingerprint.
+    Fingerprint(oneshot::Sender<Result<Option<Fingerprint>, capnp::Error>>),
+}
+
+/// Why the RPC task stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcExit {
+    /// The blocker closed the connection, e.g. because it was asked to stop.
+    Disconnected,
+    /// The connection or the handshake failed.
+    Failed(String),
+    /// The RPC task panicked.
+    Panicked,
+}
+
+impl fmt::Display for RpcExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcExit::Disconnected => write!(f, "blocker disconnected"),
+            RpcExit::Failed(error) => write!(f, "RPC failed: {error}"),
+            RpcExit::Panicked => write!(f, "RPC task panicked"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlockerStatus {
+    pub filtering: bool,
+    pub rule_count: usize,
+}
+
+/// Overhead of the blocker inside Spotify since it was injected.
+#[derive(Debug, Clone)]
+pub struct PerfStats {
+    pub hooks: Vec<HookPerfStats>,
+    /// Requests reported by the hooks but not yet sent to the app.
+    pub pending_logs: usize,
+    pub max_pending_logs: usize,
+    /// Working set of the whole Spotify process.
+    pub working_set_bytes: u64,
+    /// CPU time used by the whole Spotify process.
+    pub cpu_time: Duration,
+}
+
+/// Versions of the Spotify internals hooked by the blocker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Version of the CEF build loaded by Spotify, e.g. `127.3.5+chromium-127.0.6533.120`.
+    pub cef_version: String,
+    /// Hash of the CEF C API, empty if unknown.
+    pub cef_api_hash: String,
+}
+
+/// Time spent filtering requests in a hook, excluding the call to the original function.
+#[derive(Debug, Clone, Copy)]
+pub struct HookPerfStats {
+    pub hook: FilterHook,
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl HookPerfStats {
+    pub fn mean(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_nanos((self.total.as_nanos() / u128::from(calls)) as u64),
+        }
+    }
+}
+
+struct LoggerImpl {
+    filters: Option<CompiledFilters>,
+    observer: Arc<dyn RequestObserver>,
+    sequence: SequenceTracker,
+}
+
+impl LoggerImpl {
+    /// Counts and reports a request received at the given time and returns the number of requests
+    /// that are now known to be dropped.
+    fn handle_request(&mut self, request: decode::LoggedRequest, received: SystemTime) -> u64 {
+        let dropped = self.sequence.record(request.seq);
+        if dropped > 0 {
+            METRICS.requests_dropped.add(dropped);
+            warn!("{dropped} requests reported by the blocker never arrived");
+        }
+
+        let rule = if request.blocked {
+            METRICS.requests_blocked.inc();
+            self.filters
+                .as_ref()
+                .and_then(|filters| filters.blocking_rule(request.hook, &request.url))
+        } else {
+            METRICS.requests_allowed.inc();
+            None
+        };
+
+        self.observer.on_request(
+            request.time.unwrap_or(received),
+            request.hook,
+            &request.url,
+            request.blocked,
+            rule,
+        );
+
+        dropped
+    }
+}
+
+/// How far behind the newest sequence number a request may arrive before it counts as dropped.
+/// Requests of different hooks are numbered before they are queued, so they can arrive slightly
+/// out of order.
+pub const REORDER_WINDOW: u64 = 256;
+/// Most missing sequence numbers remembered, larger gaps count as dropped right away.
+const MAX_MISSING: u64 = 4096;
+
+/// Detects requests that the blocker reported but that never arrived, using their sequence
+/// numbers.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    newest: Option<u64>,
+    missing: BTreeSet<u64>,
+}
+
+impl SequenceTracker {
+    /// Records the sequence number of an arrived request and returns the number of requests that
+    /// are now known to be dropped.
+    pub fn record(&mut self, seq: u64) -> u64 {
+        // Not supported by the blocker.
+        if seq == 0 {
+            return 0;
+        }
+        let Some(newest) = self.newest else {
+            // The blocker may have numbered requests before this connection.
+            self.newest = Some(seq);
+            return 0;
+        };
+
+        let mut dropped = 0;
+        if seq > newest {
+            let gap = seq - newest - 1;
+            if gap > MAX_MISSING {
+                dropped += gap;
+            } else {
+                self.missing.extend(newest + 1..seq);
+            }
+            self.newest = Some(seq);
+        } else {
+            // Arrived late, or a duplicate if it was not missing.
+            self.missing.remove(&seq);
+        }
+
+        let newest = newest.max(seq);
+        let waiting = self
+            .missing
+            .split_off(&newest.saturating_sub(REORDER_WINDOW));
+        dropped += self.missing.len() as u64;
+        self.missing = waiting;
+        dropped
+    }
+}
+
+impl shared::rpc::blocker_service::logger::Server for LoggerImpl {
+    fn log_request(
+        &mut self,
+        params: shared::rpc::blocker_service::logger::LogRequestParams,
+        mut _results: shared::rpc::blocker_service::logger::LogRequestResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let request = pry!(pry!(params.get()).get_request());
+        recording::record_request(request);
+        let request = pry!(decode::log_request(request));
+        self.handle_request(request, SystemTime::now());
+
+        Promise::ok(())
+    }
+
+    fn log_message(
+        &mut self,
+        params: shared::rpc::blocker_service::logger::LogMessageParams,
+        mut _results: shared::rpc::blocker_service::logger::LogMessageResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let message = pry!(pry!(params.get()).get_message());
+        recording::record_message(message);
+        info!("{}", decode::log_message(message));
+
+        Promise::ok(())
+    }
+}
+
+/// How long connecting to the blocker may take, it listens on loopback so this only expires if the
+/// connection is silently dropped.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the blocker may take to answer each step of the setup after connecting.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Steps of setting up the blocker after connecting, in the order they have to complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Handshake {
+    Negotiate,
+    RegisterLogger,
+    SetFilters,
+    EnableFiltering,
+}
+
+/// Runs a step of the setup, so that a blocker that never answers cannot stall the connection.
+async fn handshake_step<T>(
+    step: Handshake,
+    future: impl Future<Output = Result<T, capnp::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, future).await {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            Err(format!("Blocker did not answer {step:?} within {HANDSHAKE_TIMEOUT:?}").into())
+        }
+    }
+}
+
+/// Connects to the RPC socket of the blocker.
+pub fn connect(socket_addr: SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT)?;
+    stream.set_nonblocking(true)?;
+    Ok(stream)
+}
+
+/// Talks to the blocker over the connection opened by [`connect`] until it disconnects. The RPC
+/// system is not `Send`, so this has to run on a [`LocalSet`](tokio::task::LocalSet).
+pub async fn run(
+    stream: TcpStream,
+    filter_config: FilterConfig,
+    mut commands: mpsc::UnboundedReceiver<RpcCommand>,
+    observer: Arc<dyn RequestObserver>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let filters = CompiledFilters::new(&filter_config)
+        .inspect_err(|e| warn!("Failed to compile filters for stats: {e}"))
+        .ok();
+    recording::record_filters(&filter_config);
+    let stream = tokio::net::TcpStream::from_std(stream)?;
+    info!("Connected to {}", stream.peer_addr()?);
+
+    stream.set_nodelay(true)?;
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let rpc_network = Box::new(twoparty::VatNetwork::new(
+        reader,
+        writer,
+        rpc_twoparty_capnp::Side::Client,
+        decode::reader_options(),
+    ));
+    let mut rpc_system = RpcSystem::new(rpc_network, None);
+    let client: shared::rpc::blocker_service::Client =
+        rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+
+    let mut rpc = tokio::task::spawn_local(Box::pin(rpc_system.map(|_| ())));
+
+    let mut register_logger_request = client.register_logger_request();
+    register_logger_request
+        .get()
+        .set_logger(capnp_rpc::new_client(LoggerImpl {
+            filters,
+            observer,
+            sequence: SequenceTracker::default(),
+        }));
+    let capabilities = handshake_step(Handshake::Negotiate, negotiate(&client)).await?;
+
+    handshake_step(Handshake::RegisterLogger, async {
+        register_logger_request.send().promise.await.map(|_| ())
+    })
+    .await?;
+    handshake_step(
+        Handshake::SetFilters,
+        set_filters(&client, &filter_config, capabilities),
+    )
+    .await?;
+    handshake_step(Handshake::EnableFiltering, async {
+        client
+            .enable_filtering_request()
+            .send()
+            .promise
+            .await
+            .map(|_| ())
+    })
+    .await?;
+    timing::record(Stage::FilterPush, start.elapsed());
+
+    loop {
+        tokio::select! {
+            result = &mut rpc => return result.map_err(|e| e.into()),
+            Some(command) = commands.recv() => match command {
+                RpcCommand::Status(response) => {
+                    let _ = response.send(get_status(&client).await);
+                }
+                RpcCommand::SetFilters(filter_config, response) => {
+                    let _ = response
+                        .send(set_filters(&client, &filter_config, capabilities).await);
+                }
+                RpcCommand::PerfStats(response) => {
+                    let _ = response.send(get_perf_stats(&client).await);
+                }
+                RpcCommand::Fingerprint(response) => {
+                    let _ = response.send(get_fingerprint(&client).await);
+                }
+            },
+        }
+    }
+}
+
+/// Rules sent to the blocker per request when it supports chunked transfers, so that large rule
+/// sets do not block its RPC thread with a single huge message.
+const CHUNK_RULES: usize = 2_000;
+
+/// Optional features of the blocker, blockers that predate the negotiation support none of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Capabilities {
+    /// Compression used for large rulesets.
+    compression: Compression,
+    /// Whether rulesets can be sent in chunks and applied together on commit.
+    chunked_rulesets: bool,
+}
+
+async fn negotiate(
+    client: &shared::rpc::blocker_service::Client,
+) -> Result<Capabilities, capnp::Error> {
+    let response = match client.get_capabilities_request().send().promise.await {
+        Ok(response) => response,
+        Err(e) if e.kind == capnp::ErrorKind::Unimplemented => {
+            return Ok(Capabilities {
+                compression: Compression::None,
+                chunked_rulesets: false,
+            })
+        }
+        Err(e) => return Err(e),
+    };
+    let results = response.get()?;
+    let supports_deflate = results
+        .get_compressions()?
+        .iter()
+        .any(|compression| matches!(compression, Ok(Compression::Deflate)));
+    Ok(Capabilities {
+        compression: if supports_deflate {
+            Compression::Deflate
+        } else {
+            Compression::None
+        },
+        chunked_rulesets: results.get_chunked_rulesets(),
+    })
+}
+
+async fn set_filters(
+    client: &shared::rpc::blocker_service::Client,
+    filter_config: &FilterConfig,
+    capabilities: Capabilities,
+) -> Result<(), capnp::Error> {
+    // The allowlist applies to hosts, the denylist to full urls.
+    let rulesets = [
+        (
+            FilterHook::GetAddrInfo,
+            &filter_config.allowlist[..],
+            &[][..],
+        ),
+        (
+            FilterHook::CefUrlRequestCreate,
+            &[][..],
+            &filter_config.denylist[..],
+        ),
+    ];
+
+    if !capabilities.chunked_rulesets {
+        // Applied hook by hook, so the hooks briefly use rulesets of different versions.
+        for (hook, whitelist, blacklist) in rulesets {
+            send_rules(client, hook, whitelist, blacklist, capabilities, false).await?;
+        }
+        return Ok(());
+    }
+
+    client.begin_rulesets_request().send().promise.await?;
+    for (hook, whitelist, blacklist) in rulesets {
+        for chunk in whitelist.chunks(CHUNK_RULES) {
+            send_rules(client, hook, chunk, &[], capabilities, true).await?;
+        }
+        for chunk in blacklist.chunks(CHUNK_RULES) {
+            send_rules(client, hook, &[], chunk, capabilities, true).await?;
+        }
+    }
+    client.commit_rulesets_request().send().promise.await?;
+
+    Ok(())
+}
+
+/// Sends rules for a hook, either replacing its ruleset or adding them to a chunked transfer.
+async fn send_rules(
+    client: &shared::rpc::blocker_service::Client,
+    hook: FilterHook,
+    whitelist: &[String],
+    blacklist: &[String],
+    capabilities: Capabilities,
+    chunked: bool,
+) -> Result<(), capnp::Error> {
+    let rule_bytes = whitelist
+        .iter()
+        .chain(blacklist)
+        .map(String::len)
+        .sum::<usize>();
+    let compression = capabilities.compression;
+
+    if compression != Compression::None && rule_bytes >= compression::THRESHOLD_BYTES {
+        let mut message = capnp::message::Builder::new_default();
+        build_ruleset(message.init_root(), whitelist, blacklist);
+        let data = compression::deflate(&capnp::serialize::write_message_to_words(&message));
+        debug!(
+            "Sending {rule_bytes} bytes of rules for {hook} compressed to {} bytes",
+            data.len()
+        );
+
+        if chunked {
+            let mut request = client.add_rules_compressed_request();
+            request.get().set_hook(hook);
+            request.get().set_compression(compression);
+            request.get().set_ruleset(&data);
+            request.send().promise.await?;
+        } else {
+            let mut request = client.set_ruleset_compressed_request();
+            request.get().set_hook(hook);
+            request.get().set_compression(compression);
+            request.get().set_ruleset(&data);
+            request.send().promise.await?;
+        }
+    } else if chunked {
+        let mut request = client.add_rules_request();
+        request.get().set_hook(hook);
+        build_ruleset(request.get().init_ruleset(), whitelist, blacklist);
+        request.send().promise.await?;
+    } else {
+        let mut request = client.set_ruleset_request();
+        request.get().set_hook(hook);
+        build_ruleset(request.get().init_ruleset(), whitelist, blacklist);
+        request.send().promise.await?;
+    }
+
+    Ok(())
+}
+
+fn build_ruleset(
+    mut ruleset: shared::rpc::blocker_service::filter_ruleset::Builder,
+    whitelist: &[String],
+    blacklist: &[String],
+) {
+    let mut list = ruleset.reborrow().init_whitelist(whitelist.len() as _);
+    for (i, url) in whitelist.iter().enumerate() {
+        list.set(i as _, url);
+    }
+    let mut list = ruleset.init_blacklist(blacklist.len() as _);
+    for (i, url) in blacklist.iter().enumerate() {
+        list.set(i as _, url);
+    }
+}
+
+async fn get_status(
+    client: &shared::rpc::blocker_service::Client,
+) -> Result<BlockerStatus, capnp::Error> {
+    let response = client.get_status_request().send().promise.await?;
+    decode::status(response.get()?)
+}
+
+async fn get_perf_stats(
+    client: &shared::rpc::blocker_service::Client,
+) -> Result<PerfStats, capnp::Error> {
+    let response = client.get_perf_stats_request().send().promise.await?;
+    decode::perf_stats(response.get()?.get_stats()?)
+}
+
+async fn get_fingerprint(
+    client: &shared::rpc::blocker_service::Client,
+) -> Result<Option<Fingerprint>, capnp::Error> {
+    let response = match client.get_fingerprint_request().send().promise.await {
+        Ok(response) => response,
+        Err(e) if e.kind == capnp::ErrorKind::Unimplemented => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    decode::fingerprint(response.get()?.get_fingerprint()?).map(Some)
+}
@@ -0,0 +1,130 @@
+//! Decoding of the messages received from the blocker. The blocker runs inside Spotify and is only
+//! semi-trusted, so every message is read with size limits and every value is checked before it is
+//! used. Malformed messages result in an error, never in a panic or an unbounded allocation.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use capnp::message::ReaderOptions;
+use shared::rpc::blocker_service::{self, logger, FilterHook};
+
+use super::{BlockerStatus, Fingerprint, HookPerfStats, PerfStats};
+
+/// Upper bound on the size of a single message, about 8 MiB. Checked before any segment is
+/// allocated.
+pub const MAX_MESSAGE_WORDS: usize = 1 << 20;
+/// Maximum depth of nested structs and lists, the schema needs less than 5.
+pub const MAX_NESTING: i32 = 16;
+/// Longest url accepted in a logged request.
+pub const MAX_URL_LEN: usize = 16 * 1024;
+/// Longest log message kept, longer messages are truncated.
+pub const MAX_LOG_MESSAGE_LEN: usize = 4 * 1024;
+/// Most hook stats accepted in a perf stats response, there are far fewer hooks.
+pub const MAX_HOOK_STATS: u32 = 16;
+/// Longest version or hash accepted in a fingerprint, real ones are below 64 bytes.
+pub const MAX_FINGERPRINT_LEN: usize = 256;
+
+/// Options for reading messages from the blocker.
+pub fn reader_options() -> ReaderOptions {
+    ReaderOptions {
+        traversal_limit_in_words: Some(MAX_MESSAGE_WORDS),
+        nesting_limit: MAX_NESTING,
+    }
+}
+
+/// Request reported by a hook of the blocker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedRequest {
+    pub hook: FilterHook,
+    pub url: String,
+    pub blocked: bool,
+    /// Sequence number assigned by the blocker, 0 if not supported by the blocker.
+    pub seq: u64,
+    /// Time the hook saw the request, if reported by the blocker.
+    pub time: Option<SystemTime>,
+}
+
+pub fn log_request(request: logger::request::Reader<'_>) -> Result<LoggedRequest, capnp::Error> {
+    let hook = request.get_hook()?;
+    let url = request.get_url()?.as_bytes();
+    if url.len() > MAX_URL_LEN {
+        return Err(capnp::Error::failed(format!(
+            "Logged url is {} bytes long, at most {MAX_URL_LEN} are accepted",
+            url.len()
+        )));
+    }
+    let time = match request.get_timestamp_micros() {
+        0 => None,
+        micros => UNIX_EPOCH.checked_add(Duration::from_micros(micros)),
+    };
+    Ok(LoggedRequest {
+        hook,
+        url: String::from_utf8_lossy(url).into_owned(),
+        blocked: request.get_blocked(),
+        seq: request.get_seq(),
+        time,
+    })
+}
+
+pub fn log_message(message: capnp::text::Reader<'_>) -> String {
+    let message = message.as_bytes();
+    let message = &message[..message.len().min(MAX_LOG_MESSAGE_LEN)];
+    String::from_utf8_lossy(message).into_owned()
+}
+
+pub fn status(
+    results: blocker_service::get_status_results::Reader<'_>,
+) -> Result<BlockerStatus, capnp::Error> {
+    Ok(BlockerStatus {
+        filtering: results.get_filtering(),
+        rule_count: results.get_rule_count() as usize,
+    })
+}
+
+pub fn perf_stats(
+    stats: blocker_service::perf_stats::Reader<'_>,
+) -> Result<PerfStats, capnp::Error> {
+    let hooks = stats.get_hooks()?;
+    if hooks.len() > MAX_HOOK_STATS {
+        return Err(capnp::Error::failed(format!(
+            "Blocker reported stats of {} hooks, at most {MAX_HOOK_STATS} are accepted",
+            hooks.len()
+        )));
+    }
+    let hooks = hooks
+        .iter()
+        .map(|hook| {
+            Ok(HookPerfStats {
+                hook: hook.get_hook()?,
+                calls: hook.get_calls(),
+                total: Duration::from_nanos(hook.get_total_nanos()),
+                max: Duration::from_nanos(hook.get_max_nanos()),
+            })
+        })
+        .collect::<Result<Vec<_>, capnp::Error>>()?;
+    Ok(PerfStats {
+        hooks,
+        pending_logs: stats.get_pending_logs() as usize,
+        max_pending_logs: stats.get_max_pending_logs() as usize,
+        working_set_bytes: stats.get_working_set_bytes(),
+        cpu_time: Duration::from_millis(stats.get_cpu_time_millis()),
+    })
+}
+
+pub fn fingerprint(
+    fingerprint: blocker_service::fingerprint::Reader<'_>,
+) -> Result<Fingerprint, capnp::Error> {
+    let text = |text: capnp::text::Reader<'_>| {
+        let text = text.as_bytes();
+        if text.len() > MAX_FINGERPRINT_LEN {
+            return Err(capnp::Error::failed(format!(
+                "Fingerprint field is {} bytes long, at most {MAX_FINGERPRINT_LEN} are accepted",
+                text.len()
+            )));
+        }
+        Ok(String::from_utf8_lossy(text).into_owned())
+    };
+    Ok(Fingerprint {
+        cef_version: text(fingerprint.get_cef_version()?)?,
+        cef_api_hash: text(fingerprint.get_cef_api_hash()?)?,
+    })
+}
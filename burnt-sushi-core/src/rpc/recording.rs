@@ -0,0 +1,185 @@
+//! Recording of the traffic received from the blocker and replaying it through the same pipeline,
+//! so that captures submitted by users reproduce filter matching or stats bugs deterministically.
+//! A recording is a stream of `RecordedEvent` messages, requests are kept as received so that
+//! replaying them also runs the decoder.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use capnp::serialize;
+use log::{info, warn};
+use shared::rpc::{blocker_service::logger, recorded_event};
+
+use super::{decode, LoggerImpl, RequestObserver, SequenceTracker};
+use crate::filters::{CompiledFilters, FilterConfig};
+
+/// Reduces a url to the detail that may be written to a recording.
+pub type RedactUrl = fn(&str) -> String;
+
+struct Recording {
+    file: File,
+    redact_url: RedactUrl,
+}
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+/// Records all traffic received from the blocker to a new file at the given path from now on,
+/// with the urls of requests passed through `redact_url`.
+pub fn start(path: &Path, redact_url: RedactUrl) -> io::Result<()> {
+    let file = File::create(path)?;
+    *RECORDING.lock().unwrap() = Some(Recording { file, redact_url });
+    info!("Recording RPC traffic to '{}'", path.display());
+    Ok(())
+}
+
+fn record(kind: &str, build: impl FnOnce(recorded_event::Builder<'_>) -> Result<(), capnp::Error>) {
+    let mut recording = RECORDING.lock().unwrap();
+    let Some(Recording { file, .. }) = recording.as_mut() else {
+        return;
+    };
+
+    let mut message = capnp::message::Builder::new_default();
+    let mut event = message.init_root::<recorded_event::Builder>();
+    event.set_received_micros(to_micros(SystemTime::now()));
+    if let Err(e) = build(event) {
+        warn!("Failed to record {kind}: {e}");
+        return;
+    }
+
+    // Written right away, so that the recording survives a crash it is meant to reproduce.
+    if let Err(e) = file.write_all(&serialize::write_message_to_words(&message)) {
+        warn!("Failed to write RPC recording, stopping it: {e}");
+        *recording = None;
+    }
+}
+
+pub(super) fn record_filters(config: &FilterConfig) {
+    record("filters", |event| {
+        let mut filters = event.init_filters();
+        let mut list = filters
+            .reborrow()
+            .init_allowlist(config.allowlist.len() as _);
+        for (i, rule) in config.allowlist.iter().enumerate() {
+            list.set(i as _, rule);
+        }
+        let mut list = filters.init_denylist(config.denylist.len() as _);
+        for (i, rule) in config.denylist.iter().enumerate() {
+            list.set(i as _, rule);
+        }
+        Ok(())
+    });
+}
+
+pub(super) fn record_request(request: logger::request::Reader<'_>) {
+    let Some(redact_url) = RECORDING.lock().unwrap().as_ref().map(|r| r.redact_url) else {
+        return;
+    };
+    // Requests with urls that are kept in full are recorded as received, even if they are
+    // malformed, so that replaying them also reproduces rejections by the decoder.
+    let redacted = match request.get_url().map(|url| url.to_str()) {
+        Ok(Ok(url)) => Some(redact_url(url)).filter(|redacted| redacted != url),
+        _ => None,
+    };
+    record("request", |event| match redacted {
+        Some(url) => {
+            let mut recorded = event.init_request();
+            recorded.set_url(url.as_str());
+            recorded.set_hook(request.get_hook()?);
+            recorded.set_blocked(request.get_blocked());
+            recorded.set_seq(request.get_seq());
+            recorded.set_timestamp_micros(request.get_timestamp_micros());
+            Ok(())
+        }
+        None => event.set_request(request),
+    });
+}
+
+pub(super) fn record_message(message: capnp::text::Reader<'_>) {
+    record("message", |mut event| {
+        event.set_message(message);
+        Ok(())
+    });
+}
+
+/// Totals of a replayed recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub requests: u64,
+    pub blocked: u64,
+    /// Requests that never arrived according to their sequence numbers.
+    pub dropped: u64,
+    /// Recorded requests the decoder rejects.
+    pub rejected: u64,
+    pub messages: u64,
+}
+
+/// Feeds a recording through the pipeline of live requests and reports them to the observer. The
+/// time a request was received stands in for the time it was seen if the blocker did not report
+/// one, so that replaying a recording always gives the same results.
+pub fn replay(
+    mut recording: &[u8],
+    observer: Arc<dyn RequestObserver>,
+) -> Result<ReplaySummary, capnp::Error> {
+    let mut logger = LoggerImpl {
+        filters: None,
+        observer,
+        sequence: SequenceTracker::default(),
+    };
+    let mut summary = ReplaySummary::default();
+
+    while let Some(message) = serialize::try_read_message(&mut recording, decode::reader_options())?
+    {
+        let event = message.get_root::<recorded_event::Reader>()?;
+        let received = from_micros(event.get_received_micros());
+        match event.which()? {
+            recorded_event::Filters(filters) => {
+                let config = FilterConfig {
+                    allowlist: rules(filters.get_allowlist()?)?,
+                    denylist: rules(filters.get_denylist()?)?,
+                };
+                logger.filters = CompiledFilters::new(&config)
+                    .inspect_err(|e| warn!("Failed to compile recorded filters: {e}"))
+                    .ok();
+            }
+            recorded_event::Request(request) => match decode::log_request(request?) {
+                Ok(request) => {
+                    summary.requests += 1;
+                    summary.blocked += u64::from(request.blocked);
+                    summary.dropped += logger.handle_request(request, received);
+                }
+                Err(e) => {
+                    warn!("Recorded request rejected: {e}");
+                    summary.rejected += 1;
+                }
+            },
+            recorded_event::Message(message) => {
+                info!("{}", decode::log_message(message?));
+                summary.messages += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn rules(list: capnp::text_list::Reader<'_>) -> Result<Vec<String>, capnp::Error> {
+    list.iter()
+        .map(|rule| Ok(rule?.to_str()?.to_owned()))
+        .collect()
+}
+
+fn to_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64)
+}
+
+fn from_micros(micros: u64) -> SystemTime {
+    UNIX_EPOCH
+        .checked_add(Duration::from_micros(micros))
+        .unwrap_or(UNIX_EPOCH)
+}
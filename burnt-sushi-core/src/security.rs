@@ -0,0 +1,193 @@
+//! Restricts the IPC endpoints to the user running the app, so that other users on the same machine
+//! can neither send commands to them nor read from them.
+
+use std::{
+    ffi::c_void,
+    io, mem,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    ptr, slice,
+};
+
+use widestring::{U16CStr, U16CString};
+use winapi::{
+    shared::{
+        iprtrmib::TCP_TABLE_OWNER_PID_CONNECTIONS,
+        minwindef::{DWORD, FALSE},
+        sddl::{
+            ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+            SDDL_REVISION_1,
+        },
+        tcpmib::MIB_TCPTABLE_OWNER_PID,
+        winerror::{ERROR_INSUFFICIENT_BUFFER, NO_ERROR},
+        ws2def::AF_INET,
+    },
+    um::{
+        handleapi::CloseHandle,
+        iphlpapi::GetExtendedTcpTable,
+        minwinbase::SECURITY_ATTRIBUTES,
+        processthreadsapi::{GetCurrentProcess, OpenProcess, OpenProcessToken},
+        securitybaseapi::GetTokenInformation,
+        winbase::LocalFree,
+        winnt::{
+            TokenUser, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION, PSECURITY_DESCRIPTOR,
+            TOKEN_QUERY, TOKEN_USER,
+        },
+    },
+};
+
+/// Security descriptor granting access to the current user and the system only.
+#[derive(Debug)]
+pub struct OwnerOnly(PSECURITY_DESCRIPTOR);
+
+// The descriptor is only freed on drop.
+unsafe impl Send for OwnerOnly {}
+unsafe impl Sync for OwnerOnly {}
+
+impl OwnerOnly {
+    pub fn new() -> io::Result<Self> {
+        let sid = current_user_sid()?;
+        let sddl = U16CString::from_str(format!("D:P(A;;GA;;;{sid})(A;;GA;;;SY)")).unwrap();
+
+        let mut descriptor = ptr::null_mut();
+        let result = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1 as DWORD,
+                &mut descriptor,
+                ptr::null_mut(),
+            )
+        };
+        if result == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self(descriptor))
+    }
+
+    /// Attributes for creating an object with this descriptor, valid as long as `self`.
+    pub fn attributes(&self) -> SECURITY_ATTRIBUTES {
+        SECURITY_ATTRIBUTES {
+            nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
+            lpSecurityDescriptor: self.0,
+            bInheritHandle: FALSE,
+        }
+    }
+}
+
+impl Drop for OwnerOnly {
+    fn drop(&mut self) {
+        unsafe { LocalFree(self.0) };
+    }
+}
+
+/// Whether a loopback connection accepted on `local` was opened by a process of the current user.
+/// Connections of processes whose owner cannot be determined are treated as foreign.
+pub fn is_current_user_peer(local: SocketAddr, peer: SocketAddr) -> io::Result<bool> {
+    let (SocketAddr::V4(local), SocketAddr::V4(peer)) = (local, peer) else {
+        return Ok(false);
+    };
+    // The peer's side of the connection is the one whose local endpoint is its address.
+    let Some(pid) = tcp_connection_owner(peer, local)? else {
+        return Ok(false);
+    };
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if process.is_null() {
+        return Ok(false);
+    }
+    let peer_sid = unsafe { process_user_sid(process) };
+    unsafe { CloseHandle(process) };
+
+    match peer_sid {
+        Ok(peer_sid) => Ok(peer_sid == current_user_sid()?),
+        Err(_) => Ok(false),
+    }
+}
+
+fn current_user_sid() -> io::Result<String> {
+    unsafe { process_user_sid(GetCurrentProcess()) }
+}
+
+/// # Safety
+/// `process` must be a valid process handle with query access.
+unsafe fn process_user_sid(process: HANDLE) -> io::Result<String> {
+    let mut token = ptr::null_mut();
+    if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let result = unsafe { token_user_sid(token) };
+    unsafe { CloseHandle(token) };
+    result
+}
+
+/// # Safety
+/// `token` must be a valid token handle with query access.
+unsafe fn token_user_sid(token: HANDLE) -> io::Result<String> {
+    let mut size: DWORD = 0;
+    unsafe { GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut size) };
+    // u64 for the alignment of the pointer in the token user.
+    let mut buffer = vec![0u64; (size as usize).div_ceil(mem::size_of::<u64>())];
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenUser,
+            buffer.as_mut_ptr().cast(),
+            size,
+            &mut size,
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let user = unsafe { &*buffer.as_ptr().cast::<TOKEN_USER>() };
+
+    let mut string_sid = ptr::null_mut();
+    if unsafe { ConvertSidToStringSidW(user.User.Sid, &mut string_sid) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let sid = unsafe { U16CStr::from_ptr_str(string_sid) }.to_string_lossy();
+    unsafe { LocalFree(string_sid as *mut c_void) };
+    Ok(sid)
+}
+
+/// Returns the id of the process owning the TCP connection from `local` to `remote`.
+fn tcp_connection_owner(local: SocketAddrV4, remote: SocketAddrV4) -> io::Result<Option<u32>> {
+    let mut size: DWORD = 0;
+    let mut buffer: Vec<u32> = Vec::new();
+    loop {
+        let result = unsafe {
+            GetExtendedTcpTable(
+                buffer.as_mut_ptr().cast(),
+                &mut size,
+                FALSE,
+                AF_INET as u32,
+                TCP_TABLE_OWNER_PID_CONNECTIONS,
+                0,
+            )
+        };
+        match result {
+            NO_ERROR => break,
+            ERROR_INSUFFICIENT_BUFFER => {
+                buffer.resize((size as usize).div_ceil(mem::size_of::<u32>()), 0)
+            }
+            code => return Err(io::Error::from_raw_os_error(code as i32)),
+        }
+    }
+
+    let table = unsafe { &*buffer.as_ptr().cast::<MIB_TCPTABLE_OWNER_PID>() };
+    let rows = unsafe { slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize) };
+    Ok(rows
+        .iter()
+        .find(|row| {
+            endpoint(row.dwLocalAddr, row.dwLocalPort) == local
+                && endpoint(row.dwRemoteAddr, row.dwRemotePort) == remote
+        })
+        .map(|row| row.dwOwningPid))
+}
+
+/// Converts an address and port in network byte order as found in the TCP table.
+fn endpoint(addr: DWORD, port: DWORD) -> SocketAddrV4 {
+    SocketAddrV4::new(
+        Ipv4Addr::from(u32::from_be(addr)),
+        u16::from_be(port as u16),
+    )
+}
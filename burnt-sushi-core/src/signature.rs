@@ -0,0 +1,170 @@
+//! Authenticode signatures of executables and modules.
+
+use std::{ffi::c_void, io, mem, path::Path, ptr};
+
+use widestring::U16CString;
+use winapi::{
+    shared::{minwindef::DWORD, windef::HWND},
+    um::{
+        handleapi::INVALID_HANDLE_VALUE,
+        softpub::WINTRUST_ACTION_GENERIC_VERIFY_V2,
+        wincrypt::{
+            CertCloseStore, CertFindCertificateInStore, CertFreeCertificateContext,
+            CertGetNameStringW, CryptMsgClose, CryptMsgGetParam, CryptQueryObject,
+            CERT_FIND_SUBJECT_CERT, CERT_INFO, CERT_NAME_SIMPLE_DISPLAY_TYPE,
+            CERT_QUERY_CONTENT_FLAG_PKCS7_SIGNED_EMBED, CERT_QUERY_FORMAT_FLAG_BINARY,
+            CERT_QUERY_OBJECT_FILE, CMSG_SIGNER_INFO, CMSG_SIGNER_INFO_PARAM, HCERTSTORE,
+            HCRYPTMSG, PKCS_7_ASN_ENCODING, X509_ASN_ENCODING,
+        },
+        wintrust::{
+            WinVerifyTrust, WINTRUST_DATA, WINTRUST_FILE_INFO, WTD_CHOICE_FILE, WTD_REVOKE_NONE,
+            WTD_STATEACTION_CLOSE, WTD_STATEACTION_VERIFY, WTD_UI_NONE,
+        },
+    },
+};
+
+/// Checks that the file has a valid Authenticode signature.
+pub fn verify(path: &Path) -> io::Result<()> {
+    let path = U16CString::from_os_str(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut file_info: WINTRUST_FILE_INFO = unsafe { mem::zeroed() };
+    file_info.cbStruct = mem::size_of::<WINTRUST_FILE_INFO>() as DWORD;
+    file_info.pcwszFilePath = path.as_ptr();
+
+    let mut data: WINTRUST_DATA = unsafe { mem::zeroed() };
+    data.cbStruct = mem::size_of::<WINTRUST_DATA>() as DWORD;
+    data.dwUIChoice = WTD_UI_NONE;
+    data.fdwRevocationChecks = WTD_REVOKE_NONE;
+    data.dwUnionChoice = WTD_CHOICE_FILE;
+    data.dwStateAction = WTD_STATEACTION_VERIFY;
+    unsafe { *data.u.pFile_mut() = &mut file_info };
+
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    let status = unsafe {
+        WinVerifyTrust(
+            INVALID_HANDLE_VALUE as HWND,
+            &mut action,
+            &mut data as *mut _ as *mut c_void,
+        )
+    };
+
+    // Releases the state allocated by the verification.
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    unsafe {
+        WinVerifyTrust(
+            INVALID_HANDLE_VALUE as HWND,
+            &mut action,
+            &mut data as *mut _ as *mut c_void,
+        )
+    };
+
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(status))
+    }
+}
+
+/// Returns the subject name of the certificate that signed the file.
+pub fn signer_name(path: &Path) -> io::Result<String> {
+    let path = U16CString::from_os_str(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut store: HCERTSTORE = ptr::null_mut();
+    let mut message: HCRYPTMSG = ptr::null_mut();
+    let found = unsafe {
+        CryptQueryObject(
+            CERT_QUERY_OBJECT_FILE,
+            path.as_ptr().cast(),
+            CERT_QUERY_CONTENT_FLAG_PKCS7_SIGNED_EMBED,
+            CERT_QUERY_FORMAT_FLAG_BINARY,
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut store,
+            &mut message,
+            ptr::null_mut(),
+        )
+    };
+    if found == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let result = unsafe { signer_name_from_message(store, message) };
+
+    unsafe {
+        CryptMsgClose(message);
+        CertCloseStore(store, 0);
+    }
+    result
+}
+
+/// # Safety
+/// `store` and `message` must be the handles returned by `CryptQueryObject` for a signed file.
+unsafe fn signer_name_from_message(store: HCERTSTORE, message: HCRYPTMSG) -> io::Result<String> {
+    let mut size: DWORD = 0;
+    if unsafe {
+        CryptMsgGetParam(
+            message,
+            CMSG_SIGNER_INFO_PARAM,
+            0,
+            ptr::null_mut(),
+            &mut size,
+        )
+    } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    // u64 for the alignment of the pointers in the signer info.
+    let mut buffer = vec![0u64; (size as usize).div_ceil(mem::size_of::<u64>())];
+    if unsafe {
+        CryptMsgGetParam(
+            message,
+            CMSG_SIGNER_INFO_PARAM,
+            0,
+            buffer.as_mut_ptr().cast(),
+            &mut size,
+        )
+    } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    let signer = unsafe { &*buffer.as_ptr().cast::<CMSG_SIGNER_INFO>() };
+
+    let mut cert_info: CERT_INFO = unsafe { mem::zeroed() };
+    cert_info.Issuer = signer.Issuer;
+    cert_info.SerialNumber = signer.SerialNumber;
+    let cert = unsafe {
+        CertFindCertificateInStore(
+            store,
+            X509_ASN_ENCODING | PKCS_7_ASN_ENCODING,
+            0,
+            CERT_FIND_SUBJECT_CERT,
+            &cert_info as *const _ as *const c_void,
+            ptr::null(),
+        )
+    };
+    if cert.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut name = [0u16; 256];
+    let len = unsafe {
+        CertGetNameStringW(
+            cert,
+            CERT_NAME_SIMPLE_DISPLAY_TYPE,
+            0,
+            ptr::null_mut(),
+            name.as_mut_ptr(),
+            name.len() as DWORD,
+        )
+    };
+    unsafe { CertFreeCertificateContext(cert) };
+
+    // The length includes the terminating nul.
+    Ok(String::from_utf16_lossy(
+        &name[..(len as usize).saturating_sub(1)],
+    ))
+}
@@ -1,9 +1,12 @@
 use std::{
+    ffi::{OsStr, OsString},
     io,
     mem::{self, MaybeUninit},
     num::{NonZeroU32, NonZeroUsize},
     os::windows::prelude::{AsRawHandle, HandleOrInvalid, OwnedHandle},
     ptr,
+    sync::OnceLock,
+    time::Duration,
 };
 use log::info;
 use dll_syringe::process::{OwnedProcess, Process};
@@ -17,6 +20,7 @@ use winapi::{
     },
     um::{
         errhandlingapi::{GetLastError, SetLastError},
+        sysinfoapi::GetTickCount,
         tlhelp32::{
             CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
         },
@@ -28,6 +32,11 @@ use winapi::{
 };
 use wineventhook::{raw_event, AccessibleObjectId, EventFilter, WindowEventHook, WindowHandle};
 
+use crate::{
+    metrics::METRICS,
+    timing::{self, Stage},
+};
+
 #[derive(Debug)]
 pub struct SpotifyProcessScanner {
     notifier: tokio::sync::watch::Sender<SpotifyState>,
@@ -64,6 +73,22 @@ impl SpotifyInfo {
             main_window: self.main_window,
         })
     }
+
+    /// Looks up the main Spotify window of the process regardless of its executable name, e.g. to
+    /// hook a process the scanner does not recognize.
+    pub fn of_process(process: OwnedProcess) -> io::Result<Option<Self>> {
+        let mut windows = list_process_windows(process.borrowed())?;
+        while let Some(window) = windows.next()? {
+            if is_main_spotify_window(window) {
+                drop(windows);
+                return Ok(Some(Self {
+                    process,
+                    main_window: window,
+                }));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl SpotifyProcessScanner {
@@ -98,6 +123,8 @@ impl SpotifyProcessScanner {
         Ok(())
     }
 
+    /// Looks for a running Spotify. Its window appeared before the scan, so no detection latency is
+    /// recorded.
     pub fn scan(&self) -> io::Result<()> {
         for process in OwnedProcess::all() {
             if !is_spotify_process(process.borrowed()) {
@@ -159,6 +186,12 @@ impl SpotifyProcessScanner {
                 })
             };
 
+            // Measured from the time the window was shown, so that the delay of the event hook is
+            // included. Both are milliseconds since system start, which wrap after 49.7 days.
+            let elapsed = unsafe { GetTickCount() }.wrapping_sub(event.timestamp());
+            let elapsed = Duration::from_millis(u64::from(elapsed));
+            METRICS.scanner_latency.observe(elapsed);
+            timing::record(Stage::Detect, elapsed);
             event_hook.unhook().await?;
             return Ok(Some(state));
         }
@@ -249,13 +282,79 @@ fn get_window_process_id(window: WindowHandle) -> NonZeroU32 {
     NonZeroU32::new(unsafe { process_id.assume_init() }).unwrap()
 }
 
-fn is_spotify_process(process: impl Process) -> bool {
-    match process.base_name() {
-        Ok(mut name) => {
-            name.make_ascii_lowercase();
-            name.to_string_lossy().contains("spotify")
+/// File name of the executable treated as Spotify instead of the real client, for development.
+static DEV_TARGET: OnceLock<OsString> = OnceLock::new();
+
+/// Treats processes of the executable with the given file name as Spotify instead of the real
+/// client, e.g. the mock Spotify of the test harness. Only the first target set is used.
+pub fn set_dev_target(file_name: impl Into<OsString>) {
+    let _ = DEV_TARGET.set(file_name.into());
+}
+
+/// Executable names treated as Spotify unless configured otherwise. Matches every name containing
+/// `spotify` like before names were configurable, e.g. also `SpotifyBeta.exe`, as the window and
+/// the signature of the process are checked anyway.
+pub const DEFAULT_PROCESS_NAMES: &[&str] = &["*Spotify*.exe"];
+
+/// Wildcard patterns of the executable names treated as Spotify.
+static PROCESS_NAMES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Treats processes whose executable name matches any of the patterns as Spotify, e.g.
+/// `Spotify*.exe` for differently named beta builds. `*` matches any number of characters and `?`
+/// a single one, case is ignored. Only the first names set are used.
+pub fn set_process_names(patterns: Vec<String>) {
+    let _ = PROCESS_NAMES.set(patterns);
+}
+
+/// Whether an executable with the given file name is treated as Spotify.
+pub fn matches_process_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    match PROCESS_NAMES.get() {
+        Some(patterns) => patterns
+            .iter()
+            .any(|pattern| matches_wildcard(pattern, &name)),
+        None => DEFAULT_PROCESS_NAMES
+            .iter()
+            .any(|pattern| matches_wildcard(pattern, &name)),
+    }
+}
+
+/// Matches the name against a pattern with `*` and `?` wildcards, ignoring ASCII case.
+pub fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase().chars().collect::<Vec<_>>();
+    let name = name.to_ascii_lowercase().chars().collect::<Vec<_>>();
+
+    // Backtracks to the last `*` on a mismatch, letting it consume one more character.
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
         }
-        Err(_) => false,
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+pub fn is_spotify_process(process: impl Process) -> bool {
+    match (process.base_name(), DEV_TARGET.get()) {
+        (Ok(name), Some(target)) => name.eq_ignore_ascii_case(target),
+        (Ok(name), None) => matches_process_name(&name),
+        (Err(_), _) => false,
     }
 }
 
@@ -280,22 +379,19 @@ fn is_main_spotify_window(window: WindowHandle) -> bool {
 }
 
 fn get_window_class_name(window: WindowHandle) -> io::Result<String> {
-    let mut class_name_buf = MaybeUninit::uninit_array::<256>();
+    let mut class_name_buf = [0u16; 256];
     let result = unsafe {
         GetClassNameW(
             window.as_ptr(),
-            class_name_buf[0].as_mut_ptr(),
+            class_name_buf.as_mut_ptr(),
             class_name_buf.len() as i32,
         )
     };
     match result {
         0 => Err(io::Error::last_os_error()),
-        name_len => {
-            let name_len = name_len as usize;
-            let class_name =
-                unsafe { MaybeUninit::slice_assume_init_ref(&class_name_buf[..name_len]) };
-            Ok(String::from_utf16_lossy(class_name))
-        }
+        name_len => Ok(String::from_utf16_lossy(
+            &class_name_buf[..name_len as usize],
+        )),
     }
 }
 
@@ -0,0 +1,106 @@
+//! Checks that a process found by the scanner is the genuine Spotify client before the blocker is
+//! injected into it, as any process can be named `Spotify.exe` and open a lookalike window. The
+//! signature is checked whatever names are configured, so that they cannot widen the match.
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    ptr,
+};
+
+use dll_syringe::process::Process;
+use thiserror::Error;
+use widestring::U16CStr;
+use winapi::{
+    shared::winerror::S_OK,
+    um::{
+        combaseapi::CoTaskMemFree, knownfolders::FOLDERID_ProgramFiles,
+        shlobj::SHGetKnownFolderPath,
+    },
+};
+
+use crate::{signature, spotify_process_scanner};
+
+/// Subject of the certificate Spotify signs its executables with.
+const PUBLISHER: &str = "Spotify AB";
+/// Directory of the Microsoft Store package, whose files can only be written by the system.
+const STORE_PACKAGE_PREFIX: &str = "SpotifyAB.SpotifyMusic_";
+
+/// Reasons why a process is not considered to be Spotify.
+#[derive(Debug, Error)]
+pub enum VerificationError {
+    #[error("Failed to locate executable")]
+    Path(#[source] io::Error),
+    #[error("Executable '{}' is not named like Spotify", .0.display())]
+    UnexpectedName(PathBuf),
+    #[error("Executable '{}' has no valid signature", .0.display())]
+    Unsigned(PathBuf, #[source] io::Error),
+    #[error("Executable '{}' is signed by '{publisher}' instead of {PUBLISHER}", .path.display())]
+    UnexpectedPublisher { path: PathBuf, publisher: String },
+}
+
+/// Checks the name, location and signature of the executable of the process.
+pub fn verify(process: impl Process) -> Result<(), VerificationError> {
+    let path = process.path().map_err(VerificationError::Path)?;
+    if !path
+        .file_name()
+        .is_some_and(spotify_process_scanner::matches_process_name)
+    {
+        return Err(VerificationError::UnexpectedName(path));
+    }
+
+    if is_store_package(&path) {
+        return Ok(());
+    }
+
+    if let Err(e) = signature::verify(&path) {
+        return Err(VerificationError::Unsigned(path, e));
+    }
+    let publisher =
+        signature::signer_name(&path).map_err(|e| VerificationError::Unsigned(path.clone(), e))?;
+    if publisher != PUBLISHER {
+        return Err(VerificationError::UnexpectedPublisher { path, publisher });
+    }
+
+    Ok(())
+}
+
+/// Whether the executable belongs to the Microsoft Store package of Spotify. Only packages
+/// installed to `%ProgramFiles%\WindowsApps` count, the `WindowsApps` directory in the local app
+/// data only holds aliases and can be written by the user.
+pub fn is_store_package(path: &Path) -> bool {
+    program_files()
+        .is_some_and(|program_files| is_in_store_package(path, &program_files.join("WindowsApps")))
+}
+
+/// Whether the path is inside a directory of the Spotify package directly below `packages_dir`.
+fn is_in_store_package(path: &Path, packages_dir: &Path) -> bool {
+    if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return false;
+    }
+    let mut components = path.components().map(Component::as_os_str);
+    let in_packages_dir = packages_dir.components().all(|expected| {
+        components
+            .next()
+            .is_some_and(|actual| actual.eq_ignore_ascii_case(expected.as_os_str()))
+    });
+    in_packages_dir
+        && components
+            .next()
+            .is_some_and(|package| package.to_string_lossy().starts_with(STORE_PACKAGE_PREFIX))
+}
+
+/// Location of the 64-bit program files, which cannot be changed through the environment.
+fn program_files() -> Option<PathBuf> {
+    let mut path = ptr::null_mut();
+    let result =
+        unsafe { SHGetKnownFolderPath(&FOLDERID_ProgramFiles, 0, ptr::null_mut(), &mut path) };
+    let program_files = (result == S_OK)
+        .then(|| PathBuf::from(unsafe { U16CStr::from_ptr_str(path) }.to_os_string()));
+    // Freed even if the call failed.
+    unsafe { CoTaskMemFree(path.cast()) };
+    program_files
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+/// Number of samples kept per stage.
+const MAX_SAMPLES: usize = 100;
+
+static TIMINGS: Mutex<[VecDeque<Duration>; Stage::ALL.len()]> =
+    Mutex::new([const { VecDeque::new() }; Stage::ALL.len()]);
+
+/// Stages of the hook pipeline whose duration is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// From a Spotify window appearing until it was identified by the scanner.
+    Detect,
+    Inject,
+    StartRpc,
+    /// From connecting to the blocker until filtering was enabled.
+    FilterPush,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::Detect,
+        Stage::Inject,
+        Stage::StartRpc,
+        Stage::FilterPush,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Detect => write!(f, "detect"),
+            Stage::Inject => write!(f, "inject"),
+            Stage::StartRpc => write!(f, "start_rpc"),
+            Stage::FilterPush => write!(f, "filter push"),
+        }
+    }
+}
+
+/// Records how long a stage took.
+pub fn record(stage: Stage, duration: Duration) {
+    debug!(
+        "Stage '{stage}' took {:.1}ms",
+        duration.as_secs_f64() * 1000.0
+    );
+
+    let mut timings = TIMINGS.lock().unwrap();
+    let samples = &mut timings[stage.index()];
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(duration);
+}
+
+/// Runs `f` and records its duration for the given stage.
+pub fn measure<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(stage, start.elapsed());
+    result
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Percentiles {
+    pub samples: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Percentiles of the recorded samples of a stage, if any were recorded.
+pub fn percentiles(stage: Stage) -> Option<Percentiles> {
+    let mut samples = TIMINGS.lock().unwrap()[stage.index()]
+        .iter()
+        .copied()
+        .collect::<Vec<_>>();
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable();
+
+    let at = |percentile: usize| samples[(samples.len() - 1) * percentile / 100];
+    Some(Percentiles {
+        samples: samples.len(),
+        p50: at(50),
+        p90: at(90),
+        p99: at(99),
+        max: samples[samples.len() - 1],
+    })
+}
@@ -0,0 +1,106 @@
+//! Evaluation of the `[[tests]]` declared in filter files against the rules.
+
+use burnt_sushi_core::filters::{
+    CompiledFilters, Expectation, FilterConfig, FilterTest, FilterTests, DECISION_CACHE_SIZE,
+    NOT_ALLOWLISTED,
+};
+use shared::rpc::blocker_service::FilterHook;
+
+const FILTER_FILE: &str = r#"
+allowlist = ['spclient\.wg\.spotify\.com', 'i\.scdn\.co']
+denylist = ['https://spclient\.wg\.spotify\.com/ads/.*']
+
+[[tests]]
+url = "https://spclient.wg.spotify.com/ads/v2/config"
+expect = "blocked"
+
+[[tests]]
+url = "https://i.scdn.co:443/image/ab67616d"
+expect = "allowed"
+"#;
+
+fn filters() -> CompiledFilters {
+    let config: FilterConfig = toml::from_str(FILTER_FILE).unwrap();
+    CompiledFilters::new(&config).unwrap()
+}
+
+fn test(url: &str, expect: Expectation) -> FilterTest {
+    FilterTest {
+        url: url.to_string(),
+        expect,
+    }
+}
+
+#[test]
+fn tests_are_parsed_next_to_the_rules() {
+    let tests: FilterTests = toml::from_str(FILTER_FILE).unwrap();
+    assert_eq!(
+        tests.tests,
+        [
+            test(
+                "https://spclient.wg.spotify.com/ads/v2/config",
+                Expectation::Blocked
+            ),
+            test("https://i.scdn.co:443/image/ab67616d", Expectation::Allowed),
+        ]
+    );
+    assert!(filters().run_tests(&tests.tests).is_empty());
+}
+
+#[test]
+fn hosts_are_checked_against_the_allowlist() {
+    assert_eq!(
+        filters().evaluate("https://tracker.example.com/spclient.wg.spotify.com"),
+        Some(NOT_ALLOWLISTED)
+    );
+    assert_eq!(filters().evaluate("https://i.scdn.co/image"), None);
+}
+
+#[test]
+fn failures_report_the_blocking_rule() {
+    let failures = filters().run_tests(&[
+        test(
+            "https://spclient.wg.spotify.com/ads/v2/config",
+            Expectation::Allowed,
+        ),
+        test("https://i.scdn.co/image", Expectation::Blocked),
+    ]);
+    assert_eq!(failures.len(), 2);
+    assert_eq!(
+        failures[0].rule.as_deref(),
+        Some(r"https://spclient\.wg\.spotify\.com/ads/.*")
+    );
+    assert_eq!(failures[1].rule, None);
+}
+
+#[test]
+fn cached_decisions_match_the_rules() {
+    let filters = filters();
+    let ad = "https://spclient.wg.spotify.com/ads/v2/config";
+    for _ in 0..2 {
+        assert_eq!(
+            filters.evaluate(ad),
+            Some(r"https://spclient\.wg\.spotify\.com/ads/.*")
+        );
+        assert_eq!(
+            filters.blocking_rule(FilterHook::CefUrlRequestCreate, ad),
+            Some(r"https://spclient\.wg\.spotify\.com/ads/.*")
+        );
+        assert_eq!(filters.evaluate("https://i.scdn.co/image"), None);
+    }
+    assert_eq!(filters.cached_decisions(), 2);
+}
+
+#[test]
+fn decision_cache_is_bounded() {
+    let filters = filters();
+    for i in 0..DECISION_CACHE_SIZE + 10 {
+        filters.evaluate(&format!("https://i.scdn.co/image/{i}"));
+    }
+    assert_eq!(filters.cached_decisions(), DECISION_CACHE_SIZE);
+    assert_eq!(
+        filters.evaluate("https://tracker.example.com/"),
+        Some(NOT_ALLOWLISTED)
+    );
+    assert_eq!(filters.cached_decisions(), DECISION_CACHE_SIZE);
+}
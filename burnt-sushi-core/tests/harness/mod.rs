@@ -0,0 +1,102 @@
+//! Mock Spotify process and stub blocker payload from `test-harness/`, built on first use. The
+//! scanner is set to look for the mock instead of the real client, which may keep running.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use burnt_sushi_core::{spotify_process_scanner, DEFAULT_BLOCKER_FILE_NAME};
+use dll_syringe::{process::OwnedProcess, Syringe};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// How long the tests wait for something to happen in the mock process.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+const MOCK_SPOTIFY_FILE_NAME: &str = "mock-spotify.exe";
+
+/// Only one mock runs at a time, so that the scanner finds the one of the current test.
+static MOCK_LOCK: Mutex<()> = Mutex::const_new(());
+
+pub struct Artifacts {
+    pub mock_spotify: PathBuf,
+    /// Stub payload, named like the real blocker.
+    pub stub_blocker: PathBuf,
+}
+
+pub fn artifacts() -> &'static Artifacts {
+    static ARTIFACTS: OnceLock<Artifacts> = OnceLock::new();
+    ARTIFACTS.get_or_init(build)
+}
+
+fn build() -> Artifacts {
+    // A separate target directory avoids waiting for the lock held by the outer build.
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("harness");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", "mock-spotify", "-p", "stub-blocker"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "failed to build test harness");
+
+    spotify_process_scanner::set_dev_target(MOCK_SPOTIFY_FILE_NAME);
+
+    let out_dir = target_dir.join("debug");
+    let stub_blocker = target_dir.join(DEFAULT_BLOCKER_FILE_NAME);
+    fs::copy(out_dir.join("stub_blocker.dll"), &stub_blocker).unwrap();
+
+    Artifacts {
+        mock_spotify: out_dir.join(MOCK_SPOTIFY_FILE_NAME),
+        stub_blocker,
+    }
+}
+
+/// Running mock Spotify process, killed on drop.
+pub struct MockSpotify {
+    child: Child,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl MockSpotify {
+    /// Starts the mock and waits until its main window exists.
+    pub async fn spawn() -> Self {
+        let lock = MOCK_LOCK.lock().await;
+        let mut child = Command::new(&artifacts().mock_spotify)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start mock Spotify");
+
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert_eq!(line.trim(), "ready");
+
+        Self { child, _lock: lock }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn syringe(&self) -> Syringe {
+        Syringe::for_process(OwnedProcess::from_pid(self.pid()).unwrap())
+    }
+
+    pub fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for MockSpotify {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
@@ -0,0 +1,174 @@
+//! Runs the scanner → inject → RPC → filter push pipeline against the mock Spotify process.
+
+mod harness;
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use burnt_sushi_core::{
+    filters::FilterConfig,
+    injector::{self, InjectedBlocker},
+    rpc::RequestObserver,
+    spotify_process_scanner::{SpotifyProcessScanner, SpotifyState},
+    DEFAULT_BLOCKER_FILE_NAME,
+};
+use dll_syringe::process::Process;
+use shared::rpc::blocker_service::FilterHook;
+use tokio::{task::LocalSet, time::timeout};
+
+use harness::{artifacts, MockSpotify, TIMEOUT};
+
+/// Reported by the stub blocker once filtering is enabled.
+const PROBE_URL: &str = "https://spclient.wg.spotify.com/ads/v2/probe";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    hook: FilterHook,
+    url: String,
+    blocked: bool,
+    rule: Option<String>,
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    requests: Mutex<Vec<Request>>,
+}
+
+impl RequestObserver for RecordingObserver {
+    fn on_request(
+        &self,
+        _time: SystemTime,
+        hook: FilterHook,
+        url: &str,
+        blocked: bool,
+        rule: Option<&str>,
+    ) {
+        self.requests.lock().unwrap().push(Request {
+            hook,
+            url: url.to_string(),
+            blocked,
+            rule: rule.map(str::to_string),
+        });
+    }
+}
+
+fn filter_config(denylist: &[&str]) -> FilterConfig {
+    FilterConfig {
+        allowlist: vec![".*".to_string()],
+        denylist: denylist.iter().map(|rule| rule.to_string()).collect(),
+    }
+}
+
+fn has_blocker(spotify: &MockSpotify) -> bool {
+    spotify
+        .syringe()
+        .process()
+        .find_module_by_name(DEFAULT_BLOCKER_FILE_NAME)
+        .unwrap()
+        .is_some()
+}
+
+#[tokio::test]
+async fn scanner_tracks_mock_spotify() {
+    let mut spotify = MockSpotify::spawn().await;
+    let (scanner, mut state) = SpotifyProcessScanner::new();
+
+    tokio::select! {
+        result = scanner.run() => panic!("scanner stopped: {result:?}"),
+        result = timeout(TIMEOUT, async {
+            let running = state
+                .wait_for(|state| matches!(state, SpotifyState::Running(_)))
+                .await
+                .unwrap();
+            let SpotifyState::Running(info) = &*running else {
+                unreachable!()
+            };
+            assert_eq!(info.process.pid().unwrap().get(), spotify.pid());
+            drop(running);
+
+            spotify.kill();
+            state
+                .wait_for(|state| *state == SpotifyState::Stopped)
+                .await
+                .unwrap();
+        }) => result.expect("mock Spotify was not tracked in time"),
+    }
+}
+
+#[tokio::test]
+async fn injects_pushes_filters_and_ejects() {
+    // The RPC task runs on the local set, like on the main thread of the app.
+    LocalSet::new()
+        .run_until(async {
+            let spotify = MockSpotify::spawn().await;
+            let observer = Arc::new(RecordingObserver::default());
+
+            let mut blocker = InjectedBlocker::inject(
+                spotify.syringe(),
+                &artifacts().stub_blocker,
+                filter_config(&["/ads/"]),
+                observer.clone(),
+            )
+            .unwrap();
+            assert!(has_blocker(&spotify));
+
+            timeout(TIMEOUT, async {
+                while observer.requests.lock().unwrap().is_empty() {
+                    tokio::time::sleep(TIMEOUT / 100).await;
+                }
+            })
+            .await
+            .expect("no request was reported");
+            assert_eq!(
+                *observer.requests.lock().unwrap(),
+                [Request {
+                    hook: FilterHook::CefUrlRequestCreate,
+                    url: PROBE_URL.to_string(),
+                    blocked: true,
+                    rule: Some("/ads/".to_string()),
+                }]
+            );
+
+            blocker.check_health().await.unwrap();
+            assert_eq!(blocker.rule_count(), 2);
+
+            blocker
+                .set_filters(filter_config(&["/ads/", "/promoted/"]))
+                .await
+                .unwrap();
+            assert_eq!(blocker.rule_count(), 3);
+            blocker.check_health().await.unwrap();
+
+            blocker.eject().await.unwrap();
+            assert!(!has_blocker(&spotify));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn ejects_leftover_blockers() {
+    let spotify = MockSpotify::spawn().await;
+    let syringe = spotify.syringe();
+    syringe.inject(&artifacts().stub_blocker).unwrap();
+    assert!(has_blocker(&spotify));
+
+    injector::eject_previous_blockers(&syringe).unwrap();
+    assert!(!has_blocker(&spotify));
+}
+
+#[tokio::test]
+async fn ejects_leftover_blockers_with_other_names() {
+    let spotify = MockSpotify::spawn().await;
+    let syringe = spotify.syringe();
+    let renamed = artifacts().stub_blocker.with_file_name("CustomBlocker.dll");
+    fs::copy(&artifacts().stub_blocker, &renamed).unwrap();
+    syringe.inject(&renamed).unwrap();
+    assert!(!has_blocker(&spotify));
+    assert_eq!(injector::find_blockers(&syringe).unwrap().len(), 1);
+
+    injector::eject_previous_blockers(&syringe).unwrap();
+    assert!(injector::find_blockers(&syringe).unwrap().is_empty());
+}
@@ -0,0 +1,41 @@
+//! Wildcard matching of the executable names treated as Spotify.
+
+use burnt_sushi_core::spotify_process_scanner::{self, matches_wildcard};
+
+#[test]
+fn exact_names_ignore_case() {
+    assert!(matches_wildcard("Spotify.exe", "Spotify.exe"));
+    assert!(matches_wildcard("Spotify.exe", "SPOTIFY.EXE"));
+    assert!(!matches_wildcard("Spotify.exe", "SpotifyLauncher.exe"));
+    assert!(!matches_wildcard("Spotify.exe", "Spotify.exe.bak"));
+}
+
+#[test]
+fn star_matches_any_number_of_characters() {
+    assert!(matches_wildcard("Spotify*.exe", "Spotify.exe"));
+    assert!(matches_wildcard("Spotify*.exe", "SpotifyBeta.exe"));
+    assert!(matches_wildcard("Spotify*.exe", "Spotify.beta.exe"));
+    assert!(matches_wildcard("*", "anything.exe"));
+    assert!(!matches_wildcard("Spotify*.exe", "NotSpotify.exe"));
+    assert!(!matches_wildcard("Spotify*.exe", "SpotifyBeta.dll"));
+}
+
+#[test]
+fn question_mark_matches_one_character() {
+    assert!(matches_wildcard("Spotify?.exe", "Spotify2.exe"));
+    assert!(!matches_wildcard("Spotify?.exe", "Spotify.exe"));
+    assert!(!matches_wildcard("Spotify?.exe", "Spotify22.exe"));
+}
+
+#[test]
+fn defaults_match_names_containing_spotify() {
+    let matches_default = |name| {
+        spotify_process_scanner::DEFAULT_PROCESS_NAMES
+            .iter()
+            .any(|pattern| matches_wildcard(pattern, name))
+    };
+    assert!(matches_default("Spotify.exe"));
+    assert!(matches_default("SpotifyBeta.exe"));
+    assert!(matches_default("mock-spotify.exe"));
+    assert!(!matches_default("Discord.exe"));
+}
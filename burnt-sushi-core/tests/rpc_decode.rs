@@ -0,0 +1,108 @@
+//! Decoding of malformed and oversized messages from the blocker, which has to fail without
+//! panicking. `fuzz/` covers the same decoder with arbitrary input.
+
+use burnt_sushi_core::rpc::decode::{
+    self, MAX_FINGERPRINT_LEN, MAX_HOOK_STATS, MAX_LOG_MESSAGE_LEN, MAX_URL_LEN,
+};
+use capnp::{message, serialize};
+use shared::rpc::blocker_service::{self, logger, FilterHook};
+
+fn log_request_message(url: &str) -> Vec<u8> {
+    let mut builder = message::Builder::new_default();
+    let mut request = builder.init_root::<logger::request::Builder>();
+    request.set_url(url);
+    request.set_hook(FilterHook::CefUrlRequestCreate);
+    request.set_blocked(true);
+    serialize::write_message_to_words(&builder)
+}
+
+fn read(mut bytes: &[u8]) -> capnp::Result<message::Reader<serialize::OwnedSegments>> {
+    serialize::read_message(&mut bytes, decode::reader_options())
+}
+
+fn decode_log_request(bytes: &[u8]) -> capnp::Result<decode::LoggedRequest> {
+    decode::log_request(read(bytes)?.get_root::<logger::request::Reader>()?)
+}
+
+#[test]
+fn decodes_log_request() {
+    let request = decode_log_request(&log_request_message("https://example.com/ad")).unwrap();
+    assert_eq!(
+        request,
+        decode::LoggedRequest {
+            hook: FilterHook::CefUrlRequestCreate,
+            url: "https://example.com/ad".to_string(),
+            blocked: true,
+            seq: 0,
+            time: None,
+        }
+    );
+}
+
+#[test]
+fn rejects_long_urls() {
+    let url = "a".repeat(MAX_URL_LEN + 1);
+    assert!(decode_log_request(&log_request_message(&url)).is_err());
+}
+
+#[test]
+fn rejects_oversized_messages() {
+    let url = "a".repeat(decode::MAX_MESSAGE_WORDS * 8);
+    assert!(read(&log_request_message(&url)).is_err());
+}
+
+#[test]
+fn truncates_log_messages() {
+    let bytes = log_request_message(&"a".repeat(MAX_LOG_MESSAGE_LEN * 2));
+
+    let message = read(&bytes).unwrap();
+    let request = message.get_root::<logger::request::Reader>().unwrap();
+    let text = decode::log_message(request.get_url().unwrap());
+    assert_eq!(text.len(), MAX_LOG_MESSAGE_LEN);
+}
+
+#[test]
+fn rejects_too_many_hook_stats() {
+    let mut builder = message::Builder::new_default();
+    builder
+        .init_root::<blocker_service::perf_stats::Builder>()
+        .init_hooks(MAX_HOOK_STATS + 1);
+    let bytes = serialize::write_message_to_words(&builder);
+
+    let message = read(&bytes).unwrap();
+    let stats = message
+        .get_root::<blocker_service::perf_stats::Reader>()
+        .unwrap();
+    assert!(decode::perf_stats(stats).is_err());
+}
+
+#[test]
+fn rejects_long_fingerprints() {
+    let mut builder = message::Builder::new_default();
+    let mut fingerprint = builder.init_root::<blocker_service::fingerprint::Builder>();
+    fingerprint.set_cef_version("127.3.5+chromium-127.0.6533.120");
+    fingerprint.set_cef_api_hash(&"a".repeat(MAX_FINGERPRINT_LEN + 1));
+    let bytes = serialize::write_message_to_words(&builder);
+
+    let message = read(&bytes).unwrap();
+    let fingerprint = message
+        .get_root::<blocker_service::fingerprint::Reader>()
+        .unwrap();
+    assert!(decode::fingerprint(fingerprint).is_err());
+}
+
+#[test]
+fn survives_truncated_and_corrupted_messages() {
+    let bytes = log_request_message("https://example.com/ad");
+
+    for len in 0..bytes.len() {
+        let _ = decode_log_request(&bytes[..len]);
+    }
+    for i in 0..bytes.len() {
+        for flip in [0x01, 0x80, 0xff] {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= flip;
+            let _ = decode_log_request(&corrupted);
+        }
+    }
+}
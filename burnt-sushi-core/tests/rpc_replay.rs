@@ -0,0 +1,133 @@
+//! Replaying recorded blocker traffic through the request pipeline.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use burnt_sushi_core::rpc::{
+    recording::{self, ReplaySummary},
+    RequestObserver,
+};
+use capnp::{message, serialize};
+use shared::rpc::{blocker_service::FilterHook, recorded_event};
+
+const RECEIVED_MICROS: u64 = 1_700_000_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Observed {
+    time: SystemTime,
+    url: String,
+    blocked: bool,
+    rule: Option<String>,
+}
+
+#[derive(Default)]
+struct Observer(Mutex<Vec<Observed>>);
+
+impl RequestObserver for Observer {
+    fn on_request(
+        &self,
+        time: SystemTime,
+        _hook: FilterHook,
+        url: &str,
+        blocked: bool,
+        rule: Option<&str>,
+    ) {
+        self.0.lock().unwrap().push(Observed {
+            time,
+            url: url.to_string(),
+            blocked,
+            rule: rule.map(str::to_string),
+        });
+    }
+}
+
+fn event(recording: &mut Vec<u8>, build: impl FnOnce(recorded_event::Builder<'_>)) {
+    let mut builder = message::Builder::new_default();
+    let mut event = builder.init_root::<recorded_event::Builder>();
+    event.set_received_micros(RECEIVED_MICROS);
+    build(event);
+    recording.extend(serialize::write_message_to_words(&builder));
+}
+
+fn filters(recording: &mut Vec<u8>, denylist: &str) {
+    event(recording, |event| {
+        let mut filters = event.init_filters();
+        filters.reborrow().init_allowlist(0);
+        filters.init_denylist(1).set(0, denylist);
+    });
+}
+
+fn request(recording: &mut Vec<u8>, url: &str, blocked: bool, seq: u64) {
+    event(recording, |event| {
+        let mut request = event.init_request();
+        request.set_url(url);
+        request.set_hook(FilterHook::CefUrlRequestCreate);
+        request.set_blocked(blocked);
+        request.set_seq(seq);
+    });
+}
+
+#[test]
+fn replays_requests_against_recorded_filters() {
+    let mut recording = Vec::new();
+    filters(&mut recording, r"https://spclient\.wg\.spotify\.com/ads/.*");
+    request(
+        &mut recording,
+        "https://spclient.wg.spotify.com/ads/v2/config",
+        true,
+        1,
+    );
+    request(&mut recording, "https://i.scdn.co/image/ab67616d", false, 2);
+    event(&mut recording, |mut event| {
+        event.set_message("Blocker started")
+    });
+
+    let observer = Arc::new(Observer::default());
+    let summary = recording::replay(&recording, observer.clone()).unwrap();
+
+    assert_eq!(
+        summary,
+        ReplaySummary {
+            requests: 2,
+            blocked: 1,
+            dropped: 0,
+            rejected: 0,
+            messages: 1,
+        }
+    );
+    let received = UNIX_EPOCH + Duration::from_micros(RECEIVED_MICROS);
+    assert_eq!(
+        *observer.0.lock().unwrap(),
+        [
+            Observed {
+                time: received,
+                url: "https://spclient.wg.spotify.com/ads/v2/config".to_string(),
+                blocked: true,
+                rule: Some(r"https://spclient\.wg\.spotify\.com/ads/.*".to_string()),
+            },
+            Observed {
+                time: received,
+                url: "https://i.scdn.co/image/ab67616d".to_string(),
+                blocked: false,
+                rule: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn counts_dropped_and_rejected_requests() {
+    let mut recording = Vec::new();
+    request(&mut recording, "https://example.com/1", false, 1);
+    request(&mut recording, &"a".repeat(64 * 1024), false, 2);
+    // Far enough ahead that 2 to 43 fall out of the reorder window.
+    request(&mut recording, "https://example.com/300", false, 300);
+
+    let summary = recording::replay(&recording, Arc::new(Observer::default())).unwrap();
+
+    assert_eq!(summary.requests, 2);
+    assert_eq!(summary.rejected, 1);
+    assert_eq!(summary.dropped, 42);
+}
@@ -0,0 +1,56 @@
+//! Detection of dropped requests by their sequence numbers.
+
+use burnt_sushi_core::rpc::{SequenceTracker, REORDER_WINDOW};
+
+fn record_all(tracker: &mut SequenceTracker, seqs: impl IntoIterator<Item = u64>) -> u64 {
+    seqs.into_iter().map(|seq| tracker.record(seq)).sum()
+}
+
+#[test]
+fn requests_in_order_are_not_dropped() {
+    let mut tracker = SequenceTracker::default();
+    assert_eq!(record_all(&mut tracker, 1..=1000), 0);
+}
+
+#[test]
+fn first_request_may_start_anywhere() {
+    let mut tracker = SequenceTracker::default();
+    assert_eq!(record_all(&mut tracker, 500..=510), 0);
+}
+
+#[test]
+fn unsupported_sequence_numbers_are_ignored() {
+    let mut tracker = SequenceTracker::default();
+    assert_eq!(record_all(&mut tracker, [1, 0, 0, 2]), 0);
+}
+
+#[test]
+fn reordered_requests_are_not_dropped() {
+    let mut tracker = SequenceTracker::default();
+    assert_eq!(record_all(&mut tracker, [1, 3, 2, 6, 5, 4, 7]), 0);
+    // Later requests push the window forward, the reordered ones must not count once it passes.
+    assert_eq!(record_all(&mut tracker, 8..=8 + 2 * REORDER_WINDOW), 0);
+}
+
+#[test]
+fn duplicates_are_not_dropped() {
+    let mut tracker = SequenceTracker::default();
+    assert_eq!(record_all(&mut tracker, [1, 2, 2, 1, 3]), 0);
+}
+
+#[test]
+fn missing_requests_count_once_the_window_passes() {
+    let mut tracker = SequenceTracker::default();
+    assert_eq!(record_all(&mut tracker, [1, 2, 5]), 0);
+    assert_eq!(record_all(&mut tracker, 6..4 + REORDER_WINDOW), 0);
+    // 3 and 4 fall out of the window together.
+    assert_eq!(tracker.record(5 + REORDER_WINDOW), 2);
+    assert_eq!(record_all(&mut tracker, [3, 4]), 0);
+}
+
+#[test]
+fn large_gaps_count_right_away() {
+    let mut tracker = SequenceTracker::default();
+    tracker.record(1);
+    assert_eq!(tracker.record(100_000), 99_998);
+}
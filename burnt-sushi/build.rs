@@ -1,4 +1,14 @@
-use std::{env, fs, path::PathBuf, process::Command};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+
+/// Languages the default filter config is written in besides English, matching `i18n::Lang`.
+const FILTER_LANGUAGES: [&str; 3] = ["de", "fr", "es"];
 
 fn main() {
     let mut res = winres::WindowsResource::new();
@@ -22,13 +32,63 @@ fn main() {
     )
     .unwrap();
 
-    let mut source_config_path = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
-    source_config_path.push("..");
-    source_config_path.push("filter.toml");
+    let root_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("..");
+    write_filter_configs(
+        &root_dir.join("filter.toml"),
+        &root_dir.join("filter-notes.toml"),
+        &PathBuf::from(env::var_os("OUT_DIR").unwrap()),
+    );
+}
+
+#[derive(Deserialize)]
+struct FilterNotes {
+    header: BTreeMap<String, String>,
+    /// Translations by language, keyed by the English note.
+    notes: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Writes `filter.toml` with the English notes and `filter.<lang>.toml` with translated ones for
+/// every language, each starting with the header in that language.
+fn write_filter_configs(source: &Path, notes: &Path, out_dir: &Path) {
+    cargo_emit::rerun_if_changed!(source.display(), notes.display());
+    let source = fs::read_to_string(source).unwrap();
+    let notes: FilterNotes = toml::from_str(&fs::read_to_string(notes).unwrap()).unwrap();
 
-    let mut target_config_path = PathBuf::from(env::var_os("OUT_DIR").unwrap());
-    target_config_path.push("filter.toml");
-    fs::copy(source_config_path, target_config_path).unwrap();
+    for lang in ["en"].into_iter().chain(FILTER_LANGUAGES) {
+        let mut config = String::new();
+        if let Some(header) = notes.header.get(lang) {
+            for line in header.trim().lines() {
+                config += &format!("# {line}\n");
+            }
+            config += "#\n";
+        }
+        for line in source.lines() {
+            config += &localize_note(line, lang, &notes);
+            config += "\n";
+        }
+
+        let file_name = match lang {
+            "en" => "filter.toml".to_string(),
+            lang => format!("filter.{lang}.toml"),
+        };
+        fs::write(out_dir.join(file_name), config).unwrap();
+    }
+}
+
+/// Replaces the note after a rule, e.g. `'i\.scdn\.co', # cover art`, with its translation.
+fn localize_note(line: &str, lang: &str, notes: &FilterNotes) -> String {
+    let Some((rule, note)) = line.rsplit_once("', # ") else {
+        return line.to_string();
+    };
+    match notes.notes.get(note).and_then(|notes| notes.get(lang)) {
+        Some(translated) => format!("{rule}', # {translated}"),
+        None => {
+            if lang != "en" {
+                cargo_emit::warning!("Filter note '{}' is not translated to {}", note, lang);
+            }
+            line.to_string()
+        }
+    }
 }
 
 fn build_crate(name: &str, target: &str, file: &str) -> PathBuf {
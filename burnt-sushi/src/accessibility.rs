@@ -0,0 +1,72 @@
+use std::sync::Mutex;
+
+use native_windows_gui as nwg;
+
+use burnt_sushi_core::error::FailureReason;
+
+use crate::{
+    events::{self, AppEvent},
+    i18n::{tr, tr_args, Msg},
+    notify, settings,
+    status::HookStatus,
+};
+
+/// Notifies the tray about status changes so that its tooltip, which screen readers announce,
+/// stays current.
+static TRAY_NOTICE: Mutex<Option<nwg::NoticeSender>> = Mutex::new(None);
+
+pub fn set_tray_notice(notice: Option<nwg::NoticeSender>) {
+    *TRAY_NOTICE.lock().unwrap() = notice;
+}
+
+/// Announces the status changes published on the event bus.
+pub fn subscribe() {
+    events::subscribe("accessibility", |event| {
+        if let AppEvent::StatusChanged(hook) = event {
+            announce_status(hook);
+        }
+    });
+}
+
+/// Reports a status change to the tray and, if enabled, as a notification which is read out by
+/// screen readers like Narrator.
+fn announce_status(hook: HookStatus) {
+    if let Some(notice) = *TRAY_NOTICE.lock().unwrap() {
+        notice.notice();
+    }
+
+    // Hooking is followed by the final state right away.
+    if hook != HookStatus::Hooking && settings::get().announce_status_changes {
+        notify::info(tr(Msg::StatusChanged), &status_text(hook));
+    }
+}
+
+/// Localized description of the status.
+pub fn status_text(hook: HookStatus) -> String {
+    let msg = match hook {
+        HookStatus::Searching => Msg::StatusSearching,
+        HookStatus::Preparing => Msg::StatusPreparing,
+        HookStatus::Hooking => Msg::StatusHooking,
+        HookStatus::Hooked => Msg::StatusBlocking,
+        HookStatus::Paused => Msg::StatusPaused,
+        HookStatus::OtherInstance => Msg::StatusOtherInstance,
+        HookStatus::Unavailable(reason) => {
+            return tr_args(Msg::StatusUnavailable, &[("reason", &reason_text(reason))]);
+        }
+    };
+    tr(msg).to_string()
+}
+
+/// Localized description of why Spotify cannot be hooked.
+pub fn reason_text(reason: FailureReason) -> &'static str {
+    tr(match reason {
+        FailureReason::ArchitectureMismatch => Msg::ReasonArchitectureMismatch,
+        FailureReason::StorePackage => Msg::ReasonStorePackage,
+        FailureReason::SecuritySoftware => Msg::ReasonSecuritySoftware,
+        FailureReason::Firewall => Msg::ReasonFirewall,
+        FailureReason::Conflict => Msg::ReasonConflict,
+        FailureReason::IntegrityMismatch => Msg::ReasonIntegrityMismatch,
+        FailureReason::Unstable => Msg::ReasonUnstable,
+        FailureReason::Other => Msg::ReasonOther,
+    })
+}
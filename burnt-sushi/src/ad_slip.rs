@@ -0,0 +1,73 @@
+//! Notifies the user when Spotify plays an ad while it is hooked, as the blocker itself cannot tell
+//! that an ad got past outdated filters.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::{
+    events::{self, AppEvent},
+    i18n::{tr, Msg},
+    notify::{self, NotificationAction},
+    settings, stats,
+    status::{self, HookStatus},
+    telemetry,
+};
+
+/// Shortest time between two notifications, a single outdated rule usually lets through many ads.
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+static LAST_NOTIFIED: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// How an ad was noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdSource {
+    /// Playback state reported by the Spotify Web API.
+    WebApi,
+    /// Short item without an artist in the Windows media session.
+    MediaSession,
+}
+
+/// Reports an ad played by Spotify, which is only unexpected while it is hooked.
+pub fn detected(source: AdSource, item: Option<&str>) {
+    let version = {
+        let status = status::get();
+        if status.hook != HookStatus::Hooked {
+            return;
+        }
+        status
+            .spotify
+            .as_ref()
+            .and_then(|spotify| spotify.version.clone())
+    };
+    if let Some(version) = version {
+        stats::get().record_ad_slip(&version);
+    }
+    telemetry::record_ad_slip();
+    events::publish(AppEvent::AdSlipped);
+    match item {
+        Some(item) => {
+            warn!("Spotify is playing an ad even though the blocker is active ({source:?}): {item}")
+        }
+        None => warn!("Spotify is playing an ad even though the blocker is active ({source:?})"),
+    }
+
+    if !settings::get().ad_slip_notifications {
+        return;
+    }
+    let mut last_notified = LAST_NOTIFIED.lock().unwrap();
+    if last_notified.is_some_and(|last| last.elapsed() < NOTIFY_INTERVAL) {
+        return;
+    }
+    *last_notified = Some(Instant::now());
+    drop(last_notified);
+
+    notify::error_with_actions(
+        tr(Msg::AdSlipped),
+        tr(Msg::AdSlippedMessage),
+        &[NotificationAction::UpdateFilters],
+    );
+}
@@ -1,19 +1,29 @@
 use std::{path::PathBuf, sync::LazyLock};
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 
-use crate::logger;
+use crate::{
+    autostart::AutostartMethod, control::DEFAULT_PAUSE_MINUTES, i18n::Lang, logger::Console,
+};
 
-pub static ARGS: LazyLock<Args> = LazyLock::new(|| {
-    // Try to attach console for printing errors during argument parsing.
-    logger::console::raw::attach();
-
-    Args::parse()
+pub static ARGS: LazyLock<Args> = LazyLock::new(|| match Args::try_parse() {
+    Ok(args) => args,
+    Err(e) => {
+        // Print errors, `--help` and `--version` to the shell the app was started from.
+        let console = Console::attach();
+        let _ = e.print();
+        drop(console);
+        std::process::exit(e.exit_code());
+    }
 });
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Show a console window with debug output.
     #[arg(long)]
     pub console: bool,
@@ -23,22 +33,69 @@ pub struct Args {
     #[arg(conflicts_with("console"))]
     pub no_attach: bool,
 
+    /// Start without console output, start notification or update prompts.
+    /// Implied by `--autostart` unless disabled in the settings.
+    #[arg(long)]
+    #[arg(conflicts_with("console"))]
+    pub silent: bool,
+
+    /// Run without a tray icon, e.g. on kiosk machines or in remote sessions.
+    /// The app is then controlled with the commands, e.g. `exit`.
+    #[arg(long)]
+    pub no_tray: bool,
+
+    /// Language of the user interface, detected from the user's locale by default.
+    #[arg(long, value_enum)]
+    pub lang: Option<Lang>,
+
     /// Level of debug output.
     #[arg(long, value_enum, default_value = "debug")]
     pub log_level: LogLevel,
 
+    /// Level of the output shown in the console, `--log-level` if not set.
+    #[arg(long, value_enum)]
+    pub console_log_level: Option<LogLevel>,
+
+    /// Level of the output written to log files, `--log-level` if not set.
+    #[arg(long, value_enum)]
+    pub file_log_level: Option<LogLevel>,
+
     /// Path to a log file to write to.
     #[arg(long)]
     pub log_file: Option<PathBuf>,
 
     /// Start a new instance of this app even if one is already running.
+    /// Spotify processes hooked by the running instance are left to it.
     #[arg(long)]
     pub ignore_singleton: bool,
 
+    /// Run the app in a child process and restart it with increasing delays if it crashes.
+    #[arg(long)]
+    pub supervise: bool,
+
     /// Exit program once spotify is closed, will wait for spotify to start if not currently running.
     #[arg(long)]
     pub shutdown_with_spotify: bool,
 
+    /// Inject into Spotify even if its executable is not signed by Spotify, e.g. for patched clients.
+    #[arg(long)]
+    pub skip_spotify_verification: bool,
+
+    /// Treat processes of the given executable as Spotify and start it, e.g. `mock-spotify.exe` from
+    /// the test harness, to exercise hooking without a Spotify installation. Implies
+    /// `--skip-spotify-verification`.
+    #[arg(long, value_name = "EXE")]
+    pub dev_target: Option<PathBuf>,
+
+    /// Record all traffic received from the blocker to the given file, which can be attached to bug
+    /// reports and played back with `replay-rpc`.
+    #[arg(long, value_name = "FILE")]
+    pub record_rpc: Option<PathBuf>,
+
+    /// Inject a blocker passed with `--blocker` even if it is not signed by the publisher of this app.
+    #[arg(long)]
+    pub allow_unsigned_blocker: bool,
+
     /// Path to the blocker module.
     /// If the file doesn't exist it will be created with the default blocker.
     /// If not specified the app will try to find it in the same directory as the app with name `burnt-sushi-blocker-x86.dll` or write it to a temp file.
@@ -51,6 +108,10 @@ pub struct Args {
     #[arg(long)]
     pub filters: Option<PathBuf>,
 
+    /// Load the filters, run the `[[tests]]` of the filter config against them and exit.
+    #[arg(long)]
+    pub check_filters: bool,
+
     #[arg(long, hide = true)]
     pub install: bool,
 
@@ -68,9 +129,177 @@ pub struct Args {
 
     #[arg(long, hide = true)]
     pub force_restart: bool,
+
+    /// Set by the watchdog of `--supervise` on the instance it runs.
+    #[arg(long, hide = true)]
+    pub supervised: bool,
+}
+
+/// Commands that are executed instead of starting the app.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Bundle logs, settings, crash dumps and a system summary into a zip archive.
+    CollectLogs {
+        /// Path of the archive, defaults to a timestamped file on the desktop.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Save settings, stats, scripts, filter history and cached filter lists to a zip archive.
+    Backup {
+        /// Path of the archive to create.
+        output: PathBuf,
+    },
+    /// Replace settings, stats, filter history and cached filter lists with a backup.
+    Restore {
+        /// Path of an archive created by `backup`.
+        input: PathBuf,
+        /// Also replace the scripts with the ones in the backup.
+        #[arg(long)]
+        scripts: bool,
+    },
+    /// Convert a filter config between TOML, JSON and YAML, picked by the file extensions.
+    ConvertFilters {
+        /// Path of the filter config to read.
+        input: PathBuf,
+        /// Path of the converted filter config to write.
+        output: PathBuf,
+    },
+    /// Play back blocker traffic recorded with `--record-rpc` and print the requests with the rules
+    /// they are attributed to.
+    ReplayRpc {
+        /// Path of the recording.
+        recording: PathBuf,
+    },
+    /// Configure starting the app on logon.
+    Autostart {
+        #[command(subcommand)]
+        action: AutostartAction,
+    },
+    /// Print a diagnostics report of the running instance for bug reports.
+    Diagnostics,
+    /// Stop the running instance and eject the blocker from every process it is loaded into, without
+    /// restarting Spotify.
+    EjectAll,
+    /// Stop the running instance.
+    Exit,
+    /// Review the recorded changes of the effective filter rules or go back to previous rules.
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Pause ad blocking of the running instance.
+    Pause {
+        #[arg(default_value_t = DEFAULT_PAUSE_MINUTES)]
+        minutes: u64,
+    },
+    /// Hook the process with the given id in the running instance, e.g. a Spotify that is not
+    /// detected automatically. It is checked like any detected Spotify before injecting.
+    Inject {
+        #[arg(long)]
+        pid: u32,
+    },
+    /// Unhook the running instance from Spotify until Spotify is restarted, e.g. to debug playback
+    /// issues.
+    PauseUntilRestart,
+    /// Reload the filters of the running instance from all providers.
+    Reload,
+    /// Manage the service that starts the app in every user session at boot.
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Change the log level of the running instance.
+    SetLogLevel {
+        #[arg(value_enum)]
+        level: LogLevel,
+    },
+    /// Open a console window of the running instance showing the requests seen by the blocker.
+    ShowActivity,
+    /// Inspect, send or discard reports of previous crashes.
+    CrashReport {
+        #[command(subcommand)]
+        action: CrashReportAction,
+    },
+    /// Preview, enable or disable the opt-in telemetry about blocker efficacy.
+    Telemetry {
+        #[command(subcommand)]
+        action: TelemetryAction,
+    },
+    /// Authorize access to the Spotify Web API, used to verify that no ads are played.
+    SpotifyLogin,
+    /// Stop the app, eject blockers and remove autostart entries, extracted files and settings.
+    Uninstall {
+        /// Also remove log files and crash dumps.
+        #[arg(long)]
+        remove_logs: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum HistoryAction {
+    /// List the recorded changes.
+    List,
+    /// Use the rules of a recorded change instead of the filter sources.
+    Revert {
+        /// Number of the change as shown by `history list`.
+        id: usize,
+    },
+    /// Follow the filter sources again after `history revert`.
+    Unpin,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AutostartAction {
+    /// Start the app on logon.
+    Enable {
+        #[arg(long, value_enum, default_value = "run-key")]
+        method: AutostartMethod,
+        /// Delay in seconds after logon before the app is started, only used by scheduled tasks.
+        #[arg(long, default_value_t = 30)]
+        delay: u64,
+        /// Start the app with `--supervise`, so that it is restarted if it crashes.
+        #[arg(long)]
+        supervise: bool,
+    },
+    /// Do not start the app on logon.
+    Disable,
+    /// Show how the app is started on logon.
+    Status,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CrashReportAction {
+    /// Print exactly what would be sent.
+    View,
+    /// Upload the pending crash reports.
+    Send,
+    /// Mark the pending crash reports as handled without sending them.
+    Discard,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TelemetryAction {
+    /// Print exactly what the running instance would send next.
+    Preview,
+    /// Send aggregate counters with the Spotify and blocker versions.
+    Enable,
+    /// Stop collecting and sending telemetry.
+    Disable,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ServiceAction {
+    /// Install and start the service.
+    Install,
+    /// Stop and remove the service.
+    Uninstall,
+    /// Entry point used by the service control manager.
+    #[command(hide = true)]
+    Run,
 }
 
-#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Off,
     Trace,
@@ -81,6 +310,26 @@ pub enum LogLevel {
 }
 
 impl LogLevel {
+    pub const ALL: [LogLevel; 6] = [
+        LogLevel::Off,
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
     pub fn into_level_filter(self) -> log::LevelFilter {
         match self {
             LogLevel::Off => log::LevelFilter::Off,
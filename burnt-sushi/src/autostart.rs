@@ -0,0 +1,188 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use log::debug;
+use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+
+use crate::{
+    environment::{Environment, System},
+    APP_NAME,
+};
+
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+const TASK_NAME: &str = APP_NAME;
+
+/// How the app is started on logon.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutostartMethod {
+    /// An entry in the `Run` registry key of the current user.
+    RunKey,
+    /// A scheduled task that starts delayed after logon with the highest available privileges.
+    Task,
+}
+
+/// Registers the app to start on logon, replacing any other registration. With `supervise` the app
+/// is started under the watchdog that restarts it after crashes.
+pub fn enable(method: AutostartMethod, delay_secs: u64, supervise: bool) -> anyhow::Result<()> {
+    let exe = env::current_exe().context("Failed to locate current executable.")?;
+    let args = if supervise {
+        "--autostart --supervise"
+    } else {
+        "--autostart"
+    };
+    disable()?;
+    match method {
+        AutostartMethod::RunKey => enable_run_key(&exe, args),
+        AutostartMethod::Task => enable_task(&exe, args, delay_secs),
+    }
+}
+
+/// Removes all autostart registrations.
+pub fn disable() -> anyhow::Result<()> {
+    match run_key()?.delete_value(APP_NAME) {
+        Ok(()) => debug!("Removed autostart registry entry"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("Failed to remove autostart registry entry."),
+    }
+
+    if task_exists() {
+        schtasks(&["/Delete", "/TN", TASK_NAME, "/F"])
+            .context("Failed to remove scheduled task.")?;
+        debug!("Removed autostart task");
+    }
+
+    Ok(())
+}
+
+/// Returns the currently registered autostart method, if any.
+pub fn status() -> anyhow::Result<Option<AutostartMethod>> {
+    if task_exists() {
+        return Ok(Some(AutostartMethod::Task));
+    }
+    let entry = System
+        .user_registry_value(RUN_KEY, APP_NAME)
+        .context("Failed to read autostart registry entry.")?;
+    Ok(entry.map(|_| AutostartMethod::RunKey))
+}
+
+/// The `Run` registry key of the current user.
+pub fn run_key() -> anyhow::Result<RegKey> {
+    let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(RUN_KEY)
+        .context("Failed to open Run registry key.")?;
+    Ok(key)
+}
+
+fn enable_run_key(exe: &Path, args: &str) -> anyhow::Result<()> {
+    run_key()?
+        .set_value(APP_NAME, &format!("\"{}\" {args}", exe.display()))
+        .context("Failed to write autostart registry entry.")?;
+    debug!("Added autostart registry entry");
+    Ok(())
+}
+
+fn enable_task(exe: &Path, args: &str, delay_secs: u64) -> anyhow::Result<()> {
+    let xml = task_xml(exe, args, delay_secs);
+
+    // schtasks expects the task definition as UTF-16 with a byte order mark.
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(xml.encode_utf16().flat_map(|c| c.to_le_bytes()));
+    let xml_path = env::temp_dir().join(format!("{APP_NAME}-task.xml"));
+    fs::write(&xml_path, bytes).context("Failed to write task definition.")?;
+
+    let result = schtasks(&[
+        "/Create",
+        "/TN",
+        TASK_NAME,
+        "/XML",
+        &xml_path.to_string_lossy(),
+        "/F",
+    ]);
+    let _ = fs::remove_file(&xml_path);
+    result.context(
+        "Failed to create scheduled task (creating it may require administrator rights).",
+    )?;
+
+    debug!("Added autostart task");
+    Ok(())
+}
+
+fn task_xml(exe: &Path, args: &str, delay_secs: u64) -> String {
+    let user = env::var("USERDOMAIN")
+        .ok()
+        .zip(env::var("USERNAME").ok())
+        .map(|(domain, user)| format!("{domain}\\{user}"))
+        .unwrap_or_default();
+    let exe = xml_escape(&exe.to_string_lossy());
+    let user = xml_escape(&user);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+      <Delay>PT{delay_secs}S</Delay>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>HighestAvailable</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <Priority>7</Priority>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{exe}</Command>
+      <Arguments>{args}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#
+    )
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn task_exists() -> bool {
+    schtasks(&["/Query", "/TN", TASK_NAME]).is_ok()
+}
+
+fn schtasks(args: &[&str]) -> anyhow::Result<()> {
+    let system_root = env::var_os("SystemRoot").unwrap_or_else(|| r"C:\Windows".into());
+    let output = Command::new(
+        PathBuf::from(system_root)
+            .join("System32")
+            .join("schtasks.exe"),
+    )
+    .args(args)
+    .output()
+    .context("Failed to run schtasks.")?;
+    if !output.status.success() {
+        bail!(
+            "schtasks failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
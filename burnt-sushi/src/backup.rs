@@ -0,0 +1,277 @@
+//! Backup of the user data to a zip archive and restoring it, e.g. when moving to another machine.
+//!
+//! The archive contains the app data directory under `data/` except for logs, crash dumps, the
+//! Spotify Web API token and state that only applies to the current machine, and the filter config
+//! next to the executable as `filter.toml`.
+
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use chrono::Local;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    control::{self, ControlCommand},
+    environment::System,
+    paths, resolver, APP_VERSION,
+};
+
+const MANIFEST_NAME: &str = "backup.toml";
+const DATA_DIR_NAME: &str = "data";
+const FILTER_CONFIG_NAME: &str = "filter.toml";
+
+/// Describes the archive, also used to recognize it when restoring.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: String,
+    created: String,
+}
+
+/// Writes the user data to a zip archive and returns the number of files written.
+pub fn backup(output: &Path) -> anyhow::Result<usize> {
+    let data_dir = paths::data_dir(&System).context("Failed to locate app data directory.")?;
+
+    let file = File::create(output).context("Failed to create archive.")?;
+    let mut archive = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let manifest = Manifest {
+        version: APP_VERSION.to_string(),
+        created: Local::now().to_rfc3339(),
+    };
+    archive
+        .start_file(MANIFEST_NAME, options)
+        .context("Failed to write manifest.")?;
+    archive
+        .write_all(
+            toml::to_string_pretty(&manifest)
+                .context("Failed to serialize manifest.")?
+                .as_bytes(),
+        )
+        .context("Failed to write manifest.")?;
+
+    let mut count = 0;
+    for path in data_files(&data_dir) {
+        let relative = path.strip_prefix(&data_dir).unwrap();
+        let name = Path::new(DATA_DIR_NAME).join(relative);
+        count += add_file(&mut archive, &path, &name, options)? as usize;
+    }
+    if let Some(filters) = resolver::filter_config_path(&System).filter(|path| path.is_file()) {
+        count += add_file(
+            &mut archive,
+            &filters,
+            Path::new(FILTER_CONFIG_NAME),
+            options,
+        )? as usize;
+    }
+
+    archive.finish().context("Failed to finish archive.")?;
+    Ok(count)
+}
+
+/// Replaces the user data with the contents of an archive written by [`backup`] and returns the
+/// number of files restored. Files of a backup that are missing from the archive are removed, the
+/// scripts are only replaced if `include_scripts` is set. Refuses to run while the app is running,
+/// as it would overwrite the restored files.
+pub async fn restore(input: &Path, include_scripts: bool) -> anyhow::Result<usize> {
+    if control::send(&ControlCommand::Version).await.is_ok() {
+        bail!("Exit the running instance before restoring.");
+    }
+    let data_dir = paths::data_dir(&System).context("Failed to locate app data directory.")?;
+
+    let file = File::open(input).context("Failed to open archive.")?;
+    let mut archive = ZipArchive::new(file).context("Failed to read archive.")?;
+    let manifest = {
+        let mut entry = archive
+            .by_name(MANIFEST_NAME)
+            .context("Not a backup of this app.")?;
+        let mut contents = String::new();
+        entry
+            .read_to_string(&mut contents)
+            .context("Failed to read manifest.")?;
+        toml::from_str::<Manifest>(&contents).context("Failed to parse manifest.")?
+    };
+    debug!(
+        "Restoring backup of version {} from {}",
+        manifest.version, manifest.created
+    );
+
+    // Extracted next to the data directory first, so that a damaged archive leaves it untouched.
+    let staging_dir = data_dir.with_extension("restore");
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)
+            .with_context(|| format!("Failed to remove '{}'.", staging_dir.display()))?;
+    }
+    let scripts_dir = paths::scripts_dir(&System);
+    let is_kept_script = |relative: &Path| {
+        !include_scripts
+            && scripts_dir
+                .as_ref()
+                .and_then(|dir| dir.strip_prefix(&data_dir).ok())
+                .is_some_and(|scripts| relative.starts_with(scripts))
+    };
+
+    let mut staged = Vec::new();
+    let mut filter_config = None;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).context("Failed to read archive.")?;
+        if entry.is_dir() {
+            continue;
+        }
+        // Rejects absolute paths and paths leaving the archive.
+        let Some(name) = entry.enclosed_name() else {
+            warn!("Skipping '{}'", entry.name());
+            continue;
+        };
+
+        let staged_path = if let Ok(relative) = name.strip_prefix(DATA_DIR_NAME) {
+            if is_kept_script(relative) {
+                debug!("Keeping current script instead of '{}'", name.display());
+                continue;
+            }
+            staged.push(relative.to_path_buf());
+            staging_dir.join(DATA_DIR_NAME).join(relative)
+        } else if name == Path::new(FILTER_CONFIG_NAME) {
+            let path = staging_dir.join(FILTER_CONFIG_NAME);
+            filter_config = Some(path.clone());
+            path
+        } else {
+            continue;
+        };
+        extract(&mut entry, &staged_path)?;
+    }
+
+    // Files of the current data that belong into a backup are replaced by the restored ones, the
+    // others only apply to this machine and are kept.
+    for path in data_files(&data_dir) {
+        let relative = path.strip_prefix(&data_dir).unwrap();
+        if is_kept_script(relative) {
+            continue;
+        }
+        debug!("Removing '{}'", path.display());
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove '{}'.", path.display()))?;
+    }
+    for relative in &staged {
+        let target = data_dir.join(relative);
+        debug!("Restoring '{}'", target.display());
+        move_file(&staging_dir.join(DATA_DIR_NAME).join(relative), &target)?;
+    }
+    let mut count = staged.len();
+    if let Some((staged_path, target)) = filter_config.zip(resolver::filter_config_path(&System)) {
+        debug!("Restoring '{}'", target.display());
+        move_file(&staged_path, &target)?;
+        count += 1;
+    }
+
+    let _ = fs::remove_dir_all(&staging_dir);
+    Ok(count)
+}
+
+fn extract(entry: &mut impl Read, target: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create '{}'.", dir.display()))?;
+    }
+    let mut file = File::create(target)
+        .with_context(|| format!("Failed to create '{}'.", target.display()))?;
+    io::copy(entry, &mut file)
+        .with_context(|| format!("Failed to write '{}'.", target.display()))?;
+    Ok(())
+}
+
+/// Moves a file, replacing the target. Copies it if the target is on another volume, which is
+/// possible for the filter config next to the executable.
+fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create '{}'.", dir.display()))?;
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).with_context(|| format!("Failed to write '{}'.", to.display()))?;
+    }
+    Ok(())
+}
+
+/// Files in the app data directory that belong into a backup.
+fn data_files(data_dir: &Path) -> Vec<PathBuf> {
+    let excluded = [
+        paths::crash_dir(&System),
+        paths::web_api_token_file(&System),
+        // Refers to the Spotify installation on this machine.
+        paths::spotify_autostart_file(&System),
+    ];
+    let log_stem = paths::log_file(&System).and_then(|path| path.file_stem().map(OsStr::to_owned));
+
+    let mut files = Vec::new();
+    collect_files(data_dir, &mut files);
+    files.sort();
+    files.retain(|path| {
+        let is_log = path.parent() == Some(data_dir)
+            && log_stem
+                .as_ref()
+                .zip(path.file_name())
+                .is_some_and(|(stem, name)| {
+                    name.to_string_lossy().starts_with(&*stem.to_string_lossy())
+                });
+        !is_log
+            && !excluded
+                .iter()
+                .flatten()
+                .any(|excluded| path.starts_with(excluded))
+    });
+    files
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+}
+
+/// Adds a file to the archive, returns `false` if it could not be read.
+fn add_file(
+    archive: &mut ZipWriter<File>,
+    path: &Path,
+    name: &Path,
+    options: SimpleFileOptions,
+) -> anyhow::Result<bool> {
+    // Zip archives use forward slashes regardless of the platform.
+    let name = name
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Skipping '{}': {e}", path.display());
+            return Ok(false);
+        }
+    };
+
+    debug!("Adding '{}' as '{name}'", path.display());
+    archive
+        .start_file(name, options)
+        .context("Failed to add file to archive.")?;
+    io::copy(&mut file, archive).context("Failed to add file to archive.")?;
+    Ok(true)
+}
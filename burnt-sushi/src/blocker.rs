@@ -1,71 +1,381 @@
-use std::{mem, net::SocketAddrV4};
+use std::{
+    fmt, io,
+    path::PathBuf,
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
 
-use anyhow::Context;
+use burnt_sushi_core::{
+    conflicts,
+    error::{Error, FailureReason, HealthError, Result},
+    filters::FilterConfig,
+    health::{self, HealthMonitor},
+    hook_claim::HookClaim,
+    injector::{self, InjectedBlocker},
+    integrity::IntegrityMismatch,
+    metrics::METRICS,
+    rpc::RpcExit,
+    spotify_process_scanner::{SpotifyInfo, SpotifyProcessScanner, SpotifyState},
+    spotify_verification,
+};
+use chrono::{Local, TimeDelta};
 use dll_syringe::{
-    error::SyringeError,
-    process::{OwnedProcessModule, Process},
+    process::{OwnedProcess, Process},
     Syringe,
 };
+use futures::future;
 use log::{debug, error, info, warn};
-use serde::Deserialize;
-use tokio::{runtime, task::LocalSet};
+use tokio::{
+    sync::Notify,
+    time::{Instant, MissedTickBehavior},
+};
 
 use crate::{
+    accessibility,
     args::ARGS,
-    resolver::{resolve_blocker, resolve_filter_config},
-    rpc,
-    spotify_process_scanner::{SpotifyInfo, SpotifyProcessScanner, SpotifyState},
-    DEFAULT_BLOCKER_FILE_NAME,
+    canary,
+    control::{self, ControlCommand, ControlRequest},
+    diagnostics,
+    environment::System,
+    events::{self, AppEvent},
+    filter_history::{self, ChangeSource},
+    filter_providers, filter_tests,
+    i18n::{tr, tr_args, Msg},
+    lifecycle::{HookPhase, HookState},
+    logger, media,
+    notify::{self, NotificationAction},
+    power, preparation,
+    request_log::RequestLog,
+    resolver::{resolve_blocker, verify_provided_blocker},
+    scripting, session,
+    settings::{self, ConflictPolicy},
+    shutdown::{self, ShutdownReason},
+    stats,
+    status::{self, HookStatus, LastError, SpotifyStatus},
+    sweep, telemetry, utils, APP_VERSION,
 };
 
-pub struct SpotifyAdBlocker {
-    scanner: SpotifyProcessScanner,
-    spotify_state: tokio::sync::watch::Receiver<SpotifyState>,
-    state: SpotifyHookState,
+const MAX_HOOK_ATTEMPTS: u32 = 3;
+/// Number of log messages returned by [`ControlCommand::Events`].
+const RECENT_EVENTS: usize = 20;
+/// Time the instance owning the control channel has to tell which process it hooked.
+const OTHER_INSTANCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+static REHOOK_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+/// Blocker injected last, to notice when it is removed while the app runs, e.g. by cleanup tools
+/// or an antivirus.
+static INJECTED_BLOCKER_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+/// Rules of the last successful load, injected with the blocker so that hooking never waits for
+/// the providers, e.g. a slow download of a remote list.
+static LOADED_FILTERS: Mutex<Option<FilterConfig>> = Mutex::new(None);
+/// Cause of a filter update requested while no blocker was injected, sent after the next hook.
+static DEFERRED_UPDATE: Mutex<Option<ChangeSource>> = Mutex::new(None);
+
+/// Asks the blocker to eject and re-inject, e.g. after the blocker module was updated.
+pub fn request_rehook() {
+    REHOOK_REQUESTED.notify_one();
+}
+
+static PAUSE_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+static PAUSE_LENGTH: Mutex<Option<PauseLength>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+enum PauseLength {
+    For(Duration),
+    UntilSpotifyRestarts,
+}
+
+/// Ad blocking is paused until the given instant or until Spotify exits.
+#[derive(Debug, Clone, Copy)]
+enum Paused {
+    Until(Instant),
+    UntilSpotifyRestarts,
 }
 
-#[allow(clippy::large_enum_variant)]
-enum SpotifyHookState {
-    Hooked(HookState),
-    Unhooked,
+/// Asks the blocker to unhook Spotify and to hook it again after the given duration.
+pub fn pause(duration: Duration) {
+    *PAUSE_LENGTH.lock().unwrap() = Some(PauseLength::For(duration));
+    PAUSE_REQUESTED.notify_one();
 }
 
-struct HookState {
-    syringe: Syringe,
-    payload: OwnedProcessModule,
-    rpc_task: async_thread::JoinHandle<()>,
+/// Asks the blocker to unhook Spotify and to stay dormant until the next Spotify launch.
+pub fn pause_until_restart() {
+    *PAUSE_LENGTH.lock().unwrap() = Some(PauseLength::UntilSpotifyRestarts);
+    PAUSE_REQUESTED.notify_one();
+}
+const HOOK_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Time the Spotify state has to stay unchanged before it is acted on, as Spotify restarts within
+/// a second while updating.
+const SPOTIFY_STATE_SETTLE_TIME: Duration = Duration::from_secs(1);
+/// Delays between the scheduled attempts after hooking failed, the last one repeats.
+const SOFT_FAIL_RETRY_DELAYS: [Duration; 4] = [
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+    Duration::from_secs(60 * 60),
+];
+
+/// Failure to hook Spotify that is retried on a schedule instead of giving up until Spotify is
+/// restarted.
+#[derive(Debug)]
+struct SoftFailure {
+    reason: FailureReason,
+    failures: usize,
+    retry_at: Option<Instant>,
+}
+
+static SOFT_FAILURE: Mutex<Option<SoftFailure>> = Mutex::new(None);
+
+pub struct SpotifyAdBlocker {
+    scanner: SpotifyProcessScanner,
+    spotify_state: tokio::sync::watch::Receiver<SpotifyState>,
+    control_requests: tokio::sync::mpsc::Receiver<ControlRequest>,
+    state: HookState,
 }
 
 impl SpotifyAdBlocker {
-    pub fn new() -> Self {
+    pub fn new(control_requests: tokio::sync::mpsc::Receiver<ControlRequest>) -> Self {
         let (scanner, spotify_state) = SpotifyProcessScanner::new();
         Self {
             scanner,
             spotify_state,
-            state: SpotifyHookState::Unhooked,
+            control_requests,
+            state: HookState::Idle,
         }
     }
 
     pub async fn run(&mut self) {
+        let Self {
+            scanner,
+            spotify_state,
+            control_requests,
+            state,
+        } = self;
+
+        let mut health_monitor = HealthMonitor::new();
+        let mut health_check = tokio::time::interval(health::HEALTH_CHECK_INTERVAL);
+        health_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut blocker_sweep = tokio::time::interval_at(
+            Instant::now() + sweep::SWEEP_INTERVAL,
+            sweep::SWEEP_INTERVAL,
+        );
+        blocker_sweep.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut power_changes = power::subscribe();
+        let mut paused: Option<Paused> = None;
+
         tokio::select! {
-            _ = self.scanner.run() => {
+            _ = scanner.run() => {
                 unreachable!("Spotify scanner should never stop on its own");
             }
             _ = async {
                 info!("Looking for Spotify...");
-                while self.spotify_state.changed().await.is_ok() {
-                    let state = self.spotify_state.borrow();
-                    match &*state {
-                        SpotifyState::Running(spotify) => {
-                            self.state.hook_spotify(spotify.try_clone().unwrap()).await.unwrap();
-                        },
-                        SpotifyState::Stopped => {
-                            self.state.unhook_spotify().await;
-                            if ARGS.shutdown_with_spotify {
-                                info!("Shutting down due to spotify exit...");
+                loop {
+                    // Checked between events only, so hooking and unhooking are never interrupted.
+                    tokio::select! {
+                        _ = shutdown::requested() => {
+                            break;
+                        }
+                        changed = spotify_state.changed() => {
+                            if changed.is_err() || !settle(spotify_state).await {
                                 break;
                             }
-                            info!("Looking for Spotify...");
+                            let spotify = match spotify_state.borrow_and_update().try_clone() {
+                                Ok(spotify) => spotify,
+                                Err(e) => {
+                                    warn!("Failed to access Spotify process: {e}");
+                                    continue;
+                                }
+                            };
+                            match spotify {
+                                SpotifyState::Running(_) if paused.is_some() => {
+                                    debug!("Not hooking Spotify while paused");
+                                },
+                                SpotifyState::Running(spotify) if state.is_at(&spotify) => {
+                                    debug!("Spotify flapped back to the process it was at");
+                                },
+                                SpotifyState::Running(spotify) => {
+                                    state.hook_spotify_with_retry(spotify).await;
+                                },
+                                SpotifyState::Stopped => {
+                                    state.unhook_spotify().await;
+                                    let _ = state.spotify_exited();
+                                    clear_soft_failure();
+                                    health_monitor.reset_rehooks();
+                                    if matches!(paused, Some(Paused::UntilSpotifyRestarts)) {
+                                        info!("Spotify exited, blocking resumes on its next launch");
+                                        paused = None;
+                                    }
+                                    if paused.is_none() {
+                                        status::set_hook(HookStatus::Searching);
+                                    }
+                                    if ARGS.shutdown_with_spotify {
+                                        shutdown::request(ShutdownReason::SpotifyExited);
+                                        break;
+                                    }
+                                    info!("Looking for Spotify...");
+                                }
+                            }
+                        }
+                        Some(request) = control_requests.recv() => {
+                            let response = match request.command {
+                                ControlCommand::Inject(pid) => {
+                                    state.inject_manually(pid, paused.is_some()).await
+                                }
+                                ref command => handle_control_command(command),
+                            };
+                            let _ = request.response.send(response);
+                            match request.command {
+                                ControlCommand::Handoff => {
+                                    shutdown::request(ShutdownReason::Handoff);
+                                    break;
+                                }
+                                ControlCommand::Exit => {
+                                    shutdown::request(ShutdownReason::Requested);
+                                    break;
+                                }
+                                _ => {}
+                            }
+                        }
+                        _ = REHOOK_REQUESTED.notified() => {
+                            if paused.is_some() {
+                                debug!("Ignoring rehook request while paused");
+                                continue;
+                            }
+                            match state.phase() {
+                                HookPhase::Running | HookPhase::Degraded => state.rehook().await,
+                                // A previous attempt failed, try again.
+                                HookPhase::Detected => state.inject_with_retry().await,
+                                _ => {
+                                    if let Some(spotify) = running_spotify(spotify_state) {
+                                        state.hook_spotify_with_retry(spotify).await;
+                                    }
+                                }
+                            }
+                        }
+                        _ = PAUSE_REQUESTED.notified() => {
+                            let Some(length) = PAUSE_LENGTH.lock().unwrap().take() else {
+                                continue;
+                            };
+                            paused = Some(match length {
+                                PauseLength::For(duration) => {
+                                    let minutes = duration.as_secs() / 60;
+                                    let Some(until) = Instant::now().checked_add(duration) else {
+                                        warn!("Not pausing ad blocking for {minutes} minutes, which is too long");
+                                        continue;
+                                    };
+                                    info!("Pausing ad blocking for {minutes} minutes");
+                                    Paused::Until(until)
+                                }
+                                PauseLength::UntilSpotifyRestarts => {
+                                    // Would otherwise skip the launch it should resume on.
+                                    if *spotify_state.borrow() == SpotifyState::Stopped {
+                                        info!("Not pausing ad blocking as Spotify is not running");
+                                        continue;
+                                    }
+                                    info!("Pausing ad blocking until Spotify restarts");
+                                    Paused::UntilSpotifyRestarts
+                                }
+                            });
+                            state.unhook_spotify().await;
+                            status::set_hook(HookStatus::Paused);
+                        }
+                        _ = async {
+                            match paused {
+                                Some(Paused::Until(until)) => tokio::time::sleep_until(until).await,
+                                _ => future::pending().await,
+                            }
+                        } => {
+                            info!("Resuming ad blocking");
+                            paused = None;
+                            status::set_hook(HookStatus::Searching);
+                            if let Some(spotify) = running_spotify(spotify_state) {
+                                state.hook_spotify_with_retry(spotify).await;
+                            }
+                        }
+                        _ = async {
+                            match scheduled_retry() {
+                                Some(retry_at) => tokio::time::sleep_until(retry_at).await,
+                                None => future::pending().await,
+                            }
+                        } => {
+                            take_scheduled_retry();
+                            if paused.is_none() && state.phase() == HookPhase::Detected {
+                                info!("Retrying to hook Spotify");
+                                state.inject_with_retry().await;
+                            }
+                        }
+                        _ = preparation::ready(), if !preparation::is_ready() => {
+                            if paused.is_none() && state.phase() == HookPhase::Detected {
+                                state.inject_with_retry().await;
+                            }
+                        }
+                        _ = scripting::rules_changed() => {
+                            state.update_filters(ChangeSource::Script).await;
+                        }
+                        source = filter_providers::refresh_requested() => {
+                            state.update_filters(source).await;
+                        }
+                        _ = async {
+                            match health_monitor.scheduled_rehook() {
+                                Some(rehook_at) => {
+                                    tokio::time::sleep_until(Instant::from_std(rehook_at)).await
+                                }
+                                None => future::pending().await,
+                            }
+                        } => {
+                            health_monitor.take_scheduled_rehook();
+                            if paused.is_some() {
+                                continue;
+                            }
+                            METRICS.reinjections.inc();
+                            match state.phase() {
+                                HookPhase::Running | HookPhase::Degraded => state.rehook().await,
+                                HookPhase::Detected => state.inject_with_retry().await,
+                                _ => {}
+                            }
+                        }
+                        exit = state.rpc_stopped() => {
+                            state.handle_rpc_exit(exit, &mut health_monitor).await;
+                        }
+                        _ = health_check.tick() => {
+                            if power::is_low_power() {
+                                continue;
+                            }
+                            state.monitor_health(&mut health_monitor).await;
+                        }
+                        _ = blocker_sweep.tick() => {
+                            if power::is_low_power() {
+                                continue;
+                            }
+                            let hooked_pid = state
+                                .hook()
+                                .and_then(|hook| hook.spotify.process.pid().ok())
+                                .map(|pid| pid.get());
+                            sweep::run(hooked_pid).await;
+                        }
+                        _ = power::resumed() => {
+                            if paused.is_some() {
+                                continue;
+                            }
+                            match state.phase() {
+                                HookPhase::Running | HookPhase::Degraded => {
+                                    state.verify_after_resume().await
+                                }
+                                HookPhase::Detected => state.inject_with_retry().await,
+                                _ => {
+                                    if let Some(spotify) = running_spotify(spotify_state) {
+                                        state.hook_spotify_with_retry(spotify).await;
+                                    }
+                                }
+                            }
+                            health_check.reset();
+                        }
+                        _ = power_changes.changed() => {
+                            // Catch up on the checks skipped while saving power.
+                            if !power::is_low_power() {
+                                state.monitor_health(&mut health_monitor).await;
+                                health_check.reset();
+                            }
                         }
                     }
                 }
@@ -74,141 +384,762 @@ impl SpotifyAdBlocker {
     }
 
     pub async fn stop(&mut self) {
-        if matches!(self.state, SpotifyHookState::Hooked(_)) {
-            self.state.unhook_spotify().await;
+        self.state.unhook_spotify().await;
+        media::unmute().await;
+    }
+}
+
+/// Adds the outcome of hooking the found Spotify to its version history and the telemetry.
+fn record_spotify_version(hooked: bool) {
+    telemetry::record_hook(hooked);
+    let version = status::get()
+        .spotify
+        .as_ref()
+        .and_then(|spotify| spotify.version.clone());
+    if let Some(version) = version {
+        stats::get().record_hook(&version, hooked);
+    }
+}
+
+/// Answers the commands that do not need the hook state, `Inject` is handled by the caller.
+fn handle_control_command(command: &ControlCommand) -> String {
+    match command {
+        ControlCommand::Diagnostics => diagnostics::report(),
+        ControlCommand::SetLogLevel(level) => {
+            logger::global::set_level(*level);
+            format!("Log level set to {}", level.name())
+        }
+        ControlCommand::Version => APP_VERSION.to_string(),
+        ControlCommand::Handoff => "ok".to_string(),
+        ControlCommand::Exit => "Exiting".to_string(),
+        ControlCommand::Status => diagnostics::status_report(),
+        ControlCommand::HookedPid => {
+            let status = status::get();
+            let pid = match status.hook {
+                HookStatus::Hooking | HookStatus::Hooked => {
+                    status.spotify.as_ref().and_then(|spotify| spotify.pid)
+                }
+                _ => None,
+            };
+            pid.map_or_else(|| "none".to_string(), |pid| pid.to_string())
+        }
+        ControlCommand::Reload => {
+            filter_providers::request_refresh();
+            "Reloading filters".to_string()
+        }
+        ControlCommand::Pause(minutes) => match minutes.checked_mul(60) {
+            Some(secs) => {
+                pause(Duration::from_secs(secs));
+                format!("Pausing ad blocking for {minutes} minutes")
+            }
+            None => format!("Cannot pause ad blocking for {minutes} minutes"),
+        },
+        ControlCommand::PauseUntilRestart => {
+            pause_until_restart();
+            "Pausing ad blocking until Spotify restarts".to_string()
+        }
+        ControlCommand::Inject(pid) => {
+            format!("Cannot inject into process {pid}, manual injection is not handled here")
+        }
+        ControlCommand::TelemetryPreview => {
+            telemetry::preview().unwrap_or_else(|e| format!("Failed to preview telemetry: {e:#}"))
+        }
+        ControlCommand::SetTelemetry(enabled) => match telemetry::set_enabled(*enabled) {
+            Ok(()) if *enabled => "Telemetry enabled".to_string(),
+            Ok(()) => "Telemetry disabled".to_string(),
+            Err(e) => format!("Failed to save settings: {e:#}"),
+        },
+        ControlCommand::ShowActivity => match logger::global::show_console() {
+            Ok(()) => "Showing activity".to_string(),
+            Err(e) => format!("Failed to open console: {e}"),
+        },
+        ControlCommand::Events => {
+            let log = logger::global::get();
+            let messages = log.recent.messages().collect::<Vec<_>>();
+            messages[messages.len().saturating_sub(RECENT_EVENTS)..].join("\n")
         }
     }
 }
 
-impl SpotifyHookState {
-    async fn hook_spotify(&mut self, spotify: SpotifyInfo) -> anyhow::Result<()> {
-        if let SpotifyHookState::Hooked(_) = self {
+impl HookState {
+    async fn monitor_health(&mut self, monitor: &mut HealthMonitor) {
+        let Some(hook) = self.hook() else {
+            monitor.record_success();
+            return;
+        };
+
+        let err = match hook.blocker.check_health().await {
+            Ok(()) => {
+                match hook.blocker.perf_stats().await {
+                    Ok(perf) => status::get().blocker_perf = Some(perf),
+                    Err(e) => debug!("Failed to collect blocker perf stats: {e}"),
+                }
+                monitor.record_success();
+                let _ = self.record_health(true);
+                return;
+            }
+            Err(HealthError::ModuleMissing) => {
+                self.handle_ejection(monitor).await;
+                return;
+            }
+            Err(err) => err,
+        };
+
+        warn!("Blocker health check failed: {}", Report(&err));
+        let _ = self.record_health(false);
+        if monitor.record_failure() {
+            notify::error_with_actions(
+                tr(Msg::NotWorking),
+                &tr_args(
+                    Msg::HealthChecksFailed,
+                    &[
+                        ("count", &monitor.consecutive_failures()),
+                        ("error", &Report(&err)),
+                    ],
+                ),
+                &[
+                    NotificationAction::RetryInjection,
+                    NotificationAction::Pause,
+                ],
+            );
+        }
+
+        if monitor.should_rehook() {
+            self.schedule_rehook(monitor).await;
+        }
+    }
+
+    /// Re-injects the blocker after its RPC task stopped while hooked, which would otherwise go
+    /// unnoticed until the next health check.
+    async fn handle_rpc_exit(&mut self, exit: RpcExit, monitor: &mut HealthMonitor) {
+        let Some(hook) = self.hook() else {
+            return;
+        };
+        if !hook.spotify.process.is_alive() {
+            // The scanner reports the exit, the blocker is gone either way.
+            debug!("RPC stopped as Spotify exited: {exit}");
             self.unhook_spotify().await;
+            return;
+        }
+        if !hook.blocker.is_loaded().unwrap_or(true) {
+            debug!("RPC stopped as the blocker was unloaded: {exit}");
+            self.handle_ejection(monitor).await;
+            return;
+        }
+
+        warn!("Lost connection to the blocker: {exit}");
+        let _ = self.rpc_lost();
+        self.schedule_rehook(monitor).await;
+    }
+
+    /// Re-injects the blocker after Spotify unloaded it, e.g. during its crash recovery, and tells
+    /// the user only if it keeps happening. Once it does, the blocker is only injected again on the
+    /// schedule of a failed hook.
+    async fn handle_ejection(&mut self, monitor: &mut HealthMonitor) {
+        warn!("Spotify unloaded the blocker");
+        if monitor.record_ejection() {
+            notify::error_with_actions(
+                tr(Msg::BlockerUnloaded),
+                &tr_args(
+                    Msg::BlockerUnloadedMessage,
+                    &[("count", &monitor.recent_ejections())],
+                ),
+                &[NotificationAction::Pause],
+            );
+        }
+
+        if monitor.ejections_recurring() {
+            warn!("Spotify keeps unloading the blocker, not injecting it again for now");
+            // The user was notified about the unloads already.
+            self.give_up().await;
+            return;
+        }
+        self.schedule_rehook(monitor).await;
+    }
+
+    /// Re-injects the blocker after a delay that grows with each re-injection in a row, or gives up
+    /// on it until the next scheduled attempt to hook Spotify once it keeps failing.
+    async fn schedule_rehook(&mut self, monitor: &mut HealthMonitor) {
+        if monitor.schedule_rehook() {
+            if let Some(rehook_at) = monitor.scheduled_rehook() {
+                let delay = rehook_at.saturating_duration_since(std::time::Instant::now());
+                info!("Re-injecting blocker in {} seconds", delay.as_secs());
+            }
+            return;
+        }
+
+        warn!(
+            "Blocker failed again after {} re-injections in a row, not injecting it again for now",
+            monitor.rehooks()
+        );
+        if self.give_up().await {
+            events::publish(AppEvent::Error {
+                message: "Blocker keeps failing after re-injecting it".to_string(),
+            });
+            notify::error_with_actions(
+                tr(Msg::NotWorking),
+                accessibility::reason_text(FailureReason::Unstable),
+                &[NotificationAction::RetryInjection],
+            );
+        }
+    }
+
+    /// Ejects the blocker and leaves Spotify unhooked until the next scheduled attempt, returns
+    /// whether the user should be notified.
+    async fn give_up(&mut self) -> bool {
+        self.unhook_spotify().await;
+        record_spotify_version(false);
+        soft_fail(FailureReason::Unstable)
+    }
+
+    /// Checks that the hooked process and the RPC connection survived system sleep and re-injects
+    /// the blocker otherwise.
+    async fn verify_after_resume(&mut self) {
+        let Some(hook) = self.hook() else {
+            return;
+        };
+        if !hook.spotify.process.is_alive() {
+            // The scanner reports the exit.
+            debug!("Spotify exited during system sleep");
+            return;
+        }
+
+        match hook.blocker.check_health().await {
+            Ok(()) => {
+                info!("Blocker survived system sleep");
+                let _ = self.record_health(true);
+            }
+            Err(err) => {
+                warn!("Blocker did not survive system sleep: {}", Report(&err));
+                let _ = self.record_health(false);
+                METRICS.reinjections.inc();
+                self.rehook().await;
+            }
+        }
+    }
+
+    /// Sends the current filter rules to the blocker without re-injecting it.
+    async fn update_filters(&mut self, source: ChangeSource) {
+        if self.hook().is_none() {
+            *DEFERRED_UPDATE.lock().unwrap() = Some(source);
+            return;
+        }
+
+        let filter_config = match load_filter_config(source).await {
+            Ok(filter_config) => filter_config,
+            Err(err) => {
+                warn!("Failed to load filter config: {}", Report(&err));
+                return;
+            }
+        };
+
+        let Some(hook) = self.hook_mut() else {
+            return;
+        };
+        match hook.blocker.set_filters(filter_config).await {
+            Ok(()) => debug!("Updated filters ({} rules)", hook.blocker.rule_count()),
+            Err(err) => warn!("Failed to update filters: {}", Report(&err)),
+        }
+    }
+
+    /// Ejects the blocker and injects it again.
+    async fn rehook(&mut self) {
+        if self.hook().is_none() {
+            return;
         }
 
-        match spotify.process.pid().ok() {
-            Some(pid) => info!("Found Spotify (PID={pid})"),
-            None => info!("Found Spotify"),
+        info!("Re-injecting blocker...");
+        self.unhook_spotify().await;
+        self.inject_with_retry().await;
+    }
+
+    /// Hooks the process with the given id as requested with `inject --pid`, bypassing the scanner
+    /// but none of the checks before injecting. Returns the response to the command.
+    async fn inject_manually(&mut self, pid: u32, paused: bool) -> String {
+        if paused {
+            return "Ad blocking is paused, resume it before hooking".to_string();
+        }
+        let hooked_pid = self.hook().and_then(|hook| hook.spotify.process.pid().ok());
+        if hooked_pid.is_some_and(|hooked_pid| hooked_pid.get() == pid) {
+            return format!("Process {pid} is already hooked");
         }
-        let syringe = Syringe::for_process(spotify.process);
 
-        while let Some(prev_payload) = syringe
-            .process()
-            .find_module_by_name(DEFAULT_BLOCKER_FILE_NAME)
-            .context("Failed to inspect modules of Spotify process.")?
+        let process = match OwnedProcess::from_pid(pid) {
+            Ok(process) => process,
+            Err(e) => return format!("Failed to open process {pid}: {e}"),
+        };
+        let spotify = match SpotifyInfo::of_process(process) {
+            Ok(Some(spotify)) => spotify,
+            Ok(None) => return format!("Process {pid} has no Spotify main window"),
+            Err(e) => return format!("Failed to list the windows of process {pid}: {e}"),
+        };
+
+        info!("Hooking process {pid} as requested");
+        let started = Local::now();
+        self.hook_spotify_with_retry(spotify).await;
+        if self.phase() == HookPhase::Running {
+            return format!("Hooked process {pid}");
+        }
+        match status::get()
+            .last_error
+            .as_ref()
+            .filter(|last_error| last_error.time >= started)
         {
-            warn!("Found previously injected blocker");
-
-            debug!("Stopping RPC of previous blocker");
-            let stop_rpc =
-                unsafe { syringe.get_payload_procedure::<fn()>(prev_payload, "stop_rpc") }
-                    .context("Failed to access spotify process.")?
-                    .context("Failed to find stop_rpc in blocker module.")?;
-            match stop_rpc.call() {
-                Ok(_) => {
-                    debug!("Stopped RPC of previous blocker");
+            Some(last_error) => format!("Failed to hook process {pid}: {}", last_error.message),
+            None => format!("Did not hook process {pid}, see the log for details"),
+        }
+    }
+
+    async fn hook_spotify_with_retry(&mut self, spotify: SpotifyInfo) {
+        self.unhook_spotify().await;
+
+        match spotify.process.pid() {
+            Ok(pid) => info!("Found Spotify (PID={pid})"),
+            Err(_) => info!("Found Spotify"),
+        }
+        if self.detect(spotify).is_ok() {
+            self.inject_with_retry().await;
+        }
+    }
+
+    /// Injects the blocker into the detected Spotify process, or only watches it until the blocker
+    /// is prepared.
+    async fn inject_with_retry(&mut self) {
+        if !preparation::is_ready() {
+            info!("Waiting for the blocker to be prepared before hooking");
+            status::set_hook(HookStatus::Preparing);
+            return;
+        }
+
+        for attempt in 1..=MAX_HOOK_ATTEMPTS {
+            let err = match self.inject().await {
+                Ok(()) => {
+                    clear_soft_failure();
+                    return;
                 }
-                Err(e) => {
-                    error!("Failed to stop RPC of previous blocker: {}", e);
+                Err(err) => err,
+            };
+            let _ = self.injection_failed();
+            status::get().last_error = Some(LastError {
+                message: Report(&err).to_string(),
+                time: Local::now(),
+            });
+
+            if err.is_process_gone() {
+                warn!("Spotify exited while hooking: {}", Report(&err));
+                return;
+            }
+
+            // Both instances found Spotify at once and the other one claimed it first.
+            if matches!(err, Error::HookedElsewhere { .. }) && ARGS.ignore_singleton {
+                info!("{}", Report(&err));
+                status::set_hook(HookStatus::OtherInstance);
+                return;
+            }
+
+            if err.is_firewall_blocked() {
+                error!(
+                    "Failed to hook Spotify, the connection to the blocker was blocked: {}",
+                    Report(&err)
+                );
+                record_spotify_version(false);
+                if soft_fail(FailureReason::Firewall) {
+                    events::publish(AppEvent::Error {
+                        message: format!("Failed to hook Spotify: {}", Report(&err)),
+                    });
+                    notify::error_with_actions(
+                        tr(Msg::FirewallBlocked),
+                        tr(Msg::FirewallBlockedMessage),
+                        &[NotificationAction::RetryInjection],
+                    );
                 }
+                return;
             }
 
-            info!("Ejecting previous blocker...");
-            match syringe.eject(prev_payload) {
-                Ok(_) => info!("Ejected previous blocker"),
-                Err(_) => error!("Failed to eject previous blocker"),
-            };
+            if !err.is_retryable() || attempt == MAX_HOOK_ATTEMPTS {
+                error!("Failed to hook Spotify: {}", Report(&err));
+                record_spotify_version(false);
+                let store_package = status::get()
+                    .spotify
+                    .as_ref()
+                    .and_then(|spotify| spotify.path.as_deref())
+                    .is_some_and(spotify_verification::is_store_package);
+                if soft_fail(err.failure_reason(store_package)) {
+                    events::publish(AppEvent::Error {
+                        message: format!("Failed to hook Spotify: {}", Report(&err)),
+                    });
+                    notify::error_with_actions(
+                        tr(Msg::HookFailed),
+                        &Report(&err).to_string(),
+                        &[
+                            NotificationAction::RetryInjection,
+                            NotificationAction::OpenConfig,
+                        ],
+                    );
+                }
+                return;
+            }
+
+            warn!(
+                "Failed to hook Spotify (attempt {attempt}/{MAX_HOOK_ATTEMPTS}): {}",
+                Report(&err)
+            );
+            tokio::time::sleep(HOOK_RETRY_DELAY * attempt).await;
         }
+    }
 
-        info!("Loading filter config...");
-        let filter_config = resolve_filter_config(ARGS.filters.as_ref().map(|p| p.as_ref()))
-            .await
-            .context("Failed to resolve filter config.")?;
+    async fn inject(&mut self) -> Result<()> {
+        let HookState::Detected(spotify) = self else {
+            return Ok(());
+        };
 
-        info!("Preparing blocker...");
-        let payload_path = resolve_blocker(ARGS.blocker.as_ref().map(|p| p.as_ref()))
-            .await
-            .context("Failed to resolve blocker.")?;
-
-        info!("Injecting blocker...");
-        let payload = syringe
-            .inject(payload_path)
-            .context("Failed to inject blocker.")?;
-
-        debug!("Starting RPC...");
-        let start_rpc =
-            unsafe { syringe.get_payload_procedure::<fn() -> SocketAddrV4>(payload, "start_rpc") }
-                .context("Failed to access spotify process.")?
-                .context("Failed to find start_rpc in blocker module.")?;
-
-        let rpc_socket_addr = start_rpc.call().unwrap();
-
-        let rpc_task = async_thread::spawn(move || {
-            let rt = runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-            let localset = LocalSet::new();
-            localset.block_on(&rt, async move {
-                rpc::run(rpc_socket_addr, filter_config).await.unwrap();
+        let pid = spotify.process.pid().ok();
+        if let Some(pid) = pid {
+            if let Ok(spotify_session) = session::of_process(pid.get()) {
+                if spotify_session != session::current_id() {
+                    info!("Ignoring Spotify in session {spotify_session}");
+                    return Ok(());
+                }
+            }
+            if hooked_by_serving_instance(pid.get()).await {
+                info!("Spotify (PID={pid}) is hooked by the instance owning the control channel");
+                status::set_hook(HookStatus::OtherInstance);
+                return Ok(());
+            }
+        }
+        let spotify_path = spotify.process.path().ok();
+        let conflicts = conflicts::detect(spotify.process.borrowed());
+        for conflict in &conflicts {
+            warn!("Spotify is also modified by {conflict}");
+        }
+        let blocking_conflict = conflicts
+            .iter()
+            .find(|conflict| conflict.hooks_same_functions())
+            .map(ToString::to_string);
+        {
+            status::get().spotify = Some(SpotifyStatus {
+                pid: pid.map(|pid| pid.get()),
+                version: spotify_path.as_deref().and_then(utils::file_version),
+                path: spotify_path,
+                conflicts,
+                internals: None,
             });
-        });
+            events::publish(AppEvent::SpotifyFound);
+            status::set_hook(HookStatus::Hooking);
+        }
+
+        // Checked first, as everything else is denied if Spotify runs at a higher level.
+        if let Some(pid) = pid {
+            match IntegrityMismatch::check(pid.get()) {
+                Ok(Some(mismatch)) if mismatch.prevents_hooking() => {
+                    return Err(Error::IntegrityMismatch(mismatch));
+                }
+                Ok(Some(mismatch)) => warn!("{mismatch}, {}", mismatch.advice()),
+                Ok(None) => {}
+                Err(e) => debug!("Failed to compare integrity levels with Spotify: {e}"),
+            }
+        }
+
+        if ARGS.skip_spotify_verification || ARGS.dev_target.is_some() {
+            debug!("Skipping Spotify verification");
+        } else {
+            spotify_verification::verify(spotify.process.borrowed()).map_err(Error::NotSpotify)?;
+        }
+        if spotify.process.is_x86().unwrap_or(false) {
+            return Err(Error::ArchitectureMismatch);
+        }
+        if let Some(conflict) = blocking_conflict {
+            if settings::get().on_conflict == ConflictPolicy::Refuse {
+                return Err(Error::Conflict(conflict));
+            }
+        }
+
+        let process = spotify.process.try_clone().map_err(Error::InspectModules)?;
+        let _ = self.start_injecting();
+
+        // Taken before looking for previous blockers, which could belong to another instance.
+        let claim = match pid {
+            Some(pid) => Some(
+                HookClaim::try_acquire(pid.get())
+                    .map_err(Error::Claim)?
+                    .ok_or(Error::HookedElsewhere { pid: pid.get() })?,
+            ),
+            None => None,
+        };
+
+        let syringe = Syringe::for_process(process);
+
+        injector::eject_previous_blockers(&syringe)?;
+
+        // Newer rules are sent once the blocker runs, see `DEFERRED_UPDATE`.
+        let loaded = LOADED_FILTERS.lock().unwrap().clone();
+        let filter_config = match loaded {
+            Some(filter_config) => filter_config,
+            None => {
+                info!("Loading filter config...");
+                load_filter_config(ChangeSource::Startup).await?
+            }
+        };
+
+        info!("Preparing blocker...");
+        let payload_path = prepare_blocker().await?;
+
+        let blocker =
+            InjectedBlocker::inject(syringe, &payload_path, filter_config, Arc::new(RequestLog))?;
+        if let Err(e) = blocker.wait_until_ready().await {
+            if let Err(e) = blocker.eject().await {
+                debug!("Failed to eject blocker that did not start: {}", Report(&e));
+            }
+            return Err(Error::NotReady(e));
+        }
+
+        // Refused if the lifecycle moved on while injecting, the blocker would be lost otherwise.
+        if let Err((_, blocker)) = self.injected(blocker, claim) {
+            if let Err(e) = blocker.eject().await {
+                debug!(
+                    "Failed to eject blocker that was not expected: {}",
+                    Report(&e)
+                );
+            }
+            return Ok(());
+        }
+        let Some(hook) = self.hook() else {
+            return Ok(());
+        };
 
         info!("Blocker up and running!");
-        *self = SpotifyHookState::Hooked(HookState {
-            payload: payload.try_to_owned().unwrap(),
-            syringe,
-            rpc_task,
+        status::get().blocker_version = hook.blocker.version().map(str::to_string);
+        check_fingerprint(&hook.blocker).await;
+        stats::get().protection_started();
+        record_spotify_version(true);
+        status::set_hook(HookStatus::Hooked);
+        events::publish(AppEvent::Injected {
+            pid: pid.map(|pid| pid.get()),
         });
+        if let Some(source) = DEFERRED_UPDATE.lock().unwrap().take() {
+            filter_providers::notify_refresh(source);
+        }
 
         Ok(())
     }
 
+    /// Ejects the blocker if it is injected.
     async fn unhook_spotify(&mut self) {
-        let state = mem::replace(self, SpotifyHookState::Unhooked);
-        let state = match state {
-            SpotifyHookState::Hooked(state) => state,
-            _ => return,
+        let Some(hook) = self.start_ejecting() else {
+            return;
         };
 
         info!("Unhooking Spotify...");
+        stats::get().protection_stopped();
 
-        let result: Result<(), SyringeError> = async {
-            let stop_rpc = unsafe {
-                state
-                    .syringe
-                    .get_payload_procedure::<fn()>(state.payload.borrowed(), "stop_rpc")
-            }?
-            .unwrap();
+        match hook.blocker.eject().await {
+            Ok(()) => {}
+            Err(e) if e.is_process_gone() => debug!("Spotify exited before unhooking"),
+            Err(e) => error!("Failed to unhook Spotify: {}", Report(&e)),
+        };
+        media::unmute().await;
 
-            debug!("Stopping RPC...");
-            stop_rpc.call()?;
-            state.rpc_task.join().await.unwrap();
-            debug!("Stopped RPC");
+        let _ = self.ejected(hook.spotify.process.is_alive().then_some(hook.spotify));
 
-            if state.payload.process().is_alive() {
-                info!("Ejecting blocker...");
-                state.syringe.eject(state.payload.borrowed())?;
-                info!("Ejected blocker");
-            }
+        {
+            let mut status = status::get();
+            status.spotify = None;
+            status.blocker_version = None;
+            status.blocker_perf = None;
+        }
+        status::set_hook(HookStatus::Searching);
+        events::publish(AppEvent::Unhooked);
+    }
+}
 
-            Ok(())
+/// Waits until the Spotify state stopped changing for [`SPOTIFY_STATE_SETTLE_TIME`], so that only
+/// the final state of a quick restart is acted on. Returns `false` if the scanner stopped.
+async fn settle(spotify_state: &mut tokio::sync::watch::Receiver<SpotifyState>) -> bool {
+    loop {
+        match tokio::time::timeout(SPOTIFY_STATE_SETTLE_TIME, spotify_state.changed()).await {
+            Ok(Ok(())) => debug!("Spotify state changed again, waiting for it to settle"),
+            Ok(Err(_)) => return false,
+            Err(_) => return true,
         }
-        .await;
+    }
+}
 
-        match result {
-            Ok(_)
-            | Err(SyringeError::ProcessInaccessible)
-            | Err(SyringeError::ModuleInaccessible) => {}
-            _ => todo!("{:#?}", result),
+/// The Spotify process reported by the scanner, if it is running and can still be accessed.
+fn running_spotify(
+    spotify_state: &tokio::sync::watch::Receiver<SpotifyState>,
+) -> Option<SpotifyInfo> {
+    match spotify_state.borrow().try_clone() {
+        Ok(SpotifyState::Running(spotify)) => Some(spotify),
+        Ok(SpotifyState::Stopped) => None,
+        Err(e) => {
+            warn!("Failed to access Spotify process: {e}");
+            None
+        }
+    }
+}
+
+/// Whether the instance owning the control channel hooked the process. Only instances started with
+/// `--ignore-singleton` run alongside it and ask.
+async fn hooked_by_serving_instance(pid: u32) -> bool {
+    if !ARGS.ignore_singleton || control::is_serving() {
+        return false;
+    }
+    // The other instance may be busy hooking and answer late.
+    let response = tokio::time::timeout(
+        OTHER_INSTANCE_TIMEOUT,
+        control::send(&ControlCommand::HookedPid),
+    )
+    .await;
+    match response {
+        Ok(Ok(response)) => response.trim() == pid.to_string(),
+        Ok(Err(e)) => {
+            debug!("Failed to ask other instance for its hooked process: {e}");
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Shows the failure in the status and schedules the next attempt to hook Spotify.
+/// Returns whether the user should be notified, which is only the case for the first failure with
+/// a reason.
+fn soft_fail(reason: FailureReason) -> bool {
+    let (delay, is_new) = {
+        let mut failure = SOFT_FAILURE.lock().unwrap();
+        let (failures, is_new) = match failure.as_ref() {
+            Some(failure) => (failure.failures + 1, failure.reason != reason),
+            None => (1, true),
         };
+        let delay = SOFT_FAIL_RETRY_DELAYS[(failures - 1).min(SOFT_FAIL_RETRY_DELAYS.len() - 1)];
+        *failure = Some(SoftFailure {
+            reason,
+            failures,
+            retry_at: Some(Instant::now() + delay),
+        });
+        (delay, is_new)
+    };
 
-        *self = SpotifyHookState::Unhooked;
+    info!(
+        "Not blocking ({reason}), retrying in {} minutes",
+        delay.as_secs() / 60
+    );
+    status::get().next_retry = Some(Local::now() + TimeDelta::seconds(delay.as_secs() as i64));
+    status::set_hook(HookStatus::Unavailable(reason));
+    is_new
+}
+
+fn clear_soft_failure() {
+    if SOFT_FAILURE.lock().unwrap().take().is_some() {
+        status::get().next_retry = None;
+    }
+}
+
+fn scheduled_retry() -> Option<Instant> {
+    SOFT_FAILURE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|failure| failure.retry_at)
+}
+
+/// Marks the scheduled attempt as done, the next one is scheduled if it fails again.
+fn take_scheduled_retry() {
+    if let Some(failure) = SOFT_FAILURE.lock().unwrap().as_mut() {
+        failure.retry_at = None;
+    }
+}
+
+/// Resolves the blocker to inject. Removed blockers are extracted again by resolving them, which
+/// is repeated once if the file disappears right after extraction, e.g. quarantined by an antivirus.
+async fn prepare_blocker() -> Result<PathBuf> {
+    let previous = INJECTED_BLOCKER_PATH.lock().unwrap().clone();
+    if let Some(previous) = previous.filter(|path| !path.is_file()) {
+        warn!(
+            "Blocker at '{}' was removed, extracting it again",
+            previous.display()
+        );
+    }
+
+    for _ in 0..2 {
+        let payload_path = resolve_blocker(&System, ARGS.blocker.as_deref())
+            .await
+            .map_err(Error::PrepareBlocker)?;
+        if ARGS.blocker.is_some() && !ARGS.allow_unsigned_blocker {
+            verify_provided_blocker(&System, &payload_path)
+                .await
+                .map_err(Error::UntrustedBlocker)?;
+        }
+        if payload_path.is_file() {
+            *INJECTED_BLOCKER_PATH.lock().unwrap() = Some(payload_path.clone());
+            return Ok(payload_path);
+        }
+        warn!(
+            "Blocker at '{}' was removed right after extraction",
+            payload_path.display()
+        );
     }
+    Err(Error::PrepareBlocker(io::Error::new(
+        io::ErrorKind::NotFound,
+        "Blocker was removed right after extraction.",
+    )))
 }
 
-#[derive(Deserialize, Debug)]
-pub struct FilterConfig {
-    pub allowlist: Vec<String>,
-    pub denylist: Vec<String>,
+/// Logs the versions of the Spotify internals hooked by the blocker and shows them in the status,
+/// so that reports of failed blocking can be matched to the CEF build.
+async fn check_fingerprint(blocker: &InjectedBlocker) {
+    let fingerprint = match blocker.fingerprint().await {
+        Ok(Some(fingerprint)) => fingerprint,
+        Ok(None) => {
+            debug!("Blocker does not report the versions of the Spotify internals");
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to get the versions of the Spotify internals: {e}");
+            return;
+        }
+    };
+    info!(
+        "Hooked CEF {} (API hash {})",
+        fingerprint.cef_version, fingerprint.cef_api_hash
+    );
+
+    if let Some(spotify) = status::get().spotify.as_mut() {
+        spotify.internals = Some(fingerprint);
+    }
+}
+
+/// Loads the filter config from all providers together with the rules added by scripts, or the
+/// rules pinned by `history revert`, and records it in the filter history.
+pub async fn load_filter_config(source: ChangeSource) -> Result<FilterConfig> {
+    if let Some(filter_config) = filter_history::pinned() {
+        debug!("Using rules pinned by `history revert`");
+        filter_history::record(&filter_config, ChangeSource::Revert);
+        *LOADED_FILTERS.lock().unwrap() = Some(filter_config.clone());
+        return Ok(filter_config);
+    }
+
+    let mut filter_config = filter_providers::load()
+        .await
+        .map_err(Error::FilterConfig)?;
+    scripting::extend_filters(&mut filter_config);
+    filter_tests::check(&filter_config);
+    canary::check(&filter_config);
+    filter_history::record(&filter_config, source);
+    *LOADED_FILTERS.lock().unwrap() = Some(filter_config.clone());
+    Ok(filter_config)
+}
+
+/// Formats an error together with its chain of sources.
+struct Report<'a>(&'a dyn std::error::Error);
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        let mut source = self.0.source();
+        while let Some(err) = source {
+            write!(f, ": {err}")?;
+            source = err.source();
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,51 @@
+//! Opt-in check of known ad endpoints against the effective rules after each filter update, so a
+//! broken filter config shows up in the log and tray before ads do.
+
+use burnt_sushi_core::filters::{CompiledFilters, FilterConfig};
+use log::{info, warn};
+
+use crate::{settings, status};
+
+/// Endpoints that serve or track ads in every supported client version.
+const CANARY_URLS: &[&str] = &[
+    "https://spclient.wg.spotify.com/ads/v1/ads/hpto",
+    "https://spclient.wg.spotify.com/ad-logic/state/config",
+    "https://spclient.wg.spotify.com/gabo-receiver-service/v3/events",
+    "https://adeventtracker.spotify.com/",
+    "https://pubads.g.doubleclick.net/gampad/ads",
+];
+
+/// Checks the canary endpoints if enabled in the settings and records the ones that are not
+/// blocked in the status.
+pub fn check(filter_config: &FilterConfig) {
+    if !settings::get().canary_check {
+        return;
+    }
+
+    let filters = match CompiledFilters::new(filter_config) {
+        Ok(filters) => filters,
+        Err(e) => {
+            warn!("Failed to compile filter rules for the canary check: {e}");
+            return;
+        }
+    };
+    let uncovered = CANARY_URLS
+        .iter()
+        .filter(|url| filters.evaluate(url).is_none())
+        .map(|url| url.to_string())
+        .collect::<Vec<_>>();
+
+    if uncovered.is_empty() {
+        info!("All {} known ad endpoints are blocked", CANARY_URLS.len());
+    } else {
+        warn!(
+            "{} of {} known ad endpoints are not blocked, the filter config may be broken",
+            uncovered.len(),
+            CANARY_URLS.len()
+        );
+        for url in &uncovered {
+            warn!("Ad endpoint not blocked: {url}");
+        }
+    }
+    status::get().uncovered_canaries = uncovered;
+}
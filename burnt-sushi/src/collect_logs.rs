@@ -0,0 +1,135 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::Local;
+use log::{debug, warn};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{args::ARGS, diagnostics, environment::System, paths, resolver, settings};
+
+/// Bundles log files, the redacted settings, the filter config, crash dumps and a system summary
+/// into a zip archive that can be attached to an issue.
+pub fn collect_logs(output: Option<&Path>) -> anyhow::Result<PathBuf> {
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => default_output_path(),
+    };
+
+    let file = File::create(&output).context("Failed to create archive.")?;
+    let mut archive = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    archive
+        .start_file("system.txt", options)
+        .context("Failed to write system summary.")?;
+    archive
+        .write_all(diagnostics::system_summary().as_bytes())
+        .context("Failed to write system summary.")?;
+
+    let settings = settings::get().redacted();
+    archive
+        .start_file("settings.toml", options)
+        .context("Failed to write settings.")?;
+    archive
+        .write_all(
+            toml::to_string_pretty(&settings)
+                .context("Failed to serialize settings.")?
+                .as_bytes(),
+        )
+        .context("Failed to write settings.")?;
+
+    for log_file in log_files() {
+        add_file(&mut archive, &log_file, "logs", options)?;
+    }
+
+    if let Some(filters) = resolver::filter_config_path(&System).filter(|path| path.is_file()) {
+        add_file(&mut archive, &filters, "", options)?;
+    }
+
+    if let Some(crash_dir) = paths::crash_dir(&System) {
+        for crash in read_dir_files(&crash_dir) {
+            add_file(&mut archive, &crash, "crashes", options)?;
+        }
+    }
+
+    archive.finish().context("Failed to finish archive.")?;
+    Ok(output)
+}
+
+fn default_output_path() -> PathBuf {
+    let file_name = format!(
+        "BurntSushi-logs-{}.zip",
+        Local::now().format("%Y%m%d-%H%M%S")
+    );
+    dirs::desktop_dir()
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default()
+        .join(file_name)
+}
+
+fn add_file(
+    archive: &mut ZipWriter<File>,
+    path: &Path,
+    dir: &str,
+    options: SimpleFileOptions,
+) -> anyhow::Result<()> {
+    let Some(file_name) = path.file_name() else {
+        return Ok(());
+    };
+    let name = if dir.is_empty() {
+        file_name.to_string_lossy().into_owned()
+    } else {
+        format!("{dir}/{}", file_name.to_string_lossy())
+    };
+
+    // The log file may be held open by a running instance, so failing to read a single file only skips it.
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Skipping '{}': {e}", path.display());
+            return Ok(());
+        }
+    };
+
+    debug!("Adding '{}' as '{name}'", path.display());
+    archive
+        .start_file(name, options)
+        .context("Failed to add file to archive.")?;
+    io::copy(&mut file, archive).context("Failed to add file to archive.")?;
+    Ok(())
+}
+
+fn log_files() -> Vec<PathBuf> {
+    let Some(log_file) = ARGS.log_file.clone().or_else(paths::log_file) else {
+        return Vec::new();
+    };
+    let (Some(dir), Some(stem)) = (log_file.parent(), log_file.file_stem()) else {
+        return Vec::new();
+    };
+
+    read_dir_files(dir)
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&*stem.to_string_lossy()))
+        })
+        .collect()
+}
+
+fn read_dir_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
@@ -0,0 +1,287 @@
+use std::{
+    ffi::c_void,
+    fmt, io, mem,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use burnt_sushi_core::security::OwnerOnly;
+use clap::ValueEnum;
+use log::{debug, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::windows::named_pipe::{ClientOptions, NamedPipeServer, ServerOptions},
+    sync::{mpsc, oneshot},
+};
+use winapi::shared::winerror::ERROR_PIPE_BUSY;
+
+use crate::{args::LogLevel, session};
+
+/// The pipe is scoped to the session, so that instances of different users do not interfere.
+fn pipe_name() -> String {
+    format!(r"\\.\pipe\BurntSushi-{}", session::current_id())
+}
+
+pub const DEFAULT_PAUSE_MINUTES: u64 = 30;
+/// Time to wait for the running instance to accept the connection while the pipe is busy.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether this instance owns the control pipe. Instances started with `--ignore-singleton` run
+/// without it if another instance owns it.
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+/// Commands that can be sent to a running instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    Diagnostics,
+    SetLogLevel(LogLevel),
+    /// Returns the version of the running instance.
+    Version,
+    /// Asks the running instance to unhook and exit so a newer version can take over.
+    Handoff,
+    /// Returns the hook state and the found Spotify instance.
+    Status,
+    /// Returns the PID of the hooked Spotify process, or `none`.
+    HookedPid,
+    /// Reloads the filters from all providers.
+    Reload,
+    /// Pauses ad blocking for the given number of minutes.
+    Pause(u64),
+    /// Pauses ad blocking until the current Spotify process exits.
+    PauseUntilRestart,
+    /// Hooks the process with the given id, bypassing the scanner.
+    Inject(u32),
+    /// Returns the most recent log messages.
+    Events,
+    /// Opens a console window showing the requests seen by the blocker.
+    ShowActivity,
+    /// Returns what the next telemetry report would contain.
+    TelemetryPreview,
+    /// Turns telemetry on or off.
+    SetTelemetry(bool),
+    /// Asks the running instance to unhook and exit.
+    Exit,
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlCommand::Diagnostics => write!(f, "diagnostics"),
+            ControlCommand::SetLogLevel(level) => write!(f, "set-log-level {}", level.name()),
+            ControlCommand::Version => write!(f, "version"),
+            ControlCommand::Handoff => write!(f, "handoff"),
+            ControlCommand::Status => write!(f, "status"),
+            ControlCommand::HookedPid => write!(f, "hooked-pid"),
+            ControlCommand::Reload => write!(f, "reload"),
+            ControlCommand::Pause(minutes) => write!(f, "pause {minutes}"),
+            ControlCommand::PauseUntilRestart => write!(f, "pause-until-restart"),
+            ControlCommand::Inject(pid) => write!(f, "inject {pid}"),
+            ControlCommand::Events => write!(f, "events"),
+            ControlCommand::ShowActivity => write!(f, "show-activity"),
+            ControlCommand::TelemetryPreview => write!(f, "telemetry-preview"),
+            ControlCommand::SetTelemetry(enabled) => {
+                write!(f, "set-telemetry {}", if *enabled { "on" } else { "off" })
+            }
+            ControlCommand::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+impl FromStr for ControlCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let command = match parts.next() {
+            Some("diagnostics") => ControlCommand::Diagnostics,
+            Some("set-log-level" | "loglevel") => {
+                let level = parts.next().context("Missing log level")?;
+                let level = LogLevel::from_str(level, true)
+                    .map_err(|_| anyhow!("Invalid log level '{level}'"))?;
+                ControlCommand::SetLogLevel(level)
+            }
+            Some("version") => ControlCommand::Version,
+            Some("handoff") => ControlCommand::Handoff,
+            Some("status") => ControlCommand::Status,
+            Some("hooked-pid") => ControlCommand::HookedPid,
+            Some("reload") => ControlCommand::Reload,
+            Some("pause") => {
+                let minutes = match parts.next() {
+                    Some(minutes) => minutes
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid number of minutes '{minutes}'"))?,
+                    None => DEFAULT_PAUSE_MINUTES,
+                };
+                ControlCommand::Pause(minutes)
+            }
+            Some("pause-until-restart") => ControlCommand::PauseUntilRestart,
+            Some("inject") => {
+                let pid = parts.next().context("Missing process id")?;
+                let pid = pid
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid process id '{pid}'"))?;
+                ControlCommand::Inject(pid)
+            }
+            Some("events") => ControlCommand::Events,
+            Some("show-activity") => ControlCommand::ShowActivity,
+            Some("telemetry-preview") => ControlCommand::TelemetryPreview,
+            Some("set-telemetry") => match parts.next() {
+                Some("on") => ControlCommand::SetTelemetry(true),
+                Some("off") => ControlCommand::SetTelemetry(false),
+                Some(other) => return Err(anyhow!("Invalid telemetry state '{other}'")),
+                None => return Err(anyhow!("Missing telemetry state")),
+            },
+            Some("exit") => ControlCommand::Exit,
+            Some(other) => return Err(anyhow!("Unknown command '{other}'")),
+            None => return Err(anyhow!("Empty command")),
+        };
+        if parts.next().is_some() {
+            return Err(anyhow!("Too many arguments for '{command}'"));
+        }
+        Ok(command)
+    }
+}
+
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    pub response: oneshot::Sender<String>,
+}
+
+/// Creates the first instance of the control pipe, failing if another instance already owns it.
+pub fn bind() -> io::Result<NamedPipeServer> {
+    let server = create_pipe(true)?;
+    SERVING.store(true, Ordering::Relaxed);
+    debug!("Listening for control commands on {}", pipe_name());
+    Ok(server)
+}
+
+pub fn is_serving() -> bool {
+    SERVING.load(Ordering::Relaxed)
+}
+
+/// Creates an instance of the control pipe that only the current user can connect to.
+fn create_pipe(first_instance: bool) -> io::Result<NamedPipeServer> {
+    let security = OwnerOnly::new()?;
+    let mut attributes = security.attributes();
+    unsafe {
+        ServerOptions::new()
+            .first_pipe_instance(first_instance)
+            .create_with_security_attributes_raw(
+                pipe_name(),
+                &mut attributes as *mut _ as *mut c_void,
+            )
+    }
+}
+
+/// Listens for commands from other instances and forwards them to the app.
+pub async fn serve(
+    mut server: NamedPipeServer,
+    requests: mpsc::Sender<ControlRequest>,
+) -> io::Result<()> {
+    loop {
+        server.connect().await?;
+        let client = mem::replace(&mut server, create_pipe(false)?);
+
+        let requests = requests.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = handle_client(client, requests).await {
+                warn!("Failed to handle control client: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    pipe: NamedPipeServer,
+    requests: mpsc::Sender<ControlRequest>,
+) -> io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(pipe);
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let response = match line.trim().parse::<ControlCommand>() {
+        Ok(command) => {
+            debug!("Received control command '{command}'");
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = ControlRequest {
+                command,
+                response: response_tx,
+            };
+            match requests.send(request).await {
+                Ok(()) => response_rx
+                    .await
+                    .unwrap_or_else(|_| "Command was dropped.".to_string()),
+                Err(_) => "App is shutting down.".to_string(),
+            }
+        }
+        Err(e) => format!("Invalid command: {e}"),
+    };
+
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Reads commands from the console and forwards them to the app, printing their responses.
+pub fn read_console(requests: mpsc::Sender<ControlRequest>) {
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let command = match line.trim().parse::<ControlCommand>() {
+                Ok(command) => command,
+                Err(e) => {
+                    println!("Invalid command: {e}");
+                    continue;
+                }
+            };
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = ControlRequest {
+                command,
+                response: response_tx,
+            };
+            if requests.blocking_send(request).is_err() {
+                break;
+            }
+            match response_rx.blocking_recv() {
+                Ok(response) => println!("{response}"),
+                Err(_) => println!("Command was dropped."),
+            }
+        }
+    });
+}
+
+/// Sends a command to the running instance and returns its response.
+pub async fn send(command: &ControlCommand) -> io::Result<String> {
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    let mut client = loop {
+        match ClientOptions::new().open(pipe_name()) {
+            Ok(client) => break client,
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Running instance did not accept the connection.",
+                    ));
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    client.write_all(format!("{command}\n").as_bytes()).await?;
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).await?;
+    Ok(response)
+}
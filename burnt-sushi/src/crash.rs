@@ -0,0 +1,147 @@
+use std::{
+    backtrace::Backtrace,
+    fs::{self, File},
+    io::Write,
+    os::windows::io::AsRawHandle,
+    panic,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::Context;
+use chrono::Local;
+use windows::Win32::{
+    Foundation::{HANDLE, TRUE},
+    System::{
+        Diagnostics::Debug::{
+            MiniDumpWithDataSegs, MiniDumpWithIndirectlyReferencedMemory, MiniDumpWithThreadInfo,
+            MiniDumpWriteDump, SetUnhandledExceptionFilter, EXCEPTION_POINTERS,
+            MINIDUMP_EXCEPTION_INFORMATION, MINIDUMP_TYPE,
+        },
+        Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId},
+    },
+};
+
+use crate::APP_NAME_WITH_VERSION;
+
+const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+const MAX_KEPT_CRASHES: usize = 5;
+
+static CRASH_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Installs a panic hook and an unhandled exception filter that write a crash log and a minidump to `dir`.
+pub fn install(dir: PathBuf) {
+    if CRASH_DIR.set(dir).is_err() {
+        return;
+    }
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // Logging is avoided here as the panic might have occurred while holding the logger lock.
+        let report = format!("{info}\n\n{}", Backtrace::force_capture());
+        write_crash(&report, None);
+        default_hook(info);
+    }));
+
+    unsafe { SetUnhandledExceptionFilter(Some(unhandled_exception_filter)) };
+}
+
+unsafe extern "system" fn unhandled_exception_filter(
+    exception_info: *const EXCEPTION_POINTERS,
+) -> i32 {
+    let code = unsafe { exception_info.as_ref() }
+        .and_then(|info| unsafe { info.ExceptionRecord.as_ref() })
+        .map_or(0, |record| record.ExceptionCode.0);
+    write_crash(
+        &format!("Unhandled exception (code={code:#010X})"),
+        Some(exception_info),
+    );
+    EXCEPTION_CONTINUE_SEARCH
+}
+
+fn write_crash(report: &str, exception_info: Option<*const EXCEPTION_POINTERS>) {
+    let Some(dir) = CRASH_DIR.get() else {
+        return;
+    };
+
+    let base_name = format!(
+        "BurntSushi-{}-{}",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        unsafe { GetCurrentThreadId() }
+    );
+    let log_path = dir.join(&base_name).with_extension("log");
+    let dump_path = dir.join(&base_name).with_extension("dmp");
+
+    let result = fs::create_dir_all(dir)
+        .context("Failed to create crash directory.")
+        .and_then(|_| write_crash_log(&log_path, report))
+        .and_then(|_| write_minidump(&dump_path, exception_info));
+    match result {
+        Ok(()) => prune_old_crashes(dir),
+        Err(e) => eprintln!("Failed to write crash dump: {e:#}"),
+    }
+}
+
+fn write_crash_log(path: &Path, report: &str) -> anyhow::Result<()> {
+    let mut file = File::create(path).context("Failed to create crash log.")?;
+    writeln!(file, "{APP_NAME_WITH_VERSION}").context("Failed to write crash log.")?;
+    writeln!(file, "{}", Local::now().format("%Y-%m-%d %H:%M:%S"))
+        .context("Failed to write crash log.")?;
+    writeln!(file).context("Failed to write crash log.")?;
+    writeln!(file, "{report}").context("Failed to write crash log.")?;
+    Ok(())
+}
+
+fn write_minidump(
+    path: &Path,
+    exception_info: Option<*const EXCEPTION_POINTERS>,
+) -> anyhow::Result<()> {
+    let file = File::create(path).context("Failed to create minidump file.")?;
+
+    let exception_param = exception_info.map(|info| MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: unsafe { GetCurrentThreadId() },
+        ExceptionPointers: info as *mut _,
+        ClientPointers: TRUE,
+    });
+    let dump_type = MINIDUMP_TYPE(
+        MiniDumpWithDataSegs.0
+            | MiniDumpWithThreadInfo.0
+            | MiniDumpWithIndirectlyReferencedMemory.0,
+    );
+
+    unsafe {
+        MiniDumpWriteDump(
+            GetCurrentProcess(),
+            GetCurrentProcessId(),
+            HANDLE(file.as_raw_handle()),
+            dump_type,
+            exception_param.as_ref().map(|p| p as *const _),
+            None,
+            None,
+        )
+    }
+    .context("MiniDumpWriteDump failed.")
+}
+
+fn prune_old_crashes(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut crashes = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dmp"))
+        .collect::<Vec<_>>();
+    if crashes.len() <= MAX_KEPT_CRASHES {
+        return;
+    }
+
+    // file names start with a sortable timestamp
+    crashes.sort();
+    for dump in &crashes[..crashes.len() - MAX_KEPT_CRASHES] {
+        let _ = fs::remove_file(dump);
+        let _ = fs::remove_file(dump.with_extension("log"));
+        let _ = fs::remove_file(dump.with_extension("handled"));
+    }
+}
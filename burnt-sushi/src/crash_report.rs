@@ -0,0 +1,207 @@
+use std::{env, fs, path::PathBuf, sync::LazyLock};
+
+use anyhow::Context;
+use log::{info, warn};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::{
+    diagnostics,
+    environment::System,
+    i18n::{tr, tr_args, Msg},
+    notify::{self, NotificationAction},
+    paths,
+    settings::{self, CrashReports},
+    utils, APP_NAME_WITH_VERSION,
+};
+
+/// Marks a crash as sent or discarded.
+const HANDLED_EXTENSION: &str = "handled";
+
+static UPLOAD_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Asks the running app to upload the pending crash reports.
+pub fn request_upload() {
+    UPLOAD_REQUESTED.notify_one();
+}
+
+/// Crash written by [`crate::crash`] which was not sent or discarded yet.
+#[derive(Debug)]
+pub struct PendingCrash {
+    log: PathBuf,
+}
+
+/// Data uploaded for a single crash. The minidump is never sent, as it contains memory of the
+/// crashed process that cannot be redacted.
+#[derive(Debug, Serialize)]
+struct Payload {
+    app: &'static str,
+    system: String,
+    report: String,
+}
+
+impl PendingCrash {
+    fn dump(&self) -> PathBuf {
+        self.log.with_extension("dmp")
+    }
+
+    fn payload(&self) -> anyhow::Result<Payload> {
+        let report = fs::read_to_string(&self.log).context("Failed to read crash log.")?;
+        Ok(Payload {
+            app: APP_NAME_WITH_VERSION,
+            system: redact(&diagnostics::system_summary()),
+            report: redact(&report),
+        })
+    }
+
+    fn mark_handled(&self) {
+        if let Err(e) = fs::write(self.log.with_extension(HANDLED_EXTENSION), "") {
+            warn!("Failed to mark crash as handled: {e}");
+        }
+    }
+}
+
+/// Returns the crashes that were neither sent nor discarded, oldest first.
+pub fn pending() -> Vec<PendingCrash> {
+    let Some(dir) = paths::crash_dir(&System) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut logs = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .filter(|path| !path.with_extension(HANDLED_EXTENSION).exists())
+        .collect::<Vec<_>>();
+    // file names start with a sortable timestamp
+    logs.sort();
+    logs.into_iter().map(|log| PendingCrash { log }).collect()
+}
+
+/// Replaces the user name and profile paths, which appear in paths of the crash log and the
+/// system summary.
+fn redact(text: &str) -> String {
+    let mut text = text.to_string();
+    if let Some(home) = dirs::home_dir() {
+        text = text.replace(&*home.to_string_lossy(), "%USERPROFILE%");
+    }
+    if let Ok(user) = env::var("USERNAME") {
+        if !user.is_empty() {
+            text = text.replace(&user, "<user>");
+        }
+    }
+    text
+}
+
+/// Configured endpoint, crashes are only sent if there is one.
+fn endpoint() -> Option<String> {
+    settings::get()
+        .crash_report_endpoint
+        .clone()
+        .filter(|endpoint| !endpoint.trim().is_empty())
+}
+
+/// Describes exactly what would be sent for the pending crashes.
+pub fn preview() -> anyhow::Result<String> {
+    let crashes = pending();
+    if crashes.is_empty() {
+        return Ok("No pending crash reports.".to_string());
+    }
+
+    let mut preview = match endpoint() {
+        Some(endpoint) => format!(
+            "{} crash report(s) would be sent to {endpoint}\n",
+            crashes.len()
+        ),
+        None => format!(
+            "{} crash report(s), which are not sent as no crash-report-endpoint is configured\n",
+            crashes.len()
+        ),
+    };
+    for crash in &crashes {
+        let payload = crash.payload()?;
+        preview.push_str(&format!(
+            "\n=== {} ===\n[App]\n{}\n\n{}\n[Crash]\n{}\n",
+            crash.log.display(),
+            payload.app,
+            payload.system,
+            payload.report
+        ));
+        if crash.dump().exists() {
+            preview.push_str(&format!(
+                "[Minidump]\n{} (kept on this computer)\n",
+                crash.dump().display()
+            ));
+        }
+    }
+    Ok(preview)
+}
+
+/// Writes the preview to a file and opens it.
+pub fn open_preview() -> anyhow::Result<()> {
+    let dir = paths::crash_dir(&System).context("Failed to locate app data directory.")?;
+    let path = dir.join("report-preview.txt");
+    fs::write(&path, preview()?).context("Failed to write crash report preview.")?;
+    utils::shell_open(&path)
+}
+
+/// Uploads the pending crashes and returns how many were sent.
+pub async fn send_pending() -> anyhow::Result<usize> {
+    let endpoint = endpoint().context("No crash-report-endpoint is configured.")?;
+    let client = reqwest::Client::new();
+    let crashes = pending();
+    for crash in &crashes {
+        client
+            .post(&endpoint)
+            .json(&crash.payload()?)
+            .send()
+            .await
+            .context("Failed to upload crash report.")?
+            .error_for_status()
+            .context("Crash report was rejected.")?;
+        crash.mark_handled();
+    }
+    Ok(crashes.len())
+}
+
+/// Marks the pending crashes as handled without sending them.
+pub fn discard_pending() -> usize {
+    let crashes = pending();
+    for crash in &crashes {
+        crash.mark_handled();
+    }
+    crashes.len()
+}
+
+/// Sends or offers to send crashes of previous runs depending on the settings and then uploads
+/// them whenever the user agrees.
+pub async fn run() {
+    let crashes = pending().len();
+    if crashes > 0 && endpoint().is_some() {
+        match settings::get().crash_reports {
+            CrashReports::Never => {}
+            CrashReports::Send => request_upload(),
+            CrashReports::Ask => notify::error_with_actions(
+                tr(Msg::CrashDetected),
+                &tr_args(Msg::CrashReportPrompt, &[("count", &crashes)]),
+                &[
+                    NotificationAction::SendCrashReport,
+                    NotificationAction::AlwaysSendCrashReports,
+                    NotificationAction::ViewCrashReport,
+                ],
+            ),
+        }
+    }
+
+    loop {
+        UPLOAD_REQUESTED.notified().await;
+        match send_pending().await {
+            Ok(0) => {}
+            Ok(count) => info!("Sent {count} crash report(s)"),
+            Err(e) => warn!("Failed to send crash reports: {e:#}"),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::{env, fmt::Write, path::PathBuf, time::Duration};
+
+use burnt_sushi_core::{
+    integrity::{IntegrityLevel, IntegrityMismatch},
+    timing::{self, Stage},
+};
+
+use crate::{
+    args::ARGS, environment::System, filter_providers::remote, logger, paths, session,
+    settings::Settings, stats, status, utils, APP_NAME_WITH_VERSION,
+};
+
+/// Builds a report that can be pasted into a GitHub issue.
+pub fn report() -> String {
+    let mut report = String::new();
+    write_report(&mut report).unwrap();
+    report
+}
+
+/// Describes whether Spotify is hooked and which Spotify instance was found.
+pub fn status_report() -> String {
+    let mut report = String::new();
+    write_status(&mut report).unwrap();
+    report
+}
+
+/// Builds a summary of the system and the paths used by the app.
+pub fn system_summary() -> String {
+    let mut summary = String::new();
+    writeln!(summary, "{APP_NAME_WITH_VERSION}").unwrap();
+    writeln!(summary).unwrap();
+    write_system_summary(&mut summary).unwrap();
+    summary
+}
+
+fn write_report(out: &mut String) -> std::fmt::Result {
+    writeln!(out, "```")?;
+    writeln!(out, "{APP_NAME_WITH_VERSION}")?;
+    writeln!(out)?;
+
+    write_system_summary(out)?;
+    write_status(out)?;
+    write_spotify_versions(out)?;
+    write_blocker_perf(out)?;
+    write_timings(out)?;
+
+    writeln!(out, "[Recent log]")?;
+    for message in logger::global::get().recent.messages() {
+        writeln!(out, "{message}")?;
+    }
+    writeln!(out, "```")?;
+
+    Ok(())
+}
+
+fn write_system_summary(out: &mut String) -> std::fmt::Result {
+    writeln!(out, "[System]")?;
+    writeln!(
+        out,
+        "Windows: {}",
+        windows_version().as_deref().unwrap_or("<unknown>")
+    )?;
+    writeln!(out, "Architecture: {}", env::consts::ARCH)?;
+    writeln!(out, "Elevated: {}", is_elevated::is_elevated())?;
+    writeln!(
+        out,
+        "Integrity: {}",
+        IntegrityLevel::current().map_or_else(|e| format!("<{e}>"), |level| level.to_string())
+    )?;
+    writeln!(out, "Session: {}", session::current_id())?;
+    writeln!(out)?;
+
+    writeln!(out, "[Paths]")?;
+    writeln!(out, "Executable: {}", display_path(env::current_exe().ok()))?;
+    writeln!(
+        out,
+        "Log file: {}",
+        display_path(ARGS.log_file.clone().or_else(paths::log_file))
+    )?;
+    writeln!(
+        out,
+        "Crash dumps: {}",
+        display_path(paths::crash_dir(&System))
+    )?;
+    writeln!(out, "Settings: {}", display_path(Settings::path()))?;
+    writeln!(out, "Blocker: {}", display_path(ARGS.blocker.clone()))?;
+    writeln!(out, "Filters: {}", display_path(ARGS.filters.clone()))?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn write_timings(out: &mut String) -> std::fmt::Result {
+    writeln!(out, "[Timings]")?;
+    for stage in Stage::ALL {
+        match timing::percentiles(stage) {
+            Some(p) => writeln!(
+                out,
+                "{stage}: p50={} p90={} p99={} max={} (n={})",
+                display_duration(p.p50),
+                display_duration(p.p90),
+                display_duration(p.p99),
+                display_duration(p.max),
+                p.samples
+            )?,
+            None => writeln!(out, "{stage}: no samples")?,
+        }
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn write_spotify_versions(out: &mut String) -> std::fmt::Result {
+    let versions = stats::get().all_time.spotify_versions.clone();
+    if versions.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "[Spotify versions]")?;
+    for version in versions {
+        writeln!(
+            out,
+            "{}: seen {} to {}, hooked {} times, {} failures, {} ads played",
+            version.version,
+            version.first_seen.format("%Y-%m-%d"),
+            version.last_seen.format("%Y-%m-%d"),
+            version.hooks,
+            version.hook_failures,
+            version.ad_slips
+        )?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn write_blocker_perf(out: &mut String) -> std::fmt::Result {
+    let Some(perf) = status::get().blocker_perf.clone() else {
+        return Ok(());
+    };
+    writeln!(out, "[Blocker performance]")?;
+    for hook in &perf.hooks {
+        writeln!(
+            out,
+            "{}: calls={} mean={} max={} total={}",
+            hook.hook,
+            hook.calls,
+            display_duration(hook.mean()),
+            display_duration(hook.max),
+            display_duration(hook.total)
+        )?;
+    }
+    writeln!(
+        out,
+        "Pending logs: {} (max {})",
+        perf.pending_logs, perf.max_pending_logs
+    )?;
+    writeln!(
+        out,
+        "Spotify working set: {:.1}MiB",
+        perf.working_set_bytes as f64 / (1024.0 * 1024.0)
+    )?;
+    writeln!(out, "Spotify CPU time: {:.1}s", perf.cpu_time.as_secs_f64())?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn write_status(out: &mut String) -> std::fmt::Result {
+    let status = status::get().clone();
+    writeln!(out, "[Status]")?;
+    writeln!(out, "State: {}", status.hook)?;
+    if let Some(next_retry) = status.next_retry {
+        writeln!(
+            out,
+            "Next retry: {}",
+            next_retry.format("%Y-%m-%d %H:%M:%S")
+        )?;
+    }
+    if let Some(last_error) = status.last_error {
+        writeln!(
+            out,
+            "Last error: {} ({})",
+            last_error.message,
+            last_error.time.format("%Y-%m-%d %H:%M:%S")
+        )?;
+    }
+    match status.spotify {
+        Some(spotify) => {
+            writeln!(out, "Spotify PID: {}", display_opt(spotify.pid))?;
+            writeln!(out, "Spotify path: {}", display_path(spotify.path))?;
+            writeln!(out, "Spotify version: {}", display_opt(spotify.version))?;
+            writeln!(
+                out,
+                "Blocker version: {}",
+                display_opt(status.blocker_version)
+            )?;
+            if let Some(Ok(Some(mismatch))) = spotify.pid.map(IntegrityMismatch::check) {
+                writeln!(out, "Integrity mismatch: {mismatch}, {}", mismatch.advice())?;
+            }
+            if let Some(internals) = spotify.internals {
+                writeln!(
+                    out,
+                    "Spotify CEF: {} (API hash {})",
+                    internals.cef_version, internals.cef_api_hash
+                )?;
+            }
+            for conflict in spotify.conflicts {
+                writeln!(out, "Conflict: {conflict}")?;
+            }
+        }
+        None => writeln!(out, "Spotify: not found")?,
+    }
+    for blocker in status.stray_blockers {
+        writeln!(out, "Stray blocker: {blocker}")?;
+    }
+    for url in status.uncovered_canaries {
+        writeln!(out, "Ad endpoint not blocked: {url}")?;
+    }
+    for failure in remote::failures() {
+        writeln!(
+            out,
+            "Filter list update failed: {} ({} times, next attempt at {}): {}",
+            failure.url,
+            failure.count,
+            failure.retry_at.format("%Y-%m-%d %H:%M:%S"),
+            failure.error
+        )?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn windows_version() -> Option<String> {
+    let system_root = env::var_os("SystemRoot")?;
+    utils::file_version(
+        &PathBuf::from(system_root)
+            .join("System32")
+            .join("kernel32.dll"),
+    )
+}
+
+fn display_path(path: Option<PathBuf>) -> String {
+    path.map_or_else(|| "<none>".to_string(), |p| p.display().to_string())
+}
+
+fn display_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn display_opt(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "<unknown>".to_string(), |v| v.to_string())
+}
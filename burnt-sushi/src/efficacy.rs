@@ -0,0 +1,105 @@
+//! Watches the share of requests that are blocked. Spotify moving its ads to new endpoints shows up
+//! as a sharp drop, after which the filter lists are refreshed and the app checks for an update
+//! that supports the new endpoints.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{filter_providers, update};
+
+/// Requests are counted in windows of this length.
+const WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Windows with fewer requests are ignored, e.g. while Spotify is idle.
+const MIN_REQUESTS: u64 = 100;
+/// Windows needed for a baseline before drops are detected.
+const MIN_BASELINE_WINDOWS: u32 = 3;
+/// Weight of the latest window in the baseline.
+const BASELINE_WEIGHT: f64 = 0.2;
+/// A window whose share of blocked requests is below this fraction of the baseline is a drop.
+const DROP_FACTOR: f64 = 0.25;
+/// Shortest time between two refreshes triggered by a drop.
+const COOLDOWN: Duration = Duration::from_secs(6 * 60 * 60);
+
+static EFFICACY: Mutex<Efficacy> = Mutex::new(Efficacy::new());
+
+struct Efficacy {
+    window_start: Option<Instant>,
+    blocked: u64,
+    total: u64,
+    /// Average share of blocked requests of the previous windows.
+    baseline: f64,
+    baseline_windows: u32,
+    last_triggered: Option<Instant>,
+}
+
+impl Efficacy {
+    const fn new() -> Self {
+        Self {
+            window_start: None,
+            blocked: 0,
+            total: 0,
+            baseline: 0.0,
+            baseline_windows: 0,
+            last_triggered: None,
+        }
+    }
+
+    /// Ends the current window and returns the share of blocked requests and the baseline if it
+    /// dropped.
+    fn close_window(&mut self) -> Option<(f64, f64)> {
+        let (blocked, total) = (self.blocked, self.total);
+        self.blocked = 0;
+        self.total = 0;
+        if total < MIN_REQUESTS {
+            return None;
+        }
+
+        let ratio = blocked as f64 / total as f64;
+        if self.baseline_windows >= MIN_BASELINE_WINDOWS && ratio < self.baseline * DROP_FACTOR {
+            // Kept out of the baseline, so that it still detects the drop in the next window.
+            return Some((ratio, self.baseline));
+        }
+
+        self.baseline = if self.baseline_windows == 0 {
+            ratio
+        } else {
+            self.baseline * (1.0 - BASELINE_WEIGHT) + ratio * BASELINE_WEIGHT
+        };
+        self.baseline_windows += 1;
+        None
+    }
+}
+
+/// Counts a request reported by the blocker.
+pub fn record(blocked: bool) {
+    let mut efficacy = EFFICACY.lock().unwrap();
+    let now = Instant::now();
+    let window_start = *efficacy.window_start.get_or_insert(now);
+    if now.duration_since(window_start) >= WINDOW {
+        efficacy.window_start = Some(now);
+        if let Some((ratio, baseline)) = efficacy.close_window() {
+            if efficacy
+                .last_triggered
+                .is_some_and(|last| last.elapsed() < COOLDOWN)
+            {
+                debug!("Blocked share dropped again, filters were refreshed recently");
+            } else {
+                efficacy.last_triggered = Some(now);
+                warn!(
+                    "Share of blocked requests dropped from {:.1}% to {:.1}%, Spotify may have changed its ad endpoints",
+                    baseline * 100.0,
+                    ratio * 100.0
+                );
+                filter_providers::request_refresh();
+                update::request_background_check();
+            }
+        }
+    }
+
+    efficacy.total += 1;
+    efficacy.blocked += u64::from(blocked);
+}
@@ -0,0 +1,124 @@
+//! Locations and settings of the system the blocker and filter loaders depend on. They are passed
+//! in as an [`Environment`] instead of being queried directly, so the loaders can be pointed at
+//! fixture directories.
+
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+};
+
+use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+
+pub trait Environment: Send + Sync {
+    /// Path of the running executable.
+    fn current_exe(&self) -> io::Result<PathBuf>;
+
+    /// Directory for temporary files of the user (`%TEMP%`).
+    fn temp_dir(&self) -> PathBuf;
+
+    /// Roaming app data directory of the user (`%APPDATA%`).
+    fn app_data_dir(&self) -> Option<PathBuf>;
+
+    /// Local app data directory of the user (`%LOCALAPPDATA%`).
+    fn local_app_data_dir(&self) -> Option<PathBuf>;
+
+    /// String value of a key below `HKEY_CURRENT_USER`, `None` if the key or value does not exist.
+    fn user_registry_value(&self, subkey: &str, name: &str) -> io::Result<Option<String>>;
+
+    /// Directory containing the running executable.
+    fn exe_dir(&self) -> Option<PathBuf> {
+        self.current_exe().ok()?.parent().map(Path::to_path_buf)
+    }
+}
+
+/// The environment the app runs in.
+pub struct System;
+
+impl Environment for System {
+    fn current_exe(&self) -> io::Result<PathBuf> {
+        env::current_exe()
+    }
+
+    fn temp_dir(&self) -> PathBuf {
+        env::temp_dir()
+    }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        dirs::data_dir()
+    }
+
+    fn local_app_data_dir(&self) -> Option<PathBuf> {
+        dirs::data_local_dir()
+    }
+
+    fn user_registry_value(&self, subkey: &str, name: &str) -> io::Result<Option<String>> {
+        let value = RegKey::predef(HKEY_CURRENT_USER)
+            .open_subkey(subkey)
+            .and_then(|key| key.get_value::<String, _>(name));
+        match value {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Environment rooted in a fixture directory, removed again when it is dropped.
+#[cfg(test)]
+pub struct Fixture {
+    pub root: PathBuf,
+    /// Temp directory outside of the fixture's `%LOCALAPPDATA%`, which is used otherwise.
+    pub temp_dir: Option<PathBuf>,
+    pub registry: std::collections::HashMap<(String, String), String>,
+}
+
+#[cfg(test)]
+impl Fixture {
+    /// Creates an empty fixture directory named after the test.
+    pub fn new(name: &str) -> Self {
+        let root =
+            env::temp_dir().join(format!("burnt-sushi-fixture-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        Self {
+            root,
+            temp_dir: None,
+            registry: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[cfg(test)]
+impl Environment for Fixture {
+    fn current_exe(&self) -> io::Result<PathBuf> {
+        Ok(self.root.join("app").join("BurntSushi.exe"))
+    }
+
+    fn temp_dir(&self) -> PathBuf {
+        self.temp_dir
+            .clone()
+            .unwrap_or_else(|| self.root.join("Local").join("Temp"))
+    }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        Some(self.root.join("Roaming"))
+    }
+
+    fn local_app_data_dir(&self) -> Option<PathBuf> {
+        Some(self.root.join("Local"))
+    }
+
+    fn user_registry_value(&self, subkey: &str, name: &str) -> io::Result<Option<String>> {
+        Ok(self
+            .registry
+            .get(&(subkey.to_string(), name.to_string()))
+            .cloned())
+    }
+}
@@ -0,0 +1,64 @@
+//! Bus of app events. Components publish what happened and optional consumers like the screen
+//! reader announcements, the `on_hooked` script callback and the webhook subscribe to it, so that
+//! new consumers do not require changes to the publishers. The bus drops events for subscribers
+//! that fall behind, so the stats, the efficacy and the request callbacks of scripts are updated
+//! directly where the requests are handled instead.
+
+use std::{sync::LazyLock, time::SystemTime};
+
+use log::warn;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{shutdown, status::HookStatus};
+
+/// Events buffered per subscriber, a subscriber that falls further behind misses the oldest ones.
+const CAPACITY: usize = 1024;
+
+static EVENTS: LazyLock<broadcast::Sender<AppEvent>> =
+    LazyLock::new(|| broadcast::channel(CAPACITY).0);
+
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    /// Spotify was found and is about to be hooked.
+    SpotifyFound,
+    /// The hook status changed.
+    StatusChanged(HookStatus),
+    /// The blocker was injected and is blocking ads.
+    Injected { pid: Option<u32> },
+    /// The blocker was ejected.
+    Unhooked,
+    /// The blocker blocked a request at `time`, attributed to `rule` if known.
+    Blocked {
+        rule: Option<String>,
+        time: SystemTime,
+    },
+    /// Hooking failed in a way the user is told about.
+    Error { message: String },
+    /// Spotify played an ad while it was hooked.
+    AdSlipped,
+    /// A newer version of the app was released.
+    UpdateAvailable { version: String },
+}
+
+/// Passes the event to all subscribers.
+pub fn publish(event: AppEvent) {
+    // Fails only if there are no subscribers, e.g. while running a command.
+    let _ = EVENTS.send(event);
+}
+
+/// Runs the handler for every published event until shutdown. Subscribes right away, so that no
+/// event published after this call is missed.
+pub fn subscribe(name: &'static str, mut handler: impl FnMut(AppEvent) + Send + 'static) {
+    let mut events = EVENTS.subscribe();
+    shutdown::spawn(name, async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => handler(event),
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Subscriber '{name}' fell behind and missed {missed} events")
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
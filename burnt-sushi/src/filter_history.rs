@@ -0,0 +1,241 @@
+//! History of the effective filter rules, so that changes can be reviewed and a previous rule set
+//! can be pinned with `history revert`. Changes are appended, only the last [`MAX_REVISIONS`] are
+//! kept.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::Context;
+use burnt_sushi_core::filters::FilterConfig;
+use chrono::Local;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{environment::System, paths};
+
+/// Number of changes kept in the history, older ones are dropped when a change is recorded.
+const MAX_REVISIONS: usize = 50;
+
+/// What caused the effective rules to change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeSource {
+    /// Rules loaded when hooking Spotify.
+    Startup,
+    /// Reload requested by the user, e.g. after editing a filter file.
+    Reload,
+    /// Periodic refresh of the filter sources.
+    Refresh,
+    /// Rules added by a script.
+    Script,
+    /// Rules pinned by `history revert`.
+    Revert,
+    /// Rule added from the recent activity in the tray menu.
+    Activity,
+    /// Remote list added or removed in the subscription manager.
+    Subscriptions,
+}
+
+impl fmt::Display for ChangeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeSource::Startup => write!(f, "startup"),
+            ChangeSource::Reload => write!(f, "reload"),
+            ChangeSource::Refresh => write!(f, "refresh"),
+            ChangeSource::Script => write!(f, "script"),
+            ChangeSource::Revert => write!(f, "revert"),
+            ChangeSource::Activity => write!(f, "activity"),
+            ChangeSource::Subscriptions => write!(f, "subscriptions"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// RFC 3339 timestamp in local time.
+    pub time: String,
+    pub source: ChangeSource,
+    pub allowlist: Vec<String>,
+    pub denylist: Vec<String>,
+}
+
+impl Entry {
+    fn filter_config(&self) -> FilterConfig {
+        FilterConfig {
+            allowlist: self.allowlist.clone(),
+            denylist: self.denylist.clone(),
+        }
+    }
+
+    fn rules(&self) -> impl Iterator<Item = &String> {
+        self.allowlist.iter().chain(&self.denylist)
+    }
+}
+
+/// Layout of the history file, each change is appended as a `[[change]]` table.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct History {
+    #[serde(default)]
+    change: Vec<Entry>,
+}
+
+/// Last recorded rules, read from the history file on first use.
+static LAST: LazyLock<Mutex<Option<FilterConfig>>> = LazyLock::new(|| {
+    let last = match load() {
+        Ok(entries) => entries.last().map(Entry::filter_config),
+        Err(e) => {
+            warn!("Failed to read filter history: {e:#}");
+            None
+        }
+    };
+    Mutex::new(last)
+});
+
+/// Appends the effective rules to the history if they differ from the last recorded ones.
+pub fn record(filter_config: &FilterConfig, source: ChangeSource) {
+    let mut last = LAST.lock().unwrap();
+    if last.as_ref() == Some(filter_config) {
+        return;
+    }
+
+    let entry = Entry {
+        time: Local::now().to_rfc3339(),
+        source,
+        allowlist: filter_config.allowlist.clone(),
+        denylist: filter_config.denylist.clone(),
+    };
+    match append(entry) {
+        Ok(()) => {
+            debug!("Recorded filter change ({source})");
+            *last = Some(filter_config.clone());
+        }
+        Err(e) => warn!("Failed to record filter change: {e:#}"),
+    }
+}
+
+fn append(entry: Entry) -> anyhow::Result<()> {
+    let path =
+        paths::filter_history_file(&System).context("Failed to locate app data directory.")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create app data directory.")?;
+    }
+    let contents = toml::to_string(&History {
+        change: vec![entry],
+    })
+    .context("Failed to serialize filter change.")?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context("Failed to open filter history.")?;
+    writeln!(file, "{contents}").context("Failed to write filter history.")?;
+    drop(file);
+
+    let entries = load()?;
+    if entries.len() > MAX_REVISIONS {
+        let kept = entries[entries.len() - MAX_REVISIONS..].to_vec();
+        let contents = toml::to_string(&History { change: kept })
+            .context("Failed to serialize filter history.")?;
+        fs::write(&path, contents).context("Failed to write filter history.")?;
+    }
+    Ok(())
+}
+
+/// Reads all recorded changes, oldest first.
+pub fn load() -> anyhow::Result<Vec<Entry>> {
+    let path =
+        paths::filter_history_file(&System).context("Failed to locate app data directory.")?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read filter history."),
+    };
+    let history =
+        toml::from_str::<History>(&contents).context("Failed to parse filter history.")?;
+    Ok(history.change)
+}
+
+/// Lists the recorded changes with the number of rules added and removed by each.
+pub fn summary() -> anyhow::Result<String> {
+    let entries = load()?;
+    if entries.is_empty() {
+        return Ok("No filter changes recorded yet.".to_string());
+    }
+
+    let mut summary = String::new();
+    let mut previous = HashSet::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let rules = entry.rules().collect::<HashSet<_>>();
+        let added = rules.difference(&previous).count();
+        let removed = previous.difference(&rules).count();
+        summary += &format!(
+            "#{:<4} {}  {:<8} {} allowed, {} denied (+{added} -{removed})\n",
+            i + 1,
+            entry.time,
+            entry.source,
+            entry.allowlist.len(),
+            entry.denylist.len()
+        );
+        previous = rules;
+    }
+    if pinned().is_some() {
+        summary += "\nRules are pinned by `history revert`, run `history unpin` to undo.\n";
+    }
+    Ok(summary.trim_end().to_string())
+}
+
+/// Pins the rules of the change with the given number (as listed by [`summary`]) so that they are
+/// used instead of the filter sources.
+pub fn revert(id: usize) -> anyhow::Result<()> {
+    let entries = load()?;
+    let entry = id
+        .checked_sub(1)
+        .and_then(|index| entries.get(index))
+        .with_context(|| format!("No filter change #{id} recorded."))?;
+
+    let path = paths::filter_pin_file(&System).context("Failed to locate app data directory.")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create app data directory.")?;
+    }
+    let contents =
+        toml::to_string_pretty(&entry.filter_config()).context("Failed to serialize rules.")?;
+    fs::write(&path, contents).context("Failed to write pinned rules.")?;
+    info!("Pinned the rules of filter change #{id}");
+    Ok(())
+}
+
+/// Removes the pinned rules, returns whether rules were pinned.
+pub fn unpin() -> anyhow::Result<bool> {
+    let path = paths::filter_pin_file(&System).context("Failed to locate app data directory.")?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).context("Failed to remove pinned rules."),
+    }
+}
+
+/// Rules pinned by [`revert`], used instead of the filter sources.
+pub fn pinned() -> Option<FilterConfig> {
+    let path = paths::filter_pin_file(&System)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Failed to read pinned rules: {e}");
+            return None;
+        }
+    };
+    match toml::from_str(&contents) {
+        Ok(filter_config) => Some(filter_config),
+        Err(e) => {
+            warn!("Failed to parse pinned rules, ignoring them: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Import of Adblock Plus filter lists.
+//!
+//! Only network rules are supported: `||domain^` and plain url patterns are added to the
+//! denylist, `@@||domain^` exceptions to the allowlist. Cosmetic rules and options are ignored.
+
+use burnt_sushi_core::filters::FilterConfig;
+use log::debug;
+
+pub fn parse(contents: &str) -> FilterConfig {
+    let mut filter_config = FilterConfig {
+        allowlist: Vec::new(),
+        denylist: Vec::new(),
+    };
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            continue;
+        }
+        if line.contains("##") || line.contains("#@#") || line.contains("#?#") {
+            // cosmetic rule
+            continue;
+        }
+
+        if let Some(exception) = line.strip_prefix("@@") {
+            // Hosts are matched by the allowlist, so only domain exceptions can be imported.
+            match strip_options(exception).strip_prefix("||") {
+                Some(domain) => filter_config.allowlist.push(domain_pattern(domain)),
+                None => debug!("Skipping unsupported exception '{line}'"),
+            }
+            continue;
+        }
+
+        filter_config
+            .denylist
+            .push(url_pattern(strip_options(line)));
+    }
+
+    filter_config
+}
+
+fn strip_options(rule: &str) -> &str {
+    // Regex rules can contain `$` themselves.
+    if rule.starts_with('/') {
+        return rule;
+    }
+    rule.split_once('$').map_or(rule, |(rule, _)| rule)
+}
+
+/// Pattern matching a host name and its subdomains.
+fn domain_pattern(domain: &str) -> String {
+    let domain = domain.trim_end_matches(['^', '|', '/']);
+    format!("^([^.]+\\.)*{}$", wildcard_pattern(domain))
+}
+
+/// Pattern matching a full url.
+fn url_pattern(rule: &str) -> String {
+    if let Some(regex) = rule
+        .strip_prefix('/')
+        .and_then(|rule| rule.strip_suffix('/'))
+    {
+        return regex.to_string();
+    }
+
+    let (prefix, rule) = if let Some(rule) = rule.strip_prefix("||") {
+        ("^[a-z][a-z0-9+.-]*://([^/?#]*\\.)?", rule)
+    } else if let Some(rule) = rule.strip_prefix('|') {
+        ("^", rule)
+    } else {
+        ("", rule)
+    };
+    let (rule, suffix) = match rule.strip_suffix('|') {
+        Some(rule) => (rule, "$"),
+        None => (rule, ""),
+    };
+    format!("{prefix}{}{suffix}", wildcard_pattern(rule))
+}
+
+/// Escapes a rule, translating `*` and the `^` separator placeholder.
+fn wildcard_pattern(rule: &str) -> String {
+    rule.split('*')
+        .map(|part| {
+            part.split('^')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join("([^a-zA-Z0-9_.%-]|$)")
+        })
+        .collect::<Vec<_>>()
+        .join(".*")
+}
@@ -0,0 +1,58 @@
+use std::{io, path::PathBuf};
+
+use burnt_sushi_core::filters::FilterConfig;
+use futures::{future::BoxFuture, FutureExt};
+
+use super::{FilterFormat, FilterProvider};
+use crate::{environment::Environment, resolver::resolve_filter_config};
+
+/// The `filter.toml` passed on the command line, next to the executable or the embedded default.
+pub struct DefaultProvider {
+    environment: &'static dyn Environment,
+    provided_path: Option<PathBuf>,
+}
+
+impl DefaultProvider {
+    pub fn new(environment: &'static dyn Environment, provided_path: Option<PathBuf>) -> Self {
+        Self {
+            environment,
+            provided_path,
+        }
+    }
+}
+
+impl FilterProvider for DefaultProvider {
+    fn name(&self) -> String {
+        "default".to_string()
+    }
+
+    fn load(&self) -> BoxFuture<'_, io::Result<FilterConfig>> {
+        resolve_filter_config(self.environment, self.provided_path.as_deref()).boxed()
+    }
+}
+
+/// Filter list in a local file.
+pub struct LocalProvider {
+    path: PathBuf,
+    format: FilterFormat,
+}
+
+impl LocalProvider {
+    pub fn new(path: PathBuf, format: FilterFormat) -> Self {
+        Self { path, format }
+    }
+}
+
+impl FilterProvider for LocalProvider {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> BoxFuture<'_, io::Result<FilterConfig>> {
+        async move {
+            let contents = tokio::fs::read_to_string(&self.path).await?;
+            self.format.parse(&contents)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,348 @@
+//! Sources of filter rules that are merged into the config sent to the blocker.
+//!
+//! Additional providers, e.g. ones compiled in behind a feature flag, are added with [`register`]
+//! before the first filter config is loaded.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use burnt_sushi_core::filters::{FilterConfig, FilterTests};
+use chrono::NaiveDate;
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{sync::Notify, time::MissedTickBehavior};
+
+use crate::{
+    args::ARGS, environment::System, filter_history::ChangeSource, power, settings, user_rules,
+};
+
+pub mod abp;
+pub mod local;
+pub mod remote;
+pub mod rules;
+pub mod subscriptions;
+
+pub use local::{DefaultProvider, LocalProvider};
+pub use remote::RemoteProvider;
+use rules::RuleFile;
+
+/// A source of filter rules.
+pub trait FilterProvider: Send + Sync {
+    /// Name of the source used in logs.
+    fn name(&self) -> String;
+
+    /// Loads the current rules, fetching them again if the source is remote.
+    fn load(&self) -> BoxFuture<'_, io::Result<FilterConfig>>;
+
+    /// Date from which the rules of the source are ignored.
+    fn expires(&self) -> Option<NaiveDate> {
+        None
+    }
+}
+
+static PROVIDERS: Mutex<Vec<Arc<dyn FilterProvider>>> = Mutex::new(Vec::new());
+static REFRESH_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+/// Cause of the pending refresh, recorded in the filter history.
+static REFRESH_SOURCE: Mutex<ChangeSource> = Mutex::new(ChangeSource::Refresh);
+
+/// Adds a provider whose rules are merged into the filter config.
+pub fn register(provider: Arc<dyn FilterProvider>) {
+    debug!("Registered filter provider '{}'", provider.name());
+    PROVIDERS.lock().unwrap().push(provider);
+}
+
+/// Removes the providers with the given name.
+pub fn unregister(name: &str) {
+    PROVIDERS
+        .lock()
+        .unwrap()
+        .retain(|provider| provider.name() != name);
+    debug!("Unregistered filter provider '{name}'");
+}
+
+/// Registers the rules added by the user and the providers configured in the settings.
+pub fn register_configured() {
+    register(Arc::new(user_rules::UserRulesProvider));
+    for source in settings::get().filter_sources.clone() {
+        register(source.into_provider());
+    }
+}
+
+/// Format of a filter list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterFormat {
+    /// `allowlist` and `denylist` of regular expressions like `filter.toml`.
+    #[default]
+    Toml,
+    /// The same structure as [`FilterFormat::Toml`] in JSON, e.g. generated by other tools.
+    Json,
+    /// The same structure as [`FilterFormat::Toml`] in YAML.
+    Yaml,
+    /// Adblock Plus filter list.
+    Abp,
+}
+
+impl FilterFormat {
+    /// Format of a filter file detected by its extension, `None` for unknown extensions.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "toml" => Some(FilterFormat::Toml),
+            "json" => Some(FilterFormat::Json),
+            "yaml" | "yml" => Some(FilterFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, contents: &str) -> io::Result<FilterConfig> {
+        match self {
+            FilterFormat::Abp => Ok(abp::parse(contents)),
+            _ => self
+                .deserialize::<RuleFile>(contents)
+                .map(RuleFile::into_config),
+        }
+    }
+
+    /// Reads a structured filter file, e.g. only its `tests`.
+    pub fn deserialize<T: DeserializeOwned>(self, contents: &str) -> io::Result<T> {
+        let invalid_data = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        match self {
+            FilterFormat::Toml => toml::from_str(contents).map_err(|e| invalid_data(e.to_string())),
+            FilterFormat::Json => {
+                serde_json::from_str(contents).map_err(|e| invalid_data(e.to_string()))
+            }
+            FilterFormat::Yaml => {
+                serde_yaml::from_str(contents).map_err(|e| invalid_data(e.to_string()))
+            }
+            FilterFormat::Abp => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Adblock Plus lists only contain rules",
+            )),
+        }
+    }
+
+    pub fn serialize(self, value: &impl Serialize) -> io::Result<String> {
+        let invalid_data = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        match self {
+            FilterFormat::Toml => {
+                toml::to_string_pretty(value).map_err(|e| invalid_data(e.to_string()))
+            }
+            FilterFormat::Json => {
+                serde_json::to_string_pretty(value).map_err(|e| invalid_data(e.to_string()))
+            }
+            FilterFormat::Yaml => {
+                serde_yaml::to_string(value).map_err(|e| invalid_data(e.to_string()))
+            }
+            FilterFormat::Abp => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Writing Adblock Plus lists is not supported",
+            )),
+        }
+    }
+}
+
+/// Rules and tests of a filter file.
+#[derive(Debug, Serialize, Deserialize)]
+struct FilterFile {
+    #[serde(flatten)]
+    rules: RuleFile,
+    #[serde(flatten)]
+    tests: FilterTests,
+}
+
+/// Converts a filter file between the formats picked by the file extensions, keeping its tests
+/// and the expiration dates of its rules.
+pub fn convert(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let format_of = |path: &Path| {
+        FilterFormat::from_path(path).with_context(|| {
+            format!(
+                "Unknown format of '{}', expected .toml, .json or .yaml.",
+                path.display()
+            )
+        })
+    };
+    let (input_format, output_format) = (format_of(input)?, format_of(output)?);
+
+    let contents = fs::read_to_string(input)
+        .with_context(|| format!("Failed to read '{}'.", input.display()))?;
+    let file: FilterFile = input_format
+        .deserialize(&contents)
+        .with_context(|| format!("Failed to parse '{}'.", input.display()))?;
+    let contents = output_format
+        .serialize(&file)
+        .context("Failed to serialize filters.")?;
+    fs::write(output, contents).with_context(|| format!("Failed to write '{}'.", output.display()))
+}
+
+/// Additional filter source configured in the settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum FilterSource {
+    /// The format is detected by the file extension if not given.
+    Local {
+        path: PathBuf,
+        #[serde(default)]
+        format: Option<FilterFormat>,
+        #[serde(default)]
+        expires: Option<NaiveDate>,
+    },
+    Remote {
+        url: String,
+        #[serde(default)]
+        format: FilterFormat,
+        #[serde(default)]
+        expires: Option<NaiveDate>,
+    },
+}
+
+impl FilterSource {
+    fn into_provider(self) -> Arc<dyn FilterProvider> {
+        let (provider, expires): (Arc<dyn FilterProvider>, _) = match self {
+            FilterSource::Local {
+                path,
+                format,
+                expires,
+            } => {
+                let format = format
+                    .or_else(|| FilterFormat::from_path(&path))
+                    .unwrap_or_default();
+                (Arc::new(LocalProvider::new(path, format)), expires)
+            }
+            FilterSource::Remote {
+                url,
+                format,
+                expires,
+            } => (Arc::new(RemoteProvider::new(url, format)), expires),
+        };
+        match expires {
+            Some(expires) => Arc::new(ExpiringProvider { provider, expires }),
+            None => provider,
+        }
+    }
+}
+
+/// Source configured with an `expires` date.
+struct ExpiringProvider {
+    provider: Arc<dyn FilterProvider>,
+    expires: NaiveDate,
+}
+
+impl FilterProvider for ExpiringProvider {
+    fn name(&self) -> String {
+        self.provider.name()
+    }
+
+    fn load(&self) -> BoxFuture<'_, io::Result<FilterConfig>> {
+        self.provider.load()
+    }
+
+    fn expires(&self) -> Option<NaiveDate> {
+        Some(self.expires)
+    }
+}
+
+/// Loads the default filter config and merges the rules of all registered providers into it.
+/// Only the default config is required, other providers that fail are skipped.
+pub async fn load() -> io::Result<FilterConfig> {
+    let mut filter_config = DefaultProvider::new(&System, ARGS.filters.clone())
+        .load()
+        .await?;
+
+    let providers = PROVIDERS.lock().unwrap().clone();
+    for provider in providers {
+        if let Some(expires) = provider
+            .expires()
+            .filter(|&expires| rules::is_expired(expires))
+        {
+            warn!(
+                "Ignoring filters from '{}', which expired on {expires}",
+                provider.name()
+            );
+            continue;
+        }
+        match provider.load().await {
+            Ok(mut rules) => {
+                drop_invalid_rules(&mut rules, &provider.name());
+                info!(
+                    "Loaded {} rules from '{}'",
+                    rules.allowlist.len() + rules.denylist.len(),
+                    provider.name()
+                );
+                merge(&mut filter_config, rules);
+            }
+            Err(e) => warn!("Failed to load filters from '{}': {e}", provider.name()),
+        }
+    }
+    remote::notify_failures();
+    crate::subscription_manager::lists_updated();
+
+    Ok(filter_config)
+}
+
+/// Removes the rules that are not valid regular expressions, so that a single broken rule of a list
+/// does not prevent all filters from compiling.
+fn drop_invalid_rules(rules: &mut FilterConfig, provider: &str) {
+    for list in [&mut rules.allowlist, &mut rules.denylist] {
+        list.retain(|rule| match Regex::new(rule) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Ignoring invalid rule '{rule}' from '{provider}': {e}");
+                false
+            }
+        });
+    }
+}
+
+fn merge(filter_config: &mut FilterConfig, rules: FilterConfig) {
+    fn merge_list(list: &mut Vec<String>, rules: Vec<String>) {
+        let mut known = list.iter().cloned().collect::<HashSet<_>>();
+        list.extend(rules.into_iter().filter(|rule| known.insert(rule.clone())));
+    }
+
+    merge_list(&mut filter_config.allowlist, rules.allowlist);
+    merge_list(&mut filter_config.denylist, rules.denylist);
+}
+
+/// Asks the blocker to reload the filters from all providers every `filter-refresh-hours`, deferred
+/// while the session is idle.
+pub async fn refresh_periodically() {
+    let Some(hours) = settings::get().filter_refresh_hours else {
+        return;
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(hours.max(1) * 60 * 60));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        power::wait_until_active("filter refresh").await;
+        debug!("Refreshing filters");
+        notify_refresh(ChangeSource::Refresh);
+    }
+}
+
+/// Asks the blocker to reload the filters from all providers.
+pub fn request_refresh() {
+    notify_refresh(ChangeSource::Reload);
+}
+
+/// Asks the blocker to reload the filters from all providers after a change from the given source.
+pub fn notify_refresh(source: ChangeSource) {
+    *REFRESH_SOURCE.lock().unwrap() = source;
+    REFRESH_REQUESTED.notify_one();
+}
+
+/// Waits until the filters should be reloaded and returns why.
+pub async fn refresh_requested() -> ChangeSource {
+    REFRESH_REQUESTED.notified().await;
+    *REFRESH_SOURCE.lock().unwrap()
+}
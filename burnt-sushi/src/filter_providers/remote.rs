@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use burnt_sushi_core::filters::FilterConfig;
+use chrono::{DateTime, Local, TimeDelta};
+use futures::{future::BoxFuture, FutureExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{FilterFormat, FilterProvider};
+use crate::{
+    environment::System,
+    i18n::{tr, tr_args, Msg},
+    notify, paths,
+};
+
+/// Consecutive failed updates of a list after which the user is notified.
+const NOTIFY_AFTER_FAILURES: u32 = 3;
+/// Delay before updating a list again after the first failure, doubled with each further one.
+const MIN_BACKOFF: TimeDelta = TimeDelta::minutes(5);
+const MAX_BACKOFF: TimeDelta = TimeDelta::hours(6);
+/// Time a download of a list may take, so that a stalled server counts as a failed update.
+const TIMEOUT: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .expect("failed to initialize HTTP client")
+});
+
+static FAILURES: LazyLock<Mutex<HashMap<String, UpdateFailure>>> = LazyLock::new(Default::default);
+
+/// Failed updates of a remote list since it was last downloaded.
+#[derive(Debug, Clone)]
+pub struct UpdateFailure {
+    pub url: String,
+    /// Number of consecutive failures.
+    pub count: u32,
+    pub error: String,
+    /// Until when the cached list is used without trying to download it.
+    pub retry_at: DateTime<Local>,
+    notified: bool,
+}
+
+/// Last download of a remote list, stored next to its cache so that it can be shown without
+/// parsing the list.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CachedList {
+    pub downloaded: DateTime<Local>,
+    pub rule_count: usize,
+}
+
+/// Filter list downloaded from a url. The last successful download is cached and used while
+/// the list cannot be fetched, with the next attempt delayed further after each failure.
+pub struct RemoteProvider {
+    url: String,
+    format: FilterFormat,
+}
+
+impl RemoteProvider {
+    pub fn new(url: String, format: FilterFormat) -> Self {
+        Self { url, format }
+    }
+
+    async fn fetch(&self) -> io::Result<String> {
+        let response = CLIENT
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(io::Error::other)?;
+        response.text().await.map_err(io::Error::other)
+    }
+}
+
+impl FilterProvider for RemoteProvider {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn load(&self) -> BoxFuture<'_, io::Result<FilterConfig>> {
+        async move {
+            let cache_path = cache_path(&self.url);
+            if let Some(retry_at) = backoff_until(&self.url) {
+                debug!("Using cached list of '{}' until {retry_at}", self.url);
+                let cache_path = cache_path.ok_or_else(|| {
+                    io::Error::other(format!("Not retrying before {}", retry_at.format("%H:%M")))
+                })?;
+                let contents = tokio::fs::read_to_string(cache_path).await?;
+                return self.format.parse(&contents);
+            }
+
+            // Only lists that can be parsed count as updated and are cached.
+            let update = self.fetch().await.and_then(|contents| {
+                self.format
+                    .parse(&contents)
+                    .map(|config| (contents, config))
+            });
+            match update {
+                Ok((contents, config)) => {
+                    record_success(&self.url);
+                    if let Some(cache_path) = &cache_path {
+                        let list = CachedList {
+                            downloaded: Local::now(),
+                            rule_count: config.allowlist.len() + config.denylist.len(),
+                        };
+                        if let Err(e) = write_cache(cache_path, &contents, &list).await {
+                            debug!("Failed to cache filter list: {e}");
+                        }
+                    }
+                    Ok(config)
+                }
+                Err(e) => {
+                    record_failure(&self.url, &e);
+                    let cache_path = cache_path.ok_or(e)?;
+                    warn!("Failed to update '{}', using cached list", self.url);
+                    let contents = tokio::fs::read_to_string(cache_path).await?;
+                    self.format.parse(&contents)
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let hash = Sha256::digest(url.as_bytes());
+    let name = hash[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    paths::filter_cache_dir(&System).map(|dir| dir.join(name))
+}
+
+/// Path of the [`CachedList`] describing the cache at `cache_path`.
+fn info_path(cache_path: &Path) -> PathBuf {
+    cache_path.with_extension("json")
+}
+
+/// When the list at the url was last downloaded and how many rules it had, `None` if it was not
+/// downloaded since its rules are counted.
+pub fn cached_list(url: &str) -> Option<CachedList> {
+    let path = info_path(&cache_path(url)?);
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Deletes the cached download of the list at the url and forgets its failed updates.
+pub fn forget(url: &str) {
+    FAILURES.lock().unwrap().remove(url);
+    if let Some(path) = cache_path(url) {
+        for path in [info_path(&path), path] {
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to delete cached list of '{url}': {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Lets the next load try to download all lists again, even those waiting after failed updates.
+pub fn retry_now() {
+    let now = Local::now();
+    for failure in FAILURES.lock().unwrap().values_mut() {
+        failure.retry_at = now;
+    }
+}
+
+/// Lists that failed to update since they were last downloaded.
+pub fn failures() -> Vec<UpdateFailure> {
+    FAILURES.lock().unwrap().values().cloned().collect()
+}
+
+/// Shows a single notification for all lists that kept failing to update since the last one.
+pub fn notify_failures() {
+    let failing = FAILURES
+        .lock()
+        .unwrap()
+        .values_mut()
+        .filter(|failure| failure.count >= NOTIFY_AFTER_FAILURES && !failure.notified)
+        .map(|failure| {
+            failure.notified = true;
+            failure.clone()
+        })
+        .collect::<Vec<_>>();
+    let Some(next_retry) = failing.iter().map(|failure| failure.retry_at).min() else {
+        return;
+    };
+
+    let count = failing.len().to_string();
+    let time = next_retry.format("%H:%M").to_string();
+    notify::error(
+        tr(Msg::FilterUpdateFailed),
+        &tr_args(
+            Msg::FilterUpdateFailedMessage,
+            &[("count", &count), ("time", &time)],
+        ),
+    );
+}
+
+fn backoff_until(url: &str) -> Option<DateTime<Local>> {
+    let failures = FAILURES.lock().unwrap();
+    let retry_at = failures.get(url)?.retry_at;
+    (retry_at > Local::now()).then_some(retry_at)
+}
+
+fn record_failure(url: &str, error: &io::Error) {
+    let mut failures = FAILURES.lock().unwrap();
+    let failure = failures
+        .entry(url.to_string())
+        .or_insert_with(|| UpdateFailure {
+            url: url.to_string(),
+            count: 0,
+            error: String::new(),
+            retry_at: Local::now(),
+            notified: false,
+        });
+    failure.count += 1;
+    failure.error = error.to_string();
+    let backoff = MIN_BACKOFF * 2i32.pow(failure.count.min(8) - 1);
+    failure.retry_at = Local::now() + backoff.min(MAX_BACKOFF);
+    debug!(
+        "Update of '{url}' failed {} time(s), next attempt at {}",
+        failure.count, failure.retry_at
+    );
+}
+
+fn record_success(url: &str) {
+    if let Some(failure) = FAILURES.lock().unwrap().remove(url) {
+        if failure.notified {
+            info!("Updated '{url}' again after {} failures", failure.count);
+        }
+    }
+}
+
+async fn write_cache(path: &Path, contents: &str, list: &CachedList) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, contents).await?;
+    let info = serde_json::to_string(list).map_err(io::Error::other)?;
+    tokio::fs::write(info_path(path), info).await
+}
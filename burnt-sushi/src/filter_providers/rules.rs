@@ -0,0 +1,49 @@
+//! Rules of structured filter files, which are either a plain pattern or a table with an
+//! `expires` date after which the rule is ignored, e.g. for temporary workarounds.
+
+use burnt_sushi_core::filters::FilterConfig;
+use chrono::{Local, NaiveDate};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Rule {
+    Pattern(String),
+    Expiring { rule: String, expires: NaiveDate },
+}
+
+/// `allowlist` and `denylist` as written in a filter file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleFile {
+    pub allowlist: Vec<Rule>,
+    pub denylist: Vec<Rule>,
+}
+
+impl RuleFile {
+    /// Rules that have not expired yet, logging the expired ones.
+    pub fn into_config(self) -> FilterConfig {
+        let active = |rules: Vec<Rule>| -> Vec<String> {
+            rules
+                .into_iter()
+                .filter_map(|rule| match rule {
+                    Rule::Pattern(rule) => Some(rule),
+                    Rule::Expiring { rule, expires } if !is_expired(expires) => Some(rule),
+                    Rule::Expiring { rule, expires } => {
+                        warn!("Ignoring filter rule '{rule}', which expired on {expires}");
+                        None
+                    }
+                })
+                .collect()
+        };
+        FilterConfig {
+            allowlist: active(self.allowlist),
+            denylist: active(self.denylist),
+        }
+    }
+}
+
+/// Whether something that expires on the given date is ignored by now.
+pub fn is_expired(expires: NaiveDate) -> bool {
+    expires <= Local::now().date_naive()
+}
@@ -0,0 +1,112 @@
+//! Remote filter lists configured in the settings, added and removed from the subscription
+//! manager in the tray.
+
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local};
+use log::info;
+
+use super::{remote, FilterFormat, FilterSource};
+use crate::{filter_history::ChangeSource, settings};
+
+/// Remote list and the state of its last download.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub url: String,
+    /// When the list was last downloaded, `None` if it never was.
+    pub last_refresh: Option<DateTime<Local>>,
+    /// Number of rules in the last download.
+    pub rule_count: Option<usize>,
+    /// Error of the last update if it failed since the list was last downloaded.
+    pub error: Option<String>,
+}
+
+/// Remote lists in the order of the settings.
+pub fn list() -> Vec<Subscription> {
+    let failures = remote::failures();
+    let sources = settings::get().filter_sources.clone();
+    sources
+        .into_iter()
+        .filter_map(|source| match source {
+            FilterSource::Remote { url, .. } => Some(url),
+            FilterSource::Local { .. } => None,
+        })
+        .map(|url| {
+            let cached = remote::cached_list(&url);
+            let error = failures
+                .iter()
+                .find(|failure| failure.url == url)
+                .map(|failure| failure.error.clone());
+            Subscription {
+                last_refresh: cached.map(|list| list.downloaded),
+                rule_count: cached.map(|list| list.rule_count),
+                error,
+                url,
+            }
+        })
+        .collect()
+}
+
+/// Adds the remote list to the settings and loads it. The format is guessed from the extension of
+/// the url, lists without a known one are assumed to be Adblock Plus lists.
+pub fn add(url: &str) -> anyhow::Result<()> {
+    let url = url.trim();
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("Invalid url '{url}'."))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        bail!("Only http and https urls are supported.");
+    }
+    let format = FilterFormat::from_path(Path::new(parsed.path())).unwrap_or(FilterFormat::Abp);
+    let source = FilterSource::Remote {
+        url: url.to_string(),
+        format,
+        expires: None,
+    };
+
+    let mut settings = settings::get();
+    if settings
+        .filter_sources
+        .iter()
+        .any(|source| is_remote(source, url))
+    {
+        bail!("Already subscribed to '{url}'.");
+    }
+    settings.filter_sources.push(source.clone());
+    settings.save()?;
+    drop(settings);
+
+    info!("Subscribed to '{url}'");
+    super::register(source.into_provider());
+    super::notify_refresh(ChangeSource::Subscriptions);
+    Ok(())
+}
+
+/// Removes the remote list from the settings and deletes its cached download.
+pub fn remove(url: &str) -> anyhow::Result<()> {
+    let mut settings = settings::get();
+    let count = settings.filter_sources.len();
+    settings
+        .filter_sources
+        .retain(|source| !is_remote(source, url));
+    if settings.filter_sources.len() == count {
+        bail!("Not subscribed to '{url}'.");
+    }
+    settings.save()?;
+    drop(settings);
+
+    info!("Unsubscribed from '{url}'");
+    super::unregister(url);
+    remote::forget(url);
+    super::notify_refresh(ChangeSource::Subscriptions);
+    Ok(())
+}
+
+/// Downloads all remote lists again, including those waiting to retry after failed updates.
+pub fn refresh() {
+    remote::retry_now();
+    super::request_refresh();
+}
+
+fn is_remote(source: &FilterSource, url: &str) -> bool {
+    matches!(source, FilterSource::Remote { url: known, .. } if known == url)
+}
@@ -0,0 +1,52 @@
+//! Tests declared as `[[tests]]` in the filter file. They run against the effective rules whenever
+//! those are loaded and with `--check-filters`, so maintainers of filter lists notice regressions.
+
+use std::io;
+
+use anyhow::Context;
+use burnt_sushi_core::filters::{CompiledFilters, FilterConfig, FilterTestFailure, FilterTests};
+use log::{debug, warn};
+
+use crate::{environment::System, filter_providers::FilterFormat, resolver};
+
+/// Runs the tests of the filter file against the rules and returns the number of tests together
+/// with the failed ones.
+pub fn run(filter_config: &FilterConfig) -> anyhow::Result<(usize, Vec<FilterTestFailure>)> {
+    let tests = load()?.tests;
+    if tests.is_empty() {
+        return Ok((0, Vec::new()));
+    }
+    let filters = CompiledFilters::new(filter_config).context("Failed to compile filter rules.")?;
+    Ok((tests.len(), filters.run_tests(&tests)))
+}
+
+/// Runs the tests and logs the failed ones, which does not keep the rules from being used.
+pub fn check(filter_config: &FilterConfig) {
+    match run(filter_config) {
+        Ok((0, _)) => {}
+        Ok((count, failures)) if failures.is_empty() => debug!("All {count} filter tests passed"),
+        Ok((count, failures)) => {
+            warn!("{} of {count} filter tests failed", failures.len());
+            for failure in failures {
+                warn!("Filter test failed: {failure}");
+            }
+        }
+        Err(e) => warn!("Failed to run filter tests: {e:#}"),
+    }
+}
+
+fn load() -> anyhow::Result<FilterTests> {
+    let Some(path) = resolver::filter_config_path(&System) else {
+        return Ok(FilterTests::default());
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        // The default rules are used, which come without tests.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FilterTests::default()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'.", path.display())),
+    };
+    FilterFormat::from_path(&path)
+        .unwrap_or_default()
+        .deserialize(&contents)
+        .with_context(|| format!("Failed to parse the tests in '{}'.", path.display()))
+}
@@ -0,0 +1,105 @@
+//! Optional `GET /healthz` endpoint on localhost returning the hook status as JSON, so the app can
+//! be monitored on an always-on machine with tools like Uptime Kuma. Unlike the metrics endpoint it
+//! answers other users too, as it only reveals the status.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::status::{self, HookStatus};
+
+/// Longest request line that is read, longer ones are answered with 404.
+const MAX_REQUEST_LINE_LEN: u64 = 1024;
+/// Time a client has to send the request line before the connection is closed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+struct Health {
+    /// Whether the app is blocking or able to block once Spotify starts.
+    healthy: bool,
+    status: &'static str,
+    description: String,
+    spotify_pid: Option<u32>,
+    /// Only the time of the last error is shown, as its message can contain local paths.
+    last_error_at: Option<String>,
+}
+
+impl Health {
+    fn current() -> Self {
+        let status = status::get().clone();
+        let key = match status.hook {
+            HookStatus::Searching => "searching",
+            HookStatus::Preparing => "preparing",
+            HookStatus::Hooking => "hooking",
+            HookStatus::Hooked => "hooked",
+            HookStatus::Paused => "paused",
+            HookStatus::OtherInstance => "other-instance",
+            HookStatus::Unavailable(_) => "unavailable",
+        };
+        Self {
+            healthy: !matches!(status.hook, HookStatus::Unavailable(_)),
+            status: key,
+            description: status.hook.to_string(),
+            spotify_pid: status.spotify.and_then(|spotify| spotify.pid),
+            last_error_at: status.last_error.map(|e| e.time.to_rfc3339()),
+        }
+    }
+}
+
+pub async fn serve(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).await?;
+    debug!(
+        "Serving health check on http://{}/healthz",
+        listener.local_addr()?
+    );
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::task::spawn(async move {
+            if let Err(e) = handle_client(stream).await {
+                warn!("Failed to serve health check: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_client(stream: TcpStream) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LINE_LEN));
+    tokio::time::timeout(REQUEST_TIMEOUT, reader.read_line(&mut request_line))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Request timed out."))??;
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/healthz")) => {
+            let health = Health::current();
+            let status_line = if health.healthy {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            let body = serde_json::to_string(&health)
+                .map_err(|_| io::Error::other("Failed to serialize health."))?;
+            format!(
+                "HTTP/1.1 {status_line}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
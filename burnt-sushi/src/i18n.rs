@@ -0,0 +1,589 @@
+use std::sync::LazyLock;
+
+use clap::ValueEnum;
+use log::debug;
+use windows::Win32::Globalization::GetUserDefaultLocaleName;
+
+use crate::args::ARGS;
+
+// Missing from windows.
+const LOCALE_NAME_MAX_LENGTH: usize = 85;
+
+/// Language of the tray menu, notifications and dialogs.
+pub static LANG: LazyLock<Lang> = LazyLock::new(|| {
+    let lang = ARGS.lang.or_else(Lang::detect).unwrap_or_default();
+    debug!("Using language {lang:?}");
+    lang
+});
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+impl Lang {
+    /// Picks the language from the user's locale, e.g. `de-AT`.
+    fn detect() -> Option<Self> {
+        let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH];
+        let len = unsafe { GetUserDefaultLocaleName(&mut buffer) };
+        if len <= 1 {
+            return None;
+        }
+        // The length includes the terminating nul.
+        let locale = String::from_utf16_lossy(&buffer[..len as usize - 1]);
+        let language = locale.split('-').next()?;
+        Lang::from_str(language, true).ok()
+    }
+}
+
+/// Translatable user-facing texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    TrayShowConsole,
+    TrayLogLevel,
+    TrayPauseUntilRestart,
+    TrayStatistics,
+    TrayShareStatistics,
+    TrayManageSubscriptions,
+    TrayBlockRecentHost,
+    /// Placeholders: `host`.
+    HostBlocked,
+    TrayCopyDiagnostics,
+    TrayCheckForUpdates,
+    TrayExit,
+    JumpListPause,
+    JumpListShowActivity,
+    JumpListReloadFilters,
+    Started,
+    WatchingForSpotify,
+    HookFailed,
+    FirewallBlocked,
+    FirewallBlockedMessage,
+    NotWorking,
+    /// Placeholders: `count`, `error`.
+    HealthChecksFailed,
+    BlockerUnloaded,
+    /// Placeholders: `count`.
+    BlockerUnloadedMessage,
+    BlockerUpdated,
+    BlockerUpdatedMessage,
+    NoUpdate,
+    /// Placeholders: `version`.
+    LatestVersion,
+    BlockerUpdateFailed,
+    UpdateFailed,
+    /// Placeholders: `version`.
+    UpdatePrompt,
+    UpdateAction,
+    IgnoreAction,
+    MilestoneReached,
+    /// Placeholders: `count`.
+    MilestoneMessage,
+    ThisSession,
+    AllTime,
+    /// Placeholders: `count`.
+    AdsBlocked,
+    /// Placeholders: `duration`.
+    Protected,
+    /// Placeholders: `rate`, `minutes`.
+    BlockedPerMinute,
+    /// Placeholders: `duration`.
+    ListenedAdFree,
+    /// Placeholders: `duration`.
+    AdsAvoided,
+    ActionPause,
+    ActionOpenConfig,
+    ActionRetryInjection,
+    /// Placeholders: `app`.
+    StartFailed,
+    StatusChanged,
+    StatusSearching,
+    StatusPreparing,
+    StatusHooking,
+    StatusBlocking,
+    StatusPaused,
+    StatusOtherInstance,
+    /// Placeholders: `reason`.
+    StatusUnavailable,
+    /// Placeholders: `time`.
+    StatusRetryAt,
+    /// Placeholders: `count`.
+    StatusAdsUncovered,
+    ReasonArchitectureMismatch,
+    ReasonStorePackage,
+    ReasonSecuritySoftware,
+    ReasonFirewall,
+    ReasonConflict,
+    ReasonIntegrityMismatch,
+    ReasonUnstable,
+    ReasonOther,
+    CrashDetected,
+    /// Placeholders: `count`.
+    CrashReportPrompt,
+    FilterUpdateFailed,
+    /// Placeholders: `count`, `time`.
+    FilterUpdateFailedMessage,
+    ActionSendCrashReport,
+    ActionAlwaysSendCrashReports,
+    ActionViewCrashReport,
+    AdSlipped,
+    AdSlippedMessage,
+    ActionUpdateFilters,
+    SubscriptionAdd,
+    SubscriptionRemove,
+    SubscriptionRefresh,
+    SubscriptionClose,
+    /// Placeholders: `count`, `time`.
+    SubscriptionStatus,
+    SubscriptionNotDownloaded,
+    /// Placeholders: `error`.
+    SubscriptionFailing,
+}
+
+impl Msg {
+    /// Returns the text in the given language.
+    pub fn text(self, lang: Lang) -> &'static str {
+        // en, de, fr, es
+        let texts = match self {
+            Msg::TrayShowConsole => [
+                "Show Console",
+                "Konsole anzeigen",
+                "Afficher la console",
+                "Mostrar consola",
+            ],
+            Msg::TrayLogLevel => [
+                "Log Level",
+                "Protokollstufe",
+                "Niveau de journalisation",
+                "Nivel de registro",
+            ],
+            Msg::TrayPauseUntilRestart => [
+                "Disable until Spotify Restarts",
+                "Bis zum Neustart von Spotify deaktivieren",
+                "Désactiver jusqu'au redémarrage de Spotify",
+                "Desactivar hasta reiniciar Spotify",
+            ],
+            Msg::TrayStatistics => ["Statistics", "Statistiken", "Statistiques", "Estadísticas"],
+            Msg::TrayShareStatistics => [
+                "Share Anonymous Statistics",
+                "Anonyme Statistiken teilen",
+                "Partager des statistiques anonymes",
+                "Compartir estadísticas anónimas",
+            ],
+            Msg::TrayManageSubscriptions => [
+                "Manage Subscriptions",
+                "Abonnements verwalten",
+                "Gérer les abonnements",
+                "Administrar suscripciones",
+            ],
+            Msg::TrayBlockRecentHost => [
+                "Block Recent Host",
+                "Kürzlichen Host blockieren",
+                "Bloquer un hôte récent",
+                "Bloquear un host reciente",
+            ],
+            Msg::HostBlocked => [
+                "Requests to {host} are blocked from now on",
+                "Anfragen an {host} werden ab jetzt blockiert",
+                "Les requêtes vers {host} sont désormais bloquées",
+                "Las solicitudes a {host} se bloquean a partir de ahora",
+            ],
+            Msg::TrayCopyDiagnostics => [
+                "Copy Diagnostics",
+                "Diagnose kopieren",
+                "Copier le diagnostic",
+                "Copiar diagnóstico",
+            ],
+            Msg::TrayCheckForUpdates => [
+                "Check for Updates",
+                "Nach Updates suchen",
+                "Rechercher des mises à jour",
+                "Buscar actualizaciones",
+            ],
+            Msg::TrayExit => ["Exit", "Beenden", "Quitter", "Salir"],
+            Msg::JumpListPause => [
+                "Pause for 30 Minutes",
+                "30 Minuten pausieren",
+                "Suspendre pendant 30 minutes",
+                "Pausar durante 30 minutos",
+            ],
+            Msg::JumpListShowActivity => [
+                "Show Activity",
+                "Aktivität anzeigen",
+                "Afficher l'activité",
+                "Mostrar actividad",
+            ],
+            Msg::JumpListReloadFilters => [
+                "Reload Filters",
+                "Filter neu laden",
+                "Recharger les filtres",
+                "Recargar filtros",
+            ],
+            Msg::Started => ["Started", "Gestartet", "Démarré", "Iniciado"],
+            Msg::WatchingForSpotify => [
+                "Watching for Spotify...",
+                "Warte auf Spotify...",
+                "En attente de Spotify...",
+                "Esperando a Spotify...",
+            ],
+            Msg::HookFailed => [
+                "Failed to block ads in Spotify",
+                "Werbung in Spotify konnte nicht blockiert werden",
+                "Impossible de bloquer les publicités dans Spotify",
+                "No se pudieron bloquear los anuncios en Spotify",
+            ],
+            Msg::FirewallBlocked => [
+                "Blocker connection blocked",
+                "Verbindung zum Blocker blockiert",
+                "Connexion au bloqueur bloquée",
+                "Conexión con el bloqueador bloqueada",
+            ],
+            Msg::FirewallBlockedMessage => [
+                "A firewall or antivirus blocks local connections between the app and Spotify. Allow the app and Spotify to use the loopback network, then retry.",
+                "Eine Firewall oder ein Virenscanner blockiert lokale Verbindungen zwischen der App und Spotify. Erlauben Sie beiden das Loopback-Netzwerk und versuchen Sie es erneut.",
+                "Un pare-feu ou un antivirus bloque les connexions locales entre l'application et Spotify. Autorisez-les à utiliser le réseau de bouclage, puis réessayez.",
+                "Un cortafuegos o antivirus bloquea las conexiones locales entre la aplicación y Spotify. Permite que ambos usen la red de bucle invertido y vuelve a intentarlo.",
+            ],
+            Msg::NotWorking => [
+                "Ad blocking is not working",
+                "Der Werbeblocker funktioniert nicht",
+                "Le blocage des publicités ne fonctionne pas",
+                "El bloqueo de anuncios no funciona",
+            ],
+            Msg::HealthChecksFailed => [
+                "The blocker failed {count} health checks in a row: {error}",
+                "Der Blocker hat {count} Statusprüfungen in Folge nicht bestanden: {error}",
+                "Le bloqueur a échoué à {count} vérifications consécutives : {error}",
+                "El bloqueador falló {count} comprobaciones seguidas: {error}",
+            ],
+            Msg::BlockerUnloaded => [
+                "Spotify keeps unloading the blocker",
+                "Spotify entlädt den Blocker wiederholt",
+                "Spotify décharge le bloqueur à répétition",
+                "Spotify descarga el bloqueador repetidamente",
+            ],
+            Msg::BlockerUnloadedMessage => [
+                "Spotify unloaded the blocker {count} times in the last hour. It is injected again each time, but ads may play in between.",
+                "Spotify hat den Blocker in der letzten Stunde {count}-mal entladen. Er wird jedes Mal erneut eingefügt, dazwischen kann aber Werbung laufen.",
+                "Spotify a déchargé le bloqueur {count} fois au cours de la dernière heure. Il est réinjecté à chaque fois, mais des publicités peuvent passer entre-temps.",
+                "Spotify descargó el bloqueador {count} veces en la última hora. Se vuelve a inyectar cada vez, pero pueden sonar anuncios mientras tanto.",
+            ],
+            Msg::BlockerUpdated => [
+                "Blocker updated",
+                "Blocker aktualisiert",
+                "Bloqueur mis à jour",
+                "Bloqueador actualizado",
+            ],
+            Msg::BlockerUpdatedMessage => [
+                "The updated blocker is now active.",
+                "Der aktualisierte Blocker ist jetzt aktiv.",
+                "Le bloqueur mis à jour est maintenant actif.",
+                "El bloqueador actualizado ya está activo.",
+            ],
+            Msg::NoUpdate => [
+                "No update available",
+                "Kein Update verfügbar",
+                "Aucune mise à jour disponible",
+                "No hay actualizaciones disponibles",
+            ],
+            Msg::LatestVersion => [
+                "You are running the latest version ({version}).",
+                "Sie verwenden die neueste Version ({version}).",
+                "Vous utilisez la dernière version ({version}).",
+                "Estás usando la última versión ({version}).",
+            ],
+            Msg::BlockerUpdateFailed => [
+                "Blocker update failed",
+                "Aktualisierung des Blockers fehlgeschlagen",
+                "Échec de la mise à jour du bloqueur",
+                "Error al actualizar el bloqueador",
+            ],
+            Msg::UpdateFailed => [
+                "Update failed",
+                "Update fehlgeschlagen",
+                "Échec de la mise à jour",
+                "Error en la actualización",
+            ],
+            Msg::UpdatePrompt => [
+                "Update app to {version}?",
+                "App auf {version} aktualisieren?",
+                "Mettre à jour l'application vers {version} ?",
+                "¿Actualizar la aplicación a {version}?",
+            ],
+            Msg::UpdateAction => ["Update", "Aktualisieren", "Mettre à jour", "Actualizar"],
+            Msg::IgnoreAction => ["Ignore", "Ignorieren", "Ignorer", "Ignorar"],
+            Msg::MilestoneReached => [
+                "Milestone reached",
+                "Meilenstein erreicht",
+                "Étape franchie",
+                "Hito alcanzado",
+            ],
+            Msg::MilestoneMessage => [
+                "{count} ads have been blocked so far.",
+                "Bisher wurden {count} Werbungen blockiert.",
+                "{count} publicités ont été bloquées jusqu'à présent.",
+                "Se han bloqueado {count} anuncios hasta ahora.",
+            ],
+            Msg::ThisSession => [
+                "This session",
+                "Diese Sitzung",
+                "Cette session",
+                "Esta sesión",
+            ],
+            Msg::AllTime => ["All time", "Insgesamt", "Au total", "En total"],
+            Msg::AdsBlocked => [
+                "{count} ads blocked",
+                "{count} Werbungen blockiert",
+                "{count} publicités bloquées",
+                "{count} anuncios bloqueados",
+            ],
+            Msg::Protected => [
+                "{duration} protected",
+                "{duration} geschützt",
+                "{duration} protégé",
+                "{duration} protegido",
+            ],
+            Msg::BlockedPerMinute => [
+                "{rate} ads blocked per minute (last {minutes} minutes)",
+                "{rate} Werbungen pro Minute blockiert (letzte {minutes} Minuten)",
+                "{rate} publicités bloquées par minute ({minutes} dernières minutes)",
+                "{rate} anuncios bloqueados por minuto (últimos {minutes} minutos)",
+            ],
+            Msg::ListenedAdFree => [
+                "{duration} listened ad-free",
+                "{duration} werbefrei gehört",
+                "{duration} d'écoute sans publicité",
+                "{duration} escuchado sin anuncios",
+            ],
+            Msg::AdsAvoided => [
+                "about {duration} of ads avoided",
+                "etwa {duration} Werbung erspart",
+                "environ {duration} de publicités évitées",
+                "unos {duration} de anuncios evitados",
+            ],
+            Msg::ActionPause => [
+                "Pause 30m",
+                "30 Min. pausieren",
+                "Pause 30 min",
+                "Pausar 30 min",
+            ],
+            Msg::ActionOpenConfig => [
+                "Open config",
+                "Einstellungen öffnen",
+                "Ouvrir la configuration",
+                "Abrir configuración",
+            ],
+            Msg::ActionRetryInjection => [
+                "Retry injection",
+                "Erneut injizieren",
+                "Réessayer l'injection",
+                "Reintentar inyección",
+            ],
+            Msg::StartFailed => [
+                "{app} did not start correctly:",
+                "{app} wurde nicht korrekt gestartet:",
+                "{app} n'a pas démarré correctement :",
+                "{app} no se inició correctamente:",
+            ],
+            Msg::StatusChanged => [
+                "Status changed",
+                "Status geändert",
+                "Statut modifié",
+                "Estado cambiado",
+            ],
+            Msg::StatusSearching => [
+                "Looking for Spotify",
+                "Suche nach Spotify",
+                "Recherche de Spotify",
+                "Buscando Spotify",
+            ],
+            Msg::StatusPreparing => [
+                "Preparing blocker",
+                "Blocker wird vorbereitet",
+                "Préparation du bloqueur",
+                "Preparando el bloqueador",
+            ],
+            Msg::StatusHooking => [
+                "Hooking Spotify",
+                "Verbinde mit Spotify",
+                "Connexion à Spotify",
+                "Conectando con Spotify",
+            ],
+            Msg::StatusBlocking => [
+                "Blocking ads",
+                "Werbung wird blockiert",
+                "Blocage des publicités",
+                "Bloqueando anuncios",
+            ],
+            Msg::StatusPaused => ["Paused", "Pausiert", "En pause", "En pausa"],
+            Msg::StatusOtherInstance => [
+                "Blocked by another instance",
+                "Wird von einer anderen Instanz blockiert",
+                "Bloqué par une autre instance",
+                "Bloqueado por otra instancia",
+            ],
+            Msg::StatusUnavailable => [
+                "Not blocking: {reason}",
+                "Blockiert nicht: {reason}",
+                "Blocage inactif : {reason}",
+                "Sin bloqueo: {reason}",
+            ],
+            Msg::StatusRetryAt => [
+                "retrying at {time}",
+                "neuer Versuch um {time}",
+                "nouvel essai à {time}",
+                "reintento a las {time}",
+            ],
+            Msg::StatusAdsUncovered => [
+                "{count} known ad endpoint(s) not blocked",
+                "{count} bekannte Werbeadresse(n) nicht blockiert",
+                "{count} adresse(s) publicitaire(s) connue(s) non bloquée(s)",
+                "{count} dirección(es) de anuncios conocida(s) sin bloquear",
+            ],
+            Msg::ReasonArchitectureMismatch => [
+                "32-bit Spotify is not supported",
+                "32-Bit-Spotify wird nicht unterstützt",
+                "Spotify 32 bits n'est pas pris en charge",
+                "Spotify de 32 bits no es compatible",
+            ],
+            Msg::ReasonStorePackage => [
+                "Microsoft Store Spotify refused the blocker",
+                "Spotify aus dem Microsoft Store hat den Blocker abgelehnt",
+                "Spotify du Microsoft Store a refusé le bloqueur",
+                "Spotify de Microsoft Store rechazó el bloqueador",
+            ],
+            Msg::ReasonSecuritySoftware => [
+                "blocked by antivirus or security policy",
+                "durch Virenschutz oder Sicherheitsrichtlinie blockiert",
+                "bloqué par l'antivirus ou une stratégie de sécurité",
+                "bloqueado por el antivirus o una directiva de seguridad",
+            ],
+            Msg::ReasonFirewall => [
+                "blocked by firewall",
+                "durch Firewall blockiert",
+                "bloqué par le pare-feu",
+                "bloqueado por el cortafuegos",
+            ],
+            Msg::ReasonConflict => [
+                "another ad blocker is loaded",
+                "ein anderer Werbeblocker ist geladen",
+                "un autre bloqueur de publicités est chargé",
+                "otro bloqueador de anuncios está cargado",
+            ],
+            Msg::ReasonIntegrityMismatch => [
+                "Spotify runs as administrator",
+                "Spotify läuft als Administrator",
+                "Spotify s'exécute en tant qu'administrateur",
+                "Spotify se ejecuta como administrador",
+            ],
+            Msg::ReasonUnstable => [
+                "blocker keeps stopping",
+                "Blocker stoppt immer wieder",
+                "le bloqueur s'arrête sans cesse",
+                "el bloqueador se detiene una y otra vez",
+            ],
+            Msg::ReasonOther => [
+                "hooking failed",
+                "Einhaken fehlgeschlagen",
+                "échec de l'accrochage",
+                "error al enganchar",
+            ],
+            Msg::CrashDetected => [
+                "The app crashed",
+                "Die App ist abgestürzt",
+                "L'application a planté",
+                "La aplicación se bloqueó",
+            ],
+            Msg::CrashReportPrompt => [
+                "Send {count} crash report(s) to help fix the problem?",
+                "{count} Absturzbericht(e) senden, um das Problem zu beheben?",
+                "Envoyer {count} rapport(s) de plantage pour aider à corriger le problème ?",
+                "¿Enviar {count} informe(s) de error para ayudar a solucionar el problema?",
+            ],
+            Msg::FilterUpdateFailed => [
+                "Filter lists out of date",
+                "Filterlisten veraltet",
+                "Listes de filtres obsolètes",
+                "Listas de filtros desactualizadas",
+            ],
+            Msg::FilterUpdateFailedMessage => [
+                "{count} filter list(s) repeatedly failed to update, the cached rules are used until the next attempt at {time}.",
+                "{count} Filterliste(n) konnte(n) wiederholt nicht aktualisiert werden, bis zum nächsten Versuch um {time} werden die zwischengespeicherten Regeln verwendet.",
+                "{count} liste(s) de filtres n'a (ont) pas pu être mise(s) à jour à plusieurs reprises, les règles en cache sont utilisées jusqu'au prochain essai à {time}.",
+                "{count} lista(s) de filtros no se pudo (pudieron) actualizar repetidamente, se usan las reglas en caché hasta el próximo intento a las {time}.",
+            ],
+            Msg::ActionSendCrashReport => ["Send", "Senden", "Envoyer", "Enviar"],
+            Msg::ActionAlwaysSendCrashReports => [
+                "Always send",
+                "Immer senden",
+                "Toujours envoyer",
+                "Enviar siempre",
+            ],
+            Msg::ActionViewCrashReport => ["View", "Anzeigen", "Afficher", "Ver"],
+            Msg::AdSlipped => [
+                "An ad slipped through",
+                "Eine Werbung ist durchgerutscht",
+                "Une publicité est passée",
+                "Se ha colado un anuncio",
+            ],
+            Msg::AdSlippedMessage => [
+                "Spotify played an ad although ads are blocked, your filters may be outdated.",
+                "Spotify hat trotz Blockierung Werbung abgespielt, Ihre Filter sind möglicherweise veraltet.",
+                "Spotify a diffusé une publicité malgré le blocage, vos filtres sont peut-être obsolètes.",
+                "Spotify reprodujo un anuncio aunque los anuncios están bloqueados, es posible que tus filtros estén desactualizados.",
+            ],
+            Msg::ActionUpdateFilters => [
+                "Update filters",
+                "Filter aktualisieren",
+                "Mettre à jour les filtres",
+                "Actualizar filtros",
+            ],
+            Msg::SubscriptionAdd => ["Add", "Hinzufügen", "Ajouter", "Añadir"],
+            Msg::SubscriptionRemove => ["Remove", "Entfernen", "Supprimer", "Quitar"],
+            Msg::SubscriptionRefresh => [
+                "Refresh All",
+                "Alle aktualisieren",
+                "Tout actualiser",
+                "Actualizar todo",
+            ],
+            Msg::SubscriptionClose => ["Close", "Schließen", "Fermer", "Cerrar"],
+            Msg::SubscriptionStatus => [
+                "{count} rules, refreshed {time}",
+                "{count} Regeln, aktualisiert {time}",
+                "{count} règles, actualisé {time}",
+                "{count} reglas, actualizado {time}",
+            ],
+            Msg::SubscriptionNotDownloaded => [
+                "not downloaded yet",
+                "noch nicht heruntergeladen",
+                "pas encore téléchargé",
+                "aún no descargado",
+            ],
+            Msg::SubscriptionFailing => [
+                "update failed: {error}",
+                "Aktualisierung fehlgeschlagen: {error}",
+                "échec de la mise à jour : {error}",
+                "error al actualizar: {error}",
+            ],
+        };
+        texts[lang as usize]
+    }
+}
+
+/// Returns the text in the current language.
+pub fn tr(msg: Msg) -> &'static str {
+    msg.text(*LANG)
+}
+
+/// Returns the text in the current language with its `{name}` placeholders replaced.
+pub fn tr_args(msg: Msg, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+    let mut text = tr(msg).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
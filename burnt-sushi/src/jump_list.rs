@@ -0,0 +1,95 @@
+//! Tasks in the jump list of the taskbar button and Start Menu shortcut. They start the executable
+//! with a subcommand, which forwards it to the running instance over the control channel.
+
+use std::env;
+
+use anyhow::Context;
+use log::debug;
+use windows::{
+    core::{Interface, HSTRING, PCWSTR, PROPVARIANT},
+    Win32::{
+        Storage::EnhancedStorage::PKEY_Title,
+        System::{
+            Com::{
+                CoCreateInstance, CoInitializeEx, CoUninitialize,
+                StructuredStorage::{PropVariantChangeType, PVCHF_DEFAULT},
+                CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
+            },
+            Variant::VT_LPWSTR,
+        },
+        UI::Shell::{
+            Common::{IObjectArray, IObjectCollection},
+            DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW,
+            PropertiesSystem::IPropertyStore,
+            ShellLink,
+        },
+    },
+};
+
+use crate::{
+    control::DEFAULT_PAUSE_MINUTES,
+    i18n::{tr, Msg},
+};
+
+/// Replaces the tasks of the jump list with the current ones, e.g. in the current language.
+pub fn register() -> anyhow::Result<()> {
+    let exe = env::current_exe().context("Failed to locate current executable.")?;
+    let exe = HSTRING::from(exe.as_os_str());
+    let tasks = [
+        (Msg::JumpListPause, format!("pause {DEFAULT_PAUSE_MINUTES}")),
+        (Msg::JumpListShowActivity, "show-activity".to_string()),
+        (Msg::JumpListReloadFilters, "reload".to_string()),
+    ];
+
+    with_com(|| unsafe {
+        let list: ICustomDestinationList =
+            CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+        let mut min_slots = 0;
+        let _removed: IObjectArray = list.BeginList(&mut min_slots)?;
+
+        let collection: IObjectCollection =
+            CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+        for (title, arguments) in tasks {
+            let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+            link.SetPath(&exe)?;
+            link.SetArguments(&HSTRING::from(arguments))?;
+            link.SetIconLocation(&exe, 0)?;
+            // Tasks show the title instead of the file name, which has to be a wide string.
+            let mut title_value = PROPVARIANT::default();
+            PropVariantChangeType(
+                &mut title_value,
+                &PROPVARIANT::from(tr(title)),
+                PVCHF_DEFAULT,
+                VT_LPWSTR,
+            )?;
+            let properties: IPropertyStore = link.cast()?;
+            properties.SetValue(&PKEY_Title, &title_value)?;
+            properties.Commit()?;
+            collection.AddObject(&link)?;
+        }
+
+        list.AddUserTasks(&collection.cast::<IObjectArray>()?)?;
+        list.CommitList()
+    })
+    .context("Failed to update jump list.")?;
+
+    debug!("Registered jump list tasks");
+    Ok(())
+}
+
+/// Removes the tasks from the jump list.
+pub fn remove() -> anyhow::Result<()> {
+    with_com(|| unsafe {
+        let list: ICustomDestinationList =
+            CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+        list.DeleteList(PCWSTR::null())
+    })
+    .context("Failed to remove jump list.")
+}
+
+fn with_com<T>(f: impl FnOnce() -> windows::core::Result<T>) -> windows::core::Result<T> {
+    unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.ok()?;
+    let result = f();
+    unsafe { CoUninitialize() };
+    result
+}
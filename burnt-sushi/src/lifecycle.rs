@@ -0,0 +1,459 @@
+//! Lifecycle of the blocker in a Spotify process. A found Spotify is `Detected`, `Injecting` leads
+//! to `Running` or back to `Detected`, a failed health check or a lost RPC connection moves
+//! `Running` to `Degraded` and both are left through `Ejecting`, to `Detected` if Spotify keeps
+//! running and `Idle` otherwise.
+//!
+//! All changes go through the methods of [`HookState`], which check them against the table in
+//! [`HookPhase::allows`] and log them. A change the table does not allow is refused with an
+//! [`InvalidTransition`] and leaves the state as it was.
+
+use std::{error, fmt, mem};
+
+use burnt_sushi_core::{
+    hook_claim::HookClaim, injector::InjectedBlocker, rpc::RpcExit,
+    spotify_process_scanner::SpotifyInfo,
+};
+use futures::future;
+use log::{debug, warn};
+
+#[allow(clippy::large_enum_variant)]
+pub enum HookState {
+    /// No Spotify process is known.
+    Idle,
+    /// Spotify is running without the blocker, e.g. while paused or after a failed injection.
+    Detected(SpotifyInfo),
+    /// The blocker is being injected.
+    Injecting(SpotifyInfo),
+    /// The blocker is injected and passed its last health check.
+    Running(Hook),
+    /// The blocker is injected but failed its last health check, e.g. because RPC died.
+    Degraded(Hook),
+    /// The blocker is being ejected.
+    Ejecting,
+}
+
+/// Blocker injected into a Spotify process.
+pub struct Hook {
+    pub spotify: SpotifyInfo,
+    pub blocker: InjectedBlocker,
+    _claim: Option<HookClaim>,
+}
+
+/// [`HookState`] without its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPhase {
+    Idle,
+    Detected,
+    Injecting,
+    Running,
+    Degraded,
+    Ejecting,
+}
+
+/// Reasons for moving between [`HookPhase`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    SpotifyFound,
+    SpotifyExited,
+    InjectionStarted,
+    Injected,
+    InjectionFailed,
+    HealthCheckFailed,
+    HealthCheckPassed,
+    RpcStopped,
+    EjectionStarted,
+    Ejected,
+}
+
+/// A change of the [`HookState`] that [`HookPhase::allows`] does not allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: HookPhase,
+    pub event: HookEvent,
+    pub to: HookPhase,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid hook state transition {:?} -> {:?} ({:?})",
+            self.from, self.to, self.event
+        )
+    }
+}
+
+impl error::Error for InvalidTransition {}
+
+impl HookPhase {
+    /// Whether `event` may move the lifecycle from this phase to `to`.
+    pub fn allows(self, event: HookEvent, to: HookPhase) -> bool {
+        use HookEvent::*;
+        use HookPhase::*;
+
+        matches!(
+            (self, event, to),
+            (Idle | Detected, SpotifyFound, Detected)
+                | (Detected, SpotifyExited, Idle)
+                | (Detected, InjectionStarted, Injecting)
+                | (Injecting, Injected, Running)
+                | (Injecting, InjectionFailed, Detected)
+                | (Injecting, SpotifyExited, Idle)
+                | (Running, HealthCheckFailed, Degraded)
+                | (Degraded, HealthCheckPassed, Running)
+                | (Running, RpcStopped, Degraded)
+                | (Running | Degraded, EjectionStarted, Ejecting)
+                | (Ejecting, Ejected, Detected | Idle)
+        )
+    }
+}
+
+impl HookState {
+    pub fn phase(&self) -> HookPhase {
+        match self {
+            HookState::Idle => HookPhase::Idle,
+            HookState::Detected(_) => HookPhase::Detected,
+            HookState::Injecting(_) => HookPhase::Injecting,
+            HookState::Running(_) => HookPhase::Running,
+            HookState::Degraded(_) => HookPhase::Degraded,
+            HookState::Ejecting => HookPhase::Ejecting,
+        }
+    }
+
+    /// The injected blocker, healthy or not.
+    pub fn hook(&self) -> Option<&Hook> {
+        match self {
+            HookState::Running(hook) | HookState::Degraded(hook) => Some(hook),
+            _ => None,
+        }
+    }
+
+    /// The Spotify process the lifecycle is at, if any.
+    pub fn spotify(&self) -> Option<&SpotifyInfo> {
+        match self {
+            HookState::Detected(spotify) | HookState::Injecting(spotify) => Some(spotify),
+            HookState::Running(hook) | HookState::Degraded(hook) => Some(&hook.spotify),
+            HookState::Idle | HookState::Ejecting => None,
+        }
+    }
+
+    /// Whether the lifecycle is already at the given Spotify process, which is still running.
+    pub fn is_at(&self, spotify: &SpotifyInfo) -> bool {
+        self.spotify().is_some_and(|current| {
+            current.process.is_alive()
+                && matches!(
+                    (current.process.pid(), spotify.process.pid()),
+                    (Ok(current), Ok(new)) if current == new
+                )
+        })
+    }
+
+    pub fn hook_mut(&mut self) -> Option<&mut Hook> {
+        match self {
+            HookState::Running(hook) | HookState::Degraded(hook) => Some(hook),
+            _ => None,
+        }
+    }
+
+    /// Starts tracking a found Spotify process.
+    pub fn detect(&mut self, spotify: SpotifyInfo) -> Result<(), InvalidTransition> {
+        self.transition(HookEvent::SpotifyFound, HookState::Detected(spotify))
+            .map(drop)
+    }
+
+    /// Forgets the Spotify process after it exited, does nothing if it was hooked or not known.
+    pub fn spotify_exited(&mut self) -> Result<(), InvalidTransition> {
+        if !matches!(self, HookState::Detected(_) | HookState::Injecting(_)) {
+            return Ok(());
+        }
+        self.transition(HookEvent::SpotifyExited, HookState::Idle)
+            .map(drop)
+    }
+
+    pub fn start_injecting(&mut self) -> Result<(), InvalidTransition> {
+        self.transition_with(
+            HookEvent::InjectionStarted,
+            HookPhase::Injecting,
+            |state| match state {
+                HookState::Detected(spotify) => HookState::Injecting(spotify),
+                state => state,
+            },
+        )
+    }
+
+    /// Moves to `Running` with the injected blocker, which is handed back if the blocker was not
+    /// being injected, so that it can be ejected.
+    pub fn injected(
+        &mut self,
+        blocker: InjectedBlocker,
+        claim: Option<HookClaim>,
+    ) -> Result<(), (InvalidTransition, InjectedBlocker)> {
+        let (from, event, to) = (self.phase(), HookEvent::Injected, HookPhase::Running);
+        let spotify = match mem::replace(self, HookState::Idle) {
+            HookState::Injecting(spotify) if from.allows(event, to) => spotify,
+            state => {
+                *self = state;
+                return Err((self.invalid(event, to), blocker));
+            }
+        };
+        *self = HookState::Running(Hook {
+            spotify,
+            blocker,
+            _claim: claim,
+        });
+        debug!("Hook state {from:?} -> {to:?} ({event:?})");
+        Ok(())
+    }
+
+    /// Returns to `Detected` after a failed injection, keeping the process for another attempt.
+    /// Does nothing if the blocker was not being injected.
+    pub fn injection_failed(&mut self) -> Result<(), InvalidTransition> {
+        if !matches!(self, HookState::Injecting(_)) {
+            return Ok(());
+        }
+        self.transition_with(
+            HookEvent::InjectionFailed,
+            HookPhase::Detected,
+            |state| match state {
+                HookState::Injecting(spotify) => HookState::Detected(spotify),
+                state => state,
+            },
+        )
+    }
+
+    /// Moves between `Running` and `Degraded` according to the result of a health check.
+    pub fn record_health(&mut self, healthy: bool) -> Result<(), InvalidTransition> {
+        let (event, to) = if healthy {
+            (HookEvent::HealthCheckPassed, HookPhase::Running)
+        } else {
+            (HookEvent::HealthCheckFailed, HookPhase::Degraded)
+        };
+        if self.hook().is_none() || self.phase() == to {
+            return Ok(());
+        }
+        self.transition_with(event, to, |state| match state {
+            HookState::Running(hook) | HookState::Degraded(hook) => {
+                if healthy {
+                    HookState::Running(hook)
+                } else {
+                    HookState::Degraded(hook)
+                }
+            }
+            state => state,
+        })
+    }
+
+    /// Waits until the RPC task of the injected blocker stops, never resolves without a blocker.
+    pub async fn rpc_stopped(&self) -> RpcExit {
+        match self.hook() {
+            Some(hook) => hook.blocker.rpc_stopped().await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Marks the blocker as degraded after its RPC task stopped on its own. Does nothing unless
+    /// the blocker is running.
+    pub fn rpc_lost(&mut self) -> Result<(), InvalidTransition> {
+        if self.phase() != HookPhase::Running {
+            return Ok(());
+        }
+        self.transition_with(
+            HookEvent::RpcStopped,
+            HookPhase::Degraded,
+            |state| match state {
+                HookState::Running(hook) => HookState::Degraded(hook),
+                state => state,
+            },
+        )
+    }
+
+    /// Moves to `Ejecting` and hands out the hook to eject, `None` if no blocker is injected.
+    pub fn start_ejecting(&mut self) -> Option<Hook> {
+        self.hook()?;
+        match self.transition(HookEvent::EjectionStarted, HookState::Ejecting) {
+            Ok(HookState::Running(hook) | HookState::Degraded(hook)) => Some(hook),
+            _ => None,
+        }
+    }
+
+    /// Leaves `Ejecting`, to `Detected` if the process is still running.
+    pub fn ejected(&mut self, spotify: Option<SpotifyInfo>) -> Result<(), InvalidTransition> {
+        let next = match spotify {
+            Some(spotify) => HookState::Detected(spotify),
+            None => HookState::Idle,
+        };
+        self.transition(HookEvent::Ejected, next).map(drop)
+    }
+
+    /// Moves to `next` on `event` and returns the previous state.
+    fn transition(
+        &mut self,
+        event: HookEvent,
+        next: HookState,
+    ) -> Result<HookState, InvalidTransition> {
+        let (from, to) = (self.phase(), next.phase());
+        if !from.allows(event, to) {
+            return Err(self.invalid(event, to));
+        }
+        debug!("Hook state {from:?} -> {to:?} ({event:?})");
+        Ok(mem::replace(self, next))
+    }
+
+    /// Moves to the state built from the current one on `event`, which has to be in phase `to`.
+    fn transition_with(
+        &mut self,
+        event: HookEvent,
+        to: HookPhase,
+        next: impl FnOnce(HookState) -> HookState,
+    ) -> Result<(), InvalidTransition> {
+        let from = self.phase();
+        if !from.allows(event, to) {
+            return Err(self.invalid(event, to));
+        }
+        let previous = mem::replace(self, HookState::Idle);
+        *self = next(previous);
+        debug!("Hook state {from:?} -> {to:?} ({event:?})");
+        Ok(())
+    }
+
+    /// Reports a transition missing from [`HookPhase::allows`], which is a bug.
+    fn invalid(&self, event: HookEvent, to: HookPhase) -> InvalidTransition {
+        let error = InvalidTransition {
+            from: self.phase(),
+            event,
+            to,
+        };
+        warn!("{error}");
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHASES: [HookPhase; 6] = [
+        HookPhase::Idle,
+        HookPhase::Detected,
+        HookPhase::Injecting,
+        HookPhase::Running,
+        HookPhase::Degraded,
+        HookPhase::Ejecting,
+    ];
+
+    #[test]
+    fn allows_the_lifecycle() {
+        use HookEvent::*;
+        use HookPhase::*;
+
+        for (from, event, to) in [
+            (Idle, SpotifyFound, Detected),
+            (Detected, InjectionStarted, Injecting),
+            (Injecting, Injected, Running),
+            (Running, HealthCheckFailed, Degraded),
+            (Degraded, HealthCheckPassed, Running),
+            (Running, RpcStopped, Degraded),
+            (Degraded, EjectionStarted, Ejecting),
+            (Ejecting, Ejected, Detected),
+            (Injecting, InjectionFailed, Detected),
+            (Detected, SpotifyExited, Idle),
+        ] {
+            assert!(from.allows(event, to), "{from:?} -> {to:?} ({event:?})");
+        }
+    }
+
+    #[test]
+    fn refuses_skipping_phases() {
+        use HookEvent::*;
+        use HookPhase::*;
+
+        for (from, event, to) in [
+            (Idle, Injected, Running),
+            (Detected, Injected, Running),
+            (Running, SpotifyExited, Idle),
+            (Degraded, SpotifyExited, Idle),
+            (Running, Ejected, Idle),
+            (Idle, EjectionStarted, Ejecting),
+            (Degraded, RpcStopped, Degraded),
+        ] {
+            assert!(!from.allows(event, to), "{from:?} -> {to:?} ({event:?})");
+        }
+    }
+
+    #[test]
+    fn only_ejecting_leaves_a_hook() {
+        for from in [HookPhase::Running, HookPhase::Degraded] {
+            for to in [HookPhase::Idle, HookPhase::Detected, HookPhase::Injecting] {
+                for event in [
+                    HookEvent::SpotifyFound,
+                    HookEvent::SpotifyExited,
+                    HookEvent::InjectionStarted,
+                    HookEvent::InjectionFailed,
+                    HookEvent::Ejected,
+                ] {
+                    assert!(!from.allows(event, to), "{from:?} -> {to:?} ({event:?})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_leads_out_of_idle_but_finding_spotify() {
+        for to in PHASES {
+            for event in [
+                HookEvent::SpotifyExited,
+                HookEvent::InjectionStarted,
+                HookEvent::Injected,
+                HookEvent::InjectionFailed,
+                HookEvent::HealthCheckFailed,
+                HookEvent::HealthCheckPassed,
+                HookEvent::RpcStopped,
+                HookEvent::EjectionStarted,
+                HookEvent::Ejected,
+            ] {
+                assert!(
+                    !HookPhase::Idle.allows(event, to),
+                    "Idle -> {to:?} ({event:?})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ejected_leaves_ejecting() {
+        let mut state = HookState::Ejecting;
+        assert_eq!(state.ejected(None), Ok(()));
+        assert_eq!(state.phase(), HookPhase::Idle);
+    }
+
+    #[test]
+    fn invalid_transition_keeps_the_state() {
+        let mut state = HookState::Idle;
+        assert_eq!(
+            state.ejected(None),
+            Err(InvalidTransition {
+                from: HookPhase::Idle,
+                event: HookEvent::Ejected,
+                to: HookPhase::Idle,
+            })
+        );
+        assert_eq!(state.phase(), HookPhase::Idle);
+
+        assert_eq!(
+            state.start_injecting().map_err(|e| e.to),
+            Err(HookPhase::Injecting)
+        );
+        assert_eq!(state.phase(), HookPhase::Idle);
+    }
+
+    #[test]
+    fn guarded_events_are_ignored_without_a_hook() {
+        let mut state = HookState::Idle;
+        assert!(state.start_ejecting().is_none());
+        assert_eq!(state.record_health(false), Ok(()));
+        assert_eq!(state.rpc_lost(), Ok(()));
+        assert_eq!(state.injection_failed(), Ok(()));
+        assert_eq!(state.spotify_exited(), Ok(()));
+        assert_eq!(state.phase(), HookPhase::Idle);
+    }
+}
@@ -23,12 +23,19 @@ use winapi::{
     },
 };
 
-use crate::{logger::SimpleLog, APP_NAME_WITH_VERSION};
+use crate::{
+    logger::{FileLog, SimpleLog},
+    APP_NAME_WITH_VERSION,
+};
 
 use super::raw;
 
 #[derive(Debug)]
-pub struct Console(ConsoleImpl);
+pub struct Console {
+    inner: ConsoleImpl,
+    /// Log file receiving the same messages as the console.
+    tee: Option<FileLog>,
+}
 
 #[derive(Debug)]
 enum ConsoleImpl {
@@ -40,11 +47,25 @@ enum ConsoleImpl {
 unsafe impl Send for Console {}
 
 impl Console {
+    fn new(inner: ConsoleImpl) -> Self {
+        Self { inner, tee: None }
+    }
+
+    /// Attaches to the console of the parent process, e.g. the shell the app was started from.
+    /// Output redirected by the shell is kept.
     pub fn attach() -> Option<Self> {
-        raw::attach().then(|| Self(ConsoleImpl::Attach))
+        let redirected = raw::is_output_redirected();
+        if !raw::attach() {
+            return None;
+        }
+        if !redirected {
+            // The shell already printed its prompt, start on a fresh line.
+            println!();
+        }
+        Some(Self::new(ConsoleImpl::Attach))
     }
     pub fn alloc() -> Option<Self> {
-        raw::alloc().then(|| Self(ConsoleImpl::Alloc))
+        raw::alloc().then(|| Self::new(ConsoleImpl::Alloc))
     }
     pub fn piped() -> io::Result<Self> {
         let mut security_attributes = SECURITY_ATTRIBUTES {
@@ -124,24 +145,35 @@ impl Console {
 
         let process = unsafe { OwnedProcess::from_raw_handle(process_info.hProcess) };
         let pipe = File::from(child_stdin_write_pipe);
-        Ok(Self(ConsoleImpl::Piped { process, pipe }))
+        Ok(Self::new(ConsoleImpl::Piped { process, pipe }))
+    }
+
+    /// Also writes the messages logged to the console to the given file.
+    pub fn tee(&mut self, file: FileLog) {
+        self.tee = Some(file);
+    }
+    pub fn tee_file(&mut self) -> Option<&mut FileLog> {
+        self.tee.as_mut()
     }
 
     pub fn is_active(&self) -> bool {
-        match &self.0 {
+        match &self.inner {
             ConsoleImpl::Attach | ConsoleImpl::Alloc => true,
             ConsoleImpl::Piped { process, .. } => process.is_alive(),
         }
     }
     pub fn is_attached(&self) -> bool {
-        matches!(self.0, ConsoleImpl::Attach | ConsoleImpl::Alloc)
+        matches!(self.inner, ConsoleImpl::Attach | ConsoleImpl::Alloc)
+    }
+    pub fn is_allocated(&self) -> bool {
+        matches!(self.inner, ConsoleImpl::Alloc)
     }
     pub fn is_piped(&self) -> bool {
-        matches!(self.0, ConsoleImpl::Piped { .. })
+        matches!(self.inner, ConsoleImpl::Piped { .. })
     }
 
     pub fn println(&mut self, message: impl Display) -> io::Result<()> {
-        match &mut self.0 {
+        match &mut self.inner {
             ConsoleImpl::Attach | ConsoleImpl::Alloc => println!("{message}"),
             ConsoleImpl::Piped { pipe, .. } => writeln!(pipe, "{message}")?,
         }
@@ -157,7 +189,9 @@ impl SimpleLog for Console {
 
 impl Drop for Console {
     fn drop(&mut self) {
-        match self.0 {
+        match self.inner {
+            // The shell printed its prompt before our output and shows a fresh one once Enter is
+            // pressed, simulating the key press would also run whatever was typed meanwhile.
             ConsoleImpl::Attach => {}
             ConsoleImpl::Alloc => raw::free(),
             ConsoleImpl::Piped { ref process, .. } => {
@@ -10,6 +10,10 @@
 // panics.
 
 use winapi::um::consoleapi::AllocConsole;
+use winapi::um::fileapi::GetFileType;
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::processenv::GetStdHandle;
+use winapi::um::winbase::{FILE_TYPE_CHAR, FILE_TYPE_UNKNOWN, STD_OUTPUT_HANDLE};
 use winapi::um::wincon::{AttachConsole, FreeConsole, GetConsoleWindow, ATTACH_PARENT_PROCESS};
 use winapi::um::winuser::ShowWindow;
 use winapi::um::winuser::SW_HIDE;
@@ -33,6 +37,19 @@ pub fn attach() -> bool {
     unsafe { AttachConsole(ATTACH_PARENT_PROCESS) != 0 }
 }
 
+/// Check if stdout was redirected to a file or pipe by the parent, e.g. with `> file`.
+///
+/// Redirected handles are inherited even by GUI applications and are kept when attaching to
+/// the parent console.
+pub fn is_output_redirected() -> bool {
+    let handle = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+    if handle.is_null() || handle == INVALID_HANDLE_VALUE {
+        return false;
+    }
+    let file_type = unsafe { GetFileType(handle) };
+    file_type != FILE_TYPE_CHAR && file_type != FILE_TYPE_UNKNOWN
+}
+
 /// Try to allocate ourselves a new console.
 pub fn alloc() -> bool {
     unsafe { AllocConsole() != 0 }
@@ -1,15 +1,24 @@
 use std::{
     fmt::Debug,
-    sync::{Mutex, MutexGuard}
+    io,
+    sync::{Mutex, MutexGuard},
 };
 
 use chrono::Local;
 
 use log::Log;
 
-use crate::APP_NAME;
+use crate::{args::LogLevel, environment::System, paths, privacy, APP_NAME};
 
-use super::{Console, FileLog, SimpleLog};
+use super::{Console, FileLog, MemoryLog, SimpleLog};
+
+const RECENT_MESSAGE_CAPACITY: usize = 500;
+/// Prefixes of the targets that are logged, messages of other crates are dropped.
+const LOG_TARGETS: [&str; 2] = [APP_NAME, "burnt_sushi_core"];
+
+/// Target for messages containing urls, which are redacted in persistent sinks.
+/// The url has to be attached as the `url` key-value.
+pub const URL_LOG_TARGET: &str = "BurntSushi::url";
 
 static LOGGER: GlobalLoggerHolder = GlobalLoggerHolder(Mutex::new(GlobalLogger::new()));
 
@@ -22,6 +31,38 @@ pub fn get() -> MutexGuard<'static, GlobalLogger> {
     LOGGER.0.lock().unwrap()
 }
 
+/// Sets the level of messages passed to the sinks, the console and log files use their own level
+/// if one is given.
+pub fn configure(level: LogLevel, console_level: Option<LogLevel>, file_level: Option<LogLevel>) {
+    let mut logger = get();
+    logger.level = level;
+    logger.console_level = console_level;
+    logger.file_level = file_level;
+    logger.update_max_level();
+}
+
+/// Changes the level of messages passed to the sinks without a level of their own.
+pub fn set_level(level: LogLevel) {
+    let mut logger = get();
+    logger.level = level;
+    logger.update_max_level();
+    drop(logger);
+    log::info!("Log level set to {}", level.name());
+}
+
+pub fn level() -> LogLevel {
+    get().level
+}
+
+/// Opens a console window showing the log unless one is already open.
+pub fn show_console() -> io::Result<()> {
+    let mut logger = get();
+    if logger.console.is_none() {
+        logger.set_console(Console::piped()?);
+    }
+    Ok(())
+}
+
 pub fn unset() {
     let mut logger = get();
     logger.console = None;
@@ -35,6 +76,10 @@ pub struct GlobalLoggerHolder(Mutex<GlobalLogger>);
 pub struct GlobalLogger {
     pub console: Option<Console>,
     pub file: Option<FileLog>,
+    pub recent: MemoryLog,
+    level: LogLevel,
+    console_level: Option<LogLevel>,
+    file_level: Option<LogLevel>,
 }
 
 impl GlobalLogger {
@@ -42,8 +87,41 @@ impl GlobalLogger {
         GlobalLogger {
             console: None,
             file: None,
+            recent: MemoryLog::new(RECENT_MESSAGE_CAPACITY),
+            level: LogLevel::Debug,
+            console_level: None,
+            file_level: None,
         }
     }
+
+    fn console_level(&self) -> log::LevelFilter {
+        self.console_level.unwrap_or(self.level).into_level_filter()
+    }
+
+    fn file_level(&self) -> log::LevelFilter {
+        self.file_level.unwrap_or(self.level).into_level_filter()
+    }
+
+    /// Lets through the messages of the most verbose sink, the others filter them in [`Log::log`].
+    fn update_max_level(&self) {
+        log::set_max_level(
+            self.level
+                .into_level_filter()
+                .max(self.console_level())
+                .max(self.file_level()),
+        );
+    }
+
+    /// Sets the console, which keeps its output in the default log file unless a log file is
+    /// already written.
+    pub fn set_console(&mut self, mut console: Console) {
+        if self.file.is_none() {
+            if let Some(path) = paths::log_file(&System) {
+                console.tee(FileLog::new(path));
+            }
+        }
+        self.console = Some(console);
+    }
 }
 
 impl Log for GlobalLoggerHolder {
@@ -52,20 +130,43 @@ impl Log for GlobalLoggerHolder {
     }
 
     fn log(&self, record: &log::Record) {
-        if !record.target().starts_with(APP_NAME) {
+        if !LOG_TARGETS
+            .iter()
+            .any(|target| record.target().starts_with(target))
+        {
             return;
         }
 
+        let mut args = record.args().to_string();
+        if record.target() == URL_LOG_TARGET {
+            if let Some(url) = record.key_values().get("url".into()) {
+                let url = url.to_string();
+                args = args.replace(&url, &privacy::redact_url(&url, privacy::url_privacy()));
+            }
+        }
+
+        let date_time = Local::now().format("%Y-%m-%d %H:%M:%S");
+        let message = format!("{} [{}] {}", date_time, record.level(), args);
+
         let mut logger = self.0.lock().unwrap();
+        let level = record.level();
+        let log_console = level <= logger.console_level();
+        let log_file = level <= logger.file_level();
         if let Some(log) = &mut logger.console {
-            let message = format!("[{}] {}", record.level(), record.args());
-            log.log(&message);
+            if log_console {
+                log.log(&format!("{} [{}] {}", date_time, level, record.args()));
+            }
+            if let Some(tee) = log.tee_file().filter(|_| log_file) {
+                tee.log(&message);
+            }
         }
-        if let Some(log) = &mut logger.file {
-            let date_time = Local::now().format("%Y-%m-%d %H:%M:%S");
-            let message = format!("{} [{}] {}", date_time, record.level(), record.args());
+
+        if let Some(log) = logger.file.as_mut().filter(|_| log_file) {
             log.log(&message);
         }
+        if level <= logger.level.into_level_filter() {
+            logger.recent.log(&message);
+        }
     }
 
     fn flush(&self) {}
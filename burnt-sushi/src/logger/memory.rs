@@ -0,0 +1,32 @@
+use std::collections::VecDeque;
+
+use super::SimpleLog;
+
+/// Keeps the most recent log messages in memory for diagnostics.
+#[derive(Debug)]
+pub struct MemoryLog {
+    messages: VecDeque<String>,
+    capacity: usize,
+}
+
+impl MemoryLog {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|m| m.as_str())
+    }
+}
+
+impl SimpleLog for MemoryLog {
+    fn log(&mut self, message: &str) {
+        if self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message.to_string());
+    }
+}
@@ -1,10 +1,12 @@
 pub mod console;
 pub mod file;
 pub mod global;
+pub mod memory;
 pub mod noop;
 
 mod traits;
 
 pub use console::Console;
 pub use file::FileLog;
+pub use memory::MemoryLog;
 pub use traits::*;
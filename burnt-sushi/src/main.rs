@@ -1,82 +1,148 @@
-#![feature(
-    once_cell_try,
-    maybe_uninit_uninit_array,
-    maybe_uninit_slice,
-    iter_intersperse
-)]
 #![warn(unsafe_op_in_unsafe_fn)]
 #![allow(clippy::module_inception, non_snake_case)]
 #![windows_subsystem = "windows"]
 
 use anyhow::{anyhow, Context};
+use burnt_sushi_core::{
+    metrics, rpc, spotify_process_scanner, APP_NAME, DEFAULT_BLOCKER_FILE_NAME,
+};
 use dll_syringe::process::{OwnedProcess, Process};
 use log::{debug, error, info, trace, warn};
+use tokio::task::LocalSet;
 use winapi::{
     shared::minwindef::FALSE,
     um::{processthreadsapi::OpenProcess, synchapi::WaitForSingleObject, winnt::PROCESS_TERMINATE},
 };
 
-use std::{env, io, os::windows::prelude::FromRawHandle, time::Duration};
+use std::{
+    env, io,
+    os::windows::prelude::FromRawHandle,
+    path::Path,
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    args::{LogLevel, ARGS},
+    args::{
+        AutostartAction, Command, CrashReportAction, HistoryAction, LogLevel, ServiceAction,
+        TelemetryAction, ARGS,
+    },
     blocker::SpotifyAdBlocker,
+    control::ControlCommand,
+    environment::System,
+    i18n::{tr, Msg},
     logger::{Console, FileLog},
     named_mutex::NamedMutex,
+    self_test::SelfTest,
+    shutdown::ShutdownReason,
 };
 
+mod accessibility;
+mod ad_slip;
 mod args;
+mod autostart;
+mod backup;
 mod blocker;
+mod canary;
+mod collect_logs;
+mod control;
+mod crash;
+mod crash_report;
+mod diagnostics;
+mod efficacy;
+mod environment;
+mod events;
+mod filter_history;
+mod filter_providers;
+mod filter_tests;
+mod healthz;
+mod i18n;
+mod jump_list;
+mod lifecycle;
 mod logger;
+mod media;
 mod named_mutex;
+mod notify;
+mod paths;
+mod power;
+mod preparation;
+mod privacy;
+mod quiet_hours;
+mod request_log;
 mod resolver;
-mod rpc;
-mod spotify_process_scanner;
+mod scripting;
+mod self_test;
+mod service;
+mod session;
+mod settings;
+mod shutdown;
+mod spotify_autostart;
+mod stats;
+mod status;
+mod subscription_manager;
+mod supervisor;
+mod sweep;
+mod telemetry;
 mod tray;
+mod uninstall;
 mod update;
+mod user_rules;
+mod utils;
+mod web_api;
+mod webhook;
 
-const APP_NAME: &str = "BurntSushi";
 const APP_AUTHOR: &str = "OpenByteDev";
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 const APP_NAME_WITH_VERSION: &str = concat!("BurntSushi v", env!("CARGO_PKG_VERSION"));
-const DEFAULT_BLOCKER_FILE_NAME: &str = "BurntSushiBlocker_x64.dll";
 const DEFAULT_FILTER_FILE_NAME: &str = "filter.toml";
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(30);
+const SHELL_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     logger::global::init();
 
-    log::set_max_level(ARGS.log_level.into_level_filter());
+    if let Some(crash_dir) = paths::crash_dir(&System) {
+        crash::install(crash_dir);
+    }
+
+    logger::global::configure(ARGS.log_level, ARGS.console_log_level, ARGS.file_log_level);
+
+    let mut log_file = ARGS.log_file.clone();
+    if log_file.is_none() && (ARGS.log_level == LogLevel::Debug || ARGS.file_log_level.is_some()) {
+        log_file = paths::log_file(&System);
+    }
+    if let Some(log_file) = log_file {
+        logger::global::get().file = Some(FileLog::new(log_file));
+    }
 
-    if !ARGS.no_attach {
+    // Settings are not loaded yet, so only the flags decide about attaching consoles.
+    if !ARGS.no_attach && !ARGS.silent && !ARGS.autostart {
         if let Some(console) = Console::attach() {
-            logger::global::get().console = Some(console);
+            logger::global::get().set_console(console);
             debug!("Attached to console");
         }
     }
 
     if ARGS.console {
         if let Some(console) = Console::alloc() {
-            logger::global::get().console = Some(console);
+            logger::global::get().set_console(console);
             debug!("Allocated new console");
         }
     }
 
-    let mut log_file = ARGS.log_file.clone();
-    if log_file.is_none() && ARGS.log_level == LogLevel::Debug {
-        let mut auto_log_file = dirs::data_dir();
-        if let Some(ref mut log_file) = auto_log_file {
-            log_file.push("OpenByte");
-            log_file.push("BurntSushi");
-            log_file.push("BurntSushi.log");
-        }
-        log_file = auto_log_file;
-    }
-    if let Some(log_file) = log_file {
-        logger::global::get().file = Some(FileLog::new(log_file));
-    }
-
     info!("{}", APP_NAME_WITH_VERSION);
+
+    // Load settings up front so a broken settings file is reported early.
+    let (console_log_level, file_log_level) = {
+        let settings = settings::get();
+        (settings.console_log_level, settings.file_log_level)
+    };
+    logger::global::configure(
+        ARGS.log_level,
+        ARGS.console_log_level.or(console_log_level),
+        ARGS.file_log_level.or(file_log_level),
+    );
     trace!(
         "Running from {}",
         env::current_exe()
@@ -84,6 +150,18 @@ async fn main() {
             .display()
     );
 
+    if let Some(command) = &ARGS.command {
+        run_command(command).await;
+        logger::global::unset();
+        return;
+    }
+
+    if ARGS.check_filters {
+        let passed = check_filters().await;
+        logger::global::unset();
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     if ARGS.install {
         match handle_install().await {
             Ok(()) => info!("App successfully installed."),
@@ -106,13 +184,27 @@ async fn main() {
         }
     }
 
+    if ARGS.supervise {
+        supervisor::run();
+        logger::global::unset();
+        return;
+    }
+
     if ARGS.ignore_singleton {
         run().await;
     } else {
-        let lock = NamedMutex::new(&format!("{APP_NAME} SINGLETON MUTEX")).unwrap();
+        let lock = singleton_mutex().unwrap();
 
         let mut guard_result = lock.try_lock();
 
+        if matches!(guard_result, Ok(None)) && request_handoff().await {
+            let deadline = Instant::now() + HANDOFF_TIMEOUT;
+            while matches!(guard_result, Ok(None)) && Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                guard_result = lock.try_lock();
+            }
+        }
+
         if ARGS.singleton_wait_for_shutdown {
             while matches!(guard_result, Ok(None)) {
                 tokio::time::sleep(Duration::from_millis(100)).await;
@@ -135,40 +227,537 @@ async fn main() {
     logger::global::unset();
 }
 
+/// One instance per session, instances of other users are coordinated when hooking.
+fn singleton_mutex() -> io::Result<NamedMutex> {
+    NamedMutex::new(&format!(
+        "{APP_NAME} SINGLETON MUTEX {}",
+        session::current_id()
+    ))
+}
+
+/// Asks the running instance to unhook and exit and waits until it did, so that it does not inject
+/// again. Instances started with `--ignore-singleton` are not stopped.
+async fn stop_running_instance() {
+    match control::send(&ControlCommand::Exit).await {
+        Ok(_) => info!("Stopping the running instance..."),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("Failed to stop the running instance: {e}");
+            return;
+        }
+    }
+
+    let lock = match singleton_mutex() {
+        Ok(lock) => lock,
+        Err(e) => {
+            warn!("Failed to wait for the running instance to exit: {e}");
+            return;
+        }
+    };
+    let deadline = Instant::now() + HANDOFF_TIMEOUT;
+    while matches!(lock.try_lock(), Ok(None)) {
+        if Instant::now() >= deadline {
+            warn!("Running instance did not exit in time");
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Loads the rules from all providers and runs the tests of the filter file against them.
+async fn check_filters() -> bool {
+    filter_providers::register_configured();
+    let filter_config = match filter_providers::load().await {
+        Ok(filter_config) => filter_config,
+        Err(e) => {
+            error!("Failed to load filters: {e}");
+            return false;
+        }
+    };
+    println!(
+        "Loaded {} allowlist and {} denylist rules",
+        filter_config.allowlist.len(),
+        filter_config.denylist.len()
+    );
+
+    match filter_tests::run(&filter_config) {
+        Ok((count, failures)) => {
+            for failure in &failures {
+                println!("FAILED: {failure}");
+            }
+            println!("{} of {count} filter tests passed", count - failures.len());
+            failures.is_empty()
+        }
+        Err(e) => {
+            error!("{e:#}");
+            false
+        }
+    }
+}
+
+/// Applies the configured Spotify process names, keeping the defaults if none are configured.
+fn configure_process_names() {
+    let names = settings::get().spotify_process_names.clone();
+    if names.iter().all(|name| name.trim().is_empty()) {
+        warn!("No Spotify process names configured, using the defaults");
+        return;
+    }
+    debug!("Treating processes named {names:?} as Spotify");
+    spotify_process_scanner::set_process_names(names);
+}
+
+/// Starts the executable passed with `--dev-target`, which the scanner treats as Spotify.
+fn start_dev_target(target: &Path) {
+    if let Some(file_name) = target.file_name() {
+        spotify_process_scanner::set_dev_target(file_name);
+    }
+    match std::process::Command::new(target)
+        .stdout(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => info!(
+            "Started development target '{}' (PID={})",
+            target.display(),
+            child.id()
+        ),
+        Err(e) => error!(
+            "Failed to start development target '{}': {e}",
+            target.display()
+        ),
+    }
+}
+
+/// Whether the app should start without notifications and prompts.
+fn is_silent_start() -> bool {
+    ARGS.silent || (ARGS.autostart && settings::get().silent_autostart)
+}
+
 async fn run() {
-    let mut system_tray = tray::SystemTrayManager::build_and_run().await.unwrap();
+    let silent = is_silent_start();
+    configure_process_names();
+    if let Some(target) = &ARGS.dev_target {
+        start_dev_target(target);
+    }
+    if let Some(path) = &ARGS.record_rpc {
+        let redact_url = |url: &str| privacy::redact_url(url, privacy::url_privacy());
+        if let Err(e) = rpc::recording::start(path, redact_url) {
+            warn!("Failed to start recording RPC traffic: {e}");
+        }
+    }
 
-    let mut app = SpotifyAdBlocker::new();
+    accessibility::subscribe();
+    scripting::subscribe();
+    webhook::subscribe();
 
-    let (update_restart_tx, update_restart_rx) = tokio::sync::oneshot::channel();
-    tokio::task::spawn(async move {
-        match update::update().await {
-            Ok(true) => update_restart_tx.send(()).unwrap(),
-            Ok(false) => {}
-            Err(e) => error!("App update failed: {e:#}"),
+    let mut self_test = SelfTest::new();
+    self_test.check_config();
+    if let Some(blocker) = preparation::start().await {
+        self_test.check_blocker(blocker);
+    }
+
+    let system_tray = if ARGS.no_tray {
+        debug!("Running without tray icon");
+        None
+    } else {
+        if ARGS.autostart {
+            // On logon the app may start before the taskbar exists.
+            tray::wait_for_shell(SHELL_WAIT_TIMEOUT).await;
         }
+        self_test.check(
+            "Tray icon",
+            "Restart Windows Explorer or sign out and back in.",
+            tray::SystemTrayManager::build_and_run().await,
+        )
+    };
+
+    // The tray icon's window handles the shell messages, e.g. the session end, if there is one.
+    let shell_window = match system_tray {
+        Some(_) => None,
+        None => tray::ShellWindowManager::build_and_run()
+            .await
+            .inspect_err(|e| warn!("Failed to listen for shell messages: {e}"))
+            .ok(),
+    };
+
+    let (control_tx, control_rx) = tokio::sync::mpsc::channel(8);
+    // An attached console keeps being read by the parent shell.
+    if logger::global::get()
+        .console
+        .as_ref()
+        .is_some_and(|console| console.is_allocated())
+    {
+        control::read_console(control_tx.clone());
+    }
+    let control_server = if ARGS.ignore_singleton {
+        // Running alongside the instance owning the pipe, which is asked before hooking.
+        control::bind()
+            .inspect_err(|_| {
+                info!("Another instance owns the control channel, sharing Spotify with it")
+            })
+            .ok()
+    } else {
+        self_test.check(
+            "Control channel",
+            "Close other running instances of the app.",
+            control::bind(),
+        )
+    };
+    let control_task = control_server.map(|server| {
+        // Stopped last to not interrupt the response to a shutdown request.
+        tokio::task::spawn(async move {
+            if let Err(e) = control::serve(server, control_tx).await {
+                warn!("Control channel failed: {e}");
+            }
+        })
     });
 
-    tokio::select! {
-        _ = app.run() => {
+    let started = self_test.passed();
+    self_test.report();
+    if started && !silent && settings::get().start_notification {
+        notify::info(tr(Msg::Started), tr(Msg::WatchingForSpotify));
+    }
+
+    if let Some(port) = settings::get().metrics_port {
+        shutdown::spawn("metrics", async move {
+            if let Err(e) = metrics::serve(port).await {
+                warn!("Metrics endpoint unavailable: {e}");
+            }
+        });
+    }
+    if let Some(port) = settings::get().healthz_port {
+        shutdown::spawn("health check", async move {
+            if let Err(e) = healthz::serve(port).await {
+                warn!("Health check endpoint unavailable: {e}");
+            }
+        });
+    }
+
+    session::install_console_handler();
+    media::start();
+
+    let stats_task = shutdown::spawn("stats", stats::save_periodically());
+    shutdown::spawn("web api", web_api::run());
+    shutdown::spawn("crash reports", crash_report::run());
+    shutdown::spawn("telemetry", telemetry::run());
+    filter_providers::register_configured();
+    shutdown::spawn("filter refresh", filter_providers::refresh_periodically());
+    if let Err(e) = spotify_autostart::sync() {
+        warn!("Failed to update Spotify autostart: {e:#}");
+    }
+    shutdown::spawn("spotify autostart", spotify_autostart::start_when_ready());
+    tokio::task::spawn_blocking(|| {
+        if let Err(e) = jump_list::register() {
+            warn!("{e:#}");
+        }
+    });
+
+    shutdown::spawn("update", async move {
+        update::run(silent).await;
+        shutdown::request(ShutdownReason::Update);
+    });
+    shutdown::spawn("ctrl-c", async {
+        if wait_for_ctrl_c().await.is_ok() {
+            shutdown::request(ShutdownReason::CtrlC);
+        }
+    });
+    shutdown::spawn("session end", async {
+        session::wait_for_session_end().await;
+        shutdown::request(ShutdownReason::SessionEnd);
+    });
+    // The tray is handed back once the shutdown is requested, it is closed last.
+    let tray_task = system_tray.map(|mut system_tray| {
+        tokio::task::spawn(async move {
+            tokio::select! {
+                _ = system_tray.wait_for_exit() => shutdown::request(ShutdownReason::TrayExit),
+                _ = shutdown::requested() => {}
+            }
+            system_tray
+        })
+    });
+
+    // Runs until the shutdown is requested, never in the middle of hooking or unhooking. The RPC
+    // tasks of the blocker run on this thread alongside the app, as the RPC system of capnp-rpc is
+    // not `Send`. The requests they report are handled on a thread of their own, see `RequestLog`.
+    let rpc_tasks = LocalSet::new();
+    let mut app = SpotifyAdBlocker::new(control_rx);
+    rpc_tasks.run_until(app.run()).await;
+    // Also stops on its own, e.g. if the scanner failed.
+    shutdown::request(ShutdownReason::AppStopped);
+
+    if let Some(reason) = shutdown::reason() {
+        info!("Shutting down ({reason})...");
+    }
+
+    rpc_tasks.run_until(app.stop()).await;
+    let _ = stats_task.await;
+    if let Err(e) = stats::get().save() {
+        warn!("Failed to save stats: {e:#}");
+    }
+    telemetry::save();
+    session::shutdown_complete();
+    // Release the pipe so that an instance taking over can bind it.
+    if let Some(control_task) = control_task {
+        control_task.abort();
+    }
+    if let Some(tray_task) = tray_task {
+        match tray_task.await {
+            Ok(system_tray) => system_tray.exit().await,
+            Err(e) => error!("Tray task failed: {e}"),
+        }
+    }
+    if let Some(shell_window) = shell_window {
+        shell_window.exit().await;
+    }
+
+    info!("Exiting...");
+}
+
+/// Asks an older running instance to shut down so this one can take over.
+/// Returns whether the running instance agreed to.
+async fn request_handoff() -> bool {
+    let running_version = match control::send(&ControlCommand::Version).await {
+        Ok(version) => version,
+        Err(e) => {
+            debug!("Failed to query running instance: {e}");
+            return false;
+        }
+    };
+    let Ok(running_version) = lenient_semver::parse(running_version.trim()) else {
+        debug!("Running instance does not support handoff");
+        return false;
+    };
+    if running_version >= lenient_semver::parse(APP_VERSION).unwrap() {
+        return false;
+    }
+
+    info!("Taking over from running instance (v{running_version})...");
+    match control::send(&ControlCommand::Handoff).await {
+        Ok(response) if response == "ok" => true,
+        Ok(response) => {
+            warn!("Running instance refused handoff: {response}");
+            false
+        }
+        Err(e) => {
+            warn!("Failed to request handoff: {e}");
+            false
+        }
+    }
+}
+
+async fn run_command(command: &Command) {
+    let command = match command {
+        Command::CollectLogs { output } => {
+            match collect_logs::collect_logs(output.as_deref()) {
+                Ok(path) => println!("Saved logs to '{}'", path.display()),
+                Err(e) => error!("Failed to collect logs: {e:#}"),
+            }
+            return;
+        }
+        Command::Backup { output } => {
+            match backup::backup(output) {
+                Ok(count) => println!("Backed up {count} files to '{}'", output.display()),
+                Err(e) => error!("Failed to back up user data: {e:#}"),
+            }
+            return;
+        }
+        Command::Restore { input, scripts } => {
+            match backup::restore(input, *scripts).await {
+                Ok(count) => println!("Restored {count} files from '{}'", input.display()),
+                Err(e) => error!("Failed to restore user data: {e:#}"),
+            }
+            return;
+        }
+        Command::ConvertFilters { input, output } => {
+            match filter_providers::convert(input, output) {
+                Ok(()) => println!("Converted filters to '{}'", output.display()),
+                Err(e) => error!("Failed to convert filters: {e:#}"),
+            }
+            return;
+        }
+        Command::ReplayRpc { recording } => {
+            match request_log::replay(recording) {
+                Ok((summary, stats)) => {
+                    println!(
+                        "Replayed {} requests, {} blocked ({} ads), {} dropped, {} rejected",
+                        summary.requests,
+                        summary.blocked,
+                        stats.ads_blocked,
+                        summary.dropped,
+                        summary.rejected
+                    );
+                    for (rule, hits) in stats.top_rules() {
+                        println!("  {hits:>6}  {rule}");
+                    }
+                }
+                Err(e) => error!("Failed to replay RPC recording: {e:#}"),
+            }
+            return;
+        }
+        Command::Autostart { action } => {
+            match handle_autostart(action) {
+                Ok(message) => println!("{message}"),
+                Err(e) => error!("Failed to configure autostart: {e:#}"),
+            }
+            return;
+        }
+        Command::History { action } => {
+            match handle_history(action).await {
+                Ok(message) => println!("{message}"),
+                Err(e) => error!("Failed to access filter history: {e:#}"),
+            }
+            return;
+        }
+        Command::Service { action } => {
+            let result = match action {
+                ServiceAction::Install => service::install().map(|_| "Service installed"),
+                ServiceAction::Uninstall => service::uninstall().map(|_| "Service uninstalled"),
+                ServiceAction::Run => service::run().map(|_| "Service stopped"),
+            };
+            match result {
+                Ok(message) => info!("{message}"),
+                Err(e) => error!("Service command failed: {e:#}"),
+            }
+            return;
+        }
+        Command::CrashReport { action } => {
+            match action {
+                CrashReportAction::View => match crash_report::preview() {
+                    Ok(preview) => println!("{preview}"),
+                    Err(e) => error!("Failed to read crash reports: {e:#}"),
+                },
+                CrashReportAction::Send => match crash_report::send_pending().await {
+                    Ok(count) => info!("Sent {count} crash report(s)."),
+                    Err(e) => error!("Failed to send crash reports: {e:#}"),
+                },
+                CrashReportAction::Discard => {
+                    info!(
+                        "Discarded {} crash report(s).",
+                        crash_report::discard_pending()
+                    )
+                }
+            }
+            return;
+        }
+        Command::Telemetry { action } => {
+            handle_telemetry(action).await;
+            return;
+        }
+        Command::SpotifyLogin => {
+            match web_api::login().await {
+                Ok(()) => info!("Spotify Web API authorized."),
+                Err(e) => error!("Failed to authorize Spotify Web API: {e:#}"),
+            }
+            return;
         }
-        _ = wait_for_ctrl_c() => {
-            debug!("Ctrl-C received");
+        Command::Uninstall { remove_logs } => {
+            match uninstall::uninstall(*remove_logs) {
+                Ok(()) => info!("{APP_NAME} was uninstalled."),
+                Err(e) => error!("Failed to uninstall: {e:#}"),
+            }
+            return;
         }
-        _ = system_tray.wait_for_exit() => {
-            debug!("System tray exited");
+        Command::EjectAll => {
+            stop_running_instance().await;
+            match tokio::task::spawn_blocking(sweep::eject_all).await {
+                Ok(Ok(0)) => info!("No blockers are loaded."),
+                Ok(Ok(count)) => info!("Ejected {count} blockers."),
+                Ok(Err(e)) => error!("{e:#}"),
+                Err(e) => error!("Failed to eject blockers: {e}"),
+            }
+            return;
         }
-        Ok(_) = update_restart_rx => {
-            debug!("Shutting down due to update");
+        Command::Diagnostics => ControlCommand::Diagnostics,
+        Command::Exit => ControlCommand::Exit,
+        Command::Pause { minutes } => ControlCommand::Pause(*minutes),
+        Command::Inject { pid } => ControlCommand::Inject(*pid),
+        Command::PauseUntilRestart => ControlCommand::PauseUntilRestart,
+        Command::Reload => ControlCommand::Reload,
+        Command::ShowActivity => ControlCommand::ShowActivity,
+        Command::SetLogLevel { level } => ControlCommand::SetLogLevel(*level),
+    };
+
+    match control::send(&command).await {
+        Ok(response) => println!("{response}"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            error!("{APP_NAME} is not running.")
         }
+        Err(e) => error!("Failed to send command to running instance: {e}"),
     }
+}
+
+async fn handle_history(action: &HistoryAction) -> anyhow::Result<String> {
+    match action {
+        HistoryAction::List => filter_history::summary(),
+        HistoryAction::Revert { id } => {
+            filter_history::revert(*id)?;
+            reload_running_instance().await;
+            Ok(format!(
+                "Using the rules of change #{id}, run `history unpin` to follow the filter sources again"
+            ))
+        }
+        HistoryAction::Unpin => {
+            if !filter_history::unpin()? {
+                return Ok("No rules are pinned".to_string());
+            }
+            reload_running_instance().await;
+            Ok("Following the filter sources again".to_string())
+        }
+    }
+}
 
-    info!("Shutting down...");
+/// Asks the running instance, which holds the counters and would overwrite its settings, and only
+/// falls back to this process if there is none.
+async fn handle_telemetry(action: &TelemetryAction) {
+    let command = match action {
+        TelemetryAction::Preview => ControlCommand::TelemetryPreview,
+        TelemetryAction::Enable => ControlCommand::SetTelemetry(true),
+        TelemetryAction::Disable => ControlCommand::SetTelemetry(false),
+    };
+    match control::send(&command).await {
+        Ok(response) => println!("{response}"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match command {
+            ControlCommand::SetTelemetry(enabled) => {
+                if let Err(e) = telemetry::set_enabled(enabled) {
+                    error!("Failed to save settings: {e:#}");
+                }
+            }
+            _ => match telemetry::preview() {
+                Ok(preview) => println!("{preview}"),
+                Err(e) => error!("{e:#}"),
+            },
+        },
+        Err(e) => error!("Failed to send command to running instance: {e}"),
+    }
+}
 
-    app.stop().await;
-    system_tray.exit().await;
+/// Applies changed filter files to the running instance, if there is one.
+async fn reload_running_instance() {
+    match control::send(&ControlCommand::Reload).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to reload filters of running instance: {e}"),
+    }
+}
 
-    info!("Exiting...");
+fn handle_autostart(action: &AutostartAction) -> anyhow::Result<String> {
+    match action {
+        AutostartAction::Enable {
+            method,
+            delay,
+            supervise,
+        } => {
+            autostart::enable(*method, *delay, *supervise)?;
+            Ok(format!("Autostart enabled ({method:?})"))
+        }
+        AutostartAction::Disable => {
+            autostart::disable()?;
+            Ok("Autostart disabled".to_string())
+        }
+        AutostartAction::Status => Ok(match autostart::status()? {
+            Some(method) => format!("Autostart enabled ({method:?})"),
+            None => "Autostart disabled".to_string(),
+        }),
+    }
 }
 
 async fn wait_for_ctrl_c() -> Result<(), ctrlc::Error> {
@@ -193,7 +782,7 @@ async fn handle_install() -> anyhow::Result<()> {
         .parent()
         .ok_or_else(|| anyhow!("Failed to determine parent directory"))?
         .join(DEFAULT_BLOCKER_FILE_NAME);
-    resolver::resolve_blocker(Some(&blocker_location))
+    resolver::resolve_blocker(&System, Some(&blocker_location))
         .await
         .context("Failed to write blocker to disk")?;
 
@@ -0,0 +1,272 @@
+use std::{
+    fmt, mem, ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use windows::{
+    core::Interface,
+    Media::Control::{
+        GlobalSystemMediaTransportControlsSession as Session,
+        GlobalSystemMediaTransportControlsSessionManager as SessionManager,
+        GlobalSystemMediaTransportControlsSessionPlaybackStatus as PlaybackStatus,
+    },
+    Win32::{
+        Foundation::BOOL,
+        Media::Audio::{
+            eRender, IAudioSessionControl2, IAudioSessionManager2, IMMDeviceEnumerator,
+            ISimpleAudioVolume, MMDeviceEnumerator, DEVICE_STATE_ACTIVE,
+        },
+        System::{
+            Com::{
+                CoCreateInstance, CoInitializeEx, CoTaskMemFree, CLSCTX_ALL, COINIT_MULTITHREADED,
+            },
+            WinRT::{RoInitialize, RO_INIT_MULTITHREADED},
+        },
+    },
+};
+
+use crate::{
+    ad_slip::{self, AdSource},
+    power, privacy, settings, stats,
+    status::{self, HookStatus},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const LOW_POWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Longest item that is taken for an ad, Spotify's ads last 15 to 30 seconds while podcast
+/// episodes, which are reported without an artist as well, are far longer.
+const MAX_AD_DURATION: Duration = Duration::from_secs(60);
+
+static NOW_PLAYING: Mutex<Option<NowPlaying>> = Mutex::new(None);
+/// Whether Spotify was muted for an ad that could not be skipped.
+static MUTED: AtomicBool = AtomicBool::new(false);
+
+/// What Spotify reports to the Windows media session (SMTC).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+    pub playing: bool,
+    /// Length of the item, if reported.
+    pub duration: Option<Duration>,
+}
+
+impl NowPlaying {
+    /// Whether the item looks like an ad, which Spotify reports without an artist and which is
+    /// short. Items of unknown length are not taken for ads.
+    pub fn is_likely_ad(&self) -> bool {
+        self.playing
+            && self.artist.is_empty()
+            && !self.title.is_empty()
+            && self
+                .duration
+                .is_some_and(|duration| duration <= MAX_AD_DURATION)
+    }
+}
+
+/// Only shows the item if urls are logged in full, see [`privacy::redact_title`].
+impl fmt::Display for NowPlaying {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.artist.is_empty() {
+            write!(f, "{}", privacy::redact_title(&self.title))
+        } else {
+            let item = format!("{} - {}", self.artist, self.title);
+            write!(f, "{}", privacy::redact_title(&item))
+        }
+    }
+}
+
+/// Returns what Spotify is currently playing, if it has a media session.
+pub fn now_playing() -> Option<NowPlaying> {
+    NOW_PLAYING.lock().unwrap().clone()
+}
+
+/// Unmutes Spotify if it was muted for an ad, e.g. when blocking stops before the ad ended.
+pub async fn unmute() {
+    if !MUTED.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    info!("Unmuting Spotify");
+    // COM may not be initialized on the calling thread.
+    let result = tokio::task::spawn_blocking(|| {
+        unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.ok()?;
+        set_spotify_muted(false)
+    })
+    .await;
+    match result {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => warn!("Failed to unmute Spotify: {e}"),
+        Err(e) => warn!("Failed to unmute Spotify: {e}"),
+    }
+}
+
+/// Starts a background thread that keeps track of Spotify's media session.
+pub fn start() {
+    thread::spawn(|| {
+        if let Err(e) = unsafe { RoInitialize(RO_INIT_MULTITHREADED) } {
+            warn!("Failed to initialize WinRT, media session is unavailable: {e}");
+            return;
+        }
+
+        let manager = match SessionManager::RequestAsync().and_then(|op| op.get()) {
+            Ok(manager) => manager,
+            Err(e) => {
+                warn!("Failed to access media sessions: {e}");
+                return;
+            }
+        };
+
+        let mut last_poll = Instant::now();
+        loop {
+            let (session, now_playing) = match read_spotify_session(&manager) {
+                Ok(Some((session, now_playing))) => (Some(session), Some(now_playing)),
+                Ok(None) => (None, None),
+                Err(e) => {
+                    debug!("Failed to read media session: {e}");
+                    (None, None)
+                }
+            };
+
+            // Only swapped under the lock, which `now_playing` takes for every request.
+            let previous = mem::replace(&mut *NOW_PLAYING.lock().unwrap(), now_playing.clone());
+
+            // Credited to what was playing since the last poll, unless the system slept meanwhile.
+            let elapsed = last_poll.elapsed();
+            last_poll = Instant::now();
+            if let Some(playing) = previous.as_ref() {
+                if playing.playing
+                    && !playing.is_likely_ad()
+                    && elapsed <= 2 * LOW_POWER_POLL_INTERVAL
+                {
+                    stats::get().record_listening(elapsed);
+                }
+            }
+            if previous != now_playing {
+                match &now_playing {
+                    Some(now_playing) if now_playing.is_likely_ad() => {
+                        debug!("Spotify is playing what looks like an ad: {now_playing}");
+                        ad_slip::detected(AdSource::MediaSession, Some(&now_playing.to_string()));
+                        if let Some(session) = session.as_ref().filter(|_| should_mute_ads()) {
+                            if skip_or_mute(session) {
+                                MUTED.store(true, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    Some(now_playing) if now_playing.playing => {
+                        debug!("Spotify is playing {now_playing}")
+                    }
+                    _ => {}
+                }
+                let ad_ended = !now_playing.as_ref().is_some_and(NowPlaying::is_likely_ad);
+                if ad_ended && MUTED.swap(false, Ordering::Relaxed) {
+                    info!("Ad ended, unmuting Spotify");
+                    if let Err(e) = set_spotify_muted(false) {
+                        warn!("Failed to unmute Spotify: {e}");
+                    }
+                }
+            }
+
+            thread::sleep(if power::is_low_power() {
+                LOW_POWER_POLL_INTERVAL
+            } else {
+                POLL_INTERVAL
+            });
+        }
+    });
+}
+
+/// Whether ads that got past the blocker are skipped or muted, which is only done while hooked so
+/// that ads are never muted for a user who paused blocking.
+fn should_mute_ads() -> bool {
+    settings::get().mute_ads && status::get().hook == HookStatus::Hooked
+}
+
+/// Skips the ad, or mutes Spotify if it cannot be skipped. Returns whether Spotify was muted.
+fn skip_or_mute(session: &Session) -> bool {
+    match session.TrySkipNextAsync().and_then(|op| op.get()) {
+        Ok(true) => {
+            info!("Skipped ad");
+            return false;
+        }
+        Ok(false) => debug!("Spotify refused to skip the ad"),
+        Err(e) => debug!("Failed to skip ad: {e}"),
+    }
+    match set_spotify_muted(true) {
+        Ok(0) => {
+            debug!("Spotify has no audio session to mute");
+            false
+        }
+        Ok(_) => {
+            info!("Muted Spotify until the ad ends");
+            true
+        }
+        Err(e) => {
+            warn!("Failed to mute Spotify: {e}");
+            false
+        }
+    }
+}
+
+/// Mutes or unmutes the audio sessions of Spotify on all output devices and returns their number.
+fn set_spotify_muted(muted: bool) -> windows::core::Result<usize> {
+    let enumerator: IMMDeviceEnumerator =
+        unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)? };
+    let devices = unsafe { enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)? };
+    let mut count = 0;
+    for i in 0..unsafe { devices.GetCount()? } {
+        let device = unsafe { devices.Item(i)? };
+        let manager: IAudioSessionManager2 = unsafe { device.Activate(CLSCTX_ALL, None)? };
+        let sessions = unsafe { manager.GetSessionEnumerator()? };
+        for j in 0..unsafe { sessions.GetCount()? } {
+            let control = unsafe { sessions.GetSession(j)? }.cast::<IAudioSessionControl2>()?;
+            // Contains the path of the executable playing the audio.
+            let id = unsafe { control.GetSessionIdentifier()? };
+            let is_spotify =
+                unsafe { id.to_string() }.is_ok_and(|id| id.to_lowercase().contains("spotify.exe"));
+            unsafe { CoTaskMemFree(Some(id.0 as _)) };
+            if !is_spotify {
+                continue;
+            }
+            let volume = control.cast::<ISimpleAudioVolume>()?;
+            unsafe { volume.SetMute(BOOL::from(muted), ptr::null())? };
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn read_spotify_session(
+    manager: &SessionManager,
+) -> windows::core::Result<Option<(Session, NowPlaying)>> {
+    let sessions = manager.GetSessions()?;
+    for i in 0..sessions.Size()? {
+        let session = sessions.GetAt(i)?;
+        let app_id = session.SourceAppUserModelId()?.to_string();
+        if !app_id.to_lowercase().contains("spotify") {
+            continue;
+        }
+
+        let properties = session.TryGetMediaPropertiesAsync()?.get()?;
+        let playback = session.GetPlaybackInfo()?;
+        let timeline = session.GetTimelineProperties()?;
+        let duration = timeline.EndTime()?.Duration - timeline.StartTime()?.Duration;
+        let now_playing = NowPlaying {
+            title: properties.Title()?.to_string(),
+            artist: properties.Artist()?.to_string(),
+            playing: playback.PlaybackStatus()? == PlaybackStatus::Playing,
+            // In units of 100 nanoseconds, zero if Spotify does not report it.
+            duration: u64::try_from(duration)
+                .ok()
+                .filter(|&duration| duration > 0)
+                .map(|duration| Duration::from_nanos(duration.saturating_mul(100))),
+        };
+        return Ok(Some((session, now_playing)));
+    }
+    Ok(None)
+}
@@ -2,6 +2,7 @@
 
 use std::{io, marker::PhantomData, os::windows::raw::HANDLE, ptr};
 
+use burnt_sushi_core::security::OwnerOnly;
 use widestring::U16CString;
 use winapi::{
     shared::winerror::WAIT_TIMEOUT,
@@ -18,7 +19,10 @@ impl NamedMutex {
     pub fn new(name: &str) -> io::Result<Self> {
         let name = U16CString::from_str(format!("Global\\{}", &name)).unwrap();
 
-        let handle = unsafe { CreateMutexW(ptr::null_mut(), 0, name.as_ptr()) };
+        // Other users cannot open the mutex, e.g. to hold it and keep this instance from starting.
+        let security = OwnerOnly::new()?;
+        let mut attributes = security.attributes();
+        let handle = unsafe { CreateMutexW(&mut attributes, 0, name.as_ptr()) };
 
         if handle.is_null() {
             Err(io::Error::last_os_error())
@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, error, info, warn};
+use winrt_toast::{Action, Text, Toast, ToastManager};
+
+use crate::{
+    blocker, crash_report, filter_providers,
+    i18n::{tr, Msg},
+    quiet_hours,
+    settings::{self, CrashReports, Settings},
+    utils, APP_NAME,
+};
+
+const POWERSHELL_APP_ID: &str =
+    "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+
+/// How long ad blocking is paused by [`NotificationAction::Pause`].
+const PAUSE_DURATION: Duration = Duration::from_secs(30 * 60);
+
+/// Buttons that can be added to a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationAction {
+    /// Pauses ad blocking for [`PAUSE_DURATION`].
+    Pause,
+    /// Opens the settings file.
+    OpenConfig,
+    /// Injects the blocker again.
+    RetryInjection,
+    /// Uploads the pending crash reports once.
+    SendCrashReport,
+    /// Uploads the pending and all future crash reports.
+    AlwaysSendCrashReports,
+    /// Shows what would be sent in a crash report.
+    ViewCrashReport,
+    /// Reloads the filters from all providers.
+    UpdateFilters,
+}
+
+impl NotificationAction {
+    const ALL: [NotificationAction; 7] = [
+        NotificationAction::Pause,
+        NotificationAction::OpenConfig,
+        NotificationAction::RetryInjection,
+        NotificationAction::SendCrashReport,
+        NotificationAction::AlwaysSendCrashReports,
+        NotificationAction::ViewCrashReport,
+        NotificationAction::UpdateFilters,
+    ];
+
+    fn label(self) -> &'static str {
+        tr(match self {
+            NotificationAction::Pause => Msg::ActionPause,
+            NotificationAction::OpenConfig => Msg::ActionOpenConfig,
+            NotificationAction::RetryInjection => Msg::ActionRetryInjection,
+            NotificationAction::SendCrashReport => Msg::ActionSendCrashReport,
+            NotificationAction::AlwaysSendCrashReports => Msg::ActionAlwaysSendCrashReports,
+            NotificationAction::ViewCrashReport => Msg::ActionViewCrashReport,
+            NotificationAction::UpdateFilters => Msg::ActionUpdateFilters,
+        })
+    }
+
+    fn argument(self) -> &'static str {
+        match self {
+            NotificationAction::Pause => "pause",
+            NotificationAction::OpenConfig => "open-config",
+            NotificationAction::RetryInjection => "retry-injection",
+            NotificationAction::SendCrashReport => "send-crash-report",
+            NotificationAction::AlwaysSendCrashReports => "always-send-crash-reports",
+            NotificationAction::ViewCrashReport => "view-crash-report",
+            NotificationAction::UpdateFilters => "update-filters",
+        }
+    }
+
+    fn from_argument(argument: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.argument() == argument)
+    }
+
+    fn run(self) {
+        info!("Running notification action '{}'", self.argument());
+        match self {
+            NotificationAction::Pause => blocker::pause(PAUSE_DURATION),
+            NotificationAction::OpenConfig => {
+                if let Err(e) = open_config() {
+                    error!("Failed to open config: {e:#}");
+                }
+            }
+            NotificationAction::RetryInjection => blocker::request_rehook(),
+            NotificationAction::SendCrashReport => crash_report::request_upload(),
+            NotificationAction::AlwaysSendCrashReports => {
+                let mut settings = settings::get();
+                settings.crash_reports = CrashReports::Send;
+                if let Err(e) = settings.save() {
+                    error!("Failed to save settings: {e:#}");
+                }
+                drop(settings);
+                crash_report::request_upload();
+            }
+            NotificationAction::ViewCrashReport => {
+                if let Err(e) = crash_report::open_preview() {
+                    error!("Failed to show crash report: {e:#}");
+                }
+            }
+            NotificationAction::UpdateFilters => filter_providers::request_refresh(),
+        }
+    }
+}
+
+/// Shows a toast notification with general information.
+pub fn info(title: &str, message: &str) {
+    show(title, message, &[]);
+}
+
+/// Shows a toast notification informing the user about a problem.
+pub fn error(title: &str, message: &str) {
+    show(title, message, &[]);
+}
+
+/// Shows a toast notification informing the user about a problem, with buttons to react to it.
+pub fn error_with_actions(title: &str, message: &str, actions: &[NotificationAction]) {
+    show(title, message, actions);
+}
+
+fn show(title: &str, message: &str, actions: &[NotificationAction]) {
+    if let Some(reason) = quiet_hours::active() {
+        info!("Notification suppressed ({reason:?}): {title}: {message}");
+        return;
+    }
+    debug!("Showing notification '{title}'");
+
+    let manager = ToastManager::new(POWERSHELL_APP_ID);
+    let mut toast = Toast::new();
+    toast
+        .text1(APP_NAME)
+        .text2(Text::new(title))
+        .text3(Text::new(message));
+    for action in actions {
+        toast.action(Action::new(
+            action.label(),
+            action.argument(),
+            action.argument(),
+        ));
+    }
+
+    let result = if actions.is_empty() {
+        manager.show(&toast)
+    } else {
+        manager.show_with_callbacks(
+            &toast,
+            Some(Box::new(move |res| match res {
+                Ok(arg) => match NotificationAction::from_argument(&arg) {
+                    Some(action) => action.run(),
+                    None => debug!("Notification activated (arg={arg})"),
+                },
+                Err(err) => debug!("Notification activation failed (err={err})"),
+            })),
+            None,
+            Some(Box::new(move |err| {
+                error!("Notification failed: {err}");
+            })),
+        )
+    };
+
+    if let Err(e) = result {
+        error!("Failed to show notification: {e}");
+    }
+}
+
+/// Opens the settings file in the default editor, creating it if necessary.
+fn open_config() -> anyhow::Result<()> {
+    let path = Settings::path().context("Failed to locate app data directory.")?;
+    if !path.exists() {
+        Settings::default().save()?;
+    }
+
+    if let Err(e) = utils::shell_open(&path) {
+        // .toml files might not have an associated program.
+        warn!("Failed to open settings file, opening its directory instead: {e:#}");
+        utils::shell_open(path.parent().unwrap_or(&path))?;
+    }
+    Ok(())
+}
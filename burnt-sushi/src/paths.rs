@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use burnt_sushi_core::UPDATED_BLOCKER_FILE_NAME;
+
+use crate::{environment::Environment, APP_AUTHOR, APP_NAME, APP_NAME_WITH_VERSION};
+
+/// Directory for persistent app data (`%APPDATA%\OpenByte\BurntSushi`).
+pub fn data_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    let mut dir = environment.app_data_dir()?;
+    dir.push("OpenByte");
+    dir.push("BurntSushi");
+    Some(dir)
+}
+
+/// Shared parent of the app's cache directories (`%LOCALAPPDATA%\OpenByteDev`).
+pub fn blocker_cache_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    environment
+        .local_app_data_dir()
+        .map(|dir| dir.join(APP_AUTHOR))
+}
+
+/// Cache directory used by versions before it was moved to `%LOCALAPPDATA%`, next to the temp
+/// directory.
+pub fn legacy_blocker_cache_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    environment
+        .temp_dir()
+        .parent()
+        .map(|dir| dir.join(APP_AUTHOR))
+}
+
+/// Extracted blockers in directories named by the SHA-256 hash of their contents
+/// (`<hash>\BurntSushiBlocker_x64.dll`), shared by all app versions.
+pub fn blocker_store_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    blocker_cache_dir(environment).map(|dir| dir.join(APP_NAME).join("blockers"))
+}
+
+/// Blocker downloaded by a blocker-only update for the running app version.
+pub fn updated_blocker(environment: &dyn Environment) -> Option<PathBuf> {
+    blocker_cache_dir(environment).map(updated_blocker_in)
+}
+
+/// Blocker downloaded by a blocker-only update, in the given cache directory.
+pub fn updated_blocker_in(cache_dir: PathBuf) -> PathBuf {
+    cache_dir
+        .join(APP_NAME_WITH_VERSION)
+        .join(UPDATED_BLOCKER_FILE_NAME)
+}
+
+pub fn log_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("BurntSushi.log"))
+}
+
+pub fn stats_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("stats.toml"))
+}
+
+/// Rules added by the user from the recent activity.
+pub fn user_rules_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("user-rules.toml"))
+}
+
+/// Telemetry counters that were not sent yet, kept next to the stats.
+pub fn telemetry_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("telemetry.toml"))
+}
+
+/// Token for the Spotify Web API, kept out of the settings as it is a secret.
+pub fn web_api_token_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("spotify-token.toml"))
+}
+
+/// Directory containing user scripts (`*.rhai`).
+pub fn scripts_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("scripts"))
+}
+
+/// Cached downloads of remote filter lists.
+pub fn filter_cache_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("filter-cache"))
+}
+
+/// Append-only history of the effective filter rules.
+pub fn filter_history_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("filter-history.toml"))
+}
+
+/// Rules pinned by `history revert`, used instead of the filter sources.
+pub fn filter_pin_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("filter-pin.toml"))
+}
+
+/// Spotify's autostart entry while it is taken over by the app.
+pub fn spotify_autostart_file(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("spotify-autostart.txt"))
+}
+
+pub fn crash_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    data_dir(environment).map(|dir| dir.join("crashes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Fixture;
+
+    #[test]
+    fn data_files_are_below_app_data() {
+        let fixture = Fixture::new("data-files");
+        let dir = fixture
+            .root
+            .join("Roaming")
+            .join("OpenByte")
+            .join("BurntSushi");
+        assert_eq!(data_dir(&fixture), Some(dir.clone()));
+        assert_eq!(stats_file(&fixture), Some(dir.join("stats.toml")));
+        assert_eq!(scripts_dir(&fixture), Some(dir.join("scripts")));
+    }
+
+    #[test]
+    fn blockers_are_below_local_app_data() {
+        let fixture = Fixture::new("blocker-dirs");
+        let cache_dir = fixture.root.join("Local").join(APP_AUTHOR);
+        assert_eq!(blocker_cache_dir(&fixture), Some(cache_dir.clone()));
+        assert_eq!(
+            blocker_store_dir(&fixture),
+            Some(cache_dir.join(APP_NAME).join("blockers"))
+        );
+        assert_eq!(
+            updated_blocker(&fixture),
+            Some(
+                cache_dir
+                    .join(APP_NAME_WITH_VERSION)
+                    .join(UPDATED_BLOCKER_FILE_NAME)
+            )
+        );
+        // The fixture's temp directory is the default one below `%LOCALAPPDATA%`.
+        assert_eq!(legacy_blocker_cache_dir(&fixture), Some(cache_dir));
+    }
+}
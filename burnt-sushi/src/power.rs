@@ -0,0 +1,203 @@
+use std::{
+    ptr,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use tokio::sync::{watch, Notify};
+use winapi::{
+    shared::{guiddef::IsEqualGUID, minwindef::DWORD, windef::HWND},
+    um::{
+        winnt::GUID_POWER_SAVING_STATUS,
+        winuser::{
+            RegisterPowerSettingNotification, UnregisterPowerSettingNotification,
+            DEVICE_NOTIFY_WINDOW_HANDLE, HPOWERNOTIFY, PBT_APMRESUMEAUTOMATIC,
+            PBT_POWERSETTINGCHANGE, POWERBROADCAST_SETTING, WM_POWERBROADCAST,
+            WM_WTSSESSION_CHANGE, WTS_CONSOLE_CONNECT, WTS_CONSOLE_DISCONNECT, WTS_REMOTE_CONNECT,
+            WTS_REMOTE_DISCONNECT, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+        },
+    },
+};
+use windows::Win32::{
+    Foundation,
+    System::RemoteDesktop::{
+        WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+    },
+};
+
+use crate::settings;
+
+/// How long the session has to be locked before non-critical work is deferred.
+const IDLE_LOCK_TIME: Duration = Duration::from_secs(15 * 60);
+
+static STATE: Mutex<PowerState> = Mutex::new(PowerState {
+    battery_saver: false,
+    locked_since: None,
+    disconnected: false,
+});
+/// Marked as changed for every change of the state, so that a change made while a receiver is
+/// busy is seen once it waits again.
+static CHANGED: LazyLock<watch::Sender<()>> = LazyLock::new(|| watch::Sender::new(()));
+/// Stores a permit if nobody waits, so that a resume is handled once the blocker waits again.
+static RESUMED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PowerState {
+    battery_saver: bool,
+    locked_since: Option<Instant>,
+    /// Whether the session has no terminal attached, e.g. after closing a remote desktop
+    /// connection without logging off.
+    disconnected: bool,
+}
+
+/// Whether background work should be reduced because battery saver is on or the session is
+/// locked or disconnected.
+pub fn is_low_power() -> bool {
+    let state = *STATE.lock().unwrap();
+    (state.battery_saver || state.locked_since.is_some() || state.disconnected)
+        && settings::get().power_saving
+}
+
+/// Whether nobody uses the session because it is disconnected or has been locked for a while, so
+/// that non-critical work like filter refreshes and update checks can wait.
+pub fn is_session_idle() -> bool {
+    let state = *STATE.lock().unwrap();
+    let idle = state.disconnected
+        || state
+            .locked_since
+            .is_some_and(|since| since.elapsed() >= IDLE_LOCK_TIME);
+    idle && settings::get().power_saving
+}
+
+/// Waits until the session is used again if it is idle, so that the deferred work runs on unlock
+/// or reconnect.
+pub async fn wait_until_active(work: &str) {
+    if !is_session_idle() {
+        return;
+    }
+    info!("Deferring {work} until the session is used again");
+    // Subscribed before checking so that no change is missed in between.
+    let mut changes = CHANGED.subscribe();
+    while is_session_idle() {
+        let _ = changes.changed().await;
+    }
+    info!("Resuming deferred {work}");
+}
+
+/// Receives a change whenever the battery saver, lock or connection state changed.
+pub fn subscribe() -> watch::Receiver<()> {
+    CHANGED.subscribe()
+}
+
+/// Waits until the system resumed from sleep or hibernation.
+pub async fn resumed() {
+    RESUMED.notified().await
+}
+
+fn update(f: impl FnOnce(&mut PowerState)) {
+    let mut state = STATE.lock().unwrap();
+    let previous = *state;
+    f(&mut state);
+    if *state != previous {
+        debug!("Power state changed to {:?}", *state);
+        drop(state);
+        CHANGED.send_replace(());
+    }
+}
+
+/// Subscribes the window to battery saver and session lock and connection notifications until
+/// dropped.
+pub struct PowerNotifications {
+    hwnd: HWND,
+    power_setting: HPOWERNOTIFY,
+    session: bool,
+}
+
+impl PowerNotifications {
+    pub fn register(hwnd: HWND) -> Self {
+        // The current value is sent right after registering.
+        let power_setting = unsafe {
+            RegisterPowerSettingNotification(
+                hwnd as _,
+                &GUID_POWER_SAVING_STATUS,
+                DEVICE_NOTIFY_WINDOW_HANDLE,
+            )
+        };
+        if power_setting.is_null() {
+            warn!("Failed to register for battery saver notifications");
+        }
+
+        let session = match unsafe {
+            WTSRegisterSessionNotification(Foundation::HWND(hwnd as _), NOTIFY_FOR_THIS_SESSION)
+        } {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to register for session lock notifications: {e}");
+                false
+            }
+        };
+
+        Self {
+            hwnd,
+            power_setting,
+            session,
+        }
+    }
+}
+
+impl Drop for PowerNotifications {
+    fn drop(&mut self) {
+        if !self.power_setting.is_null() {
+            unsafe { UnregisterPowerSettingNotification(self.power_setting) };
+        }
+        if self.session {
+            let _ = unsafe { WTSUnRegisterSessionNotification(Foundation::HWND(self.hwnd as _)) };
+        }
+    }
+}
+
+/// Handles the messages subscribed to by [`PowerNotifications`] and resume broadcasts.
+pub fn handle_message(msg: u32, w: usize, l: isize) {
+    match msg {
+        // Sent for every resume, unlike PBT_APMRESUMESUSPEND which requires user input.
+        WM_POWERBROADCAST if w == PBT_APMRESUMEAUTOMATIC => {
+            info!("System resumed from sleep");
+            RESUMED.notify_one();
+        }
+        WM_POWERBROADCAST if w == PBT_POWERSETTINGCHANGE => {
+            let setting = unsafe { &*(l as *const POWERBROADCAST_SETTING) };
+            if !IsEqualGUID(&setting.PowerSetting, &GUID_POWER_SAVING_STATUS)
+                || setting.DataLength as usize != size_of::<DWORD>()
+            {
+                return;
+            }
+            let enabled =
+                unsafe { ptr::read_unaligned(setting.Data.as_ptr() as *const DWORD) } != 0;
+            info!("Battery saver is {}", if enabled { "on" } else { "off" });
+            update(|state| state.battery_saver = enabled);
+        }
+        WM_WTSSESSION_CHANGE => match w {
+            WTS_SESSION_LOCK => {
+                debug!("Session was locked");
+                update(|state| {
+                    state.locked_since.get_or_insert_with(Instant::now);
+                });
+            }
+            WTS_SESSION_UNLOCK => {
+                debug!("Session was unlocked");
+                update(|state| state.locked_since = None);
+            }
+            WTS_CONSOLE_DISCONNECT | WTS_REMOTE_DISCONNECT => {
+                info!("Session was disconnected");
+                update(|state| state.disconnected = true);
+            }
+            WTS_CONSOLE_CONNECT | WTS_REMOTE_CONNECT => {
+                info!("Session was connected");
+                update(|state| state.disconnected = false);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
@@ -0,0 +1,59 @@
+//! Extracting the blocker and loading the filters before the first hook. Both can be slow, e.g. if
+//! an antivirus scans the extracted blocker or a remote filter list has to be downloaded, so startup
+//! only waits up to [`STARTUP_BUDGET`] and Spotify is watched but not hooked until they are done.
+//! Hooking uses the filters loaded last, changes requested while Spotify was not hooked are sent
+//! to the blocker right after hooking.
+
+use std::{io, path::PathBuf, sync::LazyLock, time::Duration};
+
+use log::{debug, info, warn};
+use tokio::{sync::watch, time::Instant};
+
+use crate::{
+    args::ARGS, blocker, environment::System, filter_history::ChangeSource,
+    resolver::resolve_blocker, shutdown,
+};
+
+/// How long startup waits for the preparation before continuing without it.
+const STARTUP_BUDGET: Duration = Duration::from_secs(3);
+
+static READY: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::Sender::new(false));
+
+/// Starts the preparation in the background and waits for it up to [`STARTUP_BUDGET`].
+/// Returns the result of the blocker extraction if the preparation finished in time.
+pub async fn start() -> Option<io::Result<PathBuf>> {
+    let (blocker_tx, blocker_rx) = tokio::sync::oneshot::channel();
+    shutdown::spawn("preparation", async move {
+        let start = Instant::now();
+        let _ = blocker_tx.send(resolve_blocker(&System, ARGS.blocker.as_deref()).await);
+        // Loaded again and reported when hooking if this fails.
+        if let Err(e) = blocker::load_filter_config(ChangeSource::Startup).await {
+            warn!("Failed to load filter config: {e}");
+        }
+        debug!("Prepared blocker in {:?}", start.elapsed());
+        READY.send_replace(true);
+    });
+
+    let prepared = async {
+        let blocker = blocker_rx.await.ok()?;
+        ready().await;
+        Some(blocker)
+    };
+    match tokio::time::timeout(STARTUP_BUDGET, prepared).await {
+        Ok(blocker) => blocker,
+        Err(_) => {
+            info!("Preparing the blocker takes longer, watching Spotify until it is ready");
+            None
+        }
+    }
+}
+
+/// Whether Spotify can be hooked.
+pub fn is_ready() -> bool {
+    *READY.borrow()
+}
+
+/// Waits until Spotify can be hooked.
+pub async fn ready() {
+    let _ = READY.subscribe().wait_for(|ready| *ready).await;
+}
@@ -0,0 +1,61 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::settings::UrlPrivacy;
+
+/// `url-privacy` from the settings, cached so that the logger never waits for the settings lock,
+/// which may be held by code that logs. [`NOT_LOADED`] until the settings are loaded.
+static URL_PRIVACY: AtomicU8 = AtomicU8::new(NOT_LOADED);
+const NOT_LOADED: u8 = u8::MAX;
+
+/// Called when the settings are loaded.
+pub fn set_url_privacy(privacy: UrlPrivacy) {
+    URL_PRIVACY.store(privacy as u8, Ordering::Relaxed);
+}
+
+/// The configured url privacy, only the host is kept until the settings are loaded.
+pub fn url_privacy() -> UrlPrivacy {
+    match URL_PRIVACY.load(Ordering::Relaxed) {
+        value if value == UrlPrivacy::Full as u8 => UrlPrivacy::Full,
+        value if value == UrlPrivacy::Hashed as u8 => UrlPrivacy::Hashed,
+        _ => UrlPrivacy::Host,
+    }
+}
+
+/// Hides titles of what Spotify plays unless urls are logged in full, as they reveal as much about
+/// the user.
+pub fn redact_title(title: &str) -> &str {
+    match url_privacy() {
+        UrlPrivacy::Full => title,
+        UrlPrivacy::Host | UrlPrivacy::Hashed => "<redacted>",
+    }
+}
+
+/// Reduces a url to the amount of detail allowed by the given privacy setting.
+pub fn redact_url(url: &str, privacy: UrlPrivacy) -> String {
+    if privacy == UrlPrivacy::Full {
+        return url.to_string();
+    }
+
+    let host_start = url.find("://").map_or(0, |i| i + 3);
+    let host_end = url[host_start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |i| host_start + i);
+    let (origin, rest) = url.split_at(host_end);
+
+    if rest.is_empty() {
+        return origin.to_string();
+    }
+
+    match privacy {
+        UrlPrivacy::Full => unreachable!(),
+        UrlPrivacy::Host => origin.to_string(),
+        UrlPrivacy::Hashed => {
+            let mut hasher = DefaultHasher::new();
+            rest.hash(&mut hasher);
+            format!("{origin}/#{:016x}", hasher.finish())
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Suppression of notifications during the configured quiet hours and while Windows does not
+//! accept notifications, e.g. during presentations, full screen games or with Focus Assist on.
+
+use chrono::{Local, NaiveTime};
+use log::debug;
+use windows::Win32::UI::Shell::{
+    SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE, QUNS_QUIET_TIME,
+    QUNS_RUNNING_D3D_FULL_SCREEN,
+};
+
+use crate::settings::{self, QuietHours};
+
+/// Why notifications are currently not shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    QuietHours,
+    FocusAssist,
+}
+
+/// Returns why notifications should not be shown right now, if they should not.
+pub fn active() -> Option<Reason> {
+    let (quiet_hours, respect_focus_assist) = {
+        let settings = settings::get();
+        (settings.quiet_hours, settings.respect_focus_assist)
+    };
+
+    if quiet_hours.is_some_and(|quiet_hours| quiet_hours.contains(Local::now().time())) {
+        return Some(Reason::QuietHours);
+    }
+    if respect_focus_assist && is_user_busy() {
+        return Some(Reason::FocusAssist);
+    }
+    None
+}
+
+impl QuietHours {
+    /// Whether the time lies in the quiet hours, which may span midnight.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// Whether Windows holds back notifications of its own. Focus Assist is not exposed directly, but
+/// its automatic rules for presentations and full screen apps are reported here.
+fn is_user_busy() -> bool {
+    match unsafe { SHQueryUserNotificationState() } {
+        Ok(state) => matches!(
+            state,
+            QUNS_BUSY | QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_PRESENTATION_MODE | QUNS_QUIET_TIME
+        ),
+        Err(e) => {
+            debug!("Failed to query notification state: {e}");
+            false
+        }
+    }
+}
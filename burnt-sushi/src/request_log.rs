@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{mpsc, Arc, LazyLock, Mutex},
+    thread,
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use burnt_sushi_core::rpc::{
+    recording::{self, ReplaySummary},
+    RequestObserver,
+};
+use log::debug;
+use shared::rpc::blocker_service::FilterHook;
+
+use crate::{
+    efficacy,
+    events::{self, AppEvent},
+    logger::global::URL_LOG_TARGET,
+    media, scripting,
+    stats::{self, Stats},
+    telemetry, user_rules,
+};
+
+/// Requests waiting for the request thread, which handles them one after the other.
+static REQUESTS: LazyLock<mpsc::Sender<Request>> = LazyLock::new(|| {
+    let (tx, rx) = mpsc::channel::<Request>();
+    thread::spawn(move || {
+        for request in rx {
+            request.handle();
+        }
+    });
+    tx
+});
+
+/// Logs the requests reported by the blocker, counts blocked ads and passes both to scripts.
+/// The requests are handled on a thread of their own, as the RPC tasks that report them share
+/// their thread with the hook loop, which scripts and stats I/O must not hold up.
+pub struct RequestLog;
+
+impl RequestObserver for RequestLog {
+    fn on_request(
+        &self,
+        time: SystemTime,
+        hook: FilterHook,
+        url: &str,
+        blocked: bool,
+        rule: Option<&str>,
+    ) {
+        let _ = REQUESTS.send(Request {
+            time,
+            hook,
+            url: url.to_string(),
+            blocked,
+            rule: rule.map(str::to_string),
+        });
+    }
+}
+
+/// Request reported by the blocker.
+struct Request {
+    time: SystemTime,
+    hook: FilterHook,
+    url: String,
+    blocked: bool,
+    rule: Option<String>,
+}
+
+impl Request {
+    fn handle(&self) {
+        let (time, hook, blocked) = (self.time, self.hook, self.blocked);
+        let url = self.url.as_str();
+        let rule = self.rule.as_deref();
+
+        efficacy::record(blocked);
+        let block_sign = if blocked {
+            stats::record_blocked(rule, time);
+            telemetry::record_blocked(rule);
+            events::publish(AppEvent::Blocked {
+                rule: rule.map(str::to_string),
+                time,
+            });
+            scripting::on_ad_blocked(hook, url, rule);
+            '-'
+        } else {
+            user_rules::record_allowed(url);
+            '+'
+        };
+
+        let playing = media::now_playing()
+            .filter(|now_playing| now_playing.playing)
+            .map(|now_playing| format!(" (playing '{now_playing}')"))
+            .unwrap_or_default();
+
+        debug!(target: URL_LOG_TARGET, url = url; "[{}] ({}) {}{}", block_sign, hook, url, playing);
+
+        scripting::on_request(hook, url, blocked);
+    }
+}
+
+/// Counts replayed requests in stats of their own, as recordings may come from other users and
+/// must not change the stats, telemetry, scripts or menus of the app.
+#[derive(Default)]
+struct ReplayStats(Mutex<Stats>);
+
+impl RequestObserver for ReplayStats {
+    fn on_request(
+        &self,
+        _time: SystemTime,
+        hook: FilterHook,
+        url: &str,
+        blocked: bool,
+        rule: Option<&str>,
+    ) {
+        let block_sign = if blocked {
+            self.0.lock().unwrap().record_blocked(rule);
+            '-'
+        } else {
+            '+'
+        };
+        debug!(target: URL_LOG_TARGET, url = url; "[{}] ({}) {}", block_sign, hook, url);
+    }
+}
+
+/// Plays back a recording made with `--record-rpc` and returns the stats of the replayed
+/// requests.
+pub fn replay(path: &Path) -> anyhow::Result<(ReplaySummary, Stats)> {
+    let recording =
+        fs::read(path).with_context(|| format!("Failed to read recording '{}'", path.display()))?;
+    let stats = Arc::new(ReplayStats::default());
+    let summary = recording::replay(&recording, stats.clone()).context("Recording is corrupted")?;
+    let stats = stats.0.lock().unwrap().clone();
+    Ok((summary, stats))
+}
@@ -1,30 +1,52 @@
 use std::{
-    env, io,
+    future::Future,
+    io,
     path::{Path, PathBuf},
+    process,
+    sync::LazyLock,
+    time::Duration,
 };
 
+use burnt_sushi_core::{filters::FilterConfig, signature};
 use log::{debug, error, warn};
+use sha2::{Digest, Sha256};
+use windows::Win32::Foundation::{
+    ERROR_ACCESS_DENIED, ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION,
+};
 
 use crate::{
-    blocker::FilterConfig, APP_AUTHOR, APP_NAME_WITH_VERSION, DEFAULT_BLOCKER_FILE_NAME,
+    args::ARGS,
+    environment::Environment,
+    filter_providers::FilterFormat,
+    i18n::{Lang, LANG},
+    paths, settings, utils, APP_NAME, APP_NAME_WITH_VERSION, DEFAULT_BLOCKER_FILE_NAME,
     DEFAULT_FILTER_FILE_NAME,
 };
 
-pub async fn resolve_blocker(provided_path: Option<&Path>) -> io::Result<PathBuf> {
-    async fn try_load_blocker(
-        path: &Path,
-        check_len: bool,
-        write_if_absent: bool,
-    ) -> io::Result<()> {
-        let payload_bytes = include_bytes!(concat!(env!("OUT_DIR"), "\\BurntSushiBlocker_x64.dll"));
+/// Blocker embedded into the executable.
+static PAYLOAD_BYTES: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "\\BurntSushiBlocker_x64.dll"));
+/// Hex-encoded SHA-256 hash of [`PAYLOAD_BYTES`], which names the directory of the extracted file.
+static PAYLOAD_HASH: LazyLock<String> = LazyLock::new(|| sha256_hex(PAYLOAD_BYTES));
+/// Attempts at writing the blocker while the file is locked, e.g. by an antivirus scanning it.
+const WRITE_ATTEMPTS: u32 = 5;
+/// Delay before the second attempt, doubled for each further one.
+const WRITE_RETRY_DELAY: Duration = Duration::from_millis(200);
+/// Publisher of the official releases, trusted for blockers if this executable is not signed.
+const PUBLISHER: &str = "OpenByte";
 
+pub async fn resolve_blocker(
+    environment: &dyn Environment,
+    provided_path: Option<&Path>,
+) -> io::Result<PathBuf> {
+    async fn try_load_blocker(path: &Path, verify: bool, write_if_absent: bool) -> io::Result<()> {
         debug!("Looking for blocker at '{}'", path.display());
         if let Ok(metadata) = tokio::fs::metadata(path).await {
             if metadata.is_file() {
                 debug!("Found blocker at '{}'", path.display());
-                if check_len && metadata.len() != payload_bytes.len() as u64 {
+                if verify && !is_embedded_blocker(path).await {
                     debug!(
-                        "Blocker at '{}' was ignored due to incorrect size.",
+                        "Blocker at '{}' was ignored due to different contents.",
                         path.display()
                     );
                 } else {
@@ -35,8 +57,10 @@ pub async fn resolve_blocker(provided_path: Option<&Path>) -> io::Result<PathBuf
         if write_if_absent {
             debug!("Writing blocker to '{}'", path.display());
             tokio::fs::create_dir_all(path.parent().unwrap()).await?;
-            tokio::fs::write(&path, payload_bytes).await?;
-            Ok(())
+            // Written next to the target first, so a blocker is never loaded half-written.
+            let partial_path = path.with_extension("dll.partial");
+            retry_locked(|| tokio::fs::write(&partial_path, PAYLOAD_BYTES)).await?;
+            retry_locked(|| tokio::fs::rename(&partial_path, path)).await
         } else {
             Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -54,24 +78,53 @@ pub async fn resolve_blocker(provided_path: Option<&Path>) -> io::Result<PathBuf
         }
     }
 
+    migrate_legacy_cache(environment).await;
+
+    if provided_path.is_none() {
+        debug!("Looking for updated blocker...");
+        if let Some(updated_path) = paths::updated_blocker(environment) {
+            if try_load_blocker(&updated_path, false, false).await.is_ok() {
+                return Ok(updated_path);
+            }
+        }
+    }
+
     debug!("Looking for blocker next to executable...");
-    if let Some(sibling_path) = env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.join(DEFAULT_BLOCKER_FILE_NAME)))
+    if let Some(sibling_path) = environment
+        .exe_dir()
+        .map(|dir| dir.join(DEFAULT_BLOCKER_FILE_NAME))
     {
         if try_load_blocker(&sibling_path, false, false).await.is_ok() {
             return Ok(sibling_path);
         }
     }
 
-    debug!("Looking for existing blocker in temporary directory...");
-    if let Some(temp_path) = env::temp_dir().parent().map(|p| {
-        p.join(APP_AUTHOR)
-            .join(APP_NAME_WITH_VERSION)
-            .join(DEFAULT_BLOCKER_FILE_NAME)
-    }) {
-        if try_load_blocker(&temp_path, true, true).await.is_ok() {
-            return Ok(temp_path);
+    debug!("Looking for existing blocker in blocker store...");
+    if let Some(store_dir) = blocker_store_dir(environment).await {
+        // The file name is kept, as previously injected blockers are found by it.
+        let store_path = store_dir
+            .join(&*PAYLOAD_HASH)
+            .join(DEFAULT_BLOCKER_FILE_NAME);
+        match try_load_blocker(&store_path, true, true).await {
+            Ok(()) => {
+                remove_unused_blockers(environment, &store_dir).await;
+                return Ok(store_path);
+            }
+            // A damaged blocker with the same name is still loaded into a running Spotify.
+            Err(e) if is_locked(&e) => {
+                let fallback_path = store_dir
+                    .join(format!("{}.{}", *PAYLOAD_HASH, process::id()))
+                    .join(DEFAULT_BLOCKER_FILE_NAME);
+                warn!(
+                    "Blocker at '{}' is locked, extracting it to '{}' instead",
+                    store_path.display(),
+                    fallback_path.display()
+                );
+                if try_load_blocker(&fallback_path, false, true).await.is_ok() {
+                    return Ok(fallback_path);
+                }
+            }
+            Err(_) => {}
         }
     }
 
@@ -82,23 +135,266 @@ pub async fn resolve_blocker(provided_path: Option<&Path>) -> io::Result<PathBuf
     ))
 }
 
-pub async fn resolve_filter_config(provided_path: Option<&Path>) -> io::Result<FilterConfig> {
+/// Runs a file operation again while the file is locked by another process.
+async fn retry_locked<F: Future<Output = io::Result<()>>>(
+    mut operation: impl FnMut() -> F,
+) -> io::Result<()> {
+    let mut delay = WRITE_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if is_locked(&e) && attempt < WRITE_ATTEMPTS => {
+                debug!("Blocker file is locked, retrying in {delay:?}: {e}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether an operation failed because another process holds the file open, e.g. an antivirus
+/// scanning it or a Spotify that loaded it.
+fn is_locked(error: &io::Error) -> bool {
+    [
+        ERROR_SHARING_VIOLATION,
+        ERROR_LOCK_VIOLATION,
+        ERROR_ACCESS_DENIED,
+    ]
+    .iter()
+    .any(|code| error.raw_os_error() == Some(code.0 as i32))
+}
+
+/// Directory the blocker is extracted to, `blocker-dir` from the settings if it can be used.
+async fn blocker_store_dir(environment: &dyn Environment) -> Option<PathBuf> {
+    let configured = settings::get().blocker_dir.clone();
+    if let Some(dir) = configured {
+        match check_blocker_dir(&dir).await {
+            Ok(()) => return Some(dir),
+            Err(e) => warn!("Ignoring blocker-dir '{}': {e}", dir.display()),
+        }
+    }
+    paths::blocker_store_dir(environment)
+}
+
+/// Checks that a configured blocker directory is on a local volume and writable.
+async fn check_blocker_dir(dir: &Path) -> io::Result<()> {
+    if !dir.is_absolute() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Path is not absolute.",
+        ));
+    }
+    if !utils::is_local_volume(dir) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Path is not on a local volume.",
+        ));
+    }
+    tokio::fs::create_dir_all(dir).await?;
+    let probe = dir.join(".write-test");
+    tokio::fs::write(&probe, b"").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+/// Checks that a blocker passed on the command line is the embedded one or carries a valid
+/// signature by the publisher of this executable, or by [`PUBLISHER`] if it is not signed.
+pub async fn verify_provided_blocker(environment: &dyn Environment, path: &Path) -> io::Result<()> {
+    if is_embedded_blocker(path).await {
+        return Ok(());
+    }
+
+    let path = path.to_path_buf();
+    let exe = environment.current_exe();
+    tokio::task::spawn_blocking(move || {
+        signature::verify(&path)?;
+        let publisher = signature::signer_name(&path)?;
+        debug!("Blocker at '{}' is signed by '{publisher}'", path.display());
+
+        let trusted_publisher = exe
+            .and_then(|exe| signature::signer_name(&exe))
+            .unwrap_or_else(|_| PUBLISHER.to_string());
+        if publisher == trusted_publisher {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Blocker is signed by '{publisher}' instead of '{trusted_publisher}'."),
+            ))
+        }
+    })
+    .await?
+}
+
+/// Whether the file has the same contents as the embedded blocker.
+async fn is_embedded_blocker(path: &Path) -> bool {
+    match tokio::fs::read(path).await {
+        Ok(contents) => sha256_hex(&contents) == *PAYLOAD_HASH,
+        Err(_) => false,
+    }
+}
+
+/// Removes the blockers extracted by other app versions. Blockers still loaded into a running
+/// Spotify cannot be removed and are collected on a later start instead.
+async fn remove_unused_blockers(environment: &dyn Environment, store_dir: &Path) {
+    let Ok(mut entries) = tokio::fs::read_dir(store_dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_name() == PAYLOAD_HASH.as_str() {
+            continue;
+        }
+        let path = entry.path().join(DEFAULT_BLOCKER_FILE_NAME);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => debug!("Removed unused blocker '{}'", path.display()),
+            Err(e) => debug!("Unused blocker '{}' not removed: {e}", path.display()),
+        }
+        let _ = tokio::fs::remove_dir(entry.path()).await;
+    }
+
+    // Blockers extracted by versions before the store was introduced.
+    let Some(cache_dir) = paths::blocker_cache_dir(environment) else {
+        return;
+    };
+    let Ok(mut entries) = tokio::fs::read_dir(&cache_dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(APP_NAME) && name != APP_NAME && name != APP_NAME_WITH_VERSION {
+            let _ = tokio::fs::remove_file(entry.path().join(DEFAULT_BLOCKER_FILE_NAME)).await;
+            let _ = tokio::fs::remove_dir(entry.path()).await;
+        }
+    }
+}
+
+/// Moves the blocker downloaded by an update out of the cache directory next to the temp directory
+/// used by earlier versions and removes the version directories extracted there. Blockers still
+/// loaded into a running Spotify cannot be removed and are collected on a later start instead.
+async fn migrate_legacy_cache(environment: &dyn Environment) {
+    let (Some(legacy_dir), Some(cache_dir)) = (
+        paths::legacy_blocker_cache_dir(environment),
+        paths::blocker_cache_dir(environment),
+    ) else {
+        return;
+    };
+    // With the default temp directory both are `%LOCALAPPDATA%\OpenByteDev`.
+    if let (Ok(legacy_dir), Ok(cache_dir)) = (
+        tokio::fs::canonicalize(&legacy_dir).await,
+        tokio::fs::canonicalize(&cache_dir).await,
+    ) {
+        if legacy_dir == cache_dir {
+            return;
+        }
+    }
+    let Ok(mut entries) = tokio::fs::read_dir(&legacy_dir).await else {
+        return;
+    };
+
+    let legacy_update = paths::updated_blocker_in(legacy_dir.clone());
+    if let Some(update) = paths::updated_blocker(environment) {
+        let pending = tokio::fs::metadata(&legacy_update).await.is_ok()
+            && tokio::fs::metadata(&update).await.is_err();
+        if pending {
+            match move_file(&legacy_update, &update).await {
+                Ok(()) => debug!("Moved updated blocker to '{}'", update.display()),
+                Err(e) => debug!("Failed to move updated blocker: {e}"),
+            }
+        }
+    }
+
+    let version_prefix = format!("{APP_NAME} v");
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&version_prefix)
+        {
+            continue;
+        }
+        // Keep an update that could not be moved.
+        if legacy_update.starts_with(entry.path())
+            && tokio::fs::metadata(&legacy_update).await.is_ok()
+        {
+            continue;
+        }
+        match tokio::fs::remove_dir_all(entry.path()).await {
+            Ok(()) => debug!("Removed legacy cache '{}'", entry.path().display()),
+            Err(e) => debug!("Legacy cache '{}' not removed: {e}", entry.path().display()),
+        }
+    }
+    // Only remove the shared parent if no other app uses it.
+    let _ = tokio::fs::remove_dir(&legacy_dir).await;
+}
+
+/// Copies a file to a directory that may be on another volume, the source is removed afterwards.
+async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(dir) = to.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::copy(from, to).await?;
+    tokio::fs::remove_file(from).await
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Default filter config embedded into the executable, with its notes in the given language.
+pub fn default_filter_config(lang: Lang) -> &'static str {
+    match lang {
+        Lang::En => include_str!(concat!(env!("OUT_DIR"), "\\filter.toml")),
+        Lang::De => include_str!(concat!(env!("OUT_DIR"), "\\filter.de.toml")),
+        Lang::Fr => include_str!(concat!(env!("OUT_DIR"), "\\filter.fr.toml")),
+        Lang::Es => include_str!(concat!(env!("OUT_DIR"), "\\filter.es.toml")),
+    }
+}
+
+/// Path of the filter config passed on the command line or the one next to the executable.
+pub fn filter_config_path(environment: &dyn Environment) -> Option<PathBuf> {
+    ARGS.filters
+        .clone()
+        .or_else(|| sibling_filter_config_path(environment))
+}
+
+fn sibling_filter_config_path(environment: &dyn Environment) -> Option<PathBuf> {
+    environment
+        .exe_dir()
+        .map(|dir| dir.join(DEFAULT_FILTER_FILE_NAME))
+}
+
+pub async fn resolve_filter_config(
+    environment: &dyn Environment,
+    provided_path: Option<&Path>,
+) -> io::Result<FilterConfig> {
     async fn try_load_filter_config_from_path(
         path: Option<&Path>,
         write_if_absent: bool,
     ) -> io::Result<FilterConfig> {
-        let default_filter_bytes = include_str!(concat!(env!("OUT_DIR"), "\\filter.toml"));
+        let default_filter_bytes = default_filter_config(*LANG);
 
         if let Some(path) = path {
+            let format = FilterFormat::from_path(path).unwrap_or_default();
             debug!("Looking for filter config at '{}'", path.display());
             if let Ok(filters) = tokio::fs::read_to_string(path).await {
                 debug!("Found filter config at '{}'", path.display());
-                try_load_filter_config_from_str(&filters)
+                try_load_filter_config_from_str(&filters, format)
             } else if write_if_absent {
                 debug!("Writing default filter config to '{}'", path.display());
+                let filter_config =
+                    try_load_filter_config_from_str(default_filter_bytes, FilterFormat::Toml)?;
+                let contents = match format {
+                    FilterFormat::Toml => default_filter_bytes.to_string(),
+                    format => format.serialize(&filter_config)?,
+                };
                 tokio::fs::create_dir_all(path.parent().unwrap()).await?;
-                tokio::fs::write(&path, default_filter_bytes).await?;
-                try_load_filter_config_from_str(default_filter_bytes)
+                tokio::fs::write(&path, contents).await?;
+                Ok(filter_config)
             } else {
                 Err(io::Error::new(
                     io::ErrorKind::NotFound,
@@ -107,12 +403,15 @@ pub async fn resolve_filter_config(provided_path: Option<&Path>) -> io::Result<F
             }
         } else {
             debug!("Loading default filter config...");
-            try_load_filter_config_from_str(default_filter_bytes)
+            try_load_filter_config_from_str(default_filter_bytes, FilterFormat::Toml)
         }
     }
 
-    fn try_load_filter_config_from_str(filter_config: &str) -> io::Result<FilterConfig> {
-        match toml::from_str(filter_config) {
+    fn try_load_filter_config_from_str(
+        filter_config: &str,
+        format: FilterFormat,
+    ) -> io::Result<FilterConfig> {
+        match format.parse(filter_config) {
             Ok(filter_config) => Ok(filter_config),
             Err(_) => {
                 warn!("Failed to parse filter config.");
@@ -132,10 +431,7 @@ pub async fn resolve_filter_config(provided_path: Option<&Path>) -> io::Result<F
     }
 
     debug!("Looking for filter config next to executable...");
-    if let Some(sibling_path) = env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.join(DEFAULT_FILTER_FILE_NAME)))
-    {
+    if let Some(sibling_path) = sibling_filter_config_path(environment) {
         if let Ok(filters) = try_load_filter_config_from_path(Some(&sibling_path), false).await {
             return Ok(filters);
         }
@@ -143,3 +439,51 @@ pub async fn resolve_filter_config(provided_path: Option<&Path>) -> io::Result<F
 
     try_load_filter_config_from_path(None, false).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{environment::Fixture, APP_AUTHOR};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn resolves_updated_blocker() {
+        let fixture = Fixture::new("updated-blocker");
+        let updated = paths::updated_blocker(&fixture).unwrap();
+        std::fs::create_dir_all(updated.parent().unwrap()).unwrap();
+        std::fs::write(&updated, b"blocker").unwrap();
+
+        assert_eq!(block_on(resolve_blocker(&fixture, None)).unwrap(), updated);
+    }
+
+    #[test]
+    fn resolves_blocker_next_to_executable() {
+        let fixture = Fixture::new("sibling-blocker");
+        let sibling = fixture.exe_dir().unwrap().join(DEFAULT_BLOCKER_FILE_NAME);
+        std::fs::create_dir_all(sibling.parent().unwrap()).unwrap();
+        std::fs::write(&sibling, b"blocker").unwrap();
+
+        assert_eq!(block_on(resolve_blocker(&fixture, None)).unwrap(), sibling);
+    }
+
+    #[test]
+    fn migrates_updated_blocker_from_legacy_cache() {
+        let mut fixture = Fixture::new("legacy-cache");
+        // Earlier versions used the parent of a temp directory outside of `%LOCALAPPDATA%`.
+        let legacy_update = paths::updated_blocker_in(fixture.root.join("Legacy").join(APP_AUTHOR));
+        std::fs::create_dir_all(legacy_update.parent().unwrap()).unwrap();
+        std::fs::write(&legacy_update, b"blocker").unwrap();
+        fixture.temp_dir = Some(fixture.root.join("Legacy").join("Temp"));
+
+        let updated = paths::updated_blocker(&fixture).unwrap();
+        assert_eq!(block_on(resolve_blocker(&fixture, None)).unwrap(), updated);
+        assert!(!legacy_update.exists());
+    }
+}
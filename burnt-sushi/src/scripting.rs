@@ -0,0 +1,196 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use burnt_sushi_core::filters::FilterConfig;
+use log::{debug, info, warn};
+use rhai::{Dynamic, Engine, FuncArgs, Map, Scope, AST};
+use shared::rpc::blocker_service::FilterHook;
+use tokio::sync::Notify;
+
+use crate::{
+    environment::System,
+    events::{self, AppEvent},
+    paths,
+};
+
+/// Limits the work a single hook invocation can do, so a broken script cannot stall the app.
+const MAX_OPERATIONS: u64 = 100_000;
+
+static SCRIPTS: LazyLock<Mutex<Scripts>> = LazyLock::new(|| Mutex::new(Scripts::load()));
+static DYNAMIC_RULES: Mutex<FilterConfig> = Mutex::new(FilterConfig {
+    allowlist: Vec::new(),
+    denylist: Vec::new(),
+});
+static RULES_CHANGED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Decision returned by the `on_request` hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Keep the decision of the filter rules.
+    Default,
+    Block,
+    Allow,
+}
+
+struct Scripts {
+    engine: Engine,
+    scripts: Vec<(String, AST)>,
+}
+
+impl Scripts {
+    fn load() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|message| info!("[script] {message}"));
+        engine.on_debug(|message, source, _| {
+            debug!("[script {}] {message}", source.unwrap_or_default())
+        });
+
+        let mut scripts = Vec::new();
+        for path in script_paths() {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            match engine.compile_file(path) {
+                Ok(mut ast) => {
+                    info!("Loaded script '{name}'");
+                    ast.set_source(name.as_str());
+                    scripts.push((name, ast));
+                }
+                Err(e) => warn!("Failed to load script '{name}': {e}"),
+            }
+        }
+
+        Self { engine, scripts }
+    }
+
+    /// Calls the function in every script that defines it and returns the results.
+    fn call(&self, name: &str, args: impl FuncArgs + Clone) -> Vec<Dynamic> {
+        let mut results = Vec::new();
+        for (script, ast) in &self.scripts {
+            if !ast.iter_functions().any(|f| f.name == name) {
+                continue;
+            }
+
+            match self
+                .engine
+                .call_fn::<Dynamic>(&mut Scope::new(), ast, name, args.clone())
+            {
+                Ok(result) => results.push(result),
+                Err(e) => warn!("Script '{script}' failed in {name}: {e}"),
+            }
+        }
+        results
+    }
+}
+
+fn script_paths() -> Vec<PathBuf> {
+    let Some(dir) = paths::scripts_dir(&System) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Runs `on_request(url, hook, blocked)` and applies a returned `"block"` or `"allow"` to future
+/// requests by adding a rule for the exact url.
+pub fn on_request(hook: FilterHook, url: &str, blocked: bool) -> Decision {
+    let results = SCRIPTS.lock().unwrap().call(
+        "on_request",
+        (url.to_string(), format!("{hook:?}"), blocked),
+    );
+
+    // The last script with an opinion wins.
+    let decision = results
+        .into_iter()
+        .filter_map(|result| result.into_immutable_string().ok())
+        .filter_map(|decision| match decision.as_str() {
+            "block" => Some(Decision::Block),
+            "allow" => Some(Decision::Allow),
+            _ => None,
+        })
+        .last()
+        .unwrap_or(Decision::Default);
+
+    match (decision, hook, blocked) {
+        (Decision::Block, FilterHook::CefUrlRequestCreate, false) => {
+            add_dynamic_rule(|rules| &mut rules.denylist, url)
+        }
+        (Decision::Allow, FilterHook::GetAddrInfo, true) => {
+            add_dynamic_rule(|rules| &mut rules.allowlist, url)
+        }
+        (Decision::Block, _, false) | (Decision::Allow, _, true) => {
+            debug!("Script decision {decision:?} cannot be applied to {hook:?} requests")
+        }
+        _ => {}
+    }
+
+    decision
+}
+
+/// Runs `on_ad_blocked(event)` with a map containing the `url`, `hook` and matched `rule`.
+pub fn on_ad_blocked(hook: FilterHook, url: &str, rule: Option<&str>) {
+    let mut event = Map::new();
+    event.insert("url".into(), url.to_string().into());
+    event.insert("hook".into(), format!("{hook:?}").into());
+    event.insert(
+        "rule".into(),
+        rule.map_or(Dynamic::UNIT, |rule| rule.to_string().into()),
+    );
+    SCRIPTS.lock().unwrap().call("on_ad_blocked", (event,));
+}
+
+/// Passes the events published on the event bus that scripts can react to.
+pub fn subscribe() {
+    events::subscribe("scripting", |event| {
+        if let AppEvent::Injected { pid: Some(pid) } = event {
+            on_hooked(pid);
+        }
+    });
+}
+
+/// Runs `on_hooked(pid)` once the blocker is active in the Spotify process.
+fn on_hooked(pid: u32) {
+    SCRIPTS.lock().unwrap().call("on_hooked", (pid as i64,));
+}
+
+fn add_dynamic_rule(list: impl FnOnce(&mut FilterConfig) -> &mut Vec<String>, url: &str) {
+    let rule = format!("^{}$", regex::escape(url));
+    let mut rules = DYNAMIC_RULES.lock().unwrap();
+    let list = list(&mut rules);
+    if list.contains(&rule) {
+        return;
+    }
+    info!("Adding rule '{rule}' from script");
+    list.push(rule);
+    RULES_CHANGED.notify_one();
+}
+
+/// Adds the rules created by scripts to the given filter config.
+pub fn extend_filters(filter_config: &mut FilterConfig) {
+    let rules = DYNAMIC_RULES.lock().unwrap();
+    filter_config
+        .allowlist
+        .extend(rules.allowlist.iter().cloned());
+    filter_config
+        .denylist
+        .extend(rules.denylist.iter().cloned());
+}
+
+/// Waits until a script added a rule.
+pub async fn rules_changed() {
+    RULES_CHANGED.notified().await
+}
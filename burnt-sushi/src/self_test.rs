@@ -0,0 +1,118 @@
+use std::{fmt::Display, io, path::PathBuf, thread};
+
+use log::{debug, error};
+use native_windows_gui as nwg;
+
+use crate::{
+    environment::System,
+    filter_providers::FilterFormat,
+    i18n::{tr_args, Msg},
+    resolver,
+    settings::Settings,
+    APP_NAME,
+};
+
+/// Collects failures of the startup steps so they can be reported to the user at once.
+#[derive(Debug, Default)]
+pub struct SelfTest {
+    failures: Vec<Failure>,
+}
+
+#[derive(Debug)]
+struct Failure {
+    step: &'static str,
+    error: String,
+    remedy: &'static str,
+}
+
+impl SelfTest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the result of a startup step, returning the value if it succeeded.
+    pub fn check<T, E: Display>(
+        &mut self,
+        step: &'static str,
+        remedy: &'static str,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                debug!("Self-test: {step} ok");
+                Some(value)
+            }
+            Err(e) => {
+                error!("Self-test: {step} failed: {e}");
+                self.failures.push(Failure {
+                    step,
+                    error: e.to_string(),
+                    remedy,
+                });
+                None
+            }
+        }
+    }
+
+    pub fn check_config(&mut self) {
+        self.check(
+            "Settings",
+            "Fix or delete settings.toml in the app data directory.",
+            Settings::check().map_err(|e| format!("{e:#}")),
+        );
+        self.check(
+            "Filter config",
+            "Fix the filter config or delete it to restore the default.",
+            check_filter_config(),
+        );
+    }
+
+    pub fn check_blocker(&mut self, result: io::Result<PathBuf>) {
+        self.check(
+            "Blocker extraction",
+            "Make sure the blocker file is not locked or quarantined by your antivirus.",
+            result,
+        );
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Shows a single message describing all failed steps, if any.
+    pub fn report(self) {
+        if self.passed() {
+            return;
+        }
+
+        let mut message = tr_args(Msg::StartFailed, &[("app", &APP_NAME)]);
+        message.push('\n');
+        for failure in &self.failures {
+            message.push_str(&format!(
+                "\n- {}: {}\n  {}\n",
+                failure.step, failure.error, failure.remedy
+            ));
+        }
+
+        // The message box blocks until closed, so it must not run on the runtime thread.
+        thread::spawn(move || {
+            nwg::error_message(APP_NAME, &message);
+        });
+    }
+}
+
+fn check_filter_config() -> io::Result<()> {
+    let Some(path) = resolver::filter_config_path(&System).filter(|path| path.exists()) else {
+        return Ok(());
+    };
+
+    let contents = std::fs::read_to_string(&path)?;
+    let format = FilterFormat::from_path(&path).unwrap_or_default();
+    format.parse(&contents).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("'{}' is invalid: {e}", path.display()),
+        )
+    })?;
+    Ok(())
+}
@@ -0,0 +1,313 @@
+use std::{
+    env,
+    ffi::{c_void, OsString},
+    mem,
+    path::{Path, PathBuf},
+    ptr,
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::{ensure, Context};
+use log::{debug, error, info, warn};
+use widestring::U16CString;
+use windows::{
+    core::{GUID, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        System::{
+            Com::CoTaskMemFree,
+            Environment::{CreateEnvironmentBlock, DestroyEnvironmentBlock},
+            RemoteDesktop::{
+                WTSActive, WTSEnumerateSessionsW, WTSFreeMemory, WTSQueryUserToken,
+                WTS_CURRENT_SERVER_HANDLE, WTS_SESSION_INFOW,
+            },
+            Threading::{
+                CreateProcessAsUserW, CREATE_NO_WINDOW, CREATE_UNICODE_ENVIRONMENT,
+                PROCESS_INFORMATION, STARTUPINFOW,
+            },
+        },
+        UI::Shell::{
+            FOLDERID_ProgramFiles, FOLDERID_ProgramFilesX86, SHGetKnownFolderPath, KF_FLAG_DEFAULT,
+        },
+    },
+};
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+        SessionChangeReason,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+use crate::APP_NAME;
+
+const SERVICE_NAME: &str = "BurntSushiService";
+const SERVICE_DISPLAY_NAME: &str = "BurntSushi Session Helper";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// Registers the service to start at boot. Requires administrator rights and the app to be in
+/// Program Files, as the service runs it as LocalSystem.
+pub fn install() -> anyhow::Result<()> {
+    let executable_path = protected_executable()?;
+
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("Failed to connect to service manager (must be run as administrator).")?;
+
+    let service_info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments: vec![OsString::from("service"), OsString::from("run")],
+        dependencies: vec![],
+        account_name: None, // LocalSystem
+        account_password: None,
+    };
+    let service = manager
+        .create_service(
+            &service_info,
+            ServiceAccess::CHANGE_CONFIG | ServiceAccess::START,
+        )
+        .context("Failed to create service.")?;
+    service
+        .set_description(format!(
+            "Starts {APP_NAME} in every user session so Spotify ads are blocked from logon."
+        ))
+        .context("Failed to set service description.")?;
+    service
+        .start::<&str>(&[])
+        .context("Failed to start service.")?;
+
+    Ok(())
+}
+
+/// Stops and removes the service. Requires administrator rights.
+pub fn uninstall() -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("Failed to connect to service manager (must be run as administrator).")?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .context("Failed to open service.")?;
+
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop().context("Failed to stop service.")?;
+    }
+    service.delete().context("Failed to delete service.")?;
+
+    Ok(())
+}
+
+/// Runs the service, must only be called when started by the service control manager.
+pub fn run() -> anyhow::Result<()> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        .context("Failed to start service dispatcher.")
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("Service failed: {e:#}");
+    }
+}
+
+enum ServiceEvent {
+    Stop,
+    Logon(u32),
+}
+
+fn run_service() -> anyhow::Result<()> {
+    let (event_tx, event_rx) = mpsc::channel();
+
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop => {
+                let _ = event_tx.send(ServiceEvent::Stop);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::SessionChange(param) => {
+                if matches!(param.reason, SessionChangeReason::SessionLogon) {
+                    let _ = event_tx.send(ServiceEvent::Logon(param.notification.session_id));
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })
+        .context("Failed to register service control handler.")?;
+
+    let set_state = |state: ServiceState, controls_accepted: ServiceControlAccept| {
+        status_handle.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+    set_state(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SESSION_CHANGE,
+    )
+    .context("Failed to report service status.")?;
+    info!("Service started");
+
+    // Checked again in case the app was moved or the service registered by other means.
+    let exe = match protected_executable() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!("Not starting {APP_NAME} in user sessions: {e:#}");
+            set_state(ServiceState::Stopped, ServiceControlAccept::empty())
+                .context("Failed to report service status.")?;
+            return Ok(());
+        }
+    };
+
+    for session_id in active_sessions() {
+        launch_in_session(&exe, session_id);
+    }
+
+    while let Ok(event) = event_rx.recv() {
+        match event {
+            ServiceEvent::Logon(session_id) => launch_in_session(&exe, session_id),
+            ServiceEvent::Stop => break,
+        }
+    }
+
+    info!("Service stopping");
+    set_state(ServiceState::Stopped, ServiceControlAccept::empty())
+        .context("Failed to report service status.")?;
+    Ok(())
+}
+
+/// The current executable if it is in Program Files, where only administrators can replace it.
+/// Anywhere else the user, or the self-update running as the user, could swap the binary the
+/// service starts as LocalSystem.
+fn protected_executable() -> anyhow::Result<PathBuf> {
+    let exe = env::current_exe()
+        .and_then(|exe| exe.canonicalize())
+        .context("Failed to locate current executable.")?;
+    let protected = [&FOLDERID_ProgramFiles, &FOLDERID_ProgramFilesX86]
+        .into_iter()
+        .filter_map(known_folder)
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| exe.starts_with(dir));
+    ensure!(
+        protected,
+        "The service runs {APP_NAME} as SYSTEM, so it must be installed from a copy in Program Files, not from '{}'.",
+        exe.display()
+    );
+    // Without the `\\?\` prefix of canonical paths, which not every consumer of the path accepts.
+    Ok(exe
+        .to_str()
+        .and_then(|path| path.strip_prefix(r"\\?\"))
+        .filter(|path| !path.starts_with("UNC"))
+        .map_or(exe.clone(), PathBuf::from))
+}
+
+fn known_folder(id: &GUID) -> Option<PathBuf> {
+    let path = unsafe { SHGetKnownFolderPath(id, KF_FLAG_DEFAULT, HANDLE::default()) }.ok()?;
+    let dir = unsafe { path.to_string() }.ok().map(PathBuf::from);
+    unsafe { CoTaskMemFree(Some(path.0 as _)) };
+    dir
+}
+
+fn active_sessions() -> Vec<u32> {
+    let mut sessions = ptr::null_mut::<WTS_SESSION_INFOW>();
+    let mut count = 0;
+    if let Err(e) =
+        unsafe { WTSEnumerateSessionsW(WTS_CURRENT_SERVER_HANDLE, 0, 1, &mut sessions, &mut count) }
+    {
+        warn!("Failed to enumerate sessions: {e}");
+        return Vec::new();
+    }
+
+    let active = unsafe { std::slice::from_raw_parts(sessions, count as usize) }
+        .iter()
+        .filter(|session| session.State == WTSActive)
+        .map(|session| session.SessionId)
+        .collect();
+    unsafe { WTSFreeMemory(sessions.cast()) };
+    active
+}
+
+/// Starts the app as the user logged into the given session.
+/// An already running instance in that session makes the new one exit again.
+fn launch_in_session(exe: &Path, session_id: u32) {
+    match spawn_as_session_user(exe, session_id) {
+        Ok(()) => debug!("Started {APP_NAME} in session {session_id}"),
+        Err(e) => warn!("Failed to start {APP_NAME} in session {session_id}: {e:#}"),
+    }
+}
+
+fn spawn_as_session_user(exe: &Path, session_id: u32) -> anyhow::Result<()> {
+    let application = U16CString::from_os_str(exe.as_os_str())
+        .context("Executable path contains invalid characters.")?;
+    let mut command_line = U16CString::from_str(format!("\"{}\" --autostart", exe.display()))
+        .context("Executable path contains invalid characters.")?
+        .into_vec_with_nul();
+    let mut desktop = U16CString::from_str("winsta0\\default")
+        .unwrap()
+        .into_vec_with_nul();
+
+    let mut token = HANDLE::default();
+    unsafe { WTSQueryUserToken(session_id, &mut token) }
+        .context("Failed to get user token of session.")?;
+
+    let mut environment = ptr::null_mut::<c_void>();
+    let result = unsafe { CreateEnvironmentBlock(&mut environment, token, false) }
+        .context("Failed to create user environment.")
+        .and_then(|_| {
+            let startup_info = STARTUPINFOW {
+                cb: mem::size_of::<STARTUPINFOW>() as u32,
+                lpDesktop: PWSTR(desktop.as_mut_ptr()),
+                ..Default::default()
+            };
+            let mut process_info = PROCESS_INFORMATION::default();
+            unsafe {
+                CreateProcessAsUserW(
+                    token,
+                    PCWSTR(application.as_ptr()),
+                    PWSTR(command_line.as_mut_ptr()),
+                    None,
+                    None,
+                    false,
+                    CREATE_UNICODE_ENVIRONMENT | CREATE_NO_WINDOW,
+                    Some(environment),
+                    PCWSTR::null(),
+                    &startup_info,
+                    &mut process_info,
+                )
+            }
+            .context("Failed to create process.")?;
+            unsafe {
+                let _ = CloseHandle(process_info.hThread);
+                let _ = CloseHandle(process_info.hProcess);
+            }
+            Ok(())
+        });
+
+    unsafe {
+        if !environment.is_null() {
+            let _ = DestroyEnvironmentBlock(environment);
+        }
+        let _ = CloseHandle(token);
+    }
+
+    result
+}
@@ -0,0 +1,80 @@
+use std::{
+    io, process,
+    sync::{Condvar, LazyLock, Mutex, OnceLock},
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use tokio::sync::Notify;
+use winapi::{
+    shared::minwindef::{BOOL, DWORD, FALSE, TRUE},
+    um::{
+        consoleapi::SetConsoleCtrlHandler,
+        wincon::{CTRL_LOGOFF_EVENT, CTRL_SHUTDOWN_EVENT},
+    },
+};
+use windows::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+
+/// How long the session end is delayed at most to unhook Spotify.
+const SESSION_END_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SESSION_END: LazyLock<Notify> = LazyLock::new(Notify::new);
+static SHUTDOWN_COMPLETE: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+/// Id of the Windows session this instance runs in.
+pub fn current_id() -> u32 {
+    static CURRENT_ID: OnceLock<u32> = OnceLock::new();
+    *CURRENT_ID.get_or_init(|| of_process(process::id()).unwrap_or(0))
+}
+
+/// Id of the Windows session the given process runs in.
+pub fn of_process(pid: u32) -> io::Result<u32> {
+    let mut session_id = 0;
+    unsafe { ProcessIdToSessionId(pid, &mut session_id) }?;
+    Ok(session_id)
+}
+
+/// Handles logoff and shutdown events sent to an attached console.
+pub fn install_console_handler() {
+    if unsafe { SetConsoleCtrlHandler(Some(console_ctrl_handler), TRUE) } == FALSE {
+        warn!("Failed to install console control handler");
+    }
+}
+
+unsafe extern "system" fn console_ctrl_handler(ctrl_type: DWORD) -> BOOL {
+    match ctrl_type {
+        CTRL_LOGOFF_EVENT | CTRL_SHUTDOWN_EVENT => {
+            end_session_blocking();
+            TRUE
+        }
+        _ => FALSE,
+    }
+}
+
+/// Asks the app to shut down because the session ends and blocks until Spotify was unhooked.
+/// Windows terminates the process once the session end handlers return.
+pub fn end_session_blocking() {
+    info!("Session is ending");
+    SESSION_END.notify_one();
+
+    let (complete, condvar) = &SHUTDOWN_COMPLETE;
+    let complete = complete.lock().unwrap();
+    let (_complete, result) = condvar
+        .wait_timeout_while(complete, SESSION_END_TIMEOUT, |complete| !*complete)
+        .unwrap();
+    if result.timed_out() {
+        warn!("Shutdown did not complete before the session ended");
+    }
+}
+
+pub async fn wait_for_session_end() {
+    SESSION_END.notified().await;
+}
+
+/// Marks the shutdown as complete, releasing a pending session end.
+pub fn shutdown_complete() {
+    debug!("Shutdown complete");
+    let (complete, condvar) = &SHUTDOWN_COMPLETE;
+    *complete.lock().unwrap() = true;
+    condvar.notify_all();
+}
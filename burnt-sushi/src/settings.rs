@@ -0,0 +1,322 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{LazyLock, Mutex, MutexGuard},
+};
+
+use anyhow::Context;
+use burnt_sushi_core::spotify_process_scanner;
+use chrono::NaiveTime;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{args::LogLevel, environment::System, filter_providers::FilterSource, paths, privacy};
+
+/// Version of the settings format, bumped whenever a migration is needed.
+const SETTINGS_VERSION: u32 = 1;
+
+static SETTINGS: LazyLock<Mutex<Settings>> = LazyLock::new(|| {
+    let settings = Settings::load();
+    privacy::set_url_privacy(settings.url_privacy);
+    Mutex::new(settings)
+});
+
+pub fn get() -> MutexGuard<'static, Settings> {
+    SETTINGS.lock().unwrap()
+}
+
+/// User settings persisted in `settings.toml` in the app data directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Settings {
+    /// Format version of the settings file, used to migrate settings written by older versions.
+    #[serde(default)]
+    pub version: u32,
+    /// How much of blocked and allowed urls is written to persistent logs and diagnostics.
+    pub url_privacy: UrlPrivacy,
+    /// Port of a localhost Prometheus `/metrics` endpoint for processes of the current user,
+    /// disabled if not set.
+    pub metrics_port: Option<u16>,
+    /// Port of a localhost `/healthz` endpoint returning the hook status as JSON, disabled if not
+    /// set.
+    pub healthz_port: Option<u16>,
+    /// Url significant events are posted to as JSON, e.g. a Discord webhook, disabled if not set.
+    pub webhook_url: Option<String>,
+    /// Interval in hours between update checks, only checked on startup if not set.
+    pub update_check_interval_hours: Option<u64>,
+    /// Whether starting on logon behaves like `--silent`.
+    pub silent_autostart: bool,
+    /// Whether a notification is shown once the app started and is watching for Spotify.
+    pub start_notification: bool,
+    /// Whether a notification is shown when the all-time number of blocked ads reaches a milestone.
+    pub milestone_notifications: bool,
+    /// Whether a notification is shown when Spotify plays an ad while it is hooked, which hints at
+    /// outdated filters.
+    pub ad_slip_notifications: bool,
+    /// Whether an ad played while Spotify is hooked is skipped, or Spotify is muted until it ends
+    /// if it cannot be skipped.
+    pub mute_ads: bool,
+    /// Spotify Web API access used to verify that no ads are played, disabled if not set.
+    pub spotify_web_api: Option<WebApiSettings>,
+    /// Additional filter lists merged into the filter config.
+    pub filter_sources: Vec<FilterSource>,
+    /// Interval in hours between reloads of the filter lists, only loaded when hooking if not set.
+    pub filter_refresh_hours: Option<u64>,
+    /// Executable names of the processes treated as Spotify, e.g. `SpotifyLauncher.exe` or
+    /// `Spotify*.exe` for beta builds. `*` and `?` are wildcards and case is ignored.
+    pub spotify_process_names: Vec<String>,
+    /// Whether Spotify's own start on logon is taken over and delayed until the blocker is ready,
+    /// so that its first requests are filtered too.
+    pub delay_spotify_autostart: bool,
+    /// Whether status changes are announced with notifications, which screen readers read out.
+    pub announce_status_changes: bool,
+    /// Daily period in local time during which notifications are only logged, e.g.
+    /// `{ start = "22:00", end = "07:00" }`.
+    pub quiet_hours: Option<QuietHours>,
+    /// Whether notifications are also only logged while Windows holds back its own, e.g. during
+    /// presentations, full screen games or with Focus Assist on.
+    pub respect_focus_assist: bool,
+    /// Whether background checks are reduced while battery saver is on or the session is locked or
+    /// disconnected, and filter refreshes and update checks wait while it stays unused.
+    pub power_saving: bool,
+    /// Whether crashes of previous runs are uploaded to help fixing them.
+    pub crash_reports: CrashReports,
+    /// Endpoint crash reports are uploaded to, they are kept on this computer if not set.
+    pub crash_report_endpoint: Option<String>,
+    /// Whether aggregate counters of blocked ads and hooking failures are sent with the Spotify and
+    /// blocker versions, to help finding Spotify versions that break the filters. Off by default,
+    /// `telemetry preview` shows what is sent.
+    pub telemetry: bool,
+    /// Endpoint telemetry is sent to, it is neither collected nor sent if not set.
+    pub telemetry_endpoint: Option<String>,
+    /// What to do when another ad blocker hooking the same functions is loaded into Spotify.
+    pub on_conflict: ConflictPolicy,
+    /// Whether blockers found in processes other than the hooked Spotify are ejected, otherwise
+    /// they are only reported. Off by default, only modules exporting the blocker marker are
+    /// ejected.
+    pub eject_stray_blockers: bool,
+    /// Whether known ad endpoints are checked against the rules after each filter update.
+    pub canary_check: bool,
+    /// Level of the output shown in the console, overridden by `--console-log-level`.
+    pub console_log_level: Option<LogLevel>,
+    /// Level of the output written to log files, overridden by `--file-log-level`.
+    pub file_log_level: Option<LogLevel>,
+    /// Directory the blocker is extracted to, e.g. one excluded from antivirus scans. It has to be
+    /// writable and on a local volume, otherwise the default location is used.
+    pub blocker_dir: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            url_privacy: UrlPrivacy::default(),
+            metrics_port: None,
+            healthz_port: None,
+            webhook_url: None,
+            update_check_interval_hours: None,
+            silent_autostart: true,
+            start_notification: true,
+            milestone_notifications: true,
+            ad_slip_notifications: true,
+            mute_ads: true,
+            spotify_web_api: None,
+            filter_sources: Vec::new(),
+            filter_refresh_hours: None,
+            spotify_process_names: spotify_process_scanner::DEFAULT_PROCESS_NAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            delay_spotify_autostart: false,
+            announce_status_changes: false,
+            quiet_hours: None,
+            respect_focus_assist: true,
+            power_saving: true,
+            crash_reports: CrashReports::default(),
+            crash_report_endpoint: None,
+            telemetry: false,
+            telemetry_endpoint: None,
+            on_conflict: ConflictPolicy::default(),
+            eject_stray_blockers: false,
+            canary_check: false,
+            console_log_level: None,
+            file_log_level: None,
+            blocker_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebApiSettings {
+    /// Client id of a Spotify app created by the user.
+    pub client_id: String,
+    /// Local port of the redirect uri, which has to be registered as
+    /// `http://127.0.0.1:<port>/callback` in the Spotify app.
+    #[serde(default = "WebApiSettings::default_redirect_port")]
+    pub redirect_port: u16,
+    /// Interval in seconds between playback state checks.
+    #[serde(default = "WebApiSettings::default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Whether ads that are played despite the blocker are skipped, requires Spotify Premium.
+    #[serde(default)]
+    pub auto_skip: bool,
+}
+
+impl WebApiSettings {
+    fn default_redirect_port() -> u16 {
+        8888
+    }
+
+    fn default_poll_interval_secs() -> u64 {
+        15
+    }
+
+    pub fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}/callback", self.redirect_port)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QuietHours {
+    /// Start of the quiet hours as `HH:MM`.
+    #[serde(with = "hour_minute")]
+    pub start: NaiveTime,
+    /// End of the quiet hours as `HH:MM`, the next day if before the start.
+    #[serde(with = "hour_minute")]
+    pub end: NaiveTime,
+}
+
+mod hour_minute {
+    use chrono::NaiveTime;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&time.format(FORMAT))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let time = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&time, FORMAT).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UrlPrivacy {
+    /// Log complete urls.
+    #[default]
+    Full,
+    /// Log only the scheme and host.
+    Host,
+    /// Log the host and a hash of the path and query.
+    Hashed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrashReports {
+    /// Ask with a notification after a crash.
+    #[default]
+    Ask,
+    /// Upload crash reports without asking.
+    Send,
+    /// Never upload crash reports.
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Log the conflict and hook Spotify anyway.
+    #[default]
+    Warn,
+    /// Leave Spotify to the other ad blocker.
+    Refuse,
+}
+
+impl Settings {
+    pub fn path() -> Option<PathBuf> {
+        paths::data_dir(&System).map(|dir| dir.join("settings.toml"))
+    }
+
+    /// Checks whether the settings file can be read and parsed, a missing file is fine.
+    pub fn check() -> anyhow::Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str::<Settings>(&contents).context("Failed to parse settings.")?;
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to read settings."),
+        }
+    }
+
+    fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => match toml::from_str::<Settings>(&contents) {
+                Ok(mut settings) => {
+                    debug!("Loaded settings from '{}'", path.display());
+                    if settings.version < SETTINGS_VERSION {
+                        settings.migrate();
+                    }
+                    settings
+                }
+                Err(e) => {
+                    warn!("Failed to parse settings, using defaults: {e}");
+                    Self::default()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                warn!("Failed to read settings, using defaults: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Returns a copy of the settings that is safe to share, without any secrets. Urls are
+    /// redacted as a whole, as webhooks and collectors often carry a token in them.
+    pub fn redacted(&self) -> Self {
+        let redact = |url: &Option<String>| url.as_ref().map(|_| "<redacted>".to_string());
+        Self {
+            webhook_url: redact(&self.webhook_url),
+            crash_report_endpoint: redact(&self.crash_report_endpoint),
+            telemetry_endpoint: redact(&self.telemetry_endpoint),
+            ..self.clone()
+        }
+    }
+
+    fn migrate(&mut self) {
+        debug!(
+            "Migrating settings from version {} to {SETTINGS_VERSION}",
+            self.version
+        );
+        // Version 0 files predate the version field and need no changes.
+        self.version = SETTINGS_VERSION;
+        if let Err(e) = self.save() {
+            warn!("Failed to save migrated settings: {e:#}");
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().context("Failed to locate app data directory.")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create settings directory.")?;
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize settings.")?;
+        fs::write(&path, contents).context("Failed to write settings.")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Shutdown of the app. Any part can request it, everything else waits for the same token and the
+//! teardown in `run` happens in a fixed order afterwards: unhook Spotify (stop RPC, join, eject),
+//! save state, release the control channel and finally close the tray icon.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{LazyLock, OnceLock},
+};
+
+use log::{debug, error};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+static TOKEN: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+static REASON: OnceLock<ShutdownReason> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    CtrlC,
+    TrayExit,
+    Update,
+    SessionEnd,
+    SpotifyExited,
+    Handoff,
+    /// Requested through the control channel.
+    Requested,
+    /// A background task panicked.
+    TaskPanicked,
+    /// The blocker stopped without a request, e.g. because the scanner failed.
+    AppStopped,
+}
+
+impl fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownReason::CtrlC => write!(f, "Ctrl-C received"),
+            ShutdownReason::TrayExit => write!(f, "system tray exited"),
+            ShutdownReason::Update => write!(f, "update"),
+            ShutdownReason::SessionEnd => write!(f, "session end"),
+            ShutdownReason::SpotifyExited => write!(f, "Spotify exit"),
+            ShutdownReason::Handoff => write!(f, "newer instance"),
+            ShutdownReason::Requested => write!(f, "request"),
+            ShutdownReason::TaskPanicked => write!(f, "panic in background task"),
+            ShutdownReason::AppStopped => write!(f, "blocker stopped"),
+        }
+    }
+}
+
+/// Asks the app to shut down. Only the first reason is kept.
+pub fn request(reason: ShutdownReason) {
+    if REASON.set(reason).is_ok() {
+        debug!("Shutdown requested ({reason})");
+    }
+    TOKEN.cancel();
+}
+
+/// Why the shutdown was requested, if it was.
+pub fn reason() -> Option<ShutdownReason> {
+    REASON.get().copied()
+}
+
+/// Waits until the shutdown is requested.
+pub async fn requested() {
+    TOKEN.cancelled().await
+}
+
+/// Spawns a background task that is stopped once the shutdown is requested. A panic in the task
+/// requests the shutdown, so that Spotify is unhooked properly instead of running on in a broken
+/// state.
+pub fn spawn(
+    name: &'static str,
+    task: impl Future<Output = ()> + Send + 'static,
+) -> JoinHandle<()> {
+    let task = tokio::task::spawn(async move {
+        tokio::select! {
+            _ = task => {}
+            _ = requested() => {}
+        }
+    });
+    tokio::task::spawn(async move {
+        if let Err(e) = task.await {
+            if e.is_panic() {
+                error!("Task '{name}' panicked");
+                request(ShutdownReason::TaskPanicked);
+            }
+        }
+    })
+}
@@ -0,0 +1,99 @@
+//! Taking over Spotify's own start on logon, so that Spotify is only started once the blocker is
+//! prepared and even its first requests are filtered.
+
+use std::{fs, io, os::windows::process::CommandExt, process::Command};
+
+use anyhow::Context;
+use burnt_sushi_core::spotify_process_scanner::is_spotify_process;
+use dll_syringe::process::{OwnedProcess, Process};
+use log::{debug, info, warn};
+
+use crate::{args::ARGS, autostart, environment::System, paths, preparation, settings};
+
+/// Name of Spotify's entry in the `Run` registry key.
+const SPOTIFY_VALUE: &str = "Spotify";
+
+/// Moves Spotify's autostart entry into the app data directory if `delay-spotify-autostart` is
+/// enabled and puts it back otherwise. Spotify re-adds the entry on updates, so this runs on every
+/// start.
+pub fn sync() -> anyhow::Result<()> {
+    if settings::get().delay_spotify_autostart {
+        take_over()
+    } else {
+        restore()
+    }
+}
+
+fn take_over() -> anyhow::Result<()> {
+    let run_key = autostart::run_key()?;
+    let command = match run_key.get_value::<String, _>(SPOTIFY_VALUE) {
+        Ok(command) => command,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read Spotify's autostart entry."),
+    };
+
+    let path =
+        paths::spotify_autostart_file(&System).context("Failed to locate app data directory.")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create app data directory.")?;
+    }
+    fs::write(&path, &command).context("Failed to save Spotify's autostart entry.")?;
+    run_key
+        .delete_value(SPOTIFY_VALUE)
+        .context("Failed to remove Spotify's autostart entry.")?;
+    info!("Took over Spotify's autostart");
+    Ok(())
+}
+
+/// Puts Spotify's autostart entry back if it was taken over.
+pub fn restore() -> anyhow::Result<()> {
+    let path =
+        paths::spotify_autostart_file(&System).context("Failed to locate app data directory.")?;
+    let command = match fs::read_to_string(&path) {
+        Ok(command) => command,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read saved Spotify autostart entry."),
+    };
+
+    autostart::run_key()?
+        .set_value(SPOTIFY_VALUE, &command)
+        .context("Failed to restore Spotify's autostart entry.")?;
+    fs::remove_file(&path).context("Failed to remove saved Spotify autostart entry.")?;
+    info!("Restored Spotify's autostart");
+    Ok(())
+}
+
+/// Starts Spotify the way its autostart entry would once the blocker is prepared, if the app was
+/// started on logon and took the entry over.
+pub async fn start_when_ready() {
+    if !ARGS.autostart {
+        return;
+    }
+    let Some(command) =
+        paths::spotify_autostart_file(&System).and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return;
+    };
+
+    preparation::ready().await;
+    if OwnedProcess::all().any(|process| is_spotify_process(process.borrowed())) {
+        debug!("Spotify was already started");
+        return;
+    }
+
+    info!("Starting Spotify");
+    if let Err(e) = spawn(&command) {
+        warn!("Failed to start Spotify: {e}");
+    }
+}
+
+/// Runs a command line as stored in the `Run` registry key.
+fn spawn(command_line: &str) -> io::Result<()> {
+    let command_line = command_line.trim();
+    let (program, args) = match command_line.strip_prefix('"') {
+        Some(rest) => rest.split_once('"').unwrap_or((rest, "")),
+        None => command_line.split_once(' ').unwrap_or((command_line, "")),
+    };
+    Command::new(program).raw_arg(args.trim()).spawn()?;
+    Ok(())
+}
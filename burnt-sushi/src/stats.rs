@@ -0,0 +1,357 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    sync::{LazyLock, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use burnt_sushi_core::filters::NOT_ALLOWLISTED;
+use chrono::{DateTime, Local};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    environment::System,
+    i18n::{tr, tr_args, Msg},
+    notify, paths, settings,
+};
+
+/// How often the all-time stats are written to disk while running.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// All-time numbers of blocked ads that are celebrated with a notification.
+const MILESTONES: [u64; 5] = [100, 1_000, 10_000, 100_000, 1_000_000];
+/// Number of rules listed in the summary.
+const TOP_RULE_COUNT: usize = 5;
+/// Number of complete minutes the current blocking rate is averaged over.
+const RATE_WINDOW_MINUTES: u64 = 10;
+/// Seconds of ads Spotify plays per hour of music on the free tier, used to estimate the ads
+/// avoided while listening ad-free.
+const AD_SECS_PER_HOUR: u64 = 4 * 60;
+/// Number of Spotify versions kept in the version history.
+const SPOTIFY_VERSION_COUNT: usize = 20;
+
+static STATS: LazyLock<Mutex<StatsState>> = LazyLock::new(|| {
+    Mutex::new(StatsState {
+        all_time: Stats::load(),
+        session: Stats::default(),
+        protected_since: None,
+        blocked_per_minute: BTreeMap::new(),
+    })
+});
+
+pub fn get() -> MutexGuard<'static, StatsState> {
+    STATS.lock().unwrap()
+}
+
+#[derive(Debug)]
+pub struct StatsState {
+    pub all_time: Stats,
+    pub session: Stats,
+    protected_since: Option<Instant>,
+    /// Blocked requests by the minute they were seen by the blocker, in minutes since the Unix
+    /// epoch.
+    blocked_per_minute: BTreeMap<u64, u64>,
+}
+
+/// Cumulative statistics about blocked requests.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Stats {
+    /// Blocked requests that were matched by a denylist rule.
+    pub ads_blocked: u64,
+    /// Time in seconds during which Spotify was hooked.
+    pub protected_secs: u64,
+    /// Time in seconds Spotify played music while it was hooked, according to its media session.
+    pub ad_free_secs: u64,
+    /// Number of blocked requests per filter rule.
+    pub rule_hits: BTreeMap<String, u64>,
+    /// Spotify versions seen when hooking, oldest first. Only kept in the all-time stats.
+    pub spotify_versions: Vec<SpotifyVersion>,
+}
+
+/// How hooking a Spotify version went, to tell whether issues started with a Spotify update.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SpotifyVersion {
+    pub version: String,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    /// Number of times the blocker was injected into this version.
+    pub hooks: u64,
+    /// Number of times hooking this version failed after all attempts.
+    pub hook_failures: u64,
+    /// Number of ads this version played while it was hooked.
+    pub ad_slips: u64,
+}
+
+impl StatsState {
+    /// Records a blocked request seen by the blocker at `time` and returns the milestone reached
+    /// by it, if any. Only requests matched by a denylist rule count as ads, hosts that are not on
+    /// the allowlist are mostly telemetry.
+    pub fn record_blocked(&mut self, rule: Option<&str>, time: SystemTime) -> Option<u64> {
+        self.all_time.record_blocked(rule);
+        self.session.record_blocked(rule);
+
+        *self.blocked_per_minute.entry(minute_of(time)).or_default() += 1;
+        let oldest = minute_of(SystemTime::now()).saturating_sub(RATE_WINDOW_MINUTES);
+        self.blocked_per_minute = self.blocked_per_minute.split_off(&oldest);
+
+        if !is_ad(rule) {
+            return None;
+        }
+        MILESTONES
+            .into_iter()
+            .find(|&milestone| milestone == self.all_time.ads_blocked)
+    }
+
+    /// Records whether hooking the Spotify version succeeded.
+    pub fn record_hook(&mut self, version: &str, hooked: bool) {
+        let spotify_version = self.spotify_version(version);
+        if hooked {
+            spotify_version.hooks += 1;
+        } else {
+            spotify_version.hook_failures += 1;
+        }
+    }
+
+    /// Records an ad played by the hooked Spotify version.
+    pub fn record_ad_slip(&mut self, version: &str) {
+        self.spotify_version(version).ad_slips += 1;
+    }
+
+    /// Entry of the version in the history, added as the newest one if it is not in there yet.
+    fn spotify_version(&mut self, version: &str) -> &mut SpotifyVersion {
+        let versions = &mut self.all_time.spotify_versions;
+        let now = Local::now();
+        let index = match versions.iter().position(|known| known.version == version) {
+            Some(index) => index,
+            None => {
+                info!("Seeing Spotify {version} for the first time");
+                versions.push(SpotifyVersion {
+                    version: version.to_string(),
+                    first_seen: now,
+                    last_seen: now,
+                    hooks: 0,
+                    hook_failures: 0,
+                    ad_slips: 0,
+                });
+                let excess = versions.len().saturating_sub(SPOTIFY_VERSION_COUNT);
+                versions.drain(..excess);
+                versions.len() - 1
+            }
+        };
+        let spotify_version = &mut versions[index];
+        spotify_version.last_seen = now;
+        spotify_version
+    }
+
+    /// Average number of blocked requests per minute over the last complete minutes.
+    pub fn blocked_per_minute(&self) -> f64 {
+        let now = minute_of(SystemTime::now());
+        let count = self
+            .blocked_per_minute
+            .range(now.saturating_sub(RATE_WINDOW_MINUTES)..now)
+            .map(|(_, count)| count)
+            .sum::<u64>();
+        count as f64 / RATE_WINDOW_MINUTES as f64
+    }
+
+    pub fn protection_started(&mut self) {
+        self.protected_since.get_or_insert_with(Instant::now);
+    }
+
+    pub fn protection_stopped(&mut self) {
+        self.flush_protected_time();
+        self.protected_since = None;
+    }
+
+    /// Records that Spotify played music for the given time, which counts as listened ad-free
+    /// while it is hooked.
+    pub fn record_listening(&mut self, duration: Duration) {
+        if self.protected_since.is_none() {
+            return;
+        }
+        let secs = duration.as_secs_f64().round() as u64;
+        self.all_time.ad_free_secs += secs;
+        self.session.ad_free_secs += secs;
+    }
+
+    /// Time protected including the currently running period.
+    pub fn protected_time(&self, stats: &Stats) -> Duration {
+        let running = self
+            .protected_since
+            .map_or(Duration::ZERO, |since| since.elapsed());
+        Duration::from_secs(stats.protected_secs) + running
+    }
+
+    fn flush_protected_time(&mut self) {
+        if let Some(since) = self.protected_since.replace(Instant::now()) {
+            let secs = since.elapsed().as_secs();
+            self.all_time.protected_secs += secs;
+            self.session.protected_secs += secs;
+        }
+    }
+
+    /// Human readable overview of the session and all-time stats.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for (title, stats) in [
+            (Msg::ThisSession, &self.session),
+            (Msg::AllTime, &self.all_time),
+        ] {
+            let duration = format_duration(self.protected_time(stats));
+            summary += &format!(
+                "{}:\n  {}\n  {}\n",
+                tr(title),
+                tr_args(Msg::AdsBlocked, &[("count", &stats.ads_blocked)]),
+                tr_args(Msg::Protected, &[("duration", &duration)])
+            );
+            if stats.ad_free_secs > 0 {
+                let listened = format_duration(Duration::from_secs(stats.ad_free_secs));
+                let avoided = format_duration(stats.ads_avoided());
+                summary += &format!(
+                    "  {}\n  {}\n",
+                    tr_args(Msg::ListenedAdFree, &[("duration", &listened)]),
+                    tr_args(Msg::AdsAvoided, &[("duration", &avoided)])
+                );
+            }
+
+            if title == Msg::ThisSession {
+                let rate = format!("{:.1}", self.blocked_per_minute());
+                summary += &format!(
+                    "  {}\n",
+                    tr_args(
+                        Msg::BlockedPerMinute,
+                        &[("rate", &rate), ("minutes", &RATE_WINDOW_MINUTES)]
+                    )
+                );
+            }
+
+            for (rule, hits) in stats.top_rules() {
+                summary += &format!("  {hits:>6}  {rule}\n");
+            }
+            summary += "\n";
+        }
+        summary.trim_end().to_string()
+    }
+
+    /// Writes the all-time stats to disk.
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        if self.protected_since.is_some() {
+            self.flush_protected_time();
+        }
+        self.all_time.save()
+    }
+}
+
+impl Stats {
+    /// Counts a blocked request attributed to the rule.
+    pub fn record_blocked(&mut self, rule: Option<&str>) {
+        if is_ad(rule) {
+            self.ads_blocked += 1;
+        }
+        if let Some(rule) = rule {
+            *self.rule_hits.entry(rule.to_string()).or_default() += 1;
+        }
+    }
+
+    /// The rules with the most hits, most hit first.
+    pub fn top_rules(&self) -> Vec<(&str, u64)> {
+        let mut rule_hits = self
+            .rule_hits
+            .iter()
+            .map(|(rule, &hits)| (rule.as_str(), hits))
+            .collect::<Vec<_>>();
+        rule_hits.sort_by(|a, b| b.1.cmp(&a.1));
+        rule_hits.truncate(TOP_RULE_COUNT);
+        rule_hits
+    }
+
+    /// Estimated time of ads Spotify would have played during the ad-free listening time.
+    pub fn ads_avoided(&self) -> Duration {
+        Duration::from_secs(self.ad_free_secs * AD_SECS_PER_HOUR / 3600)
+    }
+
+    fn load() -> Self {
+        let Some(path) = paths::stats_file(&System) else {
+            return Self::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => match toml::from_str(&contents) {
+                Ok(stats) => {
+                    debug!("Loaded stats from '{}'", path.display());
+                    stats
+                }
+                Err(e) => {
+                    warn!("Failed to parse stats, starting over: {e}");
+                    Self::default()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                warn!("Failed to read stats, starting over: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = paths::stats_file(&System).context("Failed to locate app data directory.")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create stats directory.")?;
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize stats.")?;
+        fs::write(&path, contents).context("Failed to write stats.")?;
+        Ok(())
+    }
+}
+
+/// Whether a blocked request attributed to the rule was an ad.
+pub fn is_ad(rule: Option<&str>) -> bool {
+    rule.is_some_and(|rule| rule != NOT_ALLOWLISTED)
+}
+
+/// Counts a blocked request and celebrates the milestone reached by it. Called where the requests
+/// are handled, so that no request is missed when many are blocked at once.
+pub fn record_blocked(rule: Option<&str>, time: SystemTime) {
+    let milestone = get().record_blocked(rule, time);
+    if let Some(milestone) = milestone {
+        notify_milestone(milestone);
+    }
+}
+
+/// Shows a notification for a reached milestone unless disabled in the settings.
+fn notify_milestone(milestone: u64) {
+    info!("Reached {milestone} blocked ads");
+    if settings::get().milestone_notifications {
+        notify::info(
+            tr(Msg::MilestoneReached),
+            &tr_args(Msg::MilestoneMessage, &[("count", &milestone)]),
+        );
+    }
+}
+
+/// Saves the all-time stats every [`SAVE_INTERVAL`].
+pub async fn save_periodically() {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = get().save() {
+            warn!("Failed to save stats: {e:#}");
+        }
+    }
+}
+
+fn minute_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 60)
+}
+
+/// Formats a duration as hours and minutes.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
@@ -0,0 +1,109 @@
+use std::{
+    fmt, mem,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use burnt_sushi_core::{
+    conflicts::Conflict,
+    error::FailureReason,
+    injector::LoadedBlocker,
+    rpc::{Fingerprint, PerfStats},
+};
+use chrono::{DateTime, Local};
+
+use crate::events::{self, AppEvent};
+
+static STATUS: Mutex<AppStatus> = Mutex::new(AppStatus::new());
+
+pub fn get() -> MutexGuard<'static, AppStatus> {
+    STATUS.lock().unwrap()
+}
+
+/// Updates the hook status and publishes it if it changed.
+pub fn set_hook(hook: HookStatus) {
+    let previous = mem::replace(&mut get().hook, hook);
+    if previous != hook {
+        events::publish(AppEvent::StatusChanged(hook));
+    }
+}
+
+/// Snapshot of the app state shared with the tray, diagnostics and the control channel.
+#[derive(Debug, Clone)]
+pub struct AppStatus {
+    pub hook: HookStatus,
+    pub spotify: Option<SpotifyStatus>,
+    /// Version reported by the injected blocker.
+    pub blocker_version: Option<String>,
+    /// Overhead of the blocker as of the last health check.
+    pub blocker_perf: Option<PerfStats>,
+    /// When hooking is attempted again after it failed.
+    pub next_retry: Option<DateTime<Local>>,
+    /// Blockers left in other processes as of the last sweep.
+    pub stray_blockers: Vec<LoadedBlocker>,
+    /// Known ad endpoints the rules did not block as of the last canary check.
+    pub uncovered_canaries: Vec<String>,
+    /// Most recent failure to hook Spotify.
+    pub last_error: Option<LastError>,
+}
+
+impl AppStatus {
+    pub const fn new() -> Self {
+        Self {
+            hook: HookStatus::Searching,
+            spotify: None,
+            blocker_version: None,
+            blocker_perf: None,
+            next_retry: None,
+            stray_blockers: Vec::new(),
+            uncovered_canaries: Vec::new(),
+            last_error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LastError {
+    pub message: String,
+    pub time: DateTime<Local>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStatus {
+    Searching,
+    /// Spotify was found but the blocker is not prepared yet.
+    Preparing,
+    Hooking,
+    Hooked,
+    Paused,
+    /// Spotify is hooked by another instance started with `--ignore-singleton`, or the one such an
+    /// instance runs alongside.
+    OtherInstance,
+    /// Hooking failed and is retried on a schedule.
+    Unavailable(FailureReason),
+}
+
+impl fmt::Display for HookStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookStatus::Searching => write!(f, "Looking for Spotify"),
+            HookStatus::Preparing => write!(f, "Preparing blocker"),
+            HookStatus::Hooking => write!(f, "Hooking Spotify"),
+            HookStatus::Hooked => write!(f, "Blocking"),
+            HookStatus::Paused => write!(f, "Paused"),
+            HookStatus::OtherInstance => write!(f, "Blocked by another instance"),
+            HookStatus::Unavailable(reason) => write!(f, "Not blocking ({reason})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SpotifyStatus {
+    pub pid: Option<u32>,
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+    /// Other modifications of Spotify found before hooking it.
+    pub conflicts: Vec<Conflict>,
+    /// Versions of the internals hooked by the blocker, if it reports them.
+    pub internals: Option<Fingerprint>,
+}
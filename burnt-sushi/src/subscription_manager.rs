@@ -0,0 +1,169 @@
+//! Window opened from the tray to add and remove the remote filter lists and to refresh them.
+
+use std::{cell::RefCell, sync::Mutex};
+
+use native_windows_derive as nwd;
+use native_windows_gui as nwg;
+
+use log::error;
+use nwd::NwgUi;
+use nwg::NativeUi;
+
+use crate::{
+    filter_providers::subscriptions::{self, Subscription},
+    i18n::{tr, tr_args, Msg},
+};
+
+thread_local! {
+    /// Window built on the tray thread, closing it only hides it.
+    static WINDOW: RefCell<Option<subscription_manager_ui::SubscriptionManagerUi>> =
+        const { RefCell::new(None) };
+}
+
+/// Notifies the open window that the lists were loaded, so that it shows their new state.
+static UPDATED_NOTICE: Mutex<Option<nwg::NoticeSender>> = Mutex::new(None);
+
+/// Shows the window, building it on first use. Must be called on the tray thread.
+pub fn open() {
+    WINDOW.with(|ui| {
+        let mut ui = ui.borrow_mut();
+        if let Some(manager) = &*ui {
+            manager.populate();
+            manager.window.set_visible(true);
+            manager.window.set_focus();
+            return;
+        }
+        match SubscriptionManager::build_ui(SubscriptionManager::default()) {
+            Ok(manager) => {
+                manager.populate();
+                *UPDATED_NOTICE.lock().unwrap() = Some(manager.updated_notice.sender());
+                *ui = Some(manager);
+            }
+            Err(e) => error!("Failed to open subscription manager: {e}"),
+        }
+    });
+}
+
+/// Called after the filter lists were loaded.
+pub fn lists_updated() {
+    if let Some(notice) = *UPDATED_NOTICE.lock().unwrap() {
+        notice.notice();
+    }
+}
+
+#[derive(NwgUi, Default)]
+pub struct SubscriptionManager {
+    #[nwg_control(size: (640, 360), center: true, title: tr(Msg::TrayManageSubscriptions), flags: "WINDOW|VISIBLE")]
+    window: nwg::Window,
+
+    #[nwg_layout(parent: window, spacing: 4)]
+    layout: nwg::GridLayout,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [SubscriptionManager::populate])]
+    updated_notice: nwg::Notice,
+
+    #[nwg_control]
+    #[nwg_layout_item(layout: layout, row: 0, col: 0, col_span: 4, row_span: 6)]
+    list: nwg::ListBox<String>,
+
+    #[nwg_control(placeholder_text: Some("https://"))]
+    #[nwg_layout_item(layout: layout, row: 6, col: 0, col_span: 3)]
+    url_input: nwg::TextInput,
+
+    #[nwg_control(text: tr(Msg::SubscriptionAdd))]
+    #[nwg_layout_item(layout: layout, row: 6, col: 3)]
+    #[nwg_events(OnButtonClick: [SubscriptionManager::add])]
+    add_button: nwg::Button,
+
+    #[nwg_control(text: tr(Msg::SubscriptionRemove))]
+    #[nwg_layout_item(layout: layout, row: 7, col: 0)]
+    #[nwg_events(OnButtonClick: [SubscriptionManager::remove])]
+    remove_button: nwg::Button,
+
+    #[nwg_control(text: tr(Msg::SubscriptionRefresh))]
+    #[nwg_layout_item(layout: layout, row: 7, col: 1)]
+    #[nwg_events(OnButtonClick: [SubscriptionManager::refresh])]
+    refresh_button: nwg::Button,
+
+    #[nwg_control(text: tr(Msg::SubscriptionClose))]
+    #[nwg_layout_item(layout: layout, row: 7, col: 3)]
+    #[nwg_events(OnButtonClick: [SubscriptionManager::close])]
+    close_button: nwg::Button,
+
+    /// Urls of the lists in the order they are shown.
+    urls: RefCell<Vec<String>>,
+}
+
+impl SubscriptionManager {
+    fn populate(&self) {
+        let subscriptions = subscriptions::list();
+        self.list
+            .set_collection(subscriptions.iter().map(describe).collect());
+        self.remove_button.set_enabled(!subscriptions.is_empty());
+        *self.urls.borrow_mut() = subscriptions
+            .into_iter()
+            .map(|subscription| subscription.url)
+            .collect();
+    }
+
+    fn add(&self) {
+        match subscriptions::add(&self.url_input.text()) {
+            Ok(()) => {
+                self.url_input.set_text("");
+                self.populate();
+            }
+            Err(e) => self.show_error(&e),
+        }
+    }
+
+    fn remove(&self) {
+        let Some(index) = self.list.selection() else {
+            return;
+        };
+        let Some(url) = self.urls.borrow().get(index).cloned() else {
+            return;
+        };
+        if let Err(e) = subscriptions::remove(&url) {
+            self.show_error(&e);
+        }
+        self.populate();
+    }
+
+    fn refresh(&self) {
+        subscriptions::refresh();
+    }
+
+    fn close(&self) {
+        self.window.set_visible(false);
+    }
+
+    fn show_error(&self, error: &anyhow::Error) {
+        nwg::modal_error_message(
+            &self.window,
+            tr(Msg::TrayManageSubscriptions),
+            &format!("{error:#}"),
+        );
+    }
+}
+
+/// Line of the list, e.g. `https://example.com/list.txt  (120 rules, refreshed 2024-07-01 12:00)`.
+fn describe(subscription: &Subscription) -> String {
+    let mut status = match (subscription.rule_count, subscription.last_refresh) {
+        (Some(count), Some(last_refresh)) => {
+            let time = last_refresh.format("%Y-%m-%d %H:%M");
+            tr_args(
+                Msg::SubscriptionStatus,
+                &[("count", &count), ("time", &time)],
+            )
+        }
+        _ => tr(Msg::SubscriptionNotDownloaded).to_string(),
+    };
+    if let Some(error) = &subscription.error {
+        status += &format!(
+            ", {}",
+            tr_args(Msg::SubscriptionFailing, &[("error", error)])
+        );
+    }
+    format!("{}  ({status})", subscription.url)
+}
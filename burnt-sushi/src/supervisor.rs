@@ -0,0 +1,72 @@
+//! Watchdog started with `--supervise`. It runs the app as a child process and restarts it when it
+//! exits abnormally, e.g. after a panic or crash, so that an autostarted instance recovers on its
+//! own. A normal exit, e.g. from the tray or after a handoff, also ends the watchdog. An update
+//! restarts a supervised instance under a new watchdog instead.
+
+use std::{
+    env,
+    ffi::OsString,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+
+/// Delay before the first restart, doubled after every crash up to [`MAX_BACKOFF`].
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// A child that ran this long counts as recovered, so the backoff starts over.
+const STABLE_RUNTIME: Duration = Duration::from_secs(10 * 60);
+/// Crashes in a row after which the watchdog gives up.
+const MAX_RESTARTS: u32 = 10;
+
+/// Runs the app until it exits normally. Blocks the calling thread.
+pub fn run() {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!("Failed to locate current executable: {e}");
+            return;
+        }
+    };
+    // Other instances were already terminated by the watchdog.
+    let args = env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "--supervise" && arg != "--supervised" && arg != "--force-restart")
+        .collect::<Vec<OsString>>();
+
+    let mut backoff = INITIAL_BACKOFF;
+    let mut restarts = 0;
+    loop {
+        let started = Instant::now();
+        let status = match Command::new(&exe).args(&args).arg("--supervised").status() {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Failed to start supervised instance: {e}");
+                return;
+            }
+        };
+        if status.success() {
+            info!("Supervised instance exited");
+            return;
+        }
+
+        if started.elapsed() >= STABLE_RUNTIME {
+            backoff = INITIAL_BACKOFF;
+            restarts = 0;
+        }
+        restarts += 1;
+        if restarts > MAX_RESTARTS {
+            error!("Supervised instance crashed {MAX_RESTARTS} times in a row, giving up");
+            return;
+        }
+
+        warn!(
+            "Supervised instance exited abnormally ({status}), restarting in {}s",
+            backoff.as_secs()
+        );
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
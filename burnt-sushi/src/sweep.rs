@@ -0,0 +1,107 @@
+//! Periodic search for blockers loaded into processes other than the hooked Spotify, e.g. because
+//! Spotify passed the blocker on to a child process or an instance crashed while hooking.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use burnt_sushi_core::{
+    hook_claim::HookClaim,
+    injector::{self, LoadedBlocker},
+    DEFAULT_BLOCKER_FILE_NAME, UPDATED_BLOCKER_FILE_NAME,
+};
+use dll_syringe::{process::OwnedProcess, Syringe};
+use log::{debug, info, warn};
+
+use crate::{args::ARGS, session, settings, status};
+
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Looks for blockers in the processes of this session other than `hooked_pid` and ejects them if
+/// enabled. Blockers left in place are listed in the diagnostics report.
+pub async fn run(hooked_pid: Option<u32>) {
+    let file_names = blocker_file_names();
+    let eject = settings::get().eject_stray_blockers;
+
+    let strays = tokio::task::spawn_blocking(move || {
+        let session = session::current_id();
+        let strays = injector::sweep(&file_names)
+            .into_iter()
+            .filter(|blocker| Some(blocker.pid) != hooked_pid)
+            // Claimed by another instance, e.g. one started with `--ignore-singleton`.
+            .filter(|blocker| !matches!(HookClaim::try_acquire(blocker.pid), Ok(None)))
+            // Instances in other sessions hook their own Spotify.
+            .filter(|blocker| session::of_process(blocker.pid).is_ok_and(|id| id == session))
+            .collect::<Vec<_>>();
+        debug!("Blocker sweep found {} stray blockers", strays.len());
+
+        let mut remaining = Vec::new();
+        for blocker in strays {
+            warn!("Found blocker outside the hooked Spotify: {blocker}");
+            if !eject {
+                remaining.push(blocker);
+                continue;
+            }
+            match eject(&blocker) {
+                Ok(()) => info!("Ejected stray blocker from PID={}", blocker.pid),
+                Err(e) => {
+                    warn!("{e:#}");
+                    remaining.push(blocker);
+                }
+            }
+        }
+        remaining
+    })
+    .await;
+
+    match strays {
+        Ok(strays) => status::get().stray_blockers = strays,
+        Err(e) => warn!("Blocker sweep failed: {e}"),
+    }
+}
+
+/// Ejects the blockers from all processes that can be inspected, for `eject-all` and uninstalling.
+/// Returns how many were ejected, failures are logged and reported once all were tried.
+pub fn eject_all() -> anyhow::Result<usize> {
+    let blockers = injector::sweep(&blocker_file_names());
+    debug!("Found {} blockers to eject", blockers.len());
+
+    let mut ejected = 0;
+    for blocker in &blockers {
+        match eject(blocker) {
+            Ok(()) => {
+                info!("Ejected blocker from {blocker}");
+                ejected += 1;
+            }
+            Err(e) => warn!("{e:#}"),
+        }
+    }
+    if ejected < blockers.len() {
+        anyhow::bail!(
+            "Failed to eject {} of {} blockers.",
+            blockers.len() - ejected,
+            blockers.len()
+        );
+    }
+    Ok(ejected)
+}
+
+/// File names of the modules checked for the blocker marker, including blockers installed by a
+/// blocker-only update.
+fn blocker_file_names() -> Vec<String> {
+    let mut file_names = vec![
+        DEFAULT_BLOCKER_FILE_NAME.to_string(),
+        UPDATED_BLOCKER_FILE_NAME.to_string(),
+    ];
+    if let Some(file_name) = ARGS.blocker.as_deref().and_then(Path::file_name) {
+        file_names.push(file_name.to_string_lossy().into_owned());
+    }
+    file_names
+}
+
+/// Stops the RPC of the blocker if it still runs and ejects it.
+fn eject(blocker: &LoadedBlocker) -> anyhow::Result<()> {
+    let process = OwnedProcess::from_pid(blocker.pid)
+        .with_context(|| format!("Failed to open process {}.", blocker.pid))?;
+    injector::eject_swept_blocker(&Syringe::for_process(process), blocker)
+        .with_context(|| format!("Failed to eject blocker from {blocker}."))
+}
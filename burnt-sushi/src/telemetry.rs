@@ -0,0 +1,333 @@
+//! Opt-in reports of how well blocking works, which tell which Spotify versions break the filters.
+//! Reports only go to a configured `telemetry-endpoint`, there is no default collector. Only
+//! aggregate counters per pair of Spotify and blocker version are sent, never urls, rules or
+//! anything identifying the user. `telemetry preview` prints exactly what the next report contains.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    sync::{LazyLock, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{environment::System, paths, settings, stats, status, APP_VERSION};
+
+/// Interval between reports. The counters are saved, so a report that became due while the app
+/// was not running is sent after the next start.
+const REPORT_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::load()));
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap()
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Time of the last successful report.
+    last_report: Option<DateTime<Local>>,
+    /// Counters since the last report by Spotify and blocker version.
+    counters: BTreeMap<Versions, Counters>,
+}
+
+/// [`State`] as it is saved to disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct SavedState {
+    last_report: Option<DateTime<Local>>,
+    counters: Vec<VersionCounters>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Versions {
+    spotify_version: Option<String>,
+    blocker_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct Counters {
+    ads_blocked: u64,
+    hooks: u64,
+    hook_failures: u64,
+    /// Ads Spotify played while it was hooked.
+    ad_slips: u64,
+}
+
+impl Counters {
+    /// Removes counts that were sent, keeping those that were added meanwhile.
+    fn subtract(&mut self, sent: Counters) {
+        self.ads_blocked = self.ads_blocked.saturating_sub(sent.ads_blocked);
+        self.hooks = self.hooks.saturating_sub(sent.hooks);
+        self.hook_failures = self.hook_failures.saturating_sub(sent.hook_failures);
+        self.ad_slips = self.ad_slips.saturating_sub(sent.ad_slips);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ads_blocked == 0 && self.hooks == 0 && self.hook_failures == 0 && self.ad_slips == 0
+    }
+}
+
+impl State {
+    fn load() -> Self {
+        let Some(path) = paths::telemetry_file(&System) else {
+            return Self::default();
+        };
+
+        let saved = match fs::read_to_string(&path) {
+            Ok(contents) => match toml::from_str::<SavedState>(&contents) {
+                Ok(saved) => saved,
+                Err(e) => {
+                    warn!("Failed to parse telemetry counters, starting over: {e}");
+                    SavedState::default()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SavedState::default(),
+            Err(e) => {
+                warn!("Failed to read telemetry counters, starting over: {e}");
+                SavedState::default()
+            }
+        };
+        Self {
+            last_report: saved.last_report,
+            counters: saved
+                .counters
+                .into_iter()
+                .map(|saved| (saved.versions, saved.counters))
+                .collect(),
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path =
+            paths::telemetry_file(&System).context("Failed to locate app data directory.")?;
+        if self.counters.is_empty() && self.last_report.is_none() {
+            // Nothing to keep, e.g. as telemetry is disabled.
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    Err(e).context("Failed to remove telemetry counters.")
+                }
+                _ => Ok(()),
+            };
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create telemetry directory.")?;
+        }
+        let saved = SavedState {
+            last_report: self.last_report,
+            counters: version_counters(&self.counters),
+        };
+        let contents =
+            toml::to_string_pretty(&saved).context("Failed to serialize telemetry counters.")?;
+        fs::write(&path, contents).context("Failed to write telemetry counters.")?;
+        Ok(())
+    }
+
+    /// Whether the next report should be sent, which is right away if none was sent yet.
+    fn report_due(&self) -> bool {
+        match self.last_report {
+            // A last report in the future means the clock was changed.
+            Some(last_report) => (Local::now() - last_report)
+                .to_std()
+                .map_or(true, |elapsed| elapsed >= REPORT_INTERVAL),
+            None => true,
+        }
+    }
+}
+
+/// Data uploaded in a report.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Payload {
+    app_version: &'static str,
+    interval_hours: u64,
+    counters: Vec<VersionCounters>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionCounters {
+    #[serde(flatten)]
+    versions: Versions,
+    #[serde(flatten)]
+    counters: Counters,
+}
+
+/// Whether telemetry is turned on and there is an endpoint to send it to.
+fn is_enabled() -> bool {
+    settings::get().telemetry && endpoint().is_some()
+}
+
+/// Configured endpoint, nothing is collected or sent without one.
+fn endpoint() -> Option<String> {
+    settings::get()
+        .telemetry_endpoint
+        .clone()
+        .filter(|endpoint| !endpoint.trim().is_empty())
+}
+
+/// Turns telemetry on or off and saves the setting. Counters collected so far are dropped when it
+/// is turned off.
+pub fn set_enabled(enabled: bool) -> anyhow::Result<()> {
+    let mut settings = settings::get();
+    settings.telemetry = enabled;
+    settings.save()?;
+    drop(settings);
+
+    let mut state = state();
+    if enabled {
+        // The first report covers a full interval.
+        state.last_report.get_or_insert_with(Local::now);
+    } else {
+        state.counters.clear();
+        state.last_report = None;
+    }
+    drop(state);
+    save();
+    info!("Telemetry {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
+/// Counts a blocked request if it was an ad.
+pub fn record_blocked(rule: Option<&str>) {
+    if stats::is_ad(rule) {
+        record(|counters| counters.ads_blocked += 1);
+    }
+}
+
+/// Counts whether hooking the found Spotify succeeded after all attempts.
+pub fn record_hook(hooked: bool) {
+    if hooked {
+        record(|counters| counters.hooks += 1);
+    } else {
+        record(|counters| counters.hook_failures += 1);
+    }
+}
+
+/// Counts an ad played by the hooked Spotify.
+pub fn record_ad_slip() {
+    record(|counters| counters.ad_slips += 1);
+}
+
+/// Updates the counters of the current Spotify and blocker version while telemetry is enabled.
+fn record(count: impl FnOnce(&mut Counters)) {
+    if !is_enabled() {
+        return;
+    }
+    let versions = {
+        let status = status::get();
+        Versions {
+            spotify_version: status
+                .spotify
+                .as_ref()
+                .and_then(|spotify| spotify.version.clone()),
+            blocker_version: status.blocker_version.clone(),
+        }
+    };
+    count(state().counters.entry(versions).or_default());
+}
+
+fn version_counters(counters: &BTreeMap<Versions, Counters>) -> Vec<VersionCounters> {
+    counters
+        .iter()
+        .map(|(versions, counters)| VersionCounters {
+            versions: versions.clone(),
+            counters: *counters,
+        })
+        .collect()
+}
+
+fn payload(counters: &BTreeMap<Versions, Counters>) -> Payload {
+    Payload {
+        app_version: APP_VERSION,
+        interval_hours: REPORT_INTERVAL.as_secs() / 3600,
+        counters: version_counters(counters),
+    }
+}
+
+/// Describes whether telemetry is enabled and exactly what the next report would contain.
+pub fn preview() -> anyhow::Result<String> {
+    if !settings::get().telemetry {
+        return Ok(
+            "Telemetry is disabled, nothing is collected or sent. Enable it with `telemetry enable`."
+                .to_string(),
+        );
+    }
+    let Some(endpoint) = endpoint() else {
+        return Ok(
+            "Telemetry is enabled but no telemetry-endpoint is configured, nothing is collected or sent."
+                .to_string(),
+        );
+    };
+    let payload = payload(&state().counters);
+    let payload =
+        serde_json::to_string_pretty(&payload).context("Failed to serialize telemetry.")?;
+    Ok(format!(
+        "The following is sent to {endpoint} at most every {} hours, disable it with `telemetry disable`:\n{payload}",
+        REPORT_INTERVAL.as_secs() / 3600
+    ))
+}
+
+/// Uploads the counters since the last report if there are any. Counters that could not be sent
+/// are kept for the next report.
+async fn send() -> anyhow::Result<()> {
+    let counters = state().counters.clone();
+    if counters.is_empty() {
+        debug!("Nothing to report");
+    } else {
+        upload(&payload(&counters)).await?;
+    }
+
+    let mut state = state();
+    for (versions, sent) in counters {
+        if let Some(current) = state.counters.get_mut(&versions) {
+            current.subtract(sent);
+            if current.is_empty() {
+                state.counters.remove(&versions);
+            }
+        }
+    }
+    state.last_report = Some(Local::now());
+    Ok(())
+}
+
+async fn upload(payload: &Payload) -> anyhow::Result<()> {
+    let endpoint = endpoint().context("No telemetry-endpoint is configured.")?;
+    reqwest::Client::new()
+        .post(&endpoint)
+        .json(payload)
+        .send()
+        .await
+        .context("Failed to upload telemetry.")?
+        .error_for_status()
+        .context("Telemetry was rejected.")?;
+    Ok(())
+}
+
+/// Writes the counters that were not sent yet to disk.
+pub fn save() {
+    if let Err(e) = state().save() {
+        warn!("Failed to save telemetry counters: {e:#}");
+    }
+}
+
+/// Sends a report every [`REPORT_INTERVAL`] while telemetry is enabled, starting with one that
+/// became due while the app was not running, and saves the counters in between.
+pub async fn run() {
+    let mut interval = tokio::time::interval(stats::SAVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if is_enabled() && state().report_due() {
+            match send().await {
+                Ok(()) => debug!("Sent telemetry"),
+                Err(e) => warn!("Failed to send telemetry: {e:#}"),
+            }
+        }
+        save();
+    }
+}
@@ -1,24 +1,47 @@
 use std::{
+    cell::RefCell,
+    ptr,
     sync::atomic::{AtomicBool, Ordering},
     thread,
+    time::{Duration, Instant},
 };
 
 use native_windows_derive as nwd;
 use native_windows_gui as nwg;
 
+use log::{debug, error, info, warn};
 use nwd::NwgUi;
 use nwg::NativeUi;
-use winapi::um::{
-    processthreadsapi::GetCurrentThreadId,
-    winuser::{PostThreadMessageW, WM_QUIT},
+use u16cstr::u16cstr;
+use winapi::{
+    shared::minwindef::TRUE,
+    um::{
+        processthreadsapi::GetCurrentThreadId,
+        winuser::{
+            ChangeWindowMessageFilterEx, FindWindowW, PostThreadMessageW, RegisterWindowMessageW,
+            WM_ENDSESSION, WM_POWERBROADCAST, WM_QUERYENDSESSION, WM_QUIT, WM_WTSSESSION_CHANGE,
+        },
+    },
 };
 
 use crate::{
-    logger::{self, Console},
-    APP_NAME,
+    accessibility,
+    args::LogLevel,
+    blocker, diagnostics,
+    i18n::{tr, tr_args, Msg},
+    logger, notify,
+    power::{self, PowerNotifications},
+    session, settings, stats,
+    status::{self, HookStatus},
+    subscription_manager, telemetry, update, user_rules, APP_NAME,
 };
 
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
+static TASKBAR_CREATED: AtomicBool = AtomicBool::new(false);
+
+const SHELL_MESSAGES_HANDLER_ID: usize = 0x10000;
+// Missing from winapi.
+const MSGFLT_ALLOW: u32 = 1;
 
 pub struct SystemTrayManager {
     ui_thread: Option<thread::JoinHandle<()>>,
@@ -36,19 +59,48 @@ impl SystemTrayManager {
         let (exit_tx, exit_rx) = tokio::sync::watch::channel(false);
 
         let ui_thread = thread::spawn(move || {
-            let _tray_icon = match SystemTrayIcon::build_ui(SystemTrayIcon::default()) {
-                Ok(tray_icon) => tray_icon,
-                Err(err) => {
-                    start_tx.send(Err(err)).unwrap();
-                    exit_tx.send(true).unwrap();
-                    return;
+            let mut start_tx = Some(start_tx);
+
+            // The ui is rebuilt whenever the taskbar is re-created, e.g. after Explorer crashed.
+            loop {
+                let tray_icon = match SystemTrayIcon::build_ui(SystemTrayIcon::default()) {
+                    Ok(tray_icon) => tray_icon,
+                    Err(err) => {
+                        match start_tx.take() {
+                            Some(start_tx) => start_tx.send(Err(err)).unwrap(),
+                            None => error!("Failed to re-create tray icon: {err}"),
+                        }
+                        break;
+                    }
+                };
+                let shell_messages_handler = bind_shell_messages(&tray_icon.shell_window);
+                let power_notifications = tray_icon
+                    .shell_window
+                    .handle
+                    .hwnd()
+                    .map(PowerNotifications::register);
+                tray_icon.update_tip();
+                accessibility::set_tray_notice(Some(tray_icon.status_notice.sender()));
+
+                if let Some(start_tx) = start_tx.take() {
+                    let thread_id = unsafe { GetCurrentThreadId() };
+                    start_tx.send(Ok(thread_id)).unwrap();
                 }
-            };
 
-            let thread_id = unsafe { GetCurrentThreadId() };
-            start_tx.send(Ok(thread_id)).unwrap();
+                nwg::dispatch_thread_events();
 
-            nwg::dispatch_thread_events();
+                accessibility::set_tray_notice(None);
+                drop(power_notifications);
+                if let Some(handler) = shell_messages_handler {
+                    let _ = nwg::unbind_raw_event_handler(&handler);
+                }
+                drop(tray_icon);
+
+                if !TASKBAR_CREATED.swap(false, Ordering::SeqCst) {
+                    break;
+                }
+                info!("Taskbar was re-created, restoring tray icon");
+            }
 
             exit_tx.send(true).unwrap();
         });
@@ -78,11 +130,148 @@ impl SystemTrayManager {
     }
 }
 
+/// Hidden top-level window handling the shell broadcasts when running without a tray icon, so that
+/// the session end still waits for Spotify to be unhooked and power and lock changes are tracked.
+pub struct ShellWindowManager {
+    ui_thread: Option<thread::JoinHandle<()>>,
+    ui_thread_id: u32,
+}
+
+impl ShellWindowManager {
+    pub async fn build_and_run() -> Result<Self, nwg::NwgError> {
+        if !INITIALIZED.swap(true, Ordering::SeqCst) {
+            nwg::init()?;
+        }
+
+        let (start_tx, start_rx) = tokio::sync::oneshot::channel();
+        let ui_thread = thread::spawn(move || {
+            let mut window = nwg::Window::default();
+            let result = nwg::Window::builder()
+                .flags(nwg::WindowFlags::WINDOW)
+                .title(APP_NAME)
+                .build(&mut window);
+            if let Err(err) = result {
+                start_tx.send(Err(err)).unwrap();
+                return;
+            }
+            let shell_messages_handler = bind_shell_messages(&window);
+            let power_notifications = window.handle.hwnd().map(PowerNotifications::register);
+            start_tx.send(Ok(unsafe { GetCurrentThreadId() })).unwrap();
+
+            loop {
+                nwg::dispatch_thread_events();
+                // Only stopped early to rebuild the tray icon, which does not exist.
+                if !TASKBAR_CREATED.swap(false, Ordering::SeqCst) {
+                    break;
+                }
+            }
+
+            drop(power_notifications);
+            if let Some(handler) = shell_messages_handler {
+                let _ = nwg::unbind_raw_event_handler(&handler);
+            }
+        });
+
+        Ok(Self {
+            ui_thread: Some(ui_thread),
+            ui_thread_id: start_rx.await.unwrap()?,
+        })
+    }
+
+    pub async fn exit(mut self) {
+        unsafe { PostThreadMessageW(self.ui_thread_id, WM_QUIT, 0, 0) };
+        if let Some(ui_thread) = self.ui_thread.take() {
+            let _ = tokio::task::spawn_blocking(move || ui_thread.join()).await;
+        }
+    }
+}
+
+/// Waits until the taskbar exists so that the tray icon can be added, or until the timeout expires.
+pub async fn wait_for_shell(timeout: Duration) {
+    let start = Instant::now();
+    while unsafe { FindWindowW(u16cstr!("Shell_TrayWnd").as_ptr(), ptr::null()) }.is_null() {
+        if start.elapsed() >= timeout {
+            warn!("Taskbar did not appear in time");
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    debug!("Taskbar is ready after {:?}", start.elapsed());
+}
+
+/// Adds a rule for the host picked from the block host menu.
+fn block_host(host: &str) {
+    match user_rules::block_host(host) {
+        Ok(()) => notify::info(
+            tr(Msg::TrayBlockRecentHost),
+            &tr_args(Msg::HostBlocked, &[("host", &host)]),
+        ),
+        Err(e) => {
+            error!("Failed to block '{host}': {e:#}");
+            notify::error(tr(Msg::TrayBlockRecentHost), &format!("{e:#}"));
+        }
+    }
+}
+
+/// Handles broadcasts to top-level windows:
+/// stops the ui loop for a rebuild once the taskbar was re-created and
+/// delays the session end until Spotify was unhooked and
+/// tracks battery saver and session lock changes.
+fn bind_shell_messages(window: &nwg::Window) -> Option<nwg::RawEventHandler> {
+    let taskbar_created = unsafe { RegisterWindowMessageW(u16cstr!("TaskbarCreated").as_ptr()) };
+    if taskbar_created == 0 {
+        warn!("Failed to register TaskbarCreated message");
+        return None;
+    }
+
+    // Allow the broadcast through UIPI when running elevated.
+    if let Some(hwnd) = window.handle.hwnd() {
+        unsafe {
+            ChangeWindowMessageFilterEx(hwnd, taskbar_created, MSGFLT_ALLOW, ptr::null_mut())
+        };
+    }
+
+    let result = nwg::bind_raw_event_handler(
+        &window.handle,
+        SHELL_MESSAGES_HANDLER_ID,
+        move |_hwnd, msg, w, l| match msg {
+            _ if msg == taskbar_created => {
+                TASKBAR_CREATED.store(true, Ordering::SeqCst);
+                nwg::stop_thread_dispatch();
+                None
+            }
+            WM_QUERYENDSESSION => Some(TRUE as _),
+            WM_ENDSESSION => {
+                if w != 0 {
+                    session::end_session_blocking();
+                }
+                Some(0)
+            }
+            WM_POWERBROADCAST | WM_WTSSESSION_CHANGE => {
+                power::handle_message(msg, w, l);
+                None
+            }
+            _ => None,
+        },
+    );
+    match result {
+        Ok(handler) => Some(handler),
+        Err(e) => {
+            warn!("Failed to listen for shell messages: {e}");
+            None
+        }
+    }
+}
+
 #[derive(NwgUi, Default)]
 pub struct SystemTrayIcon {
     #[nwg_control]
     window: nwg::MessageWindow,
 
+    /// Hidden top-level window, as message-only windows do not receive broadcasts.
+    #[nwg_control(flags: "WINDOW", title: APP_NAME)]
+    shell_window: nwg::Window,
+
     #[nwg_resource]
     embed: nwg::EmbedResource,
 
@@ -93,14 +282,76 @@ pub struct SystemTrayIcon {
     #[nwg_events(MousePressLeftUp: [SystemTrayIcon::show_menu], OnContextMenu: [SystemTrayIcon::show_menu])]
     tray: nwg::TrayNotification,
 
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [SystemTrayIcon::update_tip])]
+    status_notice: nwg::Notice,
+
     #[nwg_control(parent: window, popup: true)]
     tray_menu: nwg::Menu,
 
-    #[nwg_control(parent: tray_menu, text: "Show Console")]
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayShowConsole))]
     #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::show_console])]
     tray_item2: nwg::MenuItem,
 
-    #[nwg_control(parent: tray_menu, text: "Exit")]
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayLogLevel))]
+    tray_log_level_menu: nwg::Menu,
+
+    #[nwg_control(parent: tray_log_level_menu, text: "Off")]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::select_log_level(SELF, CTRL)])]
+    tray_log_level_off: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_log_level_menu, text: "Error")]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::select_log_level(SELF, CTRL)])]
+    tray_log_level_error: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_log_level_menu, text: "Warn")]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::select_log_level(SELF, CTRL)])]
+    tray_log_level_warn: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_log_level_menu, text: "Info")]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::select_log_level(SELF, CTRL)])]
+    tray_log_level_info: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_log_level_menu, text: "Debug")]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::select_log_level(SELF, CTRL)])]
+    tray_log_level_debug: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_log_level_menu, text: "Trace")]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::select_log_level(SELF, CTRL)])]
+    tray_log_level_trace: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayPauseUntilRestart))]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::pause_until_restart])]
+    tray_item_pause_until_restart: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayBlockRecentHost))]
+    tray_block_host_menu: nwg::Menu,
+
+    /// Items of the recently allowed hosts, built whenever the menu is opened.
+    block_host_items: RefCell<Vec<nwg::MenuItem>>,
+    block_host_handler: RefCell<Option<nwg::EventHandler>>,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayStatistics))]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::show_statistics])]
+    tray_item_statistics: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayShareStatistics))]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::toggle_telemetry])]
+    tray_item_telemetry: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayManageSubscriptions))]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::manage_subscriptions])]
+    tray_item_subscriptions: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayCopyDiagnostics))]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::copy_diagnostics])]
+    tray_item_diagnostics: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayCheckForUpdates))]
+    #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::check_for_updates])]
+    tray_item_update: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: tr(Msg::TrayExit))]
     #[nwg_events(OnMenuItemSelected: [SystemTrayIcon::exit])]
     tray_item3: nwg::MenuItem,
 }
@@ -110,19 +361,133 @@ impl SystemTrayIcon {
         nwg::stop_thread_dispatch();
     }
 
+    /// Shows the status in the tooltip, which is read out by screen readers.
+    fn update_tip(&self) {
+        let status = status::get().clone();
+        let mut tip = format!("{APP_NAME} - {}", accessibility::status_text(status.hook));
+        if let (HookStatus::Unavailable(_), Some(next_retry)) = (status.hook, status.next_retry) {
+            let time = next_retry.format("%H:%M");
+            tip += &format!(", {}", tr_args(Msg::StatusRetryAt, &[("time", &time)]));
+        }
+        if !status.uncovered_canaries.is_empty() {
+            let count = status.uncovered_canaries.len().to_string();
+            tip += &format!(
+                ", {}",
+                tr_args(Msg::StatusAdsUncovered, &[("count", &count)])
+            );
+        }
+        self.tray.set_tip(&tip);
+    }
+
     fn show_menu(&self) {
         let (x, y) = nwg::GlobalCursor::position();
 
         let log = logger::global::get();
         let has_console = log.console.is_some();
+        drop(log);
         self.tray_item2.set_enabled(!has_console);
+
+        let current_level = logger::global::level();
+        for (item, level) in self.log_level_items() {
+            item.set_checked(level == current_level);
+        }
+
+        self.build_block_host_menu();
+
+        self.tray_item_telemetry
+            .set_checked(settings::get().telemetry);
+
         self.tray_menu.popup(x, y);
     }
 
-    fn show_console(&self) {
-        let mut l = logger::global::get();
-        if l.console.is_none() {
-            l.console = Some(Console::piped().unwrap());
+    /// Replaces the items of the block host menu with the recently allowed hosts.
+    fn build_block_host_menu(&self) {
+        if let Some(handler) = self.block_host_handler.take() {
+            nwg::unbind_event_handler(&handler);
         }
+        // Dropping the items removes them from the menu.
+        let mut items = self.block_host_items.borrow_mut();
+        items.clear();
+
+        let hosts = user_rules::recent_allowed_hosts();
+        self.tray_block_host_menu.set_enabled(!hosts.is_empty());
+        let mut offered = Vec::new();
+        for host in hosts {
+            let mut item = nwg::MenuItem::default();
+            let result = nwg::MenuItem::builder()
+                .text(&host)
+                .parent(&self.tray_block_host_menu)
+                .build(&mut item);
+            match result {
+                Ok(()) => {
+                    offered.push((item.handle, host));
+                    items.push(item);
+                }
+                Err(e) => warn!("Failed to add '{host}' to the tray menu: {e}"),
+            }
+        }
+
+        let handler = nwg::full_bind_event_handler(&self.window.handle, move |event, _, handle| {
+            if event != nwg::Event::OnMenuItemSelected {
+                return;
+            }
+            if let Some((_, host)) = offered.iter().find(|(item, _)| *item == handle) {
+                block_host(host);
+            }
+        });
+        *self.block_host_handler.borrow_mut() = Some(handler);
+    }
+
+    fn log_level_items(&self) -> [(&nwg::MenuItem, LogLevel); 6] {
+        [
+            (&self.tray_log_level_off, LogLevel::Off),
+            (&self.tray_log_level_error, LogLevel::Error),
+            (&self.tray_log_level_warn, LogLevel::Warn),
+            (&self.tray_log_level_info, LogLevel::Info),
+            (&self.tray_log_level_debug, LogLevel::Debug),
+            (&self.tray_log_level_trace, LogLevel::Trace),
+        ]
+    }
+
+    fn select_log_level(&self, item: &nwg::MenuItem) {
+        if let Some((_, level)) = self
+            .log_level_items()
+            .into_iter()
+            .find(|(candidate, _)| candidate.handle == item.handle)
+        {
+            logger::global::set_level(level);
+        }
+    }
+
+    fn pause_until_restart(&self) {
+        blocker::pause_until_restart();
+    }
+
+    fn copy_diagnostics(&self) {
+        nwg::Clipboard::set_data_text(&self.window, &diagnostics::report());
+    }
+
+    fn show_statistics(&self) {
+        let summary = stats::get().summary();
+        nwg::simple_message(tr(Msg::TrayStatistics), &summary);
+    }
+
+    fn toggle_telemetry(&self) {
+        let enabled = !settings::get().telemetry;
+        if let Err(e) = telemetry::set_enabled(enabled) {
+            error!("Failed to save settings: {e:#}");
+        }
+    }
+
+    fn manage_subscriptions(&self) {
+        subscription_manager::open();
+    }
+
+    fn check_for_updates(&self) {
+        update::request_check();
+    }
+
+    fn show_console(&self) {
+        logger::global::show_console().unwrap();
     }
 }
@@ -0,0 +1,168 @@
+use std::{fs, io, path::Path};
+
+use anyhow::Context;
+use burnt_sushi_core::DEFAULT_BLOCKER_FILE_NAME;
+use log::{debug, info, warn};
+
+use crate::{
+    autostart,
+    environment::System,
+    jump_list, paths, service,
+    settings::{self, Settings},
+    spotify_autostart, sweep, terminate_other_instances, APP_NAME,
+};
+
+/// Removes everything the app has put on the machine.
+/// Failing steps are reported but do not stop the remaining ones.
+pub fn uninstall(remove_logs: bool) -> anyhow::Result<()> {
+    let mut failed = false;
+    let mut step = |name: &str, result: anyhow::Result<()>| match result {
+        Ok(()) => info!("{name}: done"),
+        Err(e) => {
+            warn!("{name}: {e:#}");
+            failed = true;
+        }
+    };
+
+    step("Stopping running instances", terminate_other_instances());
+    step("Ejecting blockers", sweep::eject_all().map(|_| ()));
+    step("Removing autostart", autostart::disable());
+    step("Restoring Spotify autostart", spotify_autostart::restore());
+    step("Removing service", remove_service());
+    step("Removing jump list", jump_list::remove());
+    step("Removing extracted blockers", remove_blocker_cache());
+    // Read before the settings are removed.
+    if let Some(blocker_dir) = settings::get().blocker_dir.clone() {
+        step("Removing blocker store", remove_blocker_store(&blocker_dir));
+    }
+    step(
+        "Removing settings",
+        Settings::path().map_or(Ok(()), |path| remove_file(&path)),
+    );
+    step(
+        "Removing filter history",
+        [
+            paths::filter_history_file(&System),
+            paths::filter_pin_file(&System),
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(|path| remove_file(&path)),
+    );
+    step(
+        "Removing Spotify Web API token",
+        paths::web_api_token_file(&System).map_or(Ok(()), |path| remove_file(&path)),
+    );
+    step(
+        "Removing stats, telemetry and user rules",
+        [
+            paths::stats_file(&System),
+            paths::telemetry_file(&System),
+            paths::user_rules_file(&System),
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(|path| remove_file(&path)),
+    );
+    step(
+        "Removing cached filter lists",
+        paths::filter_cache_dir(&System).map_or(Ok(()), |dir| remove_dir_all(&dir)),
+    );
+    step(
+        "Removing scripts",
+        paths::scripts_dir(&System).map_or(Ok(()), |dir| remove_dir_all(&dir)),
+    );
+    if remove_logs {
+        step("Removing logs and crash dumps", remove_logs_and_crashes());
+    }
+
+    if failed {
+        anyhow::bail!("Some steps failed, see above for details.");
+    }
+    Ok(())
+}
+
+fn remove_service() -> anyhow::Result<()> {
+    // The service is only installed on demand, so failing to open it usually means it does not exist.
+    if let Err(e) = service::uninstall() {
+        debug!("Service not removed: {e:#}");
+    }
+    Ok(())
+}
+
+fn remove_blocker_cache() -> anyhow::Result<()> {
+    let dirs = [
+        paths::blocker_cache_dir(&System),
+        paths::legacy_blocker_cache_dir(&System),
+    ];
+    for dir in dirs.into_iter().flatten() {
+        remove_blocker_cache_dir(&dir)?;
+    }
+    Ok(())
+}
+
+fn remove_blocker_cache_dir(dir: &Path) -> anyhow::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read blocker cache directory."),
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if entry.file_name().to_string_lossy().starts_with(APP_NAME) {
+            fs::remove_dir_all(entry.path())
+                .with_context(|| format!("Failed to remove '{}'.", entry.path().display()))?;
+        }
+    }
+
+    // Only remove the shared parent if no other app uses it.
+    let _ = fs::remove_dir(dir);
+    Ok(())
+}
+
+/// Removes the blockers extracted to a configured `blocker-dir`, which may hold other files and is
+/// only removed if nothing else is left in it.
+fn remove_blocker_store(dir: &Path) -> anyhow::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read blocker directory."),
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if entry.path().join(DEFAULT_BLOCKER_FILE_NAME).is_file() {
+            remove_dir_all(&entry.path())?;
+        }
+    }
+    let _ = fs::remove_dir(dir);
+    Ok(())
+}
+
+fn remove_logs_and_crashes() -> anyhow::Result<()> {
+    if let Some(log_file) = paths::log_file(&System) {
+        remove_file(&log_file)?;
+    }
+    if let Some(crash_dir) = paths::crash_dir(&System) {
+        remove_dir_all(&crash_dir)?;
+    }
+    if let Some(data_dir) = paths::data_dir(&System) {
+        let _ = fs::remove_dir(&data_dir);
+    }
+    Ok(())
+}
+
+fn remove_dir_all(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_dir_all(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove '{}'.", path.display())),
+    }
+}
+
+fn remove_file(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove '{}'.", path.display())),
+    }
+}
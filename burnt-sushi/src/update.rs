@@ -4,21 +4,117 @@ use std::{
     path::Path,
     process::Stdio,
     ptr,
+    sync::LazyLock,
+    time::Duration,
 };
 
 use anyhow::Context;
 use log::{debug, error, info};
 use reqwest::header::HeaderValue;
 use self_update::update::Release;
-use tokio::fs::{self, File};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{self, File},
+    sync::Notify,
+};
 use u16cstr::u16cstr;
 use widestring::U16CString;
 use winapi::um::{shellapi::ShellExecuteW, winuser::SW_SHOWDEFAULT};
 use winrt_toast::{Action, Text, Toast, ToastManager};
 
-use crate::{APP_NAME, APP_VERSION, ARGS};
+use crate::{
+    blocker,
+    environment::System,
+    events::{self, AppEvent},
+    i18n::{tr, tr_args, Msg},
+    notify, paths, power, resolver, settings, APP_NAME, APP_VERSION, ARGS,
+};
+
+const SILENT_START_CHECK_DELAY: Duration = Duration::from_secs(10 * 60);
+
+static CHECK_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+static BACKGROUND_CHECK_REQUESTED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Asks the update task to check for a new release now.
+pub fn request_check() {
+    CHECK_REQUESTED.notify_one();
+}
+
+/// Asks the update task to check for a new release now like a scheduled check, which asks before
+/// installing it and stays silent if there is none.
+pub fn request_background_check() {
+    BACKGROUND_CHECK_REQUESTED.notify_one();
+}
+
+/// Checks for updates on startup, on request and on the configured schedule.
+/// On a silent start the first check is delayed so the user is not prompted right after logon.
+/// Scheduled checks are deferred while the session is idle.
+/// Returns once an update was installed and the app should exit.
+pub async fn run(silent: bool) {
+    let mut manual = false;
+    if silent {
+        manual = tokio::select! {
+            _ = CHECK_REQUESTED.notified() => true,
+            _ = tokio::time::sleep(SILENT_START_CHECK_DELAY) => false,
+        };
+    }
+    loop {
+        if !manual {
+            power::wait_until_active("update check").await;
+        }
+        match update(manual).await {
+            Ok(true) => return,
+            Ok(false) => match update_blocker().await {
+                Ok(true) => {
+                    blocker::request_rehook();
+                    if manual {
+                        notify::info(tr(Msg::BlockerUpdated), tr(Msg::BlockerUpdatedMessage));
+                    }
+                }
+                Ok(false) => {
+                    if manual {
+                        notify::info(
+                            tr(Msg::NoUpdate),
+                            &tr_args(Msg::LatestVersion, &[("version", &APP_VERSION)]),
+                        );
+                    }
+                }
+                Err(e) => {
+                    error!("Blocker update failed: {e:#}");
+                    if manual {
+                        notify::error(tr(Msg::BlockerUpdateFailed), &format!("{e:#}"));
+                    }
+                }
+            },
+            Err(e) => {
+                error!("App update failed: {e:#}");
+                if manual {
+                    notify::error(tr(Msg::UpdateFailed), &format!("{e:#}"));
+                }
+            }
+        }
+
+        let interval = settings::get()
+            .update_check_interval_hours
+            .map(|hours| Duration::from_secs(hours * 60 * 60));
+        manual = tokio::select! {
+            _ = CHECK_REQUESTED.notified() => true,
+            _ = BACKGROUND_CHECK_REQUESTED.notified() => false,
+            _ = async {
+                match interval {
+                    Some(interval) => tokio::time::sleep(interval).await,
+                    None => futures::future::pending().await,
+                }
+            } => false,
+        };
+    }
+}
+
+/// Installs the latest release if it is newer than the running version.
+/// Returns whether the app is being restarted.
+async fn update(manual: bool) -> anyhow::Result<bool> {
+    debug!("Checking for updates...");
 
-pub async fn update() -> anyhow::Result<bool> {
     let releases = tokio::task::spawn_blocking(load_releases)
         .await
         .context("Failed to load releases")?
@@ -34,8 +130,11 @@ pub async fn update() -> anyhow::Result<bool> {
         info!("No new release found");
         return Ok(false);
     }
+    events::publish(AppEvent::UpdateAvailable {
+        version: release.version.clone(),
+    });
 
-    if !ARGS.update_elevate_restart {
+    if !ARGS.update_elevate_restart && !manual {
         if confirm_update(&release.version).await {
             debug!("Update confirmed");
         } else {
@@ -66,9 +165,16 @@ pub async fn update() -> anyhow::Result<bool> {
 
     let asset = release
         .assets
-        .into_iter()
+        .iter()
         .find(|asset| asset.name.ends_with(".exe"))
+        .cloned()
         .context("No release executable asset found")?;
+    let checksum_asset = release
+        .assets
+        .iter()
+        .find(|checksum| checksum.name == format!("{}.sha256", asset.name))
+        .cloned()
+        .with_context(|| format!("Release has no checksum for {}", asset.name))?;
 
     debug!(
         "Found release asset [{}] at {}",
@@ -86,13 +192,26 @@ pub async fn update() -> anyhow::Result<bool> {
         .into_std()
         .await;
 
-    tokio::task::spawn_blocking(move || download_file(&asset.download_url, tmp_bin))
+    let download_url = asset.download_url.clone();
+    tokio::task::spawn_blocking(move || download_file(&download_url, tmp_bin))
         .await
         .context("Error downloading updated executable")?
         .context("Error downloading updated executable")?;
 
     debug!("Downloaded asset to {}", tmp_bin_path.display());
 
+    let expected = tokio::task::spawn_blocking(move || {
+        let mut checksum = Vec::new();
+        download_file(&checksum_asset.download_url, &mut checksum).map(|_| checksum)
+    })
+    .await
+    .context("Error downloading checksum")?
+    .context("Error downloading checksum")?;
+    verify_checksum(&tmp_bin_path, &expected)
+        .await
+        .context("Failed to verify updated executable")?;
+    debug!("Verified checksum of {}", asset.name);
+
     let moved_bin = current_exe.with_extension("exe.bak");
 
     fs::rename(&current_exe, &moved_bin)
@@ -118,11 +237,131 @@ pub async fn update() -> anyhow::Result<bool> {
     Ok(true)
 }
 
-fn restart(new_exe: &Path, old_exe: &Path) -> anyhow::Result<()> {
-    let current_args = env::args().skip(1);
+/// Downloads the blocker of the newest release built for this app's RPC protocol if it differs
+/// from the blocker in use. Returns whether a new blocker was installed.
+async fn update_blocker() -> anyhow::Result<bool> {
+    if ARGS.blocker.is_some() {
+        debug!("Blocker was set explicitly, skipping blocker update");
+        return Ok(false);
+    }
+
+    let asset_name = format!(
+        "BurntSushiBlocker_x64-rpc{}.dll",
+        shared::RPC_PROTOCOL_VERSION
+    );
+    let checksum_name = format!("{asset_name}.sha256");
+
+    let releases = tokio::task::spawn_blocking(load_releases)
+        .await
+        .context("Failed to load releases")?
+        .context("Failed to load releases")?;
+    let Some((asset, checksum_asset)) = releases
+        .into_iter()
+        .filter_map(|r| lenient_semver::parse(&r.version).ok().map(|v| (r, v)))
+        .filter_map(|(r, v)| {
+            let asset = r.assets.iter().find(|a| a.name == asset_name)?.clone();
+            let checksum = r.assets.iter().find(|a| a.name == checksum_name)?.clone();
+            Some(((asset, checksum), v))
+        })
+        .max_by(|(_, v1), (_, v2)| v1.cmp(v2))
+        .map(|(assets, _)| assets)
+    else {
+        debug!("No compatible blocker release found");
+        return Ok(false);
+    };
+
+    let checksum = tokio::task::spawn_blocking(move || {
+        let mut checksum = Vec::new();
+        download_file(&checksum_asset.download_url, &mut checksum).map(|_| checksum)
+    })
+    .await
+    .context("Error downloading checksum")?
+    .context("Error downloading checksum")?;
+
+    let current_blocker = resolver::resolve_blocker(&System, ARGS.blocker.as_deref())
+        .await
+        .context("Failed to locate current blocker")?;
+    if verify_checksum(&current_blocker, &checksum).await.is_ok() {
+        debug!("Blocker is up to date");
+        return Ok(false);
+    }
 
+    let target =
+        paths::updated_blocker(&System).context("Failed to locate blocker cache directory")?;
+    fs::create_dir_all(target.parent().unwrap())
+        .await
+        .context("Failed to create blocker cache directory")?;
+    let download_path = target.with_extension("dll.download");
+    let download = File::create(&download_path)
+        .await
+        .context("Error creating temporary file")?
+        .into_std()
+        .await;
+    tokio::task::spawn_blocking(move || download_file(&asset.download_url, download))
+        .await
+        .context("Error downloading blocker")?
+        .context("Error downloading blocker")?;
+
+    if let Err(e) = verify_checksum(&download_path, &checksum).await {
+        let _ = fs::remove_file(&download_path).await;
+        return Err(e).context("Failed to verify downloaded blocker");
+    }
+
+    // A loaded blocker cannot be overwritten but it can be renamed out of the way.
+    let old_path = target.with_extension("dll.old");
+    let _ = fs::remove_file(&old_path).await;
+    if fs::try_exists(&target).await.unwrap_or(false) {
+        fs::rename(&target, &old_path)
+            .await
+            .context("Failed to move previous blocker")?;
+    }
+    fs::rename(&download_path, &target)
+        .await
+        .context("Failed to move downloaded blocker")?;
+    let _ = fs::remove_file(&old_path).await;
+
+    info!("Updated blocker to {}", target.display());
+    Ok(true)
+}
+
+/// Compares the SHA-256 hash of the file with a checksum file in `sha256sum` format.
+async fn verify_checksum(path: &Path, checksum_file: &[u8]) -> anyhow::Result<()> {
+    let expected = String::from_utf8_lossy(checksum_file)
+        .split_whitespace()
+        .next()
+        .map(|hash| hash.to_ascii_lowercase())
+        .context("Checksum file is empty")?;
+
+    let contents = fs::read(path)
+        .await
+        .context("Failed to read downloaded file")?;
+    let actual = Sha256::digest(&contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    if actual != expected {
+        anyhow::bail!("Checksum mismatch (expected {expected}, got {actual})");
+    }
+    Ok(())
+}
+
+/// Arguments of the restarted instance. A supervised instance is restarted with `--supervise`, as
+/// its watchdog ends together with it.
+fn restart_args() -> Vec<String> {
+    let mut args = env::args()
+        .skip(1)
+        .filter(|arg| arg != "--supervised")
+        .collect::<Vec<_>>();
+    if ARGS.supervised {
+        args.push("--supervise".to_string());
+    }
+    args
+}
+
+fn restart(new_exe: &Path, old_exe: &Path) -> anyhow::Result<()> {
     std::process::Command::new(new_exe)
-        .args(current_args)
+        .args(restart_args())
         .arg("--update-old-bin")
         .arg(old_exe)
         .arg("--singleton-wait-for-shutdown")
@@ -140,7 +379,7 @@ fn restart_elevated() -> anyhow::Result<()> {
             .into_os_string(),
     )
     .context("Current executable has an invalid path?")?;
-    let current_args = env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let current_args = restart_args().join(" ");
     let new_args = "--update-elevate-restart --singleton-wait-for-shutdown";
     let args = U16CString::from_str(format!("{current_args} {new_args}"))
         .context("Arguments contain invalid characters")?;
@@ -183,9 +422,20 @@ async fn confirm_update(version: &str) -> bool {
     let mut toast = Toast::new();
     toast
         .text1("BurntSushi")
-        .text2(Text::new(format!("Update app to to {version}?")))
-        .action(Action::new("Update", CONFIRM_ACTION, CONFIRM_ACTION))
-        .action(Action::new("Ignore", IGNORE_ACTION, IGNORE_ACTION));
+        .text2(Text::new(tr_args(
+            Msg::UpdatePrompt,
+            &[("version", &version)],
+        )))
+        .action(Action::new(
+            tr(Msg::UpdateAction),
+            CONFIRM_ACTION,
+            CONFIRM_ACTION,
+        ))
+        .action(Action::new(
+            tr(Msg::IgnoreAction),
+            IGNORE_ACTION,
+            IGNORE_ACTION,
+        ));
 
     let confirm_tx2 = confirm_tx.clone();
     let confirm_tx3 = confirm_tx.clone();
@@ -0,0 +1,170 @@
+//! Rules added by the user from the recent activity, written to their own file in the app data so
+//! that they survive updates of the filter lists and of the app.
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::Context;
+use burnt_sushi_core::filters::{self, FilterConfig};
+use futures::{future::BoxFuture, FutureExt};
+use log::info;
+use regex::Regex;
+
+use crate::{
+    environment::System,
+    filter_history::ChangeSource,
+    filter_providers::{self, FilterFormat, FilterProvider},
+    paths,
+};
+
+/// Number of recently allowed hosts offered for blocking.
+const RECENT_HOST_COUNT: usize = 8;
+
+static RECENT_ALLOWED_HOSTS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Contents of the user rules file before the first rule is added.
+const USER_RULES_TEMPLATE: &str = "\
+# Rules added from the recent activity in the tray, merged into the filter config.
+allowlist = []
+denylist = [
+]
+";
+
+static DENYLIST_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\s*denylist\s*=\s*\[").unwrap());
+
+/// Provides the rules of the user rules file, which only exists once a rule was added.
+pub struct UserRulesProvider;
+
+impl FilterProvider for UserRulesProvider {
+    fn name(&self) -> String {
+        "user rules".to_string()
+    }
+
+    fn load(&self) -> BoxFuture<'_, io::Result<FilterConfig>> {
+        async move {
+            let contents = match paths::user_rules_file(&System) {
+                Some(path) => match tokio::fs::read_to_string(&path).await {
+                    Ok(contents) => contents,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        USER_RULES_TEMPLATE.to_string()
+                    }
+                    Err(e) => return Err(e),
+                },
+                None => USER_RULES_TEMPLATE.to_string(),
+            };
+            FilterFormat::Toml.parse(&contents)
+        }
+        .boxed()
+    }
+}
+
+/// Remembers the host of an allowed request, most recent first.
+pub fn record_allowed(url: &str) {
+    let host = filters::host(url);
+    if host.is_empty() {
+        return;
+    }
+    let mut hosts = RECENT_ALLOWED_HOSTS.lock().unwrap();
+    if hosts.front().is_some_and(|recent| recent == host) {
+        return;
+    }
+    hosts.retain(|recent| recent != host);
+    hosts.push_front(host.to_string());
+    hosts.truncate(RECENT_HOST_COUNT);
+}
+
+/// Hosts of the most recently allowed requests, most recent first.
+pub fn recent_allowed_hosts() -> Vec<String> {
+    RECENT_ALLOWED_HOSTS
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect()
+}
+
+/// Adds a rule blocking all requests to the host to the denylist of the user rules and applies it.
+/// The file is edited in place to keep comments, tests and expiration dates added by the user.
+pub fn block_host(host: &str) -> anyhow::Result<()> {
+    let rule = format!("https?://{}/.*", regex::escape(host));
+    let path = paths::user_rules_file(&System).context("Failed to locate app data directory.")?;
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => USER_RULES_TEMPLATE.to_string(),
+        Err(e) => return Err(e).context("Failed to read user rules."),
+    };
+    let contents = append_to_toml_denylist(&contents, &rule, host)
+        .context("Failed to find the denylist in the user rules.")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create app data directory.")?;
+    }
+    fs::write(&path, contents).context("Failed to write user rules.")?;
+
+    info!(
+        "Blocking '{host}' with rule '{rule}' in '{}'",
+        path.display()
+    );
+    filter_providers::notify_refresh(ChangeSource::Activity);
+    Ok(())
+}
+
+/// Inserts the rule as the last entry of the `denylist` array, or returns `None` if there is none.
+fn append_to_toml_denylist(contents: &str, rule: &str, host: &str) -> Option<String> {
+    let start = DENYLIST_START.find(contents)?.end();
+    let (end, last) = scan_array(contents, start - 1)?;
+
+    let mut contents = contents.to_string();
+    let mut insert_at = match contents[..end].rfind('\n') {
+        Some(newline) if newline > last => newline + 1,
+        // The closing bracket shares the line with the last rule, e.g. in a one-line list.
+        _ => {
+            contents.insert(end, '\n');
+            end + 1
+        }
+    };
+    if !matches!(contents.as_bytes()[last], b',' | b'[') {
+        contents.insert(last + 1, ',');
+        insert_at += 1;
+    }
+    contents.insert_str(
+        insert_at,
+        &format!("    '{rule}', # {host} (added from recent activity)\n"),
+    );
+    Some(contents)
+}
+
+/// Finds the closing bracket of the array opened at `open` and the last character before it that
+/// is neither whitespace nor part of a comment.
+fn scan_array(contents: &str, open: usize) -> Option<(usize, usize)> {
+    let mut quote = None;
+    let mut comment = false;
+    let mut escaped = false;
+    let mut last = open;
+    for (i, c) in contents.char_indices().skip_while(|&(i, _)| i <= open) {
+        if comment {
+            comment = c != '\n';
+            continue;
+        }
+        match (quote, c) {
+            (Some('"'), _) if escaped => escaped = false,
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') => {
+                comment = true;
+                continue;
+            }
+            (None, ']') => return Some((i, last)),
+            (None, c) if c.is_whitespace() => continue,
+            _ => {}
+        }
+        last = i + c.len_utf8() - 1;
+    }
+    None
+}
@@ -0,0 +1,88 @@
+use std::{
+    ffi::{c_void, OsStr},
+    io, mem,
+    path::{Component, Path, PathBuf},
+    ptr,
+};
+
+use anyhow::Context;
+use u16cstr::u16cstr;
+use widestring::U16CString;
+use winapi::um::{
+    fileapi::GetDriveTypeW,
+    shellapi::ShellExecuteW,
+    winbase::{DRIVE_NO_ROOT_DIR, DRIVE_REMOTE, DRIVE_UNKNOWN},
+    winuser::SW_SHOWNORMAL,
+};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::Storage::FileSystem::{
+        GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW, VS_FIXEDFILEINFO,
+    },
+};
+
+/// Reads the file version from the version resource of the given executable or module.
+pub fn file_version(path: &Path) -> Option<String> {
+    let path = U16CString::from_os_str(path.as_os_str()).ok()?;
+    let path = PCWSTR(path.as_ptr());
+
+    let size = unsafe { GetFileVersionInfoSizeW(path, None) };
+    if size == 0 {
+        return None;
+    }
+
+    let mut data = vec![0u8; size as usize];
+    unsafe { GetFileVersionInfoW(path, 0, size, data.as_mut_ptr().cast()) }.ok()?;
+
+    let mut info = ptr::null_mut::<c_void>();
+    let mut info_len = 0;
+    let found = unsafe { VerQueryValueW(data.as_ptr().cast(), w!("\\"), &mut info, &mut info_len) };
+    if !found.as_bool()
+        || info.is_null()
+        || (info_len as usize) < mem::size_of::<VS_FIXEDFILEINFO>()
+    {
+        return None;
+    }
+
+    let info = unsafe { &*(info as *const VS_FIXEDFILEINFO) };
+    Some(format!(
+        "{}.{}.{}.{}",
+        info.dwFileVersionMS >> 16,
+        info.dwFileVersionMS & 0xFFFF,
+        info.dwFileVersionLS >> 16,
+        info.dwFileVersionLS & 0xFFFF
+    ))
+}
+
+/// Whether an absolute path is on a volume of this computer rather than a network share.
+pub fn is_local_volume(path: &Path) -> bool {
+    let root = path
+        .components()
+        .take_while(|component| matches!(component, Component::Prefix(_) | Component::RootDir))
+        .collect::<PathBuf>();
+    let Ok(root) = U16CString::from_os_str(root.as_os_str()) else {
+        return false;
+    };
+    let drive_type = unsafe { GetDriveTypeW(root.as_ptr()) };
+    !matches!(drive_type, DRIVE_UNKNOWN | DRIVE_NO_ROOT_DIR | DRIVE_REMOTE)
+}
+
+/// Opens a file, directory or url with its associated program.
+pub fn shell_open(target: impl AsRef<OsStr>) -> anyhow::Result<()> {
+    let target = U16CString::from_os_str(target).context("Target contains a nul character.")?;
+    let result = unsafe {
+        ShellExecuteW(
+            ptr::null_mut(),
+            u16cstr!("open").as_ptr(),
+            target.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+
+    if result <= 32 as _ {
+        return Err(io::Error::last_os_error()).context("Failed to run ShellExecuteW");
+    }
+    Ok(())
+}
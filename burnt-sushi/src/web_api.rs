@@ -0,0 +1,380 @@
+use std::{fs, io, time::Duration};
+
+use anyhow::{bail, Context};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use burnt_sushi_core::security;
+use chrono::Utc;
+use log::{debug, info, warn};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    ad_slip::{self, AdSource},
+    environment::System,
+    paths, power,
+    settings::{self, WebApiSettings},
+    status::{self, HookStatus},
+    utils, APP_NAME,
+};
+
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const API_URL: &str = "https://api.spotify.com/v1";
+const SCOPES: &str =
+    "user-read-playback-state user-read-currently-playing user-modify-playback-state";
+
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Access tokens are refreshed this long before they expire.
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// Authorizes the app using the PKCE flow and stores the received token.
+pub async fn login() -> anyhow::Result<()> {
+    let config = configured()?;
+
+    let verifier = random_string(48)?;
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    let state = random_string(12)?;
+    let redirect_uri = config.redirect_uri();
+
+    let listener = TcpListener::bind(("127.0.0.1", config.redirect_port))
+        .await
+        .with_context(|| format!("Failed to listen on port {}.", config.redirect_port))?;
+
+    let url = Url::parse_with_params(
+        AUTHORIZE_URL,
+        &[
+            ("client_id", config.client_id.as_str()),
+            ("response_type", "code"),
+            ("redirect_uri", &redirect_uri),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", &challenge),
+            ("state", &state),
+            ("scope", SCOPES),
+        ],
+    )?;
+    println!("Authorize {APP_NAME} in the browser, or open this url manually:\n{url}");
+    if let Err(e) = utils::shell_open(url.as_str()) {
+        warn!("Failed to open browser: {e:#}");
+    }
+
+    let code = tokio::time::timeout(LOGIN_TIMEOUT, receive_code(&listener, &state))
+        .await
+        .context("Timed out waiting for authorization.")??;
+
+    let response = request_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", &redirect_uri),
+        ("client_id", &config.client_id),
+        ("code_verifier", &verifier),
+    ])
+    .await?;
+    let refresh_token = response
+        .refresh_token
+        .clone()
+        .context("No refresh token received.")?;
+    Token::new(response, refresh_token).save()?;
+
+    Ok(())
+}
+
+/// Periodically checks the playback state while Spotify is hooked to confirm that no ads are
+/// played, skipping them if enabled. Returns immediately if the Web API is not set up.
+pub async fn run() {
+    let Some(config) = settings::get().spotify_web_api.clone() else {
+        return;
+    };
+    let token = match Token::load() {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            info!(
+                "Spotify Web API is configured but not authorized, run `{APP_NAME} spotify-login`"
+            );
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to load Spotify Web API token: {e:#}");
+            return;
+        }
+    };
+
+    let mut client = WebApiClient::new(config.client_id.clone(), token);
+    let mut interval = tokio::time::interval(
+        Duration::from_secs(config.poll_interval_secs).max(MIN_POLL_INTERVAL),
+    );
+    let mut ad_playing = false;
+    loop {
+        interval.tick().await;
+        if status::get().hook != HookStatus::Hooked || power::is_low_power() {
+            ad_playing = false;
+            continue;
+        }
+
+        match client.currently_playing().await {
+            Ok(Some(PlaybackType::Ad)) => {
+                if !ad_playing {
+                    ad_slip::detected(AdSource::WebApi, None);
+                    if config.auto_skip {
+                        match client.skip().await {
+                            Ok(()) => info!("Skipped ad"),
+                            Err(e) => warn!("Failed to skip ad: {e:#}"),
+                        }
+                    }
+                }
+                ad_playing = true;
+            }
+            Ok(_) => ad_playing = false,
+            Err(e) => warn!("Failed to read Spotify playback state: {e:#}"),
+        }
+    }
+}
+
+fn configured() -> anyhow::Result<WebApiSettings> {
+    settings::get().spotify_web_api.clone().context(
+        "Spotify Web API is not configured, set `client-id` in the `spotify-web-api` section of the settings.",
+    )
+}
+
+/// Returns a url-safe string encoding `len` random bytes.
+fn random_string(len: usize) -> anyhow::Result<String> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).context("Failed to generate random bytes.")?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Waits for the redirect from the authorization page and returns the authorization code.
+async fn receive_code(listener: &TcpListener, state: &str) -> anyhow::Result<String> {
+    loop {
+        let (mut stream, peer) = listener.accept().await?;
+        // Only the user's own browser may deliver the code.
+        if !security::is_current_user_peer(stream.local_addr()?, peer)? {
+            debug!("Ignored authorization redirect of another user");
+            continue;
+        }
+        let Some(target) = read_request_target(&mut stream).await? else {
+            continue;
+        };
+
+        let url = Url::parse(&format!("http://127.0.0.1{target}"))?;
+        if url.path() != "/callback" {
+            respond(&mut stream, "404 Not Found", "Not found").await;
+            continue;
+        }
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        if param("state").as_deref() != Some(state) {
+            respond(&mut stream, "400 Bad Request", "Invalid state.").await;
+            bail!("Received authorization with an invalid state.");
+        }
+        if let Some(error) = param("error") {
+            respond(&mut stream, "200 OK", "Authorization failed.").await;
+            bail!("Authorization failed: {error}");
+        }
+        let Some(code) = param("code") else {
+            respond(&mut stream, "400 Bad Request", "Missing code.").await;
+            bail!("Received authorization without a code.");
+        };
+
+        respond(
+            &mut stream,
+            "200 OK",
+            &format!("{APP_NAME} is now authorized, you can close this page."),
+        )
+        .await;
+        return Ok(code);
+    }
+}
+
+/// Reads a http request and returns the target of its request line.
+async fn read_request_target(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8 * 1024 {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    Ok(match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        _ => None,
+    })
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!("Failed to respond to authorization redirect: {e}");
+    }
+}
+
+async fn request_token(form: &[(&str, &str)]) -> anyhow::Result<TokenResponse> {
+    let response = reqwest::Client::new()
+        .post(TOKEN_URL)
+        .form(form)
+        .send()
+        .await
+        .context("Failed to request token.")?
+        .error_for_status()
+        .context("Token request was rejected.")?;
+    response
+        .json()
+        .await
+        .context("Failed to parse token response.")
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+    refresh_token: Option<String>,
+}
+
+/// Token persisted in the app data directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Token {
+    access_token: String,
+    refresh_token: String,
+    /// Unix timestamp in seconds.
+    expires_at: i64,
+}
+
+impl Token {
+    fn new(response: TokenResponse, refresh_token: String) -> Self {
+        Self {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token.unwrap_or(refresh_token),
+            expires_at: Utc::now().timestamp() + response.expires_in,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        Utc::now().timestamp() + TOKEN_EXPIRY_MARGIN_SECS >= self.expires_at
+    }
+
+    fn load() -> anyhow::Result<Option<Self>> {
+        let path =
+            paths::web_api_token_file(&System).context("Failed to locate app data directory.")?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(
+                toml::from_str(&contents).context("Failed to parse token.")?,
+            )),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read token."),
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path =
+            paths::web_api_token_file(&System).context("Failed to locate app data directory.")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create token directory.")?;
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize token.")?;
+        fs::write(&path, contents).context("Failed to write token.")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PlaybackType {
+    Track,
+    Episode,
+    Ad,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurrentlyPlaying {
+    currently_playing_type: PlaybackType,
+}
+
+struct WebApiClient {
+    http: reqwest::Client,
+    client_id: String,
+    token: Token,
+}
+
+impl WebApiClient {
+    fn new(client_id: String, token: Token) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            client_id,
+            token,
+        }
+    }
+
+    async fn access_token(&mut self) -> anyhow::Result<String> {
+        if self.token.is_expired() {
+            debug!("Refreshing Spotify Web API token");
+            let response = request_token(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &self.token.refresh_token),
+                ("client_id", &self.client_id),
+            ])
+            .await?;
+            self.token = Token::new(response, self.token.refresh_token.clone());
+            if let Err(e) = self.token.save() {
+                warn!("Failed to save refreshed token: {e:#}");
+            }
+        }
+        Ok(self.token.access_token.clone())
+    }
+
+    /// Returns the type of the currently playing item, or `None` if nothing is playing.
+    async fn currently_playing(&mut self) -> anyhow::Result<Option<PlaybackType>> {
+        let token = self.access_token().await?;
+        let response = self
+            .http
+            .get(format!(
+                "{API_URL}/me/player/currently-playing?additional_types=episode"
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            StatusCode::UNAUTHORIZED => {
+                // Refresh on the next request, e.g. if the token was revoked early.
+                self.token.expires_at = 0;
+                bail!("Access token was rejected.")
+            }
+            _ => {
+                let playing: CurrentlyPlaying = response.error_for_status()?.json().await?;
+                Ok(Some(playing.currently_playing_type))
+            }
+        }
+    }
+
+    async fn skip(&mut self) -> anyhow::Result<()> {
+        let token = self.access_token().await?;
+        self.http
+            .post(format!("{API_URL}/me/player/next"))
+            .bearer_auth(token)
+            .header(reqwest::header::CONTENT_LENGTH, 0)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! Optional notifications of significant events posted as JSON to a user-configured webhook, e.g.
+//! a Discord channel, for users monitoring their machines remotely.
+
+use std::{sync::Mutex, time::Duration};
+
+use chrono::Local;
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::{
+    events::{self, AppEvent},
+    settings, shutdown, APP_NAME,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Time Spotify has to stay hooked or unhooked before it is posted, so that re-injecting the
+/// blocker does not post a pair of messages each time.
+const HOOK_DEBOUNCE: Duration = Duration::from_secs(60);
+
+static HOOK_POSTS: Mutex<HookPosts> = Mutex::new(HookPosts {
+    changes: 0,
+    posted: None,
+});
+
+/// Hooked and unhooked events waiting for [`HOOK_DEBOUNCE`].
+struct HookPosts {
+    /// Number of hook changes, a waiting post is dropped if another change followed it.
+    changes: u64,
+    /// Hook event posted last.
+    posted: Option<WebhookEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    Hooked,
+    Unhooked,
+    InjectionFailed,
+    UpdateAvailable,
+}
+
+#[derive(Debug, Serialize)]
+struct Payload {
+    /// Summary shown by Discord, which ignores the other fields.
+    content: String,
+    app: &'static str,
+    event: WebhookEvent,
+    message: String,
+    time: String,
+}
+
+/// Posts the significant events published on the event bus.
+pub fn subscribe() {
+    events::subscribe("webhook", |event| match event {
+        AppEvent::Injected { .. } => {
+            send_debounced(WebhookEvent::Hooked, "Blocking ads in Spotify")
+        }
+        AppEvent::Unhooked => {
+            send_debounced(WebhookEvent::Unhooked, "Stopped blocking ads in Spotify")
+        }
+        AppEvent::Error { message } => send(WebhookEvent::InjectionFailed, message),
+        AppEvent::UpdateAvailable { version } => send(
+            WebhookEvent::UpdateAvailable,
+            format!("Version {version} is available"),
+        ),
+        _ => {}
+    });
+}
+
+/// Posts a hook change once it lasted for [`HOOK_DEBOUNCE`] and differs from the last one posted.
+fn send_debounced(event: WebhookEvent, message: &'static str) {
+    let change = {
+        let mut posts = HOOK_POSTS.lock().unwrap();
+        posts.changes += 1;
+        posts.changes
+    };
+    shutdown::spawn("webhook", async move {
+        tokio::time::sleep(HOOK_DEBOUNCE).await;
+        {
+            let mut posts = HOOK_POSTS.lock().unwrap();
+            if posts.changes != change || posts.posted == Some(event) {
+                return;
+            }
+            posts.posted = Some(event);
+        }
+        send(event, message);
+    });
+}
+
+/// Posts the event to the webhook in the background if one is configured.
+fn send(event: WebhookEvent, message: impl Into<String>) {
+    let Some(url) = settings::get().webhook_url.clone() else {
+        return;
+    };
+    let message = message.into();
+    let payload = Payload {
+        content: format!("{APP_NAME}: {message}"),
+        app: APP_NAME,
+        event,
+        message,
+        time: Local::now().to_rfc3339(),
+    };
+
+    shutdown::spawn("webhook", async move {
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&payload)
+            .timeout(TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(_) => debug!("Posted {event:?} to webhook"),
+            // The url is left out, as it contains the token of the webhook.
+            Err(e) => warn!("Failed to post {event:?} to webhook: {}", e.without_url()),
+        }
+    });
+}
@@ -1,7 +1,4 @@
-#![feature(variant_count)]
-
 use core::{fmt, hash};
-use std::mem;
 
 #[allow(dead_code)]
 mod spotify_ad_guard_capnp {
@@ -12,6 +9,39 @@ pub mod rpc {
     pub use super::spotify_ad_guard_capnp::*;
 }
 
+/// Compression of large RPC payloads, such as rulesets merged from thousands of subscribed rules.
+pub mod compression {
+    /// Payloads smaller than this are sent uncompressed, compressing them would not save time.
+    pub const THRESHOLD_BYTES: usize = 16 * 1024;
+    /// Largest decompressed payload accepted, so that a corrupt payload cannot exhaust memory.
+    pub const MAX_DECOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+
+    pub fn deflate(data: &[u8]) -> Vec<u8> {
+        // Fast levels already shrink regex lists a lot, higher ones mostly cost time.
+        miniz_oxide::deflate::compress_to_vec(data, 3)
+    }
+
+    pub fn inflate(data: &[u8]) -> Result<Vec<u8>, capnp::Error> {
+        miniz_oxide::inflate::decompress_to_vec_with_limit(data, MAX_DECOMPRESSED_BYTES)
+            .map_err(|e| capnp::Error::failed(format!("Failed to decompress payload: {e}")))
+    }
+}
+
+/// Version of the RPC protocol between app and blocker.
+/// Has to be bumped whenever the schema changes in a way that older apps or blockers cannot handle.
+pub const RPC_PROTOCOL_VERSION: u32 = 1;
+
+/// Name of the procedure exported by every blocker build, used to find injected blockers
+/// regardless of the file they were loaded from.
+pub const BLOCKER_MARKER_PROCEDURE: &str = "burnt_sushi_blocker_marker";
+
+/// Value returned by [`BLOCKER_MARKER_PROCEDURE`], so that an unrelated module exporting a
+/// procedure with the same name is not mistaken for a blocker.
+pub const BLOCKER_MARKER: &str = "5d0b6c9e-8f3a-4c71-9e2b-0a6f4d1c7e38";
+
+/// Name of the procedure returning the version of the blocker build, missing in older blockers.
+pub const BLOCKER_VERSION_PROCEDURE: &str = "burnt_sushi_blocker_version";
+
 #[allow(clippy::derived_hash_with_manual_eq)]
 impl hash::Hash for rpc::blocker_service::FilterHook {
     fn hash<H: hash::Hasher>(&self, state: &mut H) {
@@ -32,8 +62,11 @@ impl fmt::Display for rpc::blocker_service::FilterHook {
     }
 }
 
+/// Number of variants of [`rpc::blocker_service::FilterHook`].
+const FILTER_HOOK_COUNT: usize = 2;
+
 impl enum_map::Enum for rpc::blocker_service::FilterHook {
-    const LENGTH: usize = mem::variant_count::<Self>();
+    const LENGTH: usize = FILTER_HOOK_COUNT;
 
     fn from_usize(value: usize) -> Self {
         match value {
@@ -52,5 +85,5 @@ impl enum_map::Enum for rpc::blocker_service::FilterHook {
 }
 
 impl<T> enum_map::EnumArray<T> for rpc::blocker_service::FilterHook {
-    type Array = [T; mem::variant_count::<Self>()];
+    type Array = [T; FILTER_HOOK_COUNT];
 }
@@ -0,0 +1,64 @@
+//! Stand-in for the Spotify client: a process that owns a window of the class used by Spotify's
+//! main window, found by the scanner once the tests make it the dev target. Prints `ready` once
+//! the window exists.
+
+use std::{
+    io::{self, Write},
+    mem, ptr,
+};
+
+use widestring::U16CString;
+use winapi::um::{
+    libloaderapi::GetModuleHandleW,
+    winuser::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+        TranslateMessage, CW_USEDEFAULT, WNDCLASSW, WS_OVERLAPPEDWINDOW,
+    },
+};
+
+const WINDOW_CLASS: &str = "Chrome_WidgetWin_1";
+const WINDOW_TITLE: &str = "Spotify Free";
+
+fn main() {
+    let class_name = U16CString::from_str(WINDOW_CLASS).unwrap();
+    let title = U16CString::from_str(WINDOW_TITLE).unwrap();
+
+    unsafe {
+        let instance = GetModuleHandleW(ptr::null());
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(DefWindowProcW),
+            hInstance: instance,
+            lpszClassName: class_name.as_ptr(),
+            ..mem::zeroed()
+        };
+        assert_ne!(RegisterClassW(&class), 0, "{}", io::Error::last_os_error());
+
+        // Not shown, the scanner also finds hidden windows.
+        let window = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            title.as_ptr(),
+            WS_OVERLAPPEDWINDOW,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            instance,
+            ptr::null_mut(),
+        );
+        assert!(!window.is_null(), "{}", io::Error::last_os_error());
+    }
+
+    println!("ready");
+    io::stdout().flush().unwrap();
+
+    unsafe {
+        let mut message = mem::zeroed();
+        while GetMessageW(&mut message, ptr::null_mut(), 0, 0) > 0 {
+            TranslateMessage(&message);
+            DispatchMessageW(&message);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Blocker payload that speaks the blocker RPC protocol without hooking anything. Enabling
+//! filtering reports a single request for [`PROBE_URL`] to the registered loggers, blocked if it
+//! matches the pushed denylist.
+
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    sync::{LazyLock, Mutex},
+    thread,
+};
+
+use capnp::capability::Promise;
+use capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem};
+use futures::{AsyncReadExt, FutureExt};
+use regex::RegexSet;
+use shared::rpc::blocker_service::{self, FilterHook};
+use tokio::select;
+
+const PROBE_URL: &str = "https://spclient.wg.spotify.com/ads/v2/probe";
+
+static RPC_STATE: LazyLock<Mutex<Option<RpcState>>> = LazyLock::new(|| Mutex::new(None));
+
+struct RpcState {
+    rpc_thread: thread::JoinHandle<()>,
+    rpc_disconnector: tokio::sync::watch::Sender<()>,
+    socket_addr: SocketAddrV4,
+}
+
+dll_syringe::payload_procedure! {
+    fn burnt_sushi_blocker_marker() -> String {
+        shared::BLOCKER_MARKER.to_string()
+    }
+}
+
+dll_syringe::payload_procedure! {
+    fn burnt_sushi_blocker_version() -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+}
+
+dll_syringe::payload_procedure! {
+    fn start_rpc() -> SocketAddrV4 {
+        let mut state = RPC_STATE.lock().unwrap();
+        if let Some(state) = state.as_ref() {
+            return state.socket_addr;
+        }
+
+        let (end_point_tx, end_point_rx) = tokio::sync::oneshot::channel();
+        let (disconnect_tx, disconnect_rx) = tokio::sync::watch::channel(());
+
+        let rpc_thread = thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+                .block_on(tokio::task::LocalSet::new().run_until(run_rpc(end_point_tx, disconnect_rx)))
+                .unwrap()
+        });
+
+        let socket_addr = end_point_rx.blocking_recv().unwrap();
+
+        *state = Some(RpcState {
+            rpc_thread,
+            rpc_disconnector: disconnect_tx,
+            socket_addr,
+        });
+
+        socket_addr
+    }
+}
+
+dll_syringe::payload_procedure! {
+    fn stop_rpc() {
+        let mut state = RPC_STATE.lock().unwrap();
+        if let Some(state) = state.take() {
+            state.rpc_disconnector.send(()).unwrap();
+            state.rpc_thread.join().unwrap();
+        }
+    }
+}
+
+async fn run_rpc(
+    end_point: tokio::sync::oneshot::Sender<SocketAddrV4>,
+    mut disconnect_signal: tokio::sync::watch::Receiver<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = tokio::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).await?;
+    end_point
+        .send(SocketAddrV4::new(
+            Ipv4Addr::LOCALHOST,
+            listener.local_addr()?.port(),
+        ))
+        .unwrap();
+    let client: blocker_service::Client = capnp_rpc::new_client(ServerImpl::default());
+
+    loop {
+        select! {
+            res = listener.accept() => {
+                let (stream, _) = res?;
+                stream.set_nodelay(true)?;
+                let (reader, writer) =
+                    tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+                let network = twoparty::VatNetwork::new(
+                    reader,
+                    writer,
+                    rpc_twoparty_capnp::Side::Server,
+                    Default::default(),
+                );
+
+                let rpc_system = RpcSystem::new(Box::new(network), Some(client.clone().client));
+
+                let disconnector = rpc_system.get_disconnector();
+                let mut disconnect_signal = disconnect_signal.clone();
+                tokio::task::spawn_local(async move {
+                    disconnect_signal.changed().await.unwrap();
+                    disconnector.await.unwrap();
+                });
+
+                tokio::task::spawn_local(Box::pin(rpc_system.map(|_| ())));
+            },
+            _ = disconnect_signal.changed() => {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ServerImpl {
+    loggers: Vec<blocker_service::logger::Client>,
+    /// Rule counts indexed by [`FilterHook`].
+    rule_counts: [usize; 2],
+    denylist: RegexSet,
+    filtering: bool,
+}
+
+impl blocker_service::Server for ServerImpl {
+    fn register_logger(
+        &mut self,
+        params: blocker_service::RegisterLoggerParams,
+        mut _results: blocker_service::RegisterLoggerResults,
+    ) -> Promise<(), ::capnp::Error> {
+        self.loggers.push(pry!(pry!(params.get()).get_logger()));
+
+        Promise::ok(())
+    }
+
+    fn set_ruleset(
+        &mut self,
+        params: blocker_service::SetRulesetParams,
+        mut _results: blocker_service::SetRulesetResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let hook = pry!(params.get_hook());
+        let ruleset = pry!(params.get_ruleset());
+        let whitelist = pry!(ruleset.get_whitelist());
+        let blacklist = pry!(ruleset.get_blacklist());
+
+        self.rule_counts[hook as usize] = (whitelist.len() + blacklist.len()) as usize;
+        if hook == FilterHook::CefUrlRequestCreate {
+            let patterns = pry!(blacklist
+                .iter()
+                .map(|pattern| pattern.map(|p| String::from_utf8_lossy(p.as_bytes()).into_owned()))
+                .collect::<Result<Vec<_>, _>>());
+            self.denylist =
+                pry!(RegexSet::new(patterns).map_err(|e| capnp::Error::failed(e.to_string())));
+        }
+
+        Promise::ok(())
+    }
+
+    fn enable_filtering(
+        &mut self,
+        _params: blocker_service::EnableFilteringParams,
+        mut _results: blocker_service::EnableFilteringResults,
+    ) -> Promise<(), ::capnp::Error> {
+        self.filtering = true;
+
+        let blocked = self.denylist.is_match(PROBE_URL);
+        let requests = self
+            .loggers
+            .iter()
+            .map(|logger| {
+                let mut request = logger.log_request_request();
+                let mut builder = request.get().init_request();
+                builder.set_hook(FilterHook::CefUrlRequestCreate);
+                builder.set_blocked(blocked);
+                builder.set_url(PROBE_URL);
+                request.send().promise
+            })
+            .collect::<Vec<_>>();
+        // Reported after answering, like the hooks do.
+        tokio::task::spawn_local(futures::future::join_all(requests));
+
+        Promise::ok(())
+    }
+
+    fn disable_filtering(
+        &mut self,
+        _params: blocker_service::DisableFilteringParams,
+        mut _results: blocker_service::DisableFilteringResults,
+    ) -> Promise<(), ::capnp::Error> {
+        self.filtering = false;
+
+        Promise::ok(())
+    }
+
+    fn get_status(
+        &mut self,
+        _params: blocker_service::GetStatusParams,
+        mut results: blocker_service::GetStatusResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let mut results = results.get();
+        results.set_filtering(self.filtering);
+        results.set_rule_count(self.rule_counts.iter().sum::<usize>() as u32);
+
+        Promise::ok(())
+    }
+
+    fn get_perf_stats(
+        &mut self,
+        _params: blocker_service::GetPerfStatsParams,
+        mut results: blocker_service::GetPerfStatsResults,
+    ) -> Promise<(), ::capnp::Error> {
+        // Nothing is hooked, so there is no overhead to report.
+        results.get().init_stats().init_hooks(0);
+
+        Promise::ok(())
+    }
+}